@@ -0,0 +1,10 @@
+#![no_main]
+
+use deeplx_rs::{DeepLResponse, Translation};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(response) = serde_json::from_slice::<DeepLResponse>(data) {
+        let _ = Translation::from(response);
+    }
+});
@@ -0,0 +1,7 @@
+#![no_main]
+
+use deeplx_rs::formats::SrtFile;
+use deeplx_rs_fuzz::round_trip;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| round_trip::<SrtFile>(data));
@@ -0,0 +1,16 @@
+use deeplx_rs::formats::Format;
+
+/// Parses `data` as `F` and, if that succeeds, pushes the segments back
+/// unchanged. The rendered file must parse again.
+pub fn round_trip<F: Format>(data: &str) {
+    let Ok(mut file) = F::parse(data) else {
+        return;
+    };
+    let segments: Vec<String> = file.segments().iter().map(|s| s.to_string()).collect();
+    file.replace_segments(&segments)
+        .expect("replacing with the same number of segments");
+    let rendered = file.render();
+    if let Err(e) = F::parse(&rendered) {
+        panic!("rendered output does not parse: {}\n{:?}", e, rendered);
+    }
+}
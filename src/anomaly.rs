@@ -0,0 +1,136 @@
+//! Early warning signs of throttling or upstream changes: responses that
+//! succeed but are unusually slow, oddly sized, or come with a collapsed
+//! language detection confidence.
+
+use std::{fmt, time::Duration};
+
+/// Sources shorter than this are too noisy for the length ratio check;
+/// "OK" → "Einverstanden" is fine.
+const MIN_RATIO_CHARS: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    pub slow: Option<Duration>,
+    /// Allowed range of translated/source character counts.
+    pub length_ratio: Option<(f64, f64)>,
+    pub min_confidence: Option<f64>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            slow: Some(Duration::from_secs(5)),
+            length_ratio: Some((0.2, 5.0)),
+            min_confidence: Some(0.3),
+        }
+    }
+}
+
+impl Thresholds {
+    /// Never reports anything.
+    pub fn off() -> Self {
+        Self {
+            slow: None,
+            length_ratio: None,
+            min_confidence: None,
+        }
+    }
+
+    pub fn check_latency(&self, elapsed: Duration) -> Option<Anomaly> {
+        let threshold = self.slow?;
+        (elapsed > threshold).then_some(Anomaly::Slow { elapsed, threshold })
+    }
+
+    pub fn check(
+        &self,
+        source: &str,
+        translated: &str,
+        confidence: Option<f64>,
+        elapsed: Duration,
+    ) -> Vec<Anomaly> {
+        let mut out: Vec<Anomaly> = self.check_latency(elapsed).into_iter().collect();
+        let source_chars = source.chars().count();
+        if let Some((min, max)) = self
+            .length_ratio
+            .filter(|_| source_chars >= MIN_RATIO_CHARS)
+        {
+            let ratio = translated.chars().count() as f64 / source_chars as f64;
+            if ratio < min || ratio > max {
+                out.push(Anomaly::LengthRatio { ratio });
+            }
+        }
+        if let (Some(min), Some(confidence)) = (self.min_confidence, confidence) {
+            if confidence < min {
+                out.push(Anomaly::LowConfidence { confidence });
+            }
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anomaly {
+    Slow {
+        elapsed: Duration,
+        threshold: Duration,
+    },
+    LengthRatio {
+        ratio: f64,
+    },
+    LowConfidence {
+        confidence: f64,
+    },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::Slow { elapsed, threshold } => write!(
+                f,
+                "slow upstream response: {:?} (threshold {:?})",
+                elapsed, threshold
+            ),
+            Anomaly::LengthRatio { ratio } => {
+                write!(f, "translation/source length ratio {:.2} is unusual", ratio)
+            }
+            Anomaly::LowConfidence { confidence } => {
+                write!(
+                    f,
+                    "language detection confidence dropped to {:.2}",
+                    confidence
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thresholds() {
+        let thresholds = Thresholds::default();
+        let source = "A perfectly ordinary sentence to translate.";
+        assert!(thresholds
+            .check(source, "Ein ganz normaler Satz.", Some(0.9), Duration::ZERO)
+            .is_empty());
+        assert_eq!(
+            thresholds.check(source, "Ja", Some(0.1), Duration::from_secs(9)),
+            [
+                Anomaly::Slow {
+                    elapsed: Duration::from_secs(9),
+                    threshold: Duration::from_secs(5)
+                },
+                Anomaly::LengthRatio { ratio: 2.0 / 43.0 },
+                Anomaly::LowConfidence { confidence: 0.1 },
+            ]
+        );
+        assert!(thresholds
+            .check("OK", "Einverstanden", None, Duration::ZERO)
+            .is_empty());
+        assert!(Thresholds::off()
+            .check(source, "", Some(0.0), Duration::MAX)
+            .is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+//! Many independent texts translated a few at a time, each with its own
+//! request, so that one failing text does not lose the others.
+//!
+//! [`DeepLClient::translate_batch`](crate::DeepLClient::translate_batch)
+//! packs texts into as few requests as possible instead, and fails as a
+//! whole.
+
+use futures_util::{stream, StreamExt};
+
+use crate::{
+    error::DeepLError,
+    options::TranslateOptions,
+    translator::{Translation, Translator},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchOptions {
+    pub src_lang: String,
+    pub target_lang: String,
+    /// Requests in flight at once, at least 1.
+    pub concurrency: usize,
+    pub options: TranslateOptions,
+}
+
+impl BatchOptions {
+    /// Four requests at a time with no options.
+    pub fn new(src_lang: impl Into<String>, target_lang: impl Into<String>) -> Self {
+        BatchOptions {
+            src_lang: src_lang.into(),
+            target_lang: target_lang.into(),
+            concurrency: 4,
+            options: TranslateOptions::default(),
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_options(mut self, options: TranslateOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// One result per text, in input order.
+#[derive(Debug)]
+pub struct BatchResults {
+    pub results: Vec<Result<Translation, DeepLError>>,
+}
+
+impl BatchResults {
+    pub fn succeeded(&self) -> usize {
+        self.results.iter().filter(|r| r.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.succeeded()
+    }
+
+    /// The failures with the index of their text.
+    pub fn errors(&self) -> impl Iterator<Item = (usize, &DeepLError)> {
+        self.results
+            .iter()
+            .enumerate()
+            .filter_map(|(i, r)| r.as_ref().err().map(|e| (i, e)))
+    }
+
+    /// Every translation, or the first failure.
+    pub fn into_translations(self) -> Result<Vec<Translation>, DeepLError> {
+        self.results.into_iter().collect()
+    }
+}
+
+/// Translates each of `texts` with `translator`, at most
+/// [`BatchOptions::concurrency`] at once.
+pub async fn translate_many<I>(
+    translator: &dyn Translator,
+    texts: I,
+    options: &BatchOptions,
+) -> BatchResults
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let results = stream::iter(texts)
+        .map(|text| async move {
+            translator
+                .translate_with_options(
+                    text.as_ref(),
+                    &options.src_lang,
+                    &options.target_lang,
+                    &options.options,
+                )
+                .await
+        })
+        .buffered(options.concurrency.max(1))
+        .collect()
+        .await;
+    BatchResults { results }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::translator::BoxFuture;
+
+    /// Upper-cases texts, slower the shorter they are, and fails on `!`.
+    #[derive(Default)]
+    struct Slow {
+        running: AtomicUsize,
+        most: AtomicUsize,
+    }
+
+    impl Translator for Slow {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+                self.most.fetch_max(running, Ordering::SeqCst);
+                let delay = 10 * (5 - text.len().min(5)) as u64;
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                self.running.fetch_sub(1, Ordering::SeqCst);
+                if text.contains('!') {
+                    return Err(DeepLError::NoProvider);
+                }
+                Ok(Translation {
+                    text: text.to_uppercase(),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_results_keep_order_and_errors() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let slow = Slow::default();
+        let texts = ["a", "bb", "c!", "dddd", "e"];
+        let options = BatchOptions::new("EN", "DE").with_concurrency(2);
+        let batch = runtime.block_on(translate_many(&slow, texts, &options));
+        let texts: Vec<_> = batch
+            .results
+            .iter()
+            .map(|r| r.as_ref().map(|t| t.text.as_str()).ok())
+            .collect();
+        assert_eq!(
+            texts,
+            [Some("A"), Some("BB"), None, Some("DDDD"), Some("E")]
+        );
+        assert_eq!((batch.succeeded(), batch.failed()), (4, 1));
+        assert_eq!(batch.errors().map(|(i, _)| i).collect::<Vec<_>>(), [2]);
+        assert_eq!(slow.most.load(Ordering::SeqCst), 2);
+        assert!(batch.into_translations().is_err());
+    }
+}
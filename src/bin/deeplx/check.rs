@@ -0,0 +1,191 @@
+//! `deeplx check`: list the strings that target locale files are missing
+//! or have translated from an older source, for pre-commit hooks and CI.
+//!
+//! Two layouts are recognised under the locale directory: one directory per
+//! language (`en/messages.json`, `ja/messages.json`) and one file per
+//! language (`en.json` or `messages.en.json` next to `messages.ja.json`).
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use deeplx_rs::{
+    encoding,
+    formats::FileKind,
+    sync::{content_hash, gaps, manifest_key, Manifest},
+};
+
+pub struct Options {
+    pub dir: PathBuf,
+    pub source: String,
+    /// Only these languages; every language found when empty.
+    pub to: Vec<String>,
+    pub manifest: PathBuf,
+}
+
+struct Pair {
+    lang: String,
+    source: PathBuf,
+    target: PathBuf,
+}
+
+fn files_under(dir: &Path, prefix: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let relative = prefix.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            files_under(&entry.path(), &relative, out)?;
+        } else {
+            out.push(relative);
+        }
+    }
+    Ok(())
+}
+
+fn is_hidden(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+fn pairs(options: &Options) -> io::Result<Vec<Pair>> {
+    let wanted = |lang: &str| options.to.is_empty() || options.to.iter().any(|l| l == lang);
+    let mut names: Vec<(String, bool)> = fs::read_dir(&options.dir)?
+        .filter_map(Result::ok)
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            let is_dir = e.file_type().ok()?.is_dir();
+            (!is_hidden(&name)).then_some((name, is_dir))
+        })
+        .collect();
+    names.sort();
+    let mut out = Vec::new();
+
+    let source_dir = options.dir.join(&options.source);
+    if source_dir.is_dir() {
+        let mut files = Vec::new();
+        files_under(&source_dir, Path::new(""), &mut files)?;
+        files.sort();
+        let mut langs: Vec<String> = names
+            .iter()
+            .filter(|(name, is_dir)| *is_dir && *name != options.source && wanted(name))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for lang in &options.to {
+            if !langs.contains(lang) {
+                langs.push(lang.clone());
+            }
+        }
+        for lang in langs {
+            for file in &files {
+                out.push(Pair {
+                    lang: lang.clone(),
+                    source: source_dir.join(file),
+                    target: options.dir.join(&lang).join(file),
+                });
+            }
+        }
+        return Ok(out);
+    }
+
+    // `<prefix><lang>.<ext>`, where the prefix is empty or ends in a dot.
+    let split = |name: &str| -> Option<(String, String, String)> {
+        let (stem, ext) = name.rsplit_once('.')?;
+        let (prefix, lang) = match stem.rsplit_once('.') {
+            Some((prefix, lang)) => (format!("{}.", prefix), lang),
+            None => (String::new(), stem),
+        };
+        Some((prefix, lang.to_string(), ext.to_string()))
+    };
+    for (name, _) in names.iter().filter(|(_, is_dir)| !is_dir) {
+        let Some((prefix, lang, ext)) = split(name) else {
+            continue;
+        };
+        if lang != options.source {
+            continue;
+        }
+        let mut langs: Vec<String> = names
+            .iter()
+            .filter(|(_, is_dir)| !is_dir)
+            .filter_map(|(other, _)| split(other))
+            .filter(|(p, l, e)| *p == prefix && *e == ext && *l != lang && wanted(l))
+            .map(|(_, l, _)| l)
+            .collect();
+        for lang in &options.to {
+            if !langs.contains(lang) {
+                langs.push(lang.clone());
+            }
+        }
+        for target_lang in langs {
+            out.push(Pair {
+                source: options.dir.join(name),
+                target: options
+                    .dir
+                    .join(format!("{}{}.{}", prefix, target_lang, ext)),
+                lang: target_lang,
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// Prints every gap and returns how many were found.
+pub fn run(options: &Options) -> Result<usize, String> {
+    let manifest = Manifest::load(&options.manifest).map_err(|e| e.to_string())?;
+    let pairs = pairs(options).map_err(|e| format!("{}: {}", options.dir.display(), e))?;
+    if pairs.is_empty() {
+        return Err(format!(
+            "no `{}` locale files under {}",
+            options.source,
+            options.dir.display()
+        ));
+    }
+    let mut problems = 0;
+    for pair in pairs {
+        let source = fs::read(&pair.source)
+            .map(|bytes| encoding::decode(&bytes).text)
+            .map_err(|e| format!("{}: {}", pair.source.display(), e))?;
+        let Some(kind) = FileKind::detect(&pair.source, &source) else {
+            continue;
+        };
+        let record = manifest.record(&pair.lang, &manifest_key(&pair.source));
+        let entries = kind
+            .entries(&source)
+            .map_err(|e| format!("{}: {}", pair.source.display(), e))?;
+        let target = match fs::read(&pair.target) {
+            Ok(bytes) => encoding::decode(&bytes).text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                match &entries {
+                    Some(entries) => println!(
+                        "{}: missing file ({} keys)",
+                        pair.target.display(),
+                        entries.len()
+                    ),
+                    None => println!("{}: missing file", pair.target.display()),
+                }
+                problems += entries.as_ref().map_or(1, Vec::len).max(1);
+                continue;
+            }
+            Err(e) => return Err(format!("{}: {}", pair.target.display(), e)),
+        };
+        match entries {
+            Some(entries) => {
+                let translated = kind
+                    .entries(&target)
+                    .map_err(|e| format!("{}: {}", pair.target.display(), e))?
+                    .unwrap_or_default();
+                for (key, gap) in gaps(&entries, &translated, record) {
+                    println!("{}: {} {}", pair.target.display(), gap, key);
+                    problems += 1;
+                }
+            }
+            // Documents have no keys; the file as a whole is current or not.
+            None => {
+                if record.is_some_and(|r| r.source_hash != content_hash(&source)) {
+                    println!("{}: outdated file", pair.target.display());
+                    problems += 1;
+                }
+            }
+        }
+    }
+    Ok(problems)
+}
@@ -0,0 +1,327 @@
+//! `deeplx daemon`: translate the selection when a hotkey is pressed and
+//! show the result as a notification.
+//!
+//! A global hotkey needs each desktop's native event loop, so the hotkey
+//! itself is bound in the desktop's keyboard settings to `deeplx daemon
+//! trigger`. That asks the running daemon, over a local socket, to read
+//! the selection, translate it with its warm client and notify; the
+//! selection and notifications go through the desktop's own tools.
+//!
+//! The socket is on loopback, where any local user can connect, so the
+//! daemon writes a fresh token to a file only its user can read, and a
+//! trigger has to send it along.
+
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+    time::Duration,
+};
+
+use deeplx_rs::Translator;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Desktop {
+    MacOs,
+    Wayland,
+    X11,
+    Windows,
+}
+
+impl Desktop {
+    pub fn current() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            Some(Desktop::MacOs)
+        } else if cfg!(windows) {
+            Some(Desktop::Windows)
+        } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            Some(Desktop::Wayland)
+        } else if std::env::var_os("DISPLAY").is_some() {
+            Some(Desktop::X11)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// The primary selection on Linux; the clipboard elsewhere, which has
+    /// no such thing.
+    Selection,
+    Clipboard,
+}
+
+/// A command to run: the program, its arguments and extra environment.
+type Invocation = (&'static str, Vec<String>, Vec<(&'static str, String)>);
+
+fn read_command(desktop: Desktop, source: Source) -> Invocation {
+    let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect();
+    match (desktop, source) {
+        (Desktop::MacOs, _) => ("pbpaste", Vec::new(), Vec::new()),
+        (Desktop::Wayland, Source::Selection) => {
+            ("wl-paste", args(&["--no-newline", "--primary"]), Vec::new())
+        }
+        (Desktop::Wayland, Source::Clipboard) => ("wl-paste", args(&["--no-newline"]), Vec::new()),
+        (Desktop::X11, Source::Selection) => {
+            ("xclip", args(&["-o", "-selection", "primary"]), Vec::new())
+        }
+        (Desktop::X11, Source::Clipboard) => (
+            "xclip",
+            args(&["-o", "-selection", "clipboard"]),
+            Vec::new(),
+        ),
+        (Desktop::Windows, _) => (
+            "powershell",
+            args(&["-NoProfile", "-Command", "Get-Clipboard -Raw"]),
+            Vec::new(),
+        ),
+    }
+}
+
+/// The text goes through the environment where a script shows it, so it
+/// never needs quoting.
+fn notify_command(desktop: Desktop, title: &str, body: &str) -> Invocation {
+    let env = vec![
+        ("DEEPLX_TITLE", title.to_string()),
+        ("DEEPLX_TEXT", body.to_string()),
+    ];
+    match desktop {
+        Desktop::MacOs => (
+            "osascript",
+            vec![
+                "-e".to_string(),
+                "display notification (system attribute \"DEEPLX_TEXT\") \
+                 with title (system attribute \"DEEPLX_TITLE\")"
+                    .to_string(),
+            ],
+            env,
+        ),
+        Desktop::Wayland | Desktop::X11 => (
+            "notify-send",
+            vec![
+                "--app-name=deeplx".to_string(),
+                "--".to_string(),
+                title.to_string(),
+                body.to_string(),
+            ],
+            Vec::new(),
+        ),
+        Desktop::Windows => (
+            "powershell",
+            vec![
+                "-NoProfile".to_string(),
+                "-Command".to_string(),
+                "Add-Type -AssemblyName PresentationFramework; \
+                 [void][System.Windows.MessageBox]::Show($env:DEEPLX_TEXT, $env:DEEPLX_TITLE)"
+                    .to_string(),
+            ],
+            env,
+        ),
+    }
+}
+
+fn run((program, args, env): Invocation) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(&args)
+        .envs(env)
+        .output()
+        .map_err(|e| format!("{}: {}", program, e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// How long a trigger has to send its request; the daemon answers one
+/// connection at a time, so an idle one must not hold the others up.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `$XDG_RUNTIME_DIR/deeplx-daemon.token`, or the same in the temporary
+/// directory.
+pub fn default_token_file() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("deeplx-daemon.token")
+}
+
+/// Writes a new random token to `path`, readable by the current user
+/// only, replacing the one a previous daemon left there.
+pub fn write_token(path: &Path) -> io::Result<String> {
+    let token: String = (0..2)
+        .map(|_| format!("{:016x}", RandomState::new().build_hasher().finish()))
+        .collect();
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+/// Checks a trigger's request line, `translate <token>`.
+fn check_request(line: &str, token: &str) -> Result<(), &'static str> {
+    let Some(("translate", sent)) = line.trim_end().split_once(' ') else {
+        return Err("unknown request");
+    };
+    // Compared in full whatever the first difference, so timing does not
+    // give the token away.
+    let same = sent.len() == token.len()
+        && sent
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if same {
+        Ok(())
+    } else {
+        Err("wrong token")
+    }
+}
+
+pub struct Daemon {
+    pub translator: Arc<dyn Translator>,
+    pub from: String,
+    pub to: String,
+    pub desktop: Desktop,
+    pub source: Source,
+    /// The token triggers must send, from [`write_token`].
+    pub token: String,
+}
+
+impl Daemon {
+    async fn translate_selection(&self) -> Result<String, String> {
+        let text = run(read_command(self.desktop, self.source))?;
+        if text.trim().is_empty() {
+            return Err("nothing selected".to_string());
+        }
+        self.translator
+            .translate(text.trim(), &self.from, &self.to)
+            .await
+            .map(|t| t.text)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Answers triggers on `listener` one at a time, notifying with each
+    /// translation or failure and sending it back to the trigger. A
+    /// request without the token is answered with an error and nothing
+    /// else.
+    pub fn serve(
+        &self,
+        listener: TcpListener,
+        runtime: &tokio::runtime::Runtime,
+    ) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let mut stream = stream?;
+            let mut line = String::new();
+            let request = stream
+                .set_read_timeout(Some(REQUEST_TIMEOUT))
+                .and_then(|()| stream.set_write_timeout(Some(REQUEST_TIMEOUT)))
+                .and_then(|()| BufReader::new((&stream).take(256)).read_line(&mut line))
+                .map_err(|_| "no request")
+                .and_then(|_| check_request(&line, &self.token));
+            if let Err(e) = request {
+                let _ = stream.write_all(format!("error\n{}", e).as_bytes());
+                continue;
+            }
+            let result = runtime.block_on(self.translate_selection());
+            let (title, body) = match &result {
+                Ok(text) => (format!("deeplx → {}", self.to), text.as_str()),
+                Err(e) => ("deeplx failed".to_string(), e.as_str()),
+            };
+            if let Err(e) = run(notify_command(self.desktop, &title, body)) {
+                eprintln!("deeplx: {}", e);
+            }
+            let reply = match &result {
+                Ok(text) => format!("ok\n{}", text),
+                Err(e) => format!("error\n{}", e),
+            };
+            let _ = stream.write_all(reply.as_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Asks the daemon at `addr` to translate, with the token it wrote to
+/// `token_file`, returning its translation.
+pub fn trigger(addr: SocketAddr, token_file: &Path) -> Result<String, String> {
+    let token = fs::read_to_string(token_file)
+        .map_err(|e| format!("no daemon token at {}: {}", token_file.display(), e))?;
+    let mut stream =
+        TcpStream::connect(addr).map_err(|e| format!("no daemon at {}: {}", addr, e))?;
+    stream
+        .write_all(format!("translate {}\n", token.trim()).as_bytes())
+        .and_then(|()| stream.shutdown(Shutdown::Write))
+        .map_err(|e| e.to_string())?;
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .map_err(|e| e.to_string())?;
+    match reply.split_once('\n') {
+        Some(("ok", text)) => Ok(text.to_string()),
+        Some(("error", message)) => Err(message.to_string()),
+        _ => Err(format!("unexpected reply from {}", addr)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desktop_commands() {
+        let (program, args, _) = read_command(Desktop::X11, Source::Selection);
+        assert_eq!(
+            (program, args.join(" ")),
+            ("xclip", "-o -selection primary".into())
+        );
+        let (program, args, env) = notify_command(Desktop::MacOs, "t", "say \"hi\"");
+        assert_eq!(program, "osascript");
+        assert!(!args[1].contains("hi"));
+        assert_eq!(env[1], ("DEEPLX_TEXT", "say \"hi\"".to_string()));
+        let (_, args, _) = notify_command(Desktop::Wayland, "t", "-x");
+        assert_eq!(args[1..], ["--", "t", "-x"]);
+    }
+
+    #[test]
+    fn test_requests_need_the_token() {
+        let path = std::env::temp_dir().join(format!("deeplx-daemon-{}.token", std::process::id()));
+        let token = write_token(&path).unwrap();
+        assert_eq!(token.len(), 32);
+        assert_ne!(write_token(&path).unwrap(), token);
+        let token = fs::read_to_string(&path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(
+            check_request(&format!("translate {}\n", token), &token),
+            Ok(())
+        );
+        assert_eq!(check_request("translate\n", &token), Err("unknown request"));
+        assert_eq!(check_request("quit now\n", &token), Err("unknown request"));
+        assert_eq!(
+            check_request(&format!("translate {}0\n", token), &token),
+            Err("wrong token")
+        );
+    }
+}
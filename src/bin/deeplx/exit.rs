@@ -0,0 +1,82 @@
+//! The exit codes of every subcommand and `--one-shot-json`, telling
+//! scripts a failure worth retrying later from one that is not.
+
+use std::process::ExitCode;
+
+use deeplx_rs::{error::DeepLError, formats::FileError};
+
+pub const OK: u8 = 0;
+/// Checks found problems, output could not be written, or something
+/// unexpected failed.
+pub const FAILURE: u8 = 1;
+/// The request is malformed or cannot be translated as given, there is
+/// nothing to translate, or the command line, the config or a file it
+/// names is wrong or unreadable.
+pub const INVALID_REQUEST: u8 = 2;
+/// Rate limited, out of budget or paused; worth trying again later.
+pub const TRY_LATER: u8 = 3;
+/// DeepL refused the credentials or the address.
+pub const REFUSED: u8 = 4;
+/// The upstream could not be reached or answered with an error.
+pub const UPSTREAM: u8 = 5;
+
+pub fn code(e: &DeepLError) -> u8 {
+    match e {
+        DeepLError::InvalidLanguage { .. } => INVALID_REQUEST,
+        DeepLError::RateLimited { .. }
+        | DeepLError::BudgetExceeded { .. }
+        | DeepLError::Paused { .. } => TRY_LATER,
+        DeepLError::Blocked { .. }
+        | DeepLError::ChallengeRequired { .. }
+        | DeepLError::Status {
+            status: 401 | 403 | 456,
+            ..
+        } => REFUSED,
+        // Including translations that came back with broken markup.
+        DeepLError::Network(_)
+        | DeepLError::Status { .. }
+        | DeepLError::Deserialize(_)
+        | DeepLError::ValidationFailed { .. } => UPSTREAM,
+        _ => FAILURE,
+    }
+}
+
+/// What to exit with after `e`.
+pub fn failed(e: &DeepLError) -> ExitCode {
+    ExitCode::from(code(e))
+}
+
+/// As [`failed`], for a file whose translation failed, or that could not
+/// be read as its format.
+pub fn file_failed(e: &FileError) -> ExitCode {
+    match e {
+        FileError::Translate(e) => failed(e),
+        FileError::Format(_) => ExitCode::from(INVALID_REQUEST),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_errors_map_to_exit_codes() {
+        let limited = DeepLError::RateLimited {
+            retry_after: Some(Duration::from_secs(5)),
+        };
+        assert_eq!(code(&limited), TRY_LATER);
+        let banned = DeepLError::Status {
+            status: 403,
+            body: String::new(),
+        };
+        assert_eq!(code(&banned), REFUSED);
+        let failing = DeepLError::Status {
+            status: 502,
+            body: String::new(),
+        };
+        assert_eq!(code(&failing), UPSTREAM);
+        assert_eq!(code(&DeepLError::NoProvider), FAILURE);
+    }
+}
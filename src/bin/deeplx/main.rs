@@ -0,0 +1,2061 @@
+mod check;
+mod daemon;
+mod exit;
+mod oneshot;
+mod repo;
+mod service;
+mod stdio;
+
+use std::{
+    fs,
+    io::{self, BufRead, IsTerminal, Read},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use deeplx_rs::{
+    budget::{Budget, Period},
+    cache::{KeyNormalization, TranslationCache},
+    chunk::Chunked,
+    cluster::{Cluster, Coordinated},
+    config::{CacheConfig, Config},
+    conversation::Conversation,
+    cookies::CookieJar,
+    document::DocumentOptions,
+    email::{translate_email, EmailOptions},
+    encoding::Encoding,
+    eval::{evaluate, parse_tsv},
+    experiment::Experiment,
+    filter,
+    fingerprint::{FingerprintPool, Selection},
+    formats::{LineEnding, OnFailure},
+    glossary::{Enforced, Glossary},
+    limiter::{self, RateLimiter},
+    maintenance::{Mode, Switch},
+    options::{Casing, Formality, Splitting, TagHandling, TagMode},
+    report::JobReport,
+    retry::RetryPolicy,
+    server::{Compat, Server, TranslateRequest},
+    session::SessionPool,
+    signing::Signer,
+    state::{StateDir, StateHooks},
+    storage::MemoryStorage,
+    sync::{write_atomic, Layout},
+    telemetry::{PrometheusSink, StatsdSink, TelemetrySink},
+    DeepLClient, RequestStrategy, Translator,
+};
+#[cfg(unix)]
+use futures_util::future::{self, Either};
+use tokio::runtime::Runtime;
+
+#[cfg(unix)]
+use deeplx_rs::coordinator::{Coordinator, CoordinatorClient};
+
+use daemon::{Daemon, Desktop, Source};
+use service::{Platform, Service};
+
+#[derive(Parser)]
+#[command(
+    name = "deeplx",
+    version,
+    about = "DeepL translation from the command line"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Read one JSON request, `{"text", "source_lang", "target_lang"}` as
+    /// for `deeplx serve`, from stdin and print one JSON response, exiting
+    /// with 2 for an invalid request, 3 when rate limited, out of budget
+    /// or paused, 4 when refused and 5 when the upstream fails.
+    #[arg(long)]
+    one_shot_json: bool,
+    /// The endpoint for `--one-shot-json`.
+    #[arg(long, requires = "one_shot_json")]
+    endpoint: Option<String>,
+    /// Send requests through this proxy: `http://`, `https://`,
+    /// `socks5://` or `socks5h://`, with `user:password@` if it needs a
+    /// login. `HTTPS_PROXY` and `ALL_PROXY` are used otherwise.
+    #[arg(long, global = true)]
+    proxy: Option<String>,
+    /// Ignore the proxy environment variables.
+    #[arg(long, global = true, conflicts_with = "proxy")]
+    no_proxy: bool,
+    /// A file of DeepL Pro `dl_session` tokens, one per line, used in
+    /// turn and skipped once rejected. `DL_SESSION` is used otherwise,
+    /// when set.
+    #[arg(long, global = true)]
+    sessions: Option<PathBuf>,
+    /// Translate through the official DeepL API with the key in this
+    /// file. `DEEPL_AUTH_KEY` is used otherwise, when set.
+    #[arg(long, global = true)]
+    auth_key_file: Option<PathBuf>,
+    /// Send at most this many requests upstream, as `5`, `5/s` or
+    /// `90/min`, so long batches do not trip DeepL's abuse detection.
+    #[arg(long, global = true, value_parser = rate_limit)]
+    rate_limit: Option<f64>,
+    /// Requests that may go out at once before `--rate-limit` applies.
+    #[arg(long, global = true, default_value_t = 1, requires = "rate_limit")]
+    burst: u32,
+    /// Take rate permits and cached translations from the `deeplx
+    /// coordinator` listening at this socket, shared with every other
+    /// invocation using it. `DEEPLX_COORDINATOR` is used otherwise, when
+    /// set.
+    #[cfg(unix)]
+    #[arg(long, global = true)]
+    coordinator: Option<PathBuf>,
+    /// Send each request with the app, OS and device headers of another
+    /// built-in iPhone fingerprint, taken in turn or at random.
+    #[arg(long, global = true, value_enum)]
+    rotate_fingerprints: Option<SelectionArg>,
+    /// Keep the cookies DeepL sets in this file, loaded at start and saved
+    /// when the command ends, so that invocations carry on one session.
+    #[arg(long, global = true)]
+    cookie_jar: Option<PathBuf>,
+    /// Read the endpoint, proxy, timeouts, retries, cache and listen
+    /// address from this TOML file, `DEEPLX_CONFIG` otherwise. `DEEPLX_*`
+    /// variables override it, and flags override both.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+}
+
+/// Saves the `--cookie-jar` however the command ends.
+struct SaveCookies(Arc<CookieJar>, PathBuf);
+
+impl Drop for SaveCookies {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.save(&self.1) {
+            eprintln!("deeplx: {}: {}", self.1.display(), e);
+        }
+    }
+}
+
+fn rate_limit(spec: &str) -> Result<f64, String> {
+    limiter::parse_rate(spec)
+        .ok_or_else(|| format!("expected a rate such as 5/s or 90/min, not {:?}", spec))
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Translate text given as an argument, in a file or on stdin, and
+    /// print the translation.
+    Translate(TranslateArgs),
+    /// Translate a `.docx`, `.pptx`, `.pdf` or other document through the
+    /// official DeepL API, keeping its layout.
+    Document(DocumentArgs),
+    /// Translate the text and HTML parts of an `.eml` message, keeping its
+    /// headers and attachments, and print it or write it to `--output`.
+    Email(EmailArgs),
+    /// Translate a file of test cases and score the translations against
+    /// their references with chrF and BLEU.
+    Eval(EvalArgs),
+    /// Translate the selection whenever a hotkey bound to `deeplx daemon
+    /// trigger` is pressed, showing the result as a notification.
+    #[command(subcommand)]
+    Daemon(DaemonCommand),
+    /// Translate each line of stdin into whichever of the two languages it
+    /// is not in, with the lines before it as context, for chat bridges.
+    Converse(ConverseArgs),
+    /// Answer JSON-RPC requests on stdin, one per line or framed as in LSP,
+    /// for editor plugins keeping a warm child process: `translate`,
+    /// `detect` and `cancel`.
+    Stdio {
+        #[arg(long)]
+        endpoint: Option<String>,
+    },
+    /// Hand out rate permits and cached translations to the invocations
+    /// run with `--coordinator`, so that together they keep to
+    /// `--rate-limit` under `make -j` or parallel CI steps.
+    #[cfg(unix)]
+    Coordinator {
+        #[arg(long)]
+        socket: PathBuf,
+        /// Translations kept for the invocations.
+        #[arg(long, default_value_t = 10_000)]
+        cache_size: usize,
+    },
+    /// Run `deeplx serve` in the background from login on: as a launchd
+    /// agent on macOS, a Task Scheduler task on Windows.
+    #[command(subcommand)]
+    Service(ServiceCommand),
+    /// Manage the glossaries stored with the official DeepL API.
+    #[command(subcommand)]
+    Glossary(GlossaryCommand),
+    /// Check connectivity to the upstream and print diagnostics.
+    Doctor {
+        #[arg(long)]
+        endpoint: Option<String>,
+    },
+    /// Translate the matching files of a repository into a locale tree.
+    /// Files whose source is unchanged since the last run are skipped.
+    Repo(Box<RepoArgs>),
+    /// List strings that target locale files are missing or have
+    /// translated from an older source; exits non-zero if there are any.
+    /// `deeplx repo` fills them in.
+    Check(CheckArgs),
+    /// Compare request strategies on a sample workload and report how often
+    /// each one succeeds or gets banned.
+    Bench(BenchArgs),
+    /// Serve a DeepLX-compatible `POST /translate` endpoint.
+    Serve(ServeArgs),
+}
+
+#[derive(Args)]
+struct TranslateArgs {
+    /// The text to translate; read from `--file` or stdin otherwise.
+    text: Option<String>,
+    #[arg(long, conflicts_with = "text")]
+    file: Option<PathBuf>,
+    #[arg(long, short, default_value = "auto")]
+    from: String,
+    #[arg(long, short)]
+    to: String,
+    /// Undo the line wrapping of text extracted from a PDF first:
+    /// join hyphenated words and the lines of each paragraph.
+    #[arg(long)]
+    reflow: bool,
+    /// Print the alternative translations DeepL offers too, one per
+    /// line after the translation.
+    #[arg(long)]
+    alternatives: bool,
+    /// Print the full result as JSON.
+    #[arg(long, conflicts_with = "alternatives")]
+    json: bool,
+    /// The tone, for target languages that have a formal and an
+    /// informal register.
+    #[arg(long, value_enum)]
+    formality: Option<FormalityArg>,
+    /// Keep the terms of this `source<TAB>target` file fixed, by
+    /// masking them before translating and putting their translations
+    /// back afterwards.
+    #[arg(long)]
+    glossary: Option<PathBuf>,
+    /// Apply this glossary stored with DeepL, from `deeplx glossary
+    /// create`; needs the official API.
+    #[arg(long, conflicts_with = "glossary")]
+    glossary_id: Option<String>,
+    /// Recase the translation: like the source, or in the target
+    /// language's sentence or title case.
+    #[arg(long, value_enum)]
+    casing: Option<CasingArg>,
+    /// Where DeepL splits the text into segments: `sentences` or
+    /// `paragraphs` for wrapped prose, `off` to keep it in one piece.
+    #[arg(long, value_enum)]
+    splitting: Option<SplittingArg>,
+    /// Treat the text as HTML or XML and keep its markup.
+    #[arg(long, value_enum)]
+    tag_handling: Option<TagModeArg>,
+    /// Elements whose content is not translated, such as `code,pre`.
+    #[arg(long, value_delimiter = ',', requires = "tag_handling")]
+    ignore_tags: Vec<String>,
+    /// Elements that do not break a sentence, for the official API.
+    #[arg(long, value_delimiter = ',', requires = "tag_handling")]
+    non_splitting_tags: Vec<String>,
+    /// Times to retry a rate-limited or failed request; 3 unless the
+    /// config sets `retries`.
+    #[arg(long)]
+    retries: Option<u32>,
+    /// Longer texts are split at paragraphs and sentences into
+    /// requests of at most this many characters.
+    #[arg(long, default_value_t = 5000)]
+    max_chars: usize,
+    /// Chunks of a long text translated at the same time.
+    #[arg(long, default_value_t = 1)]
+    jobs: usize,
+    /// Print the request that would be sent, with secrets redacted, and
+    /// the id, spacing, proxy, session and fingerprint chosen for it,
+    /// instead of sending it. `--glossary` masking and chunking are not
+    /// applied.
+    #[arg(long)]
+    explain: bool,
+    #[arg(long)]
+    endpoint: Option<String>,
+}
+
+#[derive(Args)]
+struct DocumentArgs {
+    file: PathBuf,
+    #[arg(long, short, default_value = "auto")]
+    from: String,
+    #[arg(long, short)]
+    to: String,
+    /// Where to write the translation; `<name>.<to>.<ext>` next to
+    /// the file by default.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    #[arg(long, value_enum)]
+    formality: Option<FormalityArg>,
+    #[arg(long)]
+    glossary_id: Option<String>,
+    #[arg(long)]
+    endpoint: Option<String>,
+}
+
+#[derive(Args)]
+struct EmailArgs {
+    file: PathBuf,
+    #[arg(long, short, default_value = "auto")]
+    from: String,
+    #[arg(long, short)]
+    to: String,
+    /// Keep the original text below the translation.
+    #[arg(long)]
+    keep_original: bool,
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    #[arg(long)]
+    endpoint: Option<String>,
+}
+
+#[derive(Args)]
+struct EvalArgs {
+    /// `source<TAB>reference` lines, optionally followed by
+    /// `<TAB>source_lang<TAB>target_lang`.
+    file: PathBuf,
+    #[arg(long, short, default_value = "auto")]
+    from: String,
+    #[arg(long, short)]
+    to: String,
+    #[arg(long)]
+    endpoint: Option<String>,
+}
+
+#[derive(Args)]
+struct ConverseArgs {
+    /// The language the first line is expected in.
+    first: String,
+    second: String,
+    /// Lines before each one sent along as context.
+    #[arg(long, default_value_t = 2)]
+    context: usize,
+    #[arg(long)]
+    endpoint: Option<String>,
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    /// The locale directory, holding one directory or file per
+    /// language.
+    dir: PathBuf,
+    /// The source language's directory or file stem, such as `en`.
+    #[arg(long)]
+    source: String,
+    /// Only check these languages, comma-separated or repeated.
+    #[arg(long, value_delimiter = ',')]
+    to: Vec<String>,
+    /// The manifest written by `deeplx repo`, used to find outdated
+    /// strings.
+    #[arg(long, default_value = ".deeplx/manifest.json")]
+    manifest: PathBuf,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// Sample texts, one per line; a few built-in sentences otherwise.
+    #[arg(long)]
+    samples: Option<PathBuf>,
+    /// Strategies to compare, comma-separated; all of them by default.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    strategies: Vec<StrategyArg>,
+    /// Times to go through the samples.
+    #[arg(long, default_value_t = 1)]
+    rounds: usize,
+    /// Milliseconds to wait between requests.
+    #[arg(long, default_value_t = 1000)]
+    interval_ms: u64,
+    #[arg(long, default_value = "EN")]
+    from: String,
+    #[arg(long, default_value = "DE")]
+    to: String,
+    /// Print the report as JSON.
+    #[arg(long)]
+    json: bool,
+    #[arg(long)]
+    endpoint: Option<String>,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// The address to listen on; `server.listen` from the config, or
+    /// 127.0.0.1:1188.
+    #[arg(long)]
+    listen: Option<SocketAddr>,
+    /// Only answer requests carrying this token, as
+    /// `Authorization: Bearer <token>` or `?token=<token>`.
+    #[arg(long)]
+    token: Option<String>,
+    /// Let requests carrying this bearer token switch the gateway's
+    /// mode on `PUT /admin/mode` without a restart.
+    #[arg(long)]
+    admin_token: Option<String>,
+    /// Start in maintenance, answering from the cache with stale
+    /// entries too, or paused, answering from the cache only; every
+    /// other request fails with 503.
+    #[arg(long, value_enum, default_value_t = ModeArg::Normal)]
+    mode: ModeArg,
+    /// Flag translations more than this many times as long as their
+    /// source.
+    #[arg(long)]
+    max_length_ratio: Option<f64>,
+    /// Flag translations containing any line of this file, unless
+    /// the source contains it too.
+    #[arg(long)]
+    banned: Option<PathBuf>,
+    /// Flag translations that lost or invented placeholders or tags.
+    #[arg(long)]
+    check_placeholders: bool,
+    /// Refuse flagged translations with 422 instead of returning them
+    /// with `quality_flags`.
+    #[arg(long)]
+    reject: bool,
+    /// Answer byte for byte like the Go DeepLX server, for clients
+    /// that depend on its field order and error messages.
+    #[arg(long)]
+    strict_compat: bool,
+    /// Sign every response with HMAC-SHA256 using the key in this
+    /// file, in the `x-deeplx-signature` header.
+    #[arg(long)]
+    sign_key_file: Option<PathBuf>,
+    /// A name for the signing key, sent with each signature.
+    #[arg(long, requires = "sign_key_file")]
+    sign_key_id: Option<String>,
+    /// Seconds for which a request repeating the `Idempotency-Key` of
+    /// an earlier one gets its result back; 0 turns keys off.
+    #[arg(long, default_value_t = 600)]
+    idempotency_window: u64,
+    /// Log a warning once this many characters were translated in the
+    /// budget period.
+    #[arg(long)]
+    budget_soft: Option<u64>,
+    /// Refuse requests past this many characters in the budget
+    /// period, except those carrying `--priority-token`.
+    #[arg(long)]
+    budget_hard: Option<u64>,
+    #[arg(long, value_enum, default_value_t = PeriodArg::Day)]
+    budget_period: PeriodArg,
+    /// Requests carrying this token instead of `--token` are exempt
+    /// from `--budget-hard`.
+    #[arg(long)]
+    priority_token: Option<String>,
+    /// Restore session health, budget usage and the in-memory cache from
+    /// this directory at startup, and save them there on Ctrl-C.
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    /// Answer repeated requests from a cache of this many recent
+    /// translations; 0, the default unless the config sets
+    /// `cache.size`, turns it off.
+    #[arg(long)]
+    cache_size: Option<usize>,
+    /// Seconds a cached translation is used for; `cache.ttl` from the
+    /// config, or until evicted.
+    #[arg(long)]
+    cache_ttl: Option<u64>,
+    /// Answer from cached translations up to this many seconds past
+    /// their TTL, marked stale, while the upstream is unavailable.
+    #[arg(long)]
+    serve_stale: Option<u64>,
+    /// Let cached texts differing in these ways answer each other, on
+    /// top of surrounding whitespace: `case`, `whitespace` inside the
+    /// text, and `placeholders` by position rather than name.
+    #[arg(long, value_delimiter = ',', value_enum)]
+    cache_normalize: Vec<NormalizeArg>,
+    /// Keep cached translations in this SQLite file instead, so they
+    /// survive restarts.
+    #[cfg(feature = "storage-sqlite")]
+    #[arg(long, conflicts_with = "cache_size")]
+    cache_db: Option<PathBuf>,
+    /// Serve request, upstream and cache metrics on `GET /metrics` in
+    /// the Prometheus format.
+    #[arg(long)]
+    metrics: bool,
+    /// Send the same metrics to the StatsD agent at this address, such
+    /// as `127.0.0.1:8125`.
+    #[arg(long, conflicts_with = "metrics")]
+    statsd: Option<String>,
+    #[arg(long)]
+    endpoint: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum DaemonCommand {
+    /// Wait for triggers, keeping the client warm between them.
+    Run {
+        #[arg(long, short, default_value = "auto")]
+        from: String,
+        #[arg(long, short)]
+        to: String,
+        #[arg(long, value_enum, default_value_t = SourceArg::Selection)]
+        source: SourceArg,
+        #[arg(long, default_value = DAEMON_ADDR)]
+        listen: SocketAddr,
+        /// Where to write the token triggers must send
+        /// [default: $XDG_RUNTIME_DIR/deeplx-daemon.token]
+        #[arg(long)]
+        token_file: Option<PathBuf>,
+        #[arg(long)]
+        endpoint: Option<String>,
+    },
+    /// Have the running daemon translate the selection, and print the
+    /// translation. Bind this to a hotkey in the desktop's keyboard
+    /// settings.
+    Trigger {
+        #[arg(long, default_value = DAEMON_ADDR)]
+        addr: SocketAddr,
+        /// The token file the daemon wrote.
+        #[arg(long)]
+        token_file: Option<PathBuf>,
+    },
+}
+
+const DAEMON_ADDR: &str = "127.0.0.1:1189";
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SourceArg {
+    /// The primary selection on Linux, the clipboard elsewhere.
+    Selection,
+    Clipboard,
+}
+
+#[derive(Subcommand)]
+enum ServiceCommand {
+    /// Register and start the service, serving with the `deeplx serve`
+    /// options given after `--`.
+    Install {
+        #[arg(long, default_value = SERVICE_LABEL)]
+        label: String,
+        /// Defaults to the service manager of this system.
+        #[arg(long, value_enum)]
+        platform: Option<PlatformArg>,
+        /// Print the launchd plist or `schtasks` command instead.
+        #[arg(long)]
+        print: bool,
+        #[arg(last = true)]
+        serve_args: Vec<String>,
+    },
+    /// Stop the service and remove it.
+    Uninstall {
+        #[arg(long, default_value = SERVICE_LABEL)]
+        label: String,
+        #[arg(long, value_enum)]
+        platform: Option<PlatformArg>,
+    },
+}
+
+const SERVICE_LABEL: &str = "io.github.deeplx-rs";
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PlatformArg {
+    Launchd,
+    TaskScheduler,
+}
+
+#[derive(Subcommand)]
+enum GlossaryCommand {
+    /// Store the decided entries of a `source<TAB>target` file with
+    /// DeepL and print the new glossary's id.
+    Create {
+        file: PathBuf,
+        #[arg(long)]
+        name: String,
+        #[arg(long, short)]
+        from: String,
+        #[arg(long, short)]
+        to: String,
+        #[arg(long)]
+        endpoint: Option<String>,
+    },
+    /// List the stored glossaries: id, languages, entries and name.
+    List {
+        #[arg(long)]
+        endpoint: Option<String>,
+    },
+}
+
+#[derive(Args)]
+struct RepoArgs {
+    /// Glob of source files, relative to `--root`; repeatable.
+    #[arg(long, required = true)]
+    include: Vec<String>,
+    /// Glob of files to leave out; repeatable.
+    #[arg(long)]
+    exclude: Vec<String>,
+    #[arg(long, default_value = "auto")]
+    from: String,
+    /// Target languages, comma-separated or repeated.
+    #[arg(long, required = true, value_delimiter = ',')]
+    to: Vec<String>,
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
+    #[arg(long, value_enum, default_value_t = LayoutArg::Tree)]
+    layout: LayoutArg,
+    /// Where the `tree` layout puts the locale directories, relative to
+    /// `--root`.
+    #[arg(long, default_value = "i18n")]
+    out: PathBuf,
+    /// Target path template relative to `--root`, overriding
+    /// `--layout`: `{dir}`, `{stem}`, `{ext}` and `{lang}`, as in
+    /// `{dir}/{stem}_{lang}.{ext}`. Without `{lang}` files are
+    /// translated in place.
+    #[arg(long, conflicts_with = "layout")]
+    template: Option<String>,
+    /// Source hashes from previous runs, relative to `--root`.
+    #[arg(long, default_value = ".deeplx/manifest.json")]
+    manifest: PathBuf,
+    /// Retranslate every file, changed or not.
+    #[arg(long)]
+    force: bool,
+    /// List the files that would be translated.
+    #[arg(long)]
+    dry_run: bool,
+    /// Keep the previous version of every overwritten file as
+    /// `<name>.bak`.
+    #[arg(long)]
+    backup: bool,
+    /// Write targets in this encoding, such as `utf-8` or `gbk`,
+    /// instead of the detected encoding of each source.
+    #[arg(long)]
+    encoding: Option<String>,
+    #[arg(long, value_enum, default_value_t = LineEndingArg::Keep)]
+    line_endings: LineEndingArg,
+    #[arg(long, value_enum, default_value_t = BomArg::Keep)]
+    bom: BomArg,
+    /// Write a JSON report of every file to this path.
+    #[arg(long)]
+    report: Option<PathBuf>,
+    /// Write an HTML summary of the job to this path.
+    #[arg(long)]
+    report_html: Option<PathBuf>,
+    /// What to do with files where some segments cannot be translated.
+    #[arg(long, value_enum, default_value_t = OnFailureArg::Fail)]
+    on_failure: OnFailureArg,
+    /// Put in front of segments left untranslated by
+    /// `--on-failure keep-source`.
+    #[arg(long, default_value = "[untranslated] ")]
+    failure_marker: String,
+    /// Mark targets as machine translated: fuzzy PO entries, XLIFF
+    /// review states and, where the format has comments, `NOTE` as a
+    /// comment at the top.
+    #[arg(
+        long,
+        value_name = "NOTE",
+        num_args = 0..=1,
+        default_missing_value = "Machine translated with deeplx; review before release."
+    )]
+    mark_mt: Option<String>,
+    /// What to do about targets edited by hand since they were last
+    /// translated. `ask` keeps them when stdin is not a terminal.
+    #[arg(long, value_enum, default_value_t = ConflictArg::Ask)]
+    on_conflict: ConflictArg,
+    /// Files translated at the same time.
+    #[arg(long, default_value_t = 4)]
+    jobs: usize,
+    /// Upper bound on requests per second across all files.
+    #[arg(long)]
+    rate: Option<f64>,
+    /// Times to retry a request after a rate limit, a server error or a
+    /// network failure, backing off exponentially; 3 unless the config
+    /// sets `retries`.
+    #[arg(long)]
+    retries: Option<u32>,
+    #[arg(long)]
+    endpoint: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LayoutArg {
+    /// `<out>/<lang>/<path>`
+    Tree,
+    /// `<dir>/<stem>.<lang>.<ext>`
+    Sibling,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OnFailureArg {
+    /// Leave the target alone and count the file as failed.
+    Fail,
+    /// Write the target with failed segments left in the source language,
+    /// prefixed with `--failure-marker`.
+    KeepSource,
+    /// Write the target with failed segments left in the source language
+    /// and list them in `<target>.failures.json`.
+    Sidecar,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ConflictArg {
+    /// Ask about each one.
+    Ask,
+    /// Leave them alone and report them.
+    Keep,
+    Overwrite,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StrategyArg {
+    Alternating,
+    Spaced,
+    WideSpaced,
+    Compact,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TagModeArg {
+    Html,
+    Xml,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormalityArg {
+    #[value(alias = "more")]
+    Formal,
+    #[value(alias = "less")]
+    Informal,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CasingArg {
+    PreserveSource,
+    Sentence,
+    Title,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SplittingArg {
+    Newlines,
+    Sentences,
+    Paragraphs,
+    Off,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NormalizeArg {
+    Case,
+    Whitespace,
+    Placeholders,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SelectionArg {
+    RoundRobin,
+    Random,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ModeArg {
+    Normal,
+    Maintenance,
+    Paused,
+}
+
+/// Budget periods, in UTC.
+#[derive(Clone, Copy, ValueEnum)]
+enum PeriodArg {
+    Day,
+    Month,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LineEndingArg {
+    /// As in each source file.
+    Keep,
+    Lf,
+    Crlf,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BomArg {
+    /// As in each source file.
+    Keep,
+    Add,
+    Remove,
+}
+
+/// Alternatives asked for by `translate --alternatives` and `--json`.
+const ALTERNATIVES: u32 = 3;
+
+const BENCH_SAMPLES: &[&str] = &[
+    "Hello, world!",
+    "The quick brown fox jumps over the lazy dog.",
+    "Please restart the application to apply the update.",
+];
+
+/// Reads a glossary file, reporting why it cannot be used.
+fn read_glossary(path: &Path) -> Result<Glossary, ()> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        eprintln!("deeplx: {}: {}", path.display(), e);
+    })?;
+    Glossary::parse_tsv(&text).map_err(|e| {
+        eprintln!("deeplx: {}: {}", path.display(), e);
+    })
+}
+
+fn write_reports(
+    report: &JobReport,
+    json: Option<&Path>,
+    html: Option<&Path>,
+) -> std::io::Result<()> {
+    if let Some(path) = json {
+        let mut text = serde_json::to_string_pretty(report)?;
+        text.push('\n');
+        write_atomic(path, text.as_bytes(), false)?;
+    }
+    if let Some(path) = html {
+        write_atomic(path, report.to_html().as_bytes(), false)?;
+    }
+    Ok(())
+}
+
+/// What the global options set up, shared by every subcommand.
+struct Context {
+    runtime: Runtime,
+    config: Config,
+    proxy: Option<String>,
+    sessions: Option<Arc<SessionPool>>,
+    auth_key: Option<String>,
+    /// One bucket for every client the command builds.
+    limiter: Option<Arc<RateLimiter>>,
+    /// `--rate-limit` and `--burst`, for `deeplx coordinator`.
+    #[cfg(unix)]
+    rate: Option<(f64, u32)>,
+    #[cfg(unix)]
+    coordinator: Option<Arc<CoordinatorClient>>,
+    fingerprints: Option<Arc<FingerprintPool>>,
+    cookies: Option<SaveCookies>,
+}
+
+impl Context {
+    /// Fails with the exit code to use and why.
+    fn new(cli: &Cli) -> Result<Self, (u8, String)> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| (exit::FAILURE, format!("cannot start runtime: {}", e)))?;
+        // Everything else comes from flags and the files they name.
+        Self::configure(cli, runtime).map_err(|e| (exit::INVALID_REQUEST, e))
+    }
+
+    fn configure(cli: &Cli, runtime: Runtime) -> Result<Self, String> {
+        let config = match cli
+            .config
+            .clone()
+            .or_else(|| std::env::var_os("DEEPLX_CONFIG").map(PathBuf::from))
+            .filter(|path| !path.as_os_str().is_empty())
+        {
+            Some(path) => Config::load(&path).map_err(|e| format!("{}: {}", path.display(), e)),
+            None => Ok(Config::default()),
+        };
+        let config = config.and_then(|config| config.with_env().map_err(|e| e.to_string()))?;
+        let proxy = match (&cli.proxy, cli.no_proxy) {
+            (Some(url), _) => Some(url.clone()),
+            (None, true) => Some("direct".to_string()),
+            (None, false) => config.proxy.clone(),
+        };
+        let sessions = match &cli.sessions {
+            Some(path) => {
+                let text =
+                    fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                Some(Arc::new(SessionPool::new(text.lines().map(str::to_string))))
+            }
+            // DeepLX's variable for a single token.
+            None => std::env::var("DL_SESSION")
+                .ok()
+                .filter(|token| !token.trim().is_empty())
+                .map(|token| Arc::new(SessionPool::new([token]))),
+        };
+        let auth_key = match &cli.auth_key_file {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(key) if !key.trim().is_empty() => Some(key.trim().to_string()),
+                Ok(_) => return Err(format!("{}: empty auth key", path.display())),
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            },
+            None => std::env::var("DEEPL_AUTH_KEY")
+                .ok()
+                .filter(|key| !key.trim().is_empty()),
+        };
+        let cookies = match &cli.cookie_jar {
+            Some(path) => {
+                let jar =
+                    CookieJar::load(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                Some(SaveCookies(Arc::new(jar), path.clone()))
+            }
+            None => None,
+        };
+        Ok(Self {
+            runtime,
+            config,
+            proxy,
+            sessions,
+            auth_key,
+            limiter: cli
+                .rate_limit
+                .map(|rate| Arc::new(RateLimiter::new(rate, cli.burst))),
+            #[cfg(unix)]
+            rate: cli.rate_limit.map(|rate| (rate, cli.burst)),
+            #[cfg(unix)]
+            coordinator: cli
+                .coordinator
+                .clone()
+                .or_else(|| std::env::var_os("DEEPLX_COORDINATOR").map(PathBuf::from))
+                .filter(|path| !path.as_os_str().is_empty())
+                .map(|path| Arc::new(CoordinatorClient::new(path))),
+            fingerprints: cli.rotate_fingerprints.map(|selection| {
+                Arc::new(FingerprintPool::builtin().with_selection(match selection {
+                    SelectionArg::RoundRobin => Selection::RoundRobin,
+                    SelectionArg::Random => Selection::Random,
+                }))
+            }),
+            cookies,
+        })
+    }
+
+    fn retry(&self, retries: Option<u32>) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: retries
+                .or(self.config.retries)
+                .unwrap_or(3)
+                .saturating_add(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// A client set up from the command line and the config file, its
+    /// `[cache]` included.
+    fn client(&self, endpoint: Option<String>) -> Option<DeepLClient> {
+        self.client_with_cache(endpoint, self.config.cache)
+    }
+
+    fn client_with_cache(
+        &self,
+        endpoint: Option<String>,
+        cache: CacheConfig,
+    ) -> Option<DeepLClient> {
+        let config = Config {
+            endpoint: endpoint.or_else(|| self.config.endpoint.clone()),
+            proxy: self.proxy.clone(),
+            cache,
+            ..self.config.clone()
+        };
+        let client = match DeepLClient::from_config(&config) {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("deeplx: invalid proxy: {}", e);
+                return None;
+            }
+        };
+        let client = match &self.limiter {
+            Some(limiter) => client.with_shared_rate_limiter(limiter.clone()),
+            None => client,
+        };
+        #[cfg(unix)]
+        let client = match &self.coordinator {
+            Some(coordinator) => client.with_coordinator(coordinator.clone()),
+            None => client,
+        };
+        let client = match &self.fingerprints {
+            Some(pool) => client.with_fingerprints(pool.clone()),
+            None => client,
+        };
+        let client = match &self.cookies {
+            Some(SaveCookies(jar, _)) => client.with_cookie_jar(jar.clone()),
+            None => client,
+        };
+        let client = match &self.sessions {
+            Some(pool) => client.with_session_pool(pool.clone()),
+            None => client,
+        };
+        Some(match &self.auth_key {
+            Some(key) => client.with_auth_key(key),
+            None => client,
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let cx = match Context::new(&cli) {
+        Ok(cx) => cx,
+        Err((code, e)) => {
+            eprintln!("deeplx: {}", e);
+            return ExitCode::from(code);
+        }
+    };
+    let command = match cli.command {
+        Some(_) if cli.one_shot_json => {
+            eprintln!("deeplx: --one-shot-json takes no subcommand");
+            return ExitCode::from(exit::INVALID_REQUEST);
+        }
+        Some(command) => command,
+        None if cli.one_shot_json => return one_shot(&cx, cli.endpoint),
+        None => {
+            eprint!("{}", <Cli as clap::CommandFactory>::command().render_help());
+            return ExitCode::from(exit::INVALID_REQUEST);
+        }
+    };
+    match command {
+        Command::Translate(args) => translate(&cx, args),
+        Command::Document(args) => document(&cx, args),
+        Command::Email(args) => email(&cx, args),
+        Command::Eval(args) => eval(&cx, args),
+        Command::Daemon(command) => daemon(&cx, command),
+        #[cfg(unix)]
+        Command::Coordinator { socket, cache_size } => coordinator(&cx, socket, cache_size),
+        Command::Converse(args) => converse(&cx, args),
+        Command::Stdio { endpoint } => stdio(&cx, endpoint),
+        Command::Service(command) => service(command),
+        Command::Glossary(command) => glossary(&cx, command),
+        Command::Doctor { endpoint } => doctor(&cx, endpoint),
+        Command::Repo(args) => repo(&cx, args),
+        Command::Bench(args) => bench(&cx, args),
+        Command::Serve(args) => serve(&cx, args),
+        Command::Check(args) => check(args),
+    }
+}
+
+fn one_shot(cx: &Context, endpoint: Option<String>) -> ExitCode {
+    let mut input = String::new();
+    let (response, code) = match io::stdin().read_to_string(&mut input) {
+        Err(e) => (
+            serde_json::json!({"ok": false, "error": {"kind": "io", "message": e.to_string()}}),
+            exit::INVALID_REQUEST,
+        ),
+        Ok(_) => match cx.client(endpoint) {
+            Some(client) => cx.runtime.block_on(oneshot::respond(client, &input)),
+            None => (
+                serde_json::json!({"ok": false, "error": {"kind": "config", "message": "invalid proxy"}}),
+                exit::INVALID_REQUEST,
+            ),
+        },
+    };
+    println!("{}", response);
+    ExitCode::from(code)
+}
+
+fn translate(cx: &Context, args: TranslateArgs) -> ExitCode {
+    let TranslateArgs {
+        text,
+        file,
+        from,
+        to,
+        reflow,
+        alternatives,
+        json,
+        formality,
+        glossary,
+        glossary_id,
+        casing,
+        splitting,
+        tag_handling,
+        ignore_tags,
+        non_splitting_tags,
+        retries,
+        max_chars,
+        jobs,
+        explain,
+        endpoint,
+    } = args;
+    let text = match (text, file) {
+        (Some(text), _) => Ok(text),
+        (None, Some(path)) => fs::read(&path)
+            .map(|bytes| deeplx_rs::encoding::decode(&bytes).text)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        (None, None) => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map(|_| text)
+                .map_err(|e| format!("stdin: {}", e))
+        }
+    };
+    let text = match text {
+        Ok(text) if reflow => Ok(deeplx_rs::reflow::reflow(&text, &Default::default())),
+        other => other,
+    };
+    let text = match text {
+        Ok(text) if !text.trim().is_empty() => text,
+        Ok(_) => {
+            eprintln!("deeplx: nothing to translate");
+            return ExitCode::from(exit::INVALID_REQUEST);
+        }
+        Err(e) => {
+            eprintln!("deeplx: {}", e);
+            return ExitCode::from(exit::INVALID_REQUEST);
+        }
+    };
+    let glossary = match glossary.map(|path| read_glossary(&path)).transpose() {
+        Ok(glossary) => glossary,
+        Err(()) => return ExitCode::from(exit::INVALID_REQUEST),
+    };
+    let Some(client) = cx.client(endpoint) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    let tag_handling = tag_handling.map(|mode| {
+        TagHandling::new(match mode {
+            TagModeArg::Html => TagMode::Html,
+            TagModeArg::Xml => TagMode::Xml,
+        })
+        .with_ignore_tags(ignore_tags)
+        .with_non_splitting_tags(non_splitting_tags)
+    });
+    let client = client
+        .with_glossary_id(glossary_id)
+        .with_tag_handling(tag_handling)
+        .with_casing(casing.map(|c| match c {
+            CasingArg::PreserveSource => Casing::PreserveSource,
+            CasingArg::Sentence => Casing::Sentence,
+            CasingArg::Title => Casing::Title,
+        }))
+        .with_splitting(splitting.map(|s| match s {
+            SplittingArg::Newlines => Splitting::Newlines,
+            SplittingArg::Sentences => Splitting::Sentences,
+            SplittingArg::Paragraphs => Splitting::Paragraphs,
+            SplittingArg::Off => Splitting::Off,
+        }))
+        .with_retry(cx.retry(retries))
+        .with_alternatives(if alternatives || json {
+            ALTERNATIVES
+        } else {
+            0
+        })
+        .with_formality(formality.map(|f| match f {
+            FormalityArg::Formal => Formality::Formal,
+            FormalityArg::Informal => Formality::Informal,
+        }));
+    if explain {
+        let request = TranslateRequest {
+            text,
+            source_lang: from,
+            target_lang: to,
+            formality: None,
+            tag_handling: None,
+            casing: None,
+            splitting: None,
+        };
+        let explanation = client.explain(&request).and_then(|explanation| {
+            if json {
+                Ok(serde_json::to_string_pretty(&explanation)?)
+            } else {
+                Ok(explanation.to_string())
+            }
+        });
+        return match explanation {
+            Ok(text) => {
+                println!("{}", text.trim_end());
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("deeplx: {}", e);
+                exit::failed(&e)
+            }
+        };
+    }
+    let client: Arc<dyn Translator> = match glossary {
+        Some(glossary) => Arc::new(Enforced::new(Arc::new(client), Arc::new(glossary))),
+        None => Arc::new(client),
+    };
+    let client = Chunked::new(client, max_chars).concurrency(jobs);
+    match cx.runtime.block_on(client.translate(&text, &from, &to)) {
+        Ok(translation) if json => match serde_json::to_string_pretty(&translation) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                eprintln!("deeplx: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        Ok(translation) => {
+            println!("{}", translation.text.trim_end_matches('\n'));
+            if alternatives {
+                for alternative in &translation.alternatives {
+                    println!("{}", alternative);
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("deeplx: {}", e);
+            return exit::failed(&e);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn document(cx: &Context, args: DocumentArgs) -> ExitCode {
+    let DocumentArgs {
+        file,
+        from,
+        to,
+        output,
+        formality,
+        glossary_id,
+        endpoint,
+    } = args;
+    let output = output.unwrap_or_else(|| {
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match file.extension() {
+            Some(ext) => format!("{}.{}.{}", stem, to, ext.to_string_lossy()),
+            None => format!("{}.{}", stem, to),
+        };
+        file.with_file_name(name)
+    });
+    let Some(client) = cx.client(endpoint) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    let options = DocumentOptions::new(&to)
+        .with_source(from)
+        .with_output(&output)
+        .with_glossary_id(glossary_id)
+        .with_formality(formality.map(|f| match f {
+            FormalityArg::Formal => Formality::Formal,
+            FormalityArg::Informal => Formality::Informal,
+        }))
+        .with_progress(|status| match status.seconds_remaining {
+            Some(secs) => eprintln!("deeplx: {:?}, about {}s left", status.status, secs),
+            None => eprintln!("deeplx: {:?}", status.status),
+        });
+    match cx
+        .runtime
+        .block_on(client.translate_document(&file, &options))
+    {
+        Ok(_) => {
+            println!("{}", output.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("deeplx: {}: {}", file.display(), e);
+            exit::failed(&e)
+        }
+    }
+}
+
+fn email(cx: &Context, args: EmailArgs) -> ExitCode {
+    let EmailArgs {
+        file,
+        from,
+        to,
+        keep_original,
+        output,
+        endpoint,
+    } = args;
+    let input = match fs::read_to_string(&file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("deeplx: {}: {}", file.display(), e);
+            return ExitCode::from(exit::INVALID_REQUEST);
+        }
+    };
+    let Some(client) = cx.client(endpoint) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    let options = EmailOptions::default().with_keep_original(keep_original);
+    let outcome = match cx
+        .runtime
+        .block_on(translate_email(&client, &input, &from, &to, &options))
+    {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            eprintln!("deeplx: {}: {}", file.display(), e);
+            return exit::file_failed(&e);
+        }
+    };
+    if outcome.skipped > 0 {
+        eprintln!(
+            "deeplx: {}: {} text part(s) could not be decoded and were left untranslated",
+            file.display(),
+            outcome.skipped
+        );
+    }
+    match output {
+        Some(path) => {
+            if let Err(e) = write_atomic(&path, outcome.output.as_bytes(), false) {
+                eprintln!("deeplx: {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => print!("{}", outcome.output),
+    }
+    ExitCode::SUCCESS
+}
+
+fn eval(cx: &Context, args: EvalArgs) -> ExitCode {
+    let EvalArgs {
+        file,
+        from,
+        to,
+        endpoint,
+    } = args;
+    let cases = match fs::read_to_string(&file)
+        .map_err(|e| e.to_string())
+        .and_then(|input| parse_tsv(&input).map_err(|e| e.to_string()))
+    {
+        Ok(cases) => cases,
+        Err(e) => {
+            eprintln!("deeplx: {}: {}", file.display(), e);
+            return ExitCode::from(exit::INVALID_REQUEST);
+        }
+    };
+    let Some(client) = cx.client(endpoint) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    let report = cx.runtime.block_on(evaluate(&client, &cases, &from, &to));
+    let mut first_error = None;
+    for case in &report.cases {
+        if let Err(e) = &case.hypothesis {
+            eprintln!("deeplx: {:?}: {}", case.case.source, e);
+            first_error.get_or_insert(e);
+        }
+    }
+    println!("{}", report);
+    // The scores are still printed, but a failed case fails the run.
+    match first_error {
+        Some(e) => exit::failed(e),
+        None => ExitCode::SUCCESS,
+    }
+}
+
+fn daemon(cx: &Context, command: DaemonCommand) -> ExitCode {
+    match command {
+        DaemonCommand::Run {
+            from,
+            to,
+            source,
+            listen,
+            token_file,
+            endpoint,
+        } => {
+            let Some(desktop) = Desktop::current() else {
+                eprintln!("deeplx: no desktop session found");
+                return ExitCode::FAILURE;
+            };
+            let Some(client) = cx.client(endpoint) else {
+                return ExitCode::from(exit::INVALID_REQUEST);
+            };
+            let listener = match std::net::TcpListener::bind(listen) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    eprintln!("deeplx: {}: {}", listen, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let token_file = token_file.unwrap_or_else(daemon::default_token_file);
+            let token = match daemon::write_token(&token_file) {
+                Ok(token) => token,
+                Err(e) => {
+                    eprintln!("deeplx: {}: {}", token_file.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            eprintln!("deeplx: waiting for `deeplx daemon trigger` on {}", listen);
+            let daemon = Daemon {
+                translator: Arc::new(client),
+                from,
+                to,
+                desktop,
+                source: match source {
+                    SourceArg::Selection => Source::Selection,
+                    SourceArg::Clipboard => Source::Clipboard,
+                },
+                token,
+            };
+            match daemon.serve(listener, &cx.runtime) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("deeplx: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        DaemonCommand::Trigger { addr, token_file } => {
+            match daemon::trigger(addr, &token_file.unwrap_or_else(daemon::default_token_file)) {
+                Ok(text) => {
+                    println!("{}", text);
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("deeplx: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn coordinator(cx: &Context, socket: PathBuf, cache_size: usize) -> ExitCode {
+    let mut coordinator = Coordinator::new().with_cache(TranslationCache::new(cache_size));
+    if let Some((rate, burst)) = cx.rate {
+        coordinator = coordinator.with_rate_limiter(RateLimiter::new(rate, burst));
+    }
+    let served = cx.runtime.block_on(async {
+        let listener = Coordinator::bind(&socket)?;
+        eprintln!("deeplx: coordinating at {}", socket.display());
+        let shutdown = Box::pin(tokio::signal::ctrl_c());
+        match future::select(Box::pin(coordinator.serve(listener)), shutdown).await {
+            Either::Left((served, _)) => served,
+            Either::Right(_) => Ok(()),
+        }
+    });
+    let _ = fs::remove_file(&socket);
+    match served {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("deeplx: {}: {}", socket.display(), e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn converse(cx: &Context, args: ConverseArgs) -> ExitCode {
+    let ConverseArgs {
+        first,
+        second,
+        context,
+        endpoint,
+    } = args;
+    let Some(client) = cx.client(endpoint) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    let mut conversation = Conversation::new(Arc::new(client), first, second).with_context(context);
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("deeplx: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match cx.runtime.block_on(conversation.translate(&line)) {
+            Ok(turn) => println!("[{}] {}", turn.target_lang, turn.translation),
+            Err(e) => eprintln!("deeplx: {}", e),
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn stdio(cx: &Context, endpoint: Option<String>) -> ExitCode {
+    let Some(client) = cx.client(endpoint) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    match stdio::serve(client, &cx.runtime) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("deeplx: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn service(command: ServiceCommand) -> ExitCode {
+    let (label, platform, serve_args, print, install) = match command {
+        ServiceCommand::Install {
+            label,
+            platform,
+            print,
+            serve_args,
+        } => (label, platform, serve_args, print, true),
+        ServiceCommand::Uninstall { label, platform } => {
+            (label, platform, Vec::new(), false, false)
+        }
+    };
+    let platform = match platform {
+        Some(PlatformArg::Launchd) => Platform::Launchd,
+        Some(PlatformArg::TaskScheduler) => Platform::TaskScheduler,
+        None => match Platform::current() {
+            Some(platform) => platform,
+            None => {
+                eprintln!("deeplx: no supported service manager here; pass --platform");
+                return ExitCode::FAILURE;
+            }
+        },
+    };
+    let program = match std::env::current_exe() {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("deeplx: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut args = vec!["serve".to_string()];
+    args.extend(serve_args);
+    let service = Service {
+        label,
+        program,
+        args,
+    };
+    let result = match (install, print) {
+        (true, true) => service.describe(platform).map(|text| print!("{}", text)),
+        (true, false) => service.install(platform),
+        (false, _) => service.uninstall(platform),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("deeplx: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn glossary(cx: &Context, command: GlossaryCommand) -> ExitCode {
+    match command {
+        GlossaryCommand::Create {
+            file,
+            name,
+            from,
+            to,
+            endpoint,
+        } => {
+            let Ok(glossary) = read_glossary(&file) else {
+                return ExitCode::from(exit::INVALID_REQUEST);
+            };
+            let Some(client) = cx.client(endpoint) else {
+                return ExitCode::from(exit::INVALID_REQUEST);
+            };
+            match cx
+                .runtime
+                .block_on(client.create_glossary(&name, &from, &to, &glossary))
+            {
+                Ok(info) => {
+                    println!("{}", info.glossary_id);
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("deeplx: {}", e);
+                    exit::failed(&e)
+                }
+            }
+        }
+        GlossaryCommand::List { endpoint } => {
+            let Some(client) = cx.client(endpoint) else {
+                return ExitCode::from(exit::INVALID_REQUEST);
+            };
+            match cx.runtime.block_on(client.list_glossaries()) {
+                Ok(glossaries) => {
+                    for g in glossaries {
+                        println!(
+                            "{}\t{}->{}\t{}\t{}",
+                            g.glossary_id, g.source_lang, g.target_lang, g.entry_count, g.name
+                        );
+                    }
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("deeplx: {}", e);
+                    exit::failed(&e)
+                }
+            }
+        }
+    }
+}
+
+fn doctor(cx: &Context, endpoint: Option<String>) -> ExitCode {
+    let Some(client) = cx.client(endpoint) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    let report = cx.runtime.block_on(client.self_test());
+    print!("{}", report);
+    if report.passed() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn repo(cx: &Context, args: Box<RepoArgs>) -> ExitCode {
+    let RepoArgs {
+        include,
+        exclude,
+        from,
+        to,
+        root,
+        layout,
+        out,
+        template,
+        manifest,
+        force,
+        dry_run,
+        backup,
+        encoding,
+        line_endings,
+        bom,
+        report,
+        report_html,
+        on_failure,
+        failure_marker,
+        mark_mt,
+        on_conflict,
+        jobs,
+        rate,
+        retries,
+        endpoint,
+    } = *args;
+    let layout = match (template, layout) {
+        (Some(template), _) => match Layout::template(&template) {
+            Ok(layout) => layout,
+            Err(e) => {
+                eprintln!("deeplx: {}", e);
+                return ExitCode::from(exit::INVALID_REQUEST);
+            }
+        },
+        (None, LayoutArg::Tree) => Layout::Tree { root: out },
+        (None, LayoutArg::Sibling) => Layout::Sibling,
+    };
+    let encoding = match encoding.map(|label| (Encoding::for_label(label.as_bytes()), label)) {
+        Some((None, label)) => {
+            eprintln!("deeplx: unknown encoding `{}`", label);
+            return ExitCode::from(exit::INVALID_REQUEST);
+        }
+        Some((encoding, _)) => encoding,
+        None => None,
+    };
+    let options = repo::Options {
+        root,
+        include,
+        exclude,
+        from,
+        to,
+        layout,
+        manifest,
+        force,
+        dry_run,
+        backup,
+        encoding,
+        line_ending: match line_endings {
+            LineEndingArg::Keep => None,
+            LineEndingArg::Lf => Some(LineEnding::Lf),
+            LineEndingArg::Crlf => Some(LineEnding::Crlf),
+        },
+        bom: match bom {
+            BomArg::Keep => None,
+            BomArg::Add => Some(true),
+            BomArg::Remove => Some(false),
+        },
+        jobs,
+        on_failure: match on_failure {
+            OnFailureArg::Fail => OnFailure::Fail,
+            OnFailureArg::KeepSource => OnFailure::KeepSource {
+                marker: failure_marker,
+            },
+            OnFailureArg::Sidecar => OnFailure::KeepSource {
+                marker: String::new(),
+            },
+        },
+        sidecar: on_failure == OnFailureArg::Sidecar,
+        mark_mt,
+        on_conflict: match on_conflict {
+            ConflictArg::Ask if io::stdin().is_terminal() => repo::OnConflict::Ask,
+            ConflictArg::Ask | ConflictArg::Keep => repo::OnConflict::Keep,
+            ConflictArg::Overwrite => repo::OnConflict::Overwrite,
+        },
+    };
+    let Some(mut client) = cx.client(endpoint) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    if let Some(rate) = rate {
+        client = client.with_rate_limiter(RateLimiter::new(rate, rate.ceil() as u32));
+    }
+    let client = client.with_retry(cx.retry(retries));
+    // One cache for the whole run, so a string repeated across
+    // files is only sent once per language.
+    let cluster = Arc::new(Cluster::new(Arc::new(MemoryStorage::new()), "deeplx"));
+    let client = Coordinated::new(Arc::new(client), cluster, "deeplx")
+        .cache_ttl(Duration::from_secs(24 * 60 * 60));
+    match cx.runtime.block_on(repo::run(&client, &options)) {
+        Ok(summary) => {
+            println!(
+                "{} {}, {} partial, {} unchanged, {} conflicts, {} failed",
+                if dry_run {
+                    summary.pending
+                } else {
+                    summary.translated
+                },
+                if dry_run {
+                    "to translate"
+                } else {
+                    "translated"
+                },
+                summary.partial,
+                summary.unchanged,
+                summary.conflicts,
+                summary.failed
+            );
+            if let Err(e) = write_reports(&summary, report.as_deref(), report_html.as_deref()) {
+                eprintln!("deeplx: cannot write report: {}", e);
+                return ExitCode::FAILURE;
+            }
+            if summary.failed == 0 && summary.partial == 0 && summary.conflicts == 0 {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(e) => {
+            eprintln!("deeplx: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn bench(cx: &Context, args: BenchArgs) -> ExitCode {
+    let BenchArgs {
+        samples,
+        strategies,
+        rounds,
+        interval_ms,
+        from,
+        to,
+        json,
+        endpoint,
+    } = args;
+    let samples: Vec<String> = match samples.map(|path| (fs::read_to_string(&path), path)) {
+        Some((Ok(text), _)) => text
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(str::to_string)
+            .collect(),
+        Some((Err(e), path)) => {
+            eprintln!("deeplx: {}: {}", path.display(), e);
+            return ExitCode::from(exit::INVALID_REQUEST);
+        }
+        None => BENCH_SAMPLES.iter().map(|s| s.to_string()).collect(),
+    };
+    let Some(client) = cx.client(endpoint) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    let mut experiment = Experiment::new(samples)
+        .rounds(rounds)
+        .interval(Duration::from_millis(interval_ms))
+        .langs(from, to);
+    if !strategies.is_empty() {
+        experiment = experiment.strategies(strategies.into_iter().map(|s| match s {
+            StrategyArg::Alternating => RequestStrategy::Alternating,
+            StrategyArg::Spaced => RequestStrategy::Spaced,
+            StrategyArg::WideSpaced => RequestStrategy::WideSpaced,
+            StrategyArg::Compact => RequestStrategy::Compact,
+        }));
+    }
+    let report = cx.runtime.block_on(experiment.run(&client));
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                eprintln!("deeplx: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        print!("{}", report);
+    }
+    if report.best().is_some() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn serve(cx: &Context, args: ServeArgs) -> ExitCode {
+    let ServeArgs {
+        listen,
+        token,
+        admin_token,
+        mode,
+        max_length_ratio,
+        banned,
+        check_placeholders,
+        reject,
+        strict_compat,
+        sign_key_file,
+        sign_key_id,
+        idempotency_window,
+        budget_soft,
+        budget_hard,
+        budget_period,
+        priority_token,
+        state_dir,
+        cache_size,
+        cache_ttl,
+        serve_stale,
+        cache_normalize,
+        #[cfg(feature = "storage-sqlite")]
+        cache_db,
+        metrics,
+        statsd,
+        endpoint,
+    } = args;
+    let listen = listen
+        .or(cx.config.server.listen)
+        .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], 1188)));
+    let action = if reject {
+        filter::Action::Reject
+    } else {
+        filter::Action::Flag
+    };
+    let mut filters = filter::Filters::new();
+    if let Some(max) = max_length_ratio {
+        filters = filters.with(filter::LengthRatio::new(max), action);
+    }
+    if let Some(path) = banned {
+        match fs::read_to_string(&path) {
+            Ok(text) => filters = filters.with(filter::BannedSubstrings::parse(&text), action),
+            Err(e) => {
+                eprintln!("deeplx: {}: {}", path.display(), e);
+                return ExitCode::from(exit::INVALID_REQUEST);
+            }
+        }
+    }
+    if check_placeholders {
+        filters = filters.with(filter::Placeholders, action);
+    }
+    // Built below from `--cache-size` and the config, so that the state
+    // directory can keep it.
+    let Some(client) = cx.client_with_cache(endpoint, CacheConfig::default()) else {
+        return ExitCode::from(exit::INVALID_REQUEST);
+    };
+    let mut budget = Budget::new(match budget_period {
+        PeriodArg::Day => Period::Day,
+        PeriodArg::Month => Period::Month,
+    });
+    if let Some(chars) = budget_soft {
+        budget = budget.with_soft_limit(chars);
+    }
+    if let Some(chars) = budget_hard {
+        budget = budget.with_hard_limit(chars);
+    }
+    let budget = Arc::new(budget);
+    let prometheus = metrics.then(|| Arc::new(PrometheusSink::new()));
+    let sink: Option<Arc<dyn TelemetrySink>> = match (&prometheus, statsd) {
+        (Some(sink), _) => Some(sink.clone()),
+        (None, Some(addr)) => match StatsdSink::new(addr.as_str()) {
+            Ok(sink) => Some(Arc::new(sink)),
+            Err(e) => {
+                eprintln!("deeplx: {}: {}", addr, e);
+                return ExitCode::from(exit::INVALID_REQUEST);
+            }
+        },
+        (None, None) => None,
+    };
+    let switch = Switch::new(match mode {
+        ModeArg::Normal => Mode::Normal,
+        ModeArg::Maintenance => Mode::Maintenance,
+        ModeArg::Paused => Mode::Paused,
+    });
+    let mut client = client
+        .with_retry(match cx.config.retries {
+            Some(_) => cx.retry(None),
+            None => RetryPolicy::default(),
+        })
+        .with_budget(budget.clone())
+        .with_switch(switch.clone());
+    if let Some(sink) = &sink {
+        client = client.with_telemetry(sink.clone());
+    }
+    let mut normalization = KeyNormalization::default();
+    for arg in cache_normalize {
+        match arg {
+            NormalizeArg::Case => normalization.case_fold = true,
+            NormalizeArg::Whitespace => normalization.collapse_whitespace = true,
+            NormalizeArg::Placeholders => normalization.placeholders = true,
+        }
+    }
+    client = client.with_cache_normalization(normalization);
+    let cache_size = cache_size.or(cx.config.cache.size).unwrap_or(0);
+    let cache_ttl = cache_ttl.or(cx.config.cache.ttl).map(Duration::from_secs);
+    let mut memory = None;
+    if cache_size > 0 {
+        let mut cache = TranslationCache::new(cache_size);
+        if let Some(ttl) = cache_ttl {
+            cache = cache.with_ttl(ttl);
+        }
+        let cache = Arc::new(cache);
+        memory = Some(cache.clone());
+        client = client.with_cache(cache);
+    }
+    #[cfg(feature = "storage-sqlite")]
+    if let Some(path) = cache_db {
+        // The database keeps its entries itself.
+        memory = None;
+        let storage = match deeplx_rs::storage::SqliteStorage::open(&path) {
+            Ok(storage) => storage,
+            Err(e) => {
+                eprintln!("deeplx: {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        };
+        let mut cache = deeplx_rs::cache::StorageCache::new(Arc::new(storage));
+        if let Some(ttl) = cache_ttl {
+            cache = cache.with_ttl(ttl);
+        }
+        client = client.with_cache(Arc::new(cache));
+    }
+    if let Some(secs) = serve_stale {
+        client = client.serve_stale(Duration::from_secs(secs));
+    }
+    let hooks = state_dir.map(|dir| state_hooks(dir, &budget, &client, memory));
+    if let Some(hooks) = &hooks {
+        if let Err(e) = hooks.restore() {
+            eprintln!("deeplx: {}: {}", hooks.dir().path().display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+    let mut server = Server::new(Arc::new(client.clone()))
+        .with_pro(client.clone())
+        .with_filters(filters)
+        .with_compat(if strict_compat {
+            Compat::Strict
+        } else {
+            Compat::Extended
+        });
+    if let Some(token) = token {
+        server = server.with_token(token);
+    }
+    if let Some(token) = admin_token {
+        server = server.with_admin(token, switch);
+    }
+    match (prometheus, sink) {
+        (Some(prometheus), _) => server = server.with_metrics(prometheus),
+        (None, Some(sink)) => server = server.with_telemetry(sink),
+        (None, None) => {}
+    }
+    if let Some(token) = priority_token {
+        server = server.with_priority(token, Arc::new(client.with_priority(true)));
+    }
+    if idempotency_window > 0 {
+        server = server.with_idempotency(Duration::from_secs(idempotency_window));
+    }
+    if let Some(path) = sign_key_file {
+        let key = match fs::read_to_string(&path) {
+            Ok(key) if !key.trim().is_empty() => key.trim().to_string(),
+            Ok(_) => {
+                eprintln!("deeplx: {}: empty signing key", path.display());
+                return ExitCode::from(exit::INVALID_REQUEST);
+            }
+            Err(e) => {
+                eprintln!("deeplx: {}: {}", path.display(), e);
+                return ExitCode::from(exit::INVALID_REQUEST);
+            }
+        };
+        let mut signer = Signer::new(key);
+        if let Some(id) = sign_key_id {
+            signer = signer.with_key_id(id);
+        }
+        server = server.with_signer(signer);
+    }
+    eprintln!("deeplx: listening on http://{}", listen);
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+        eprintln!("deeplx: shutting down");
+    };
+    let served = cx
+        .runtime
+        .block_on(server.serve_with_shutdown(listen, shutdown));
+    let saved = match &hooks {
+        Some(hooks) => hooks.save().map_err(|e| {
+            eprintln!("deeplx: {}: {}", hooks.dir().path().display(), e);
+        }),
+        None => Ok(()),
+    };
+    match served {
+        Ok(()) if saved.is_ok() => ExitCode::SUCCESS,
+        Ok(()) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("deeplx: {}: {}", listen, e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// What `serve --state-dir` saves on shutdown and restores at startup.
+fn state_hooks(
+    dir: PathBuf,
+    budget: &Arc<Budget>,
+    client: &DeepLClient,
+    cache: Option<Arc<TranslationCache>>,
+) -> StateHooks {
+    let mut hooks = StateHooks::new(StateDir::new(dir)).with(budget.clone());
+    if let Some(pool) = client.session_pool() {
+        hooks = hooks.with(pool.clone());
+    }
+    if let Some(cache) = cache {
+        hooks = hooks.with(cache);
+    }
+    hooks
+}
+
+fn check(args: CheckArgs) -> ExitCode {
+    let CheckArgs {
+        dir,
+        source,
+        to,
+        manifest,
+    } = args;
+    let options = check::Options {
+        dir,
+        source,
+        to,
+        manifest,
+    };
+    match check::run(&options) {
+        Ok(0) => {
+            println!("all translations up to date");
+            ExitCode::SUCCESS
+        }
+        Ok(problems) => {
+            println!("{} strings need translation", problems);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("deeplx: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+    use deeplx_rs::{cache::CacheKey, Translation};
+
+    use super::*;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_serve_state_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("deeplx-serve-state-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let key = CacheKey::new("hello", "EN", "DE", "");
+        let start = || {
+            let budget = Arc::new(Budget::new(Period::Day));
+            let sessions = Arc::new(SessionPool::new(["a".to_string()]));
+            let client = DeepLClient::new().with_session_pool(sessions.clone());
+            let cache = Arc::new(TranslationCache::new(10));
+            let hooks = state_hooks(dir.clone(), &budget, &client, Some(cache.clone()));
+            hooks.restore().unwrap();
+            (hooks, budget, sessions, cache)
+        };
+
+        let (hooks, budget, sessions, cache) = start();
+        budget.charge(5, false).unwrap();
+        sessions.record_use(0, 5);
+        cache.insert(
+            key,
+            Translation {
+                text: "hallo".to_string(),
+                ..Default::default()
+            },
+        );
+        hooks.save().unwrap();
+
+        let (_, budget, sessions, cache) = start();
+        assert_eq!(budget.usage().used, 5);
+        assert_eq!(sessions.usage()[0].chars, 5);
+        assert_eq!(cache.get(&key).unwrap().text, "hallo");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
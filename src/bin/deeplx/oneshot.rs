@@ -0,0 +1,121 @@
+//! `deeplx --one-shot-json`: one JSON request on stdin, one JSON response
+//! on stdout and an exit code saying what went wrong, for launcher and
+//! editor plugins that run the binary once per translation.
+//!
+//! The request is the server's `POST /translate` body, plus
+//! `"alternatives": true` to get DeepL's alternatives. Diagnostics only
+//! ever go to stderr.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use deeplx_rs::{
+    error::DeepLError, options::TranslateOptions, server::TranslateRequest, DeepLClient, Translator,
+};
+
+use crate::exit;
+
+const ALTERNATIVES: u32 = 3;
+
+/// A translation request, also taken by `deeplx stdio`.
+#[derive(Deserialize)]
+pub struct OneShotRequest {
+    #[serde(flatten)]
+    pub request: TranslateRequest,
+    #[serde(default)]
+    pub alternatives: bool,
+}
+
+pub enum Failure {
+    /// The request cannot be translated as given.
+    Invalid(String),
+    Translate(DeepLError),
+}
+
+/// The success response to `request`.
+pub async fn translate(client: &DeepLClient, request: OneShotRequest) -> Result<Value, Failure> {
+    let OneShotRequest {
+        request,
+        alternatives,
+    } = request;
+    let preflight = client.validate(&request);
+    if !preflight.valid {
+        let problems: Vec<String> = preflight.problems.iter().map(|p| p.to_string()).collect();
+        return Err(Failure::Invalid(problems.join("; ")));
+    }
+    let client = client
+        .clone()
+        .with_alternatives(if alternatives { ALTERNATIVES } else { 0 });
+    let options = TranslateOptions {
+        formality: request.formality,
+        tag_handling: request.tag_handling.clone(),
+        casing: request.casing,
+        splitting: request.splitting,
+        ..Default::default()
+    };
+    let translation = client
+        .translate_with_options(
+            &request.text,
+            &request.source_lang,
+            &request.target_lang,
+            &options,
+        )
+        .await
+        .map_err(Failure::Translate)?;
+    Ok(json!({
+        "ok": true,
+        "text": translation.text,
+        "alternatives": translation.alternatives,
+        "source_lang": translation.detected_source,
+        "target_lang": request.target_lang.to_uppercase(),
+    }))
+}
+
+fn failure(code: u8, kind: &str, message: impl ToString) -> (Value, u8) {
+    (
+        json!({"ok": false, "error": {"kind": kind, "message": message.to_string()}}),
+        code,
+    )
+}
+
+/// The response to `input` and the exit code to leave with.
+pub async fn respond(client: DeepLClient, input: &str) -> (Value, u8) {
+    let request = match serde_json::from_str(input) {
+        Ok(request) => request,
+        Err(e) => return failure(exit::INVALID_REQUEST, "invalid_request", e),
+    };
+    match translate(&client, request).await {
+        Ok(response) => (response, exit::OK),
+        Err(Failure::Invalid(problems)) => {
+            failure(exit::INVALID_REQUEST, "invalid_request", problems)
+        }
+        Err(Failure::Translate(e)) => {
+            let (mut response, code) = failure(exit::code(&e), e.kind(), &e);
+            if let Some(after) = e.retry_after() {
+                response["error"]["retry_after_secs"] = json!(after.as_secs());
+            }
+            (response, code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_requests_exit_with_2() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let (response, code) = runtime.block_on(respond(DeepLClient::new(), "{\"text\": 1}"));
+        assert_eq!(code, exit::INVALID_REQUEST);
+        assert_eq!(response["error"]["kind"], "invalid_request");
+        let request = r#"{"text": "hi", "target_lang": "XX"}"#;
+        let (response, code) = runtime.block_on(respond(DeepLClient::new(), request));
+        assert_eq!(
+            (code, &response["ok"]),
+            (exit::INVALID_REQUEST, &json!(false))
+        );
+    }
+}
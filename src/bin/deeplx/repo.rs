@@ -0,0 +1,409 @@
+//! `deeplx repo`: translate the matching files of a repository into a
+//! locale tree, retranslating only sources that changed since last time.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use deeplx_rs::{
+    encoding::{self, Encoding},
+    formats::{FileKind, FileStats, LineEnding, OnFailure},
+    report::{FileReport, FileStatus, JobReport},
+    sync::{content_hash, key_hashes, manifest_key, write_atomic, FileRecord, Layout, Manifest},
+    Translator,
+};
+use futures_util::{stream, StreamExt};
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+use serde_json::{json, Value};
+
+/// What to do about targets edited by hand since they were last
+/// translated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnConflict {
+    Ask,
+    Keep,
+    Overwrite,
+}
+
+pub struct Options {
+    pub root: PathBuf,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    pub layout: Layout,
+    pub manifest: PathBuf,
+    pub force: bool,
+    pub dry_run: bool,
+    /// Keep the previous version of every overwritten target as `.bak`.
+    pub backup: bool,
+    /// Encoding of the targets; the source's own when `None`.
+    pub encoding: Option<&'static Encoding>,
+    /// Line endings of the targets; the source's own when `None`.
+    pub line_ending: Option<LineEnding>,
+    /// Whether targets start with a BOM; as the source does when `None`.
+    pub bom: Option<bool>,
+    /// Files translated at the same time.
+    pub jobs: usize,
+    pub on_failure: OnFailure,
+    /// List segments left untranslated in `<target>.failures.json`.
+    pub sidecar: bool,
+    /// Mark targets as machine translated, with this note as a comment.
+    pub mark_mt: Option<String>,
+    pub on_conflict: OnConflict,
+}
+
+/// Source files under `root` matching the include globs, honouring
+/// `.gitignore`, relative to `root` and sorted.
+fn sources(options: &Options) -> Result<Vec<PathBuf>, String> {
+    let mut overrides = OverrideBuilder::new(&options.root);
+    for glob in &options.include {
+        overrides.add(glob).map_err(|e| e.to_string())?;
+    }
+    for glob in &options.exclude {
+        overrides
+            .add(&format!("!{}", glob))
+            .map_err(|e| e.to_string())?;
+    }
+    let overrides = overrides.build().map_err(|e| e.to_string())?;
+    let manifest = options.root.join(&options.manifest);
+    let mut out = Vec::new();
+    for entry in WalkBuilder::new(&options.root)
+        .overrides(overrides)
+        .require_git(false)
+        .build()
+    {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let is_sidecar = entry
+            .file_name()
+            .to_string_lossy()
+            .ends_with(".failures.json");
+        if !entry.file_type().is_some_and(|t| t.is_file()) || entry.path() == manifest || is_sidecar
+        {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(&options.root) else {
+            continue;
+        };
+        if !options.layout.is_target(relative, &options.to) {
+            out.push(relative.to_path_buf());
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// A source file, read once and shared by its jobs.
+struct Source {
+    path: PathBuf,
+    key: String,
+    kind: FileKind,
+    text: String,
+    encoding: &'static Encoding,
+    bom: bool,
+    hash: String,
+    keys: BTreeMap<String, String>,
+}
+
+/// One source file into one language.
+struct Job {
+    source: Arc<Source>,
+    lang: String,
+    target: PathBuf,
+}
+
+/// Where failed segments of `target` are listed for a later retry.
+fn sidecar(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!("{}.failures.json", name))
+}
+
+/// Translates and writes one target, returning the translated text.
+async fn translate(
+    provider: &dyn Translator,
+    options: &Options,
+    job: &Job,
+) -> Result<(String, FileStats), String> {
+    let source = &job.source;
+    let outcome = source
+        .kind
+        .translate_with(
+            provider,
+            &source.text,
+            &options.from,
+            &job.lang,
+            &options.on_failure,
+        )
+        .await
+        .map_err(|e| format!("{} ({}): {}", source.key, job.lang, e))?;
+    let output = match &options.mark_mt {
+        Some(note) => source
+            .kind
+            .mark_machine_translated(&outcome.output, note)
+            .map_err(|e| format!("{} ({}): {}", source.key, job.lang, e))?,
+        None => outcome.output,
+    };
+    let translated = match options.line_ending {
+        Some(ending) => ending.apply(&output),
+        None => output,
+    };
+    let out = options.encoding.unwrap_or(source.encoding);
+    let bom = options.bom.unwrap_or(source.bom);
+    let bytes = encoding::encode(&translated, out, bom)
+        .map_err(|e| format!("{} ({}): {}", source.key, job.lang, e))?;
+    let target = options.root.join(&job.target);
+    write(&target, &bytes, options.backup)
+        .map_err(|e| format!("{}: {}", job.target.display(), e))?;
+    for failure in &outcome.failures {
+        eprintln!(
+            "{} ({}): segment {} left untranslated: {}",
+            source.key, job.lang, failure.index, failure.error
+        );
+    }
+    if options.sidecar {
+        let path = sidecar(&target);
+        let result = match outcome.failures.is_empty() {
+            true if path.exists() => fs::remove_file(&path),
+            true => Ok(()),
+            false => {
+                let failures: Vec<Value> = outcome
+                    .failures
+                    .iter()
+                    .map(|f| {
+                        json!({
+                            "index": f.index,
+                            "source": f.source,
+                            "error": f.error.to_string(),
+                        })
+                    })
+                    .collect();
+                let text = json!({
+                    "source": source.key,
+                    "lang": job.lang,
+                    "failures": failures,
+                });
+                let mut text = serde_json::to_string_pretty(&text).unwrap_or_default();
+                text.push('\n');
+                write_atomic(&path, text.as_bytes(), false)
+            }
+        };
+        result.map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok((translated, outcome.stats))
+}
+
+/// Whether `target` exists and differs from what was last written to it.
+fn edited(manifest: &Manifest, lang: &str, key: &str, target: &Path, options: &Options) -> bool {
+    fs::read(options.root.join(target))
+        .is_ok_and(|bytes| manifest.is_edited(lang, key, &encoding::decode(&bytes).text))
+}
+
+/// Asks whether to overwrite the edited `target`, returning the answer and
+/// how to treat the remaining conflicts.
+fn ask(target: &Path) -> Result<(bool, OnConflict), String> {
+    loop {
+        eprint!(
+            "{} was edited since it was last translated. Overwrite? [y]es, [N]o, [a]ll, [k]eep all: ",
+            target.display()
+        );
+        let mut answer = String::new();
+        let read = io::stdin()
+            .read_line(&mut answer)
+            .map_err(|e| e.to_string())?;
+        return Ok(match answer.trim().to_ascii_lowercase().as_str() {
+            _ if read == 0 => (false, OnConflict::Keep),
+            "y" | "yes" => (true, OnConflict::Ask),
+            "" | "n" | "no" => (false, OnConflict::Ask),
+            "a" | "all" => (true, OnConflict::Overwrite),
+            "k" | "keep" => (false, OnConflict::Keep),
+            _ => continue,
+        });
+    }
+}
+
+fn file_report(source: &str, lang: &str, target: &Path, status: FileStatus) -> FileReport {
+    FileReport {
+        source: source.to_string(),
+        lang: Some(lang.to_string()),
+        target: Some(manifest_key(target)),
+        status,
+        stats: FileStats::default(),
+        error: None,
+        duration_ms: 0,
+    }
+}
+
+pub async fn run(provider: &dyn Translator, options: &Options) -> Result<JobReport, String> {
+    let started = Instant::now();
+    let manifest_path = options.root.join(&options.manifest);
+    if options.to.len() > 1 && !options.layout.per_language() {
+        return Err("the output template needs `{lang}` to write more than one language".into());
+    }
+    let mut manifest = Manifest::load(&manifest_path).map_err(|e| e.to_string())?;
+    let mut report = JobReport::default();
+    let mut jobs = Vec::new();
+    let mut on_conflict = options.on_conflict;
+    for path in sources(options)? {
+        let key = manifest_key(&path);
+        let decoded = match fs::read(options.root.join(&path)) {
+            Ok(bytes) => encoding::decode(&bytes),
+            Err(e) => {
+                eprintln!("{}: {}", key, e);
+                report.push(FileReport {
+                    source: key,
+                    lang: None,
+                    target: None,
+                    status: FileStatus::Failed,
+                    stats: FileStats::default(),
+                    error: Some(e.to_string()),
+                    duration_ms: 0,
+                });
+                continue;
+            }
+        };
+        let Some(kind) = FileKind::detect(&path, &decoded.text) else {
+            eprintln!("{}: unsupported file type, skipped", key);
+            continue;
+        };
+        let source = Arc::new(Source {
+            hash: content_hash(&decoded.text),
+            keys: kind
+                .entries(&decoded.text)
+                .ok()
+                .flatten()
+                .map(|entries| key_hashes(&entries))
+                .unwrap_or_default(),
+            path,
+            key,
+            kind,
+            text: decoded.text,
+            encoding: decoded.encoding,
+            bom: decoded.bom,
+        });
+        for lang in &options.to {
+            let target = options.layout.target(&source.path, lang);
+            if !options.force
+                && manifest.is_current(lang, &source.key, &source.hash)
+                && options.root.join(&target).exists()
+            {
+                report.push(file_report(
+                    &source.key,
+                    lang,
+                    &target,
+                    FileStatus::Unchanged,
+                ));
+                continue;
+            }
+            if target != source.path && edited(&manifest, lang, &source.key, &target, options) {
+                let overwrite = match on_conflict {
+                    OnConflict::Overwrite => true,
+                    OnConflict::Keep => false,
+                    OnConflict::Ask if options.dry_run => false,
+                    OnConflict::Ask => {
+                        let (overwrite, rest) = ask(&target)?;
+                        on_conflict = rest;
+                        overwrite
+                    }
+                };
+                if !overwrite {
+                    eprintln!(
+                        "{}: edited since it was last translated, left alone",
+                        target.display()
+                    );
+                    report.push(file_report(
+                        &source.key,
+                        lang,
+                        &target,
+                        FileStatus::Conflict,
+                    ));
+                    continue;
+                }
+            }
+            if options.dry_run {
+                println!("would translate {} -> {}", source.key, target.display());
+                report.push(file_report(&source.key, lang, &target, FileStatus::Pending));
+                continue;
+            }
+            jobs.push(Job {
+                source: source.clone(),
+                lang: lang.clone(),
+                target,
+            });
+        }
+    }
+
+    // Files are translated concurrently; the provider's rate limiter and
+    // cache are shared by all of them.
+    let mut done = stream::iter(jobs)
+        .map(|job| async move {
+            let started = Instant::now();
+            let result = translate(provider, options, &job).await;
+            (job, result, started.elapsed())
+        })
+        .buffer_unordered(options.jobs.max(1));
+    while let Some((job, result, elapsed)) = done.next().await {
+        let source = &job.source;
+        let mut file = file_report(&source.key, &job.lang, &job.target, FileStatus::Failed);
+        file.duration_ms = elapsed.as_millis() as u64;
+        let translated = match result {
+            Ok((translated, stats)) => {
+                file.status = match stats.failed {
+                    0 => FileStatus::Translated,
+                    _ => FileStatus::Partial,
+                };
+                file.stats = stats;
+                translated
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                file.error = Some(e);
+                report.push(file);
+                continue;
+            }
+        };
+        let partial = file.status == FileStatus::Partial;
+        report.push(file);
+        if partial {
+            // Left out of the manifest, so the next run tries again.
+            println!(
+                "partly translated {} -> {}",
+                source.key,
+                job.target.display()
+            );
+            continue;
+        }
+        println!("translated {} -> {}", source.key, job.target.display());
+        let translated_hash = content_hash(&translated);
+        manifest.insert(
+            &job.lang,
+            &source.key,
+            FileRecord {
+                // Translated in place, the file now holds the output,
+                // which must not count as a changed source next time.
+                source_hash: match job.target == source.path {
+                    true => translated_hash.clone(),
+                    false => source.hash.clone(),
+                },
+                target: manifest_key(&job.target),
+                target_hash: translated_hash,
+                keys: source.keys.clone(),
+            },
+        );
+        // Saved after every file so an interrupted run keeps its
+        // progress.
+        manifest.save(&manifest_path).map_err(|e| e.to_string())?;
+    }
+    report.duration_ms = started.elapsed().as_millis() as u64;
+    Ok(report)
+}
+
+fn write(path: &Path, contents: &[u8], backup: bool) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_atomic(path, contents, backup)
+}
@@ -0,0 +1,244 @@
+//! `deeplx service`: run `deeplx serve` in the background from login on,
+//! restarted when it exits, through launchd on macOS and the Task
+//! Scheduler on Windows.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Launchd,
+    TaskScheduler,
+}
+
+impl Platform {
+    /// The service manager of the platform the binary was built for.
+    pub fn current() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            Some(Platform::Launchd)
+        } else if cfg!(windows) {
+            Some(Platform::TaskScheduler)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Service {
+    /// The launchd label or task name, such as `io.github.deeplx-rs`.
+    pub label: String,
+    pub program: PathBuf,
+    pub args: Vec<String>,
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn home() -> Result<PathBuf, String> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| "HOME is not set".to_string())
+}
+
+impl Service {
+    fn command_line(&self) -> Vec<String> {
+        let mut line = vec![self.program.display().to_string()];
+        line.extend(self.args.iter().cloned());
+        line
+    }
+
+    /// A launchd agent keeping the gateway running, logging to `log_dir`.
+    pub fn launchd_plist(&self, log_dir: &Path) -> String {
+        let args: String = self
+            .command_line()
+            .iter()
+            .map(|a| format!("        <string>{}</string>\n", xml_escape(a)))
+            .collect();
+        let log = xml_escape(
+            &log_dir
+                .join(format!("{}.log", self.label))
+                .display()
+                .to_string(),
+        );
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{args}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+            label = xml_escape(&self.label),
+            args = args,
+            log = log,
+        )
+    }
+
+    /// The `schtasks` arguments creating a task that starts the gateway
+    /// at logon with the user's rights.
+    pub fn schtasks_create(&self) -> Vec<String> {
+        let run = self
+            .command_line()
+            .iter()
+            .map(|a| windows_quote(a))
+            .collect::<Vec<_>>()
+            .join(" ");
+        [
+            "/Create",
+            "/TN",
+            &self.label,
+            "/TR",
+            &run,
+            "/SC",
+            "ONLOGON",
+            "/RL",
+            "LIMITED",
+            "/F",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+
+    fn plist_path(&self) -> Result<PathBuf, String> {
+        Ok(home()?
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", self.label)))
+    }
+
+    /// What [`install`](Self::install) would write or run.
+    pub fn describe(&self, platform: Platform) -> Result<String, String> {
+        Ok(match platform {
+            Platform::Launchd => self.launchd_plist(&home()?.join("Library/Logs")),
+            Platform::TaskScheduler => {
+                let args: Vec<String> = self
+                    .schtasks_create()
+                    .iter()
+                    .map(|a| windows_quote(a))
+                    .collect();
+                format!("schtasks {}\n", args.join(" "))
+            }
+        })
+    }
+
+    /// Registers the service and starts it.
+    pub fn install(&self, platform: Platform) -> Result<(), String> {
+        match platform {
+            Platform::Launchd => {
+                let path = self.plist_path()?;
+                let logs = home()?.join("Library/Logs");
+                for dir in [path.parent().unwrap_or(Path::new(".")), &logs] {
+                    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+                }
+                fs::write(&path, self.launchd_plist(&logs))
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                run("launchctl", &["load", "-w", &path.display().to_string()])
+            }
+            Platform::TaskScheduler => {
+                let args = self.schtasks_create();
+                run(
+                    "schtasks",
+                    &args.iter().map(String::as_str).collect::<Vec<_>>(),
+                )?;
+                run("schtasks", &["/Run", "/TN", &self.label])
+            }
+        }
+    }
+
+    /// Stops the service and removes it.
+    pub fn uninstall(&self, platform: Platform) -> Result<(), String> {
+        match platform {
+            Platform::Launchd => {
+                let path = self.plist_path()?;
+                run("launchctl", &["unload", "-w", &path.display().to_string()])?;
+                fs::remove_file(&path).map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            Platform::TaskScheduler => {
+                // Ending a task that is not running fails; deleting it is
+                // what matters.
+                let _ = run("schtasks", &["/End", "/TN", &self.label]);
+                run("schtasks", &["/Delete", "/TN", &self.label, "/F"])
+            }
+        }
+    }
+}
+
+/// `arg` quoted the way the Windows C runtime splits command lines.
+fn windows_quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut out = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                out.extend(std::iter::repeat('\\').take(backslashes * 2 + 1));
+                out.push('"');
+                backslashes = 0;
+            }
+            c => {
+                out.extend(std::iter::repeat('\\').take(backslashes));
+                out.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    out.extend(std::iter::repeat('\\').take(backslashes * 2));
+    out.push('"');
+    out
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), String> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| format!("{}: {}", program, e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} {} failed: {}", program, args.join(" "), status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_definitions() {
+        let service = Service {
+            label: "io.github.deeplx-rs".to_string(),
+            program: PathBuf::from(r"C:\Program Files\deeplx.exe"),
+            args: vec!["serve".into(), "--token".into(), "a&b \"c\"".into()],
+        };
+        let plist = service.launchd_plist(Path::new("/logs"));
+        assert!(plist.contains("<string>a&amp;b &quot;c&quot;</string>\n    </array>"));
+        assert!(plist.contains("<string>/logs/io.github.deeplx-rs.log</string>"));
+        assert_eq!(
+            service.schtasks_create()[4],
+            r#""C:\Program Files\deeplx.exe" serve --token "a&b \"c\"""#
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! `deeplx stdio`: JSON-RPC 2.0 on stdin and stdout, for editor plugins
+//! that keep one child process with warm connections rather than running
+//! the binary per translation.
+//!
+//! A message is either one JSON object on a line or, as in LSP, a body
+//! after `Content-Length` headers; each reply is framed like its request.
+//! `translate` takes the `--one-shot-json` request, `detect` takes
+//! `{"text"}`, and `cancel` (or `$/cancelRequest`) takes the `{"id"}` of a
+//! pending request, which then fails with -32800.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+use deeplx_rs::DeepLClient;
+
+use crate::oneshot::{self, Failure, OneShotRequest};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const TRANSLATION_FAILED: i64 = -32000;
+const REQUEST_CANCELLED: i64 = -32800;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// One message per line.
+    Lines,
+    /// `Content-Length` headers, a blank line and the body.
+    Headers,
+}
+
+/// The next message on `input` and how it was framed, or `None` at the end.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<(String, Framing)>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let length = match trimmed.split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("content-length") => value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid Content-Length"))?,
+            _ => return Ok(Some((trimmed.to_string(), Framing::Lines))),
+        };
+        // Any other headers, up to the blank line.
+        loop {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if line.trim().is_empty() {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        input.read_exact(&mut body)?;
+        let body = String::from_utf8(body).map_err(|_| invalid("body is not UTF-8"))?;
+        return Ok(Some((body, Framing::Headers)));
+    }
+}
+
+pub fn frame(message: &Value, framing: Framing) -> String {
+    match framing {
+        Framing::Lines => format!("{}\n", message),
+        Framing::Headers => {
+            let body = message.to_string();
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        }
+    }
+}
+
+fn error(id: &Value, code: i64, message: impl ToString, data: Option<Value>) -> Value {
+    let mut error = json!({"code": code, "message": message.to_string()});
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({"jsonrpc": "2.0", "id": id, "error": error})
+}
+
+#[derive(Deserialize)]
+struct DetectParams {
+    text: String,
+}
+
+/// The result of `method`, or the code, message and data to fail it with.
+async fn call(
+    client: &DeepLClient,
+    method: &str,
+    params: Value,
+) -> Result<Value, (i64, String, Option<Value>)> {
+    let invalid = |e: serde_json::Error| (INVALID_PARAMS, e.to_string(), None);
+    match method {
+        "translate" => {
+            let request: OneShotRequest = serde_json::from_value(params).map_err(invalid)?;
+            match oneshot::translate(client, request).await {
+                Ok(mut result) => {
+                    if let Some(result) = result.as_object_mut() {
+                        result.remove("ok");
+                    }
+                    Ok(result)
+                }
+                Err(Failure::Invalid(problems)) => Err((INVALID_PARAMS, problems, None)),
+                Err(Failure::Translate(e)) => Err((
+                    TRANSLATION_FAILED,
+                    e.to_string(),
+                    Some(json!({
+                        "kind": e.kind(),
+                        "retry_after_secs": e.retry_after().map(|after| after.as_secs()),
+                    })),
+                )),
+            }
+        }
+        "detect" => {
+            let DetectParams { text } = serde_json::from_value(params).map_err(invalid)?;
+            let detection = client.detect_language(&text).await.map_err(|e| {
+                (
+                    TRANSLATION_FAILED,
+                    e.to_string(),
+                    Some(json!({"kind": e.kind()})),
+                )
+            })?;
+            Ok(json!({
+                "lang": detection.lang,
+                "confident": detection.confident,
+                "confidence": detection.confidence(),
+                "scores": detection.scores,
+            }))
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("no method {:?}", method), None)),
+    }
+}
+
+struct Session<'a> {
+    client: Arc<DeepLClient>,
+    runtime: &'a Runtime,
+    /// Requests still running, by their id as JSON.
+    pending: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    replies: mpsc::Sender<String>,
+}
+
+impl Session<'_> {
+    fn reply(&self, message: &Value, framing: Framing) {
+        // Only fails once stdout is gone, when nobody is listening.
+        let _ = self.replies.send(frame(message, framing));
+    }
+
+    fn handle(&self, message: &str, framing: Framing) {
+        let message: Value = match serde_json::from_str(message) {
+            Ok(message) => message,
+            Err(e) => return self.reply(&error(&Value::Null, PARSE_ERROR, e, None), framing),
+        };
+        let id = message.get("id").cloned().unwrap_or(Value::Null);
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            let reply = error(&id, INVALID_REQUEST, "expected a method", None);
+            return self.reply(&reply, framing);
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        if matches!(method, "cancel" | "$/cancelRequest") {
+            return self.cancel(&id, &params, framing);
+        }
+        // Notifications get no reply, so there is no point running them.
+        if id.is_null() {
+            return;
+        }
+        let key = id.to_string();
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.contains_key(&key) {
+            let reply = error(
+                &id,
+                INVALID_REQUEST,
+                "a request with this id is pending",
+                None,
+            );
+            return self.reply(&reply, framing);
+        }
+        let (client, method) = (self.client.clone(), method.to_string());
+        let (tasks, replies, own) = (self.pending.clone(), self.replies.clone(), key.clone());
+        // Holding the lock until the task is in `pending` lets it find
+        // itself there however fast it finishes.
+        let task = self.runtime.spawn(async move {
+            let reply = match call(&client, &method, params).await {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                Err((code, message, data)) => error(&id, code, message, data),
+            };
+            let tasks = tasks.lock().unwrap_or_else(|e| e.into_inner()).remove(&own);
+            // Otherwise it was cancelled and has had its reply.
+            if tasks.is_some() {
+                let _ = replies.send(frame(&reply, framing));
+            }
+        });
+        pending.insert(key, task);
+    }
+
+    fn cancel(&self, id: &Value, params: &Value, framing: Framing) {
+        let Some(target) = params.get("id").filter(|target| !target.is_null()) else {
+            if !id.is_null() {
+                self.reply(&error(id, INVALID_PARAMS, "expected an id", None), framing);
+            }
+            return;
+        };
+        let task = self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&target.to_string());
+        if let Some(task) = &task {
+            task.abort();
+            let reply = error(target, REQUEST_CANCELLED, "request cancelled", None);
+            self.reply(&reply, framing);
+        }
+        if !id.is_null() {
+            let reply =
+                json!({"jsonrpc": "2.0", "id": id, "result": {"cancelled": task.is_some()}});
+            self.reply(&reply, framing);
+        }
+    }
+}
+
+/// Answers requests on stdin until it closes, running them concurrently.
+pub fn serve(client: DeepLClient, runtime: &Runtime) -> io::Result<()> {
+    let (replies, outgoing) = mpsc::channel::<String>();
+    let writer = thread::spawn(move || -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        for reply in outgoing {
+            stdout.write_all(reply.as_bytes())?;
+            stdout.flush()?;
+        }
+        Ok(())
+    });
+    let session = Session {
+        client: Arc::new(client),
+        runtime,
+        pending: Arc::default(),
+        replies,
+    };
+    let mut stdin = io::stdin().lock();
+    let read = loop {
+        match read_message(&mut stdin) {
+            Ok(Some((message, framing))) => session.handle(&message, framing),
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+    let pending = std::mem::take(&mut *session.pending.lock().unwrap_or_else(|e| e.into_inner()));
+    for task in pending.into_values() {
+        task.abort();
+    }
+    drop(session);
+    let written = writer
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "writer panicked")));
+    read.and(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_both_framings() {
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "detect"});
+        let input = format!(
+            "{}\r\n\n{}",
+            request,
+            frame(&request, Framing::Headers).replacen("\r\n", "\r\nContent-Type: x\r\n", 1)
+        );
+        let mut input = io::Cursor::new(input);
+        let (line, framing) = read_message(&mut input).unwrap().unwrap();
+        assert_eq!((line, framing), (request.to_string(), Framing::Lines));
+        let (body, framing) = read_message(&mut input).unwrap().unwrap();
+        assert_eq!((body, framing), (request.to_string(), Framing::Headers));
+        assert!(read_message(&mut input).unwrap().is_none());
+    }
+}
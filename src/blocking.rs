@@ -0,0 +1,138 @@
+//! A synchronous API for scripts and build tools, without any tokio setup:
+//! the async [`DeepLClient`](crate::DeepLClient) driven by a runtime the
+//! crate starts on first use and shares between every blocking client.
+//!
+//! Calls made from inside an async runtime block a thread of their own
+//! rather than panicking, although the async client is the better fit
+//! there.
+
+use std::{future::Future, io, sync::OnceLock, thread};
+
+use tokio::runtime::Runtime;
+
+use crate::{
+    error::DeepLError, lang::Detection, options::TranslateOptions, translator::Translation,
+    Translator,
+};
+
+fn runtime() -> Result<&'static Runtime, DeepLError> {
+    static RUNTIME: OnceLock<Result<Runtime, String>> = OnceLock::new();
+    RUNTIME
+        .get_or_init(|| {
+            // A worker keeps pooled connections alive between calls.
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name("deeplx-blocking")
+                .enable_all()
+                .build()
+                .map_err(|e| e.to_string())
+        })
+        .as_ref()
+        .map_err(|e| DeepLError::Io(io::Error::new(io::ErrorKind::Other, e.clone())))
+}
+
+fn run<F, T>(future: F) -> Result<T, DeepLError>
+where
+    F: Future<Output = Result<T, DeepLError>> + Send,
+    T: Send,
+{
+    let runtime = runtime()?;
+    if tokio::runtime::Handle::try_current().is_err() {
+        return runtime.block_on(future);
+    }
+    thread::scope(|scope| {
+        scope
+            .spawn(|| runtime.block_on(future))
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Translates `text` through the client behind
+/// [`deepl_translate`](crate::deepl_translate).
+pub fn translate(text: &str, src_lang: &str, target_lang: &str) -> Result<Translation, DeepLError> {
+    run(crate::DeepLClient::shared().translate(text, src_lang, target_lang))
+}
+
+/// Detects the language of `text` through the shared client.
+pub fn detect_language(text: &str) -> Result<Detection, DeepLError> {
+    run(crate::DeepLClient::shared().detect_language(text))
+}
+
+/// An async [`DeepLClient`](crate::DeepLClient) with blocking methods.
+/// Configure it with the async client's `with_*` methods and convert it
+/// with [`From`].
+#[derive(Clone, Default)]
+pub struct DeepLClient {
+    inner: crate::DeepLClient,
+}
+
+impl From<crate::DeepLClient> for DeepLClient {
+    fn from(inner: crate::DeepLClient) -> Self {
+        DeepLClient { inner }
+    }
+}
+
+impl DeepLClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_endpoint(endpoint: impl Into<String>) -> Self {
+        crate::DeepLClient::with_endpoint(endpoint).into()
+    }
+
+    pub fn get_ref(&self) -> &crate::DeepLClient {
+        &self.inner
+    }
+
+    pub fn into_async(self) -> crate::DeepLClient {
+        self.inner
+    }
+
+    pub fn translate(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Translation, DeepLError> {
+        run(self.inner.translate(text, src_lang, target_lang))
+    }
+
+    pub fn translate_with_options(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+        options: &TranslateOptions,
+    ) -> Result<Translation, DeepLError> {
+        run(self
+            .inner
+            .translate_with_options(text, src_lang, target_lang, options))
+    }
+
+    pub fn translate_with_alternatives(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+        n: u32,
+    ) -> Result<Translation, DeepLError> {
+        run(self
+            .inner
+            .translate_with_alternatives(text, src_lang, target_lang, n))
+    }
+
+    pub fn translate_batch(
+        &self,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Vec<Translation>, DeepLError> {
+        run(self.inner.translate_batch(texts, src_lang, target_lang))
+    }
+
+    pub fn detect_language(&self, text: &str) -> Result<Detection, DeepLError> {
+        run(self.inner.detect_language(text))
+    }
+}
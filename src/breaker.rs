@@ -0,0 +1,85 @@
+use std::{sync::Mutex, time::Duration};
+
+use crate::clock::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    /// Requests are refused for `remaining` more.
+    Open {
+        remaining: Duration,
+    },
+    /// The cooldown is over and the next request is a probe.
+    HalfOpen,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+/// Stops sending requests for `open_for` after `failure_threshold`
+/// consecutive upstream failures.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    state: Mutex<State>,
+    failure_threshold: u32,
+    open_for: Duration,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            failure_threshold: failure_threshold.max(1),
+            open_for,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.opened_at {
+            None => CircuitState::Closed,
+            Some(at) => match self.open_for.checked_sub(at.elapsed()) {
+                Some(remaining) if !remaining.is_zero() => CircuitState::Open { remaining },
+                _ => CircuitState::HalfOpen,
+            },
+        }
+    }
+
+    pub fn allows(&self) -> bool {
+        !matches!(self.state(), CircuitState::Open { .. })
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = State::default();
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.consecutive_failures += 1;
+        // A failed probe while half-open re-opens the circuit straight away.
+        if state.consecutive_failures >= self.failure_threshold || state.opened_at.is_some() {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_and_recovers() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(20));
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.record_failure();
+        assert!(!breaker.allows());
+        std::thread::sleep(Duration::from_millis(25));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
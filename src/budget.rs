@@ -0,0 +1,298 @@
+//! A character budget per day or month for a whole client or gateway, so a
+//! runaway job cannot spend the deployment's quota.
+//!
+//! Past the soft limit requests still go through, with a warning logged
+//! once per period and counted in [`BudgetUsage`]. Past the hard limit
+//! they fail with [`DeepLError::BudgetExceeded`], except priority ones.
+//! Periods follow the UTC calendar. The per-identity budgets of a
+//! [`Calendar`](crate::schedule::Calendar) count in the same periods.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{clock, diag, error::DeepLError, state::Stateful};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    #[default]
+    Day,
+    Month,
+}
+
+/// Days since 1970-01-01 of the first day of `month` (1-12) in `year`.
+fn days_from_civil(year: i64, month: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The year and month of `days` since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
+
+impl Period {
+    /// The period `now` (unix seconds) falls in, for a calendar
+    /// `utc_offset` seconds ahead of UTC, and when the next one starts, in
+    /// unix seconds.
+    pub(crate) fn window(self, now: u64, utc_offset: i32) -> (i64, u64) {
+        let local = now as i64 + i64::from(utc_offset);
+        let days = local.div_euclid(86_400);
+        let (window, next_day) = match self {
+            Period::Day => (days, days + 1),
+            Period::Month => {
+                let (year, month) = civil_from_days(days);
+                let (next_year, next_month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                (year * 12 + month, days_from_civil(next_year, next_month))
+            }
+        };
+        let next = next_day * 86_400 - i64::from(utc_offset);
+        (window, next.max(0) as u64)
+    }
+}
+
+/// Characters used in one window of a [`Period`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Usage {
+    window: i64,
+    used: u64,
+}
+
+impl Usage {
+    /// What was used in `window`, which is nothing once it is over.
+    pub(crate) fn in_window(&self, window: i64) -> u64 {
+        if self.window == window {
+            self.used
+        } else {
+            0
+        }
+    }
+
+    /// Books `chars` in `window`, starting from nothing if it is a new
+    /// one.
+    pub(crate) fn add(&mut self, window: i64, chars: u64) {
+        *self = Usage {
+            window,
+            used: self.in_window(window).saturating_add(chars),
+        };
+    }
+
+    /// Takes back `chars` booked in `window`, if it is still the current
+    /// one.
+    pub(crate) fn remove(&mut self, window: i64, chars: u64) {
+        if self.window == window {
+            self.used = self.used.saturating_sub(chars);
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    #[serde(flatten)]
+    usage: Usage,
+    warned: bool,
+    over_soft: u64,
+    rejected: u64,
+}
+
+#[derive(Debug)]
+pub struct Budget {
+    period: Period,
+    soft_limit: Option<u64>,
+    hard_limit: Option<u64>,
+    state: Mutex<State>,
+}
+
+/// Where the budget stands in the current period.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BudgetUsage {
+    pub period: Period,
+    pub used: u64,
+    pub soft_limit: Option<u64>,
+    pub hard_limit: Option<u64>,
+    /// Requests let through past the soft limit.
+    pub over_soft: u64,
+    /// Requests refused at the hard limit.
+    pub rejected: u64,
+    pub resets_in_secs: u64,
+}
+
+fn now() -> u64 {
+    clock::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl Budget {
+    /// A budget without limits, which only counts.
+    pub fn new(period: Period) -> Self {
+        Budget {
+            period,
+            soft_limit: None,
+            hard_limit: None,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn with_soft_limit(mut self, chars: u64) -> Self {
+        self.soft_limit = Some(chars);
+        self
+    }
+
+    pub fn with_hard_limit(mut self, chars: u64) -> Self {
+        self.hard_limit = Some(chars);
+        self
+    }
+
+    /// Books `chars` characters, or fails if that would cross the hard
+    /// limit and the request is not a `priority` one.
+    pub fn charge(&self, chars: u64, priority: bool) -> Result<(), DeepLError> {
+        self.charge_at(now(), chars, priority).map(drop)
+    }
+
+    /// As [`charge`](Self::charge), telling whether the request went past
+    /// the soft limit.
+    #[cfg(feature = "client")]
+    pub(crate) fn book(&self, chars: u64, priority: bool) -> Result<bool, DeepLError> {
+        self.charge_at(now(), chars, priority)
+    }
+
+    fn charge_at(&self, now: u64, chars: u64, priority: bool) -> Result<bool, DeepLError> {
+        let (window, resets_at) = self.period.window(now, 0);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.usage.window != window {
+            *state = State {
+                usage: Usage { window, used: 0 },
+                ..State::default()
+            };
+        }
+        let used = state.usage.used.saturating_add(chars);
+        if let Some(limit) = self.hard_limit.filter(|limit| used > *limit && !priority) {
+            state.rejected += 1;
+            return Err(DeepLError::BudgetExceeded {
+                used: state.usage.used,
+                limit,
+                resets_in: Duration::from_secs(resets_at.saturating_sub(now)),
+            });
+        }
+        state.usage.add(window, chars);
+        let Some(limit) = self.soft_limit.filter(|limit| used > *limit) else {
+            return Ok(false);
+        };
+        state.over_soft += 1;
+        if !state.warned {
+            state.warned = true;
+            diag::log_warn!(
+                "character budget past its soft limit: {} of {} this {:?}",
+                used,
+                limit,
+                self.period
+            );
+        }
+        Ok(true)
+    }
+
+    pub fn usage(&self) -> BudgetUsage {
+        self.usage_at(now())
+    }
+
+    fn usage_at(&self, now: u64) -> BudgetUsage {
+        let (window, resets_at) = self.period.window(now, 0);
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let current = state.usage.window == window;
+        BudgetUsage {
+            period: self.period,
+            used: state.usage.in_window(window),
+            soft_limit: self.soft_limit,
+            hard_limit: self.hard_limit,
+            over_soft: if current { state.over_soft } else { 0 },
+            rejected: if current { state.rejected } else { 0 },
+            resets_in_secs: resets_at.saturating_sub(now),
+        }
+    }
+}
+
+impl Stateful for Budget {
+    fn state_name(&self) -> &str {
+        "budget"
+    }
+
+    fn export_state(&self) -> Value {
+        serde_json::json!(*self.state.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// State from an earlier period is ignored at the next charge, as if
+    /// the process had kept running.
+    fn import_state(&self, state: Value) -> Result<(), serde_json::Error> {
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = serde_json::from_value(state)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_soft_and_hard_limits_reset_monthly() {
+        // 2026-01-31 23:00 UTC.
+        let jan = days_from_civil(2026, 2) as u64 * 86_400 - 3_600;
+        assert_eq!(civil_from_days((jan / 86_400) as i64), (2026, 1));
+
+        let budget = Budget::new(Period::Month)
+            .with_soft_limit(10)
+            .with_hard_limit(20);
+        assert!(budget.charge_at(jan, 15, false).unwrap());
+        match budget.charge_at(jan, 10, false) {
+            Err(DeepLError::BudgetExceeded {
+                used,
+                limit,
+                resets_in,
+            }) => assert_eq!((used, limit, resets_in.as_secs()), (15, 20, 3_600)),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(budget.charge_at(jan, 10, true).unwrap());
+        let usage = budget.usage_at(jan);
+        assert_eq!((usage.used, usage.over_soft, usage.rejected), (25, 2, 1));
+
+        // February starts from nothing.
+        assert!(budget.charge_at(jan + 3_600, 20, false).unwrap());
+        assert!(!Budget::new(Period::Month)
+            .with_soft_limit(10)
+            .charge_at(jan, 10, false)
+            .unwrap());
+        assert_eq!(budget.usage_at(jan + 3_600).used, 20);
+    }
+
+    #[test]
+    fn test_daily_windows() {
+        let budget = Budget::new(Period::Day).with_hard_limit(5);
+        budget.charge_at(86_400 * 3 + 10, 5, false).unwrap();
+        assert!(budget.charge_at(86_400 * 3 + 20, 1, false).is_err());
+        assert_eq!(budget.usage_at(86_400 * 4).used, 0);
+        budget.charge_at(86_400 * 4, 5, false).unwrap();
+    }
+}
@@ -0,0 +1,741 @@
+//! An in-process cache of recent translations, for one client or a few
+//! sharing it, so that repeated strings such as UI labels and retried
+//! requests do not spend quota.
+//!
+//! Entries are keyed by the text with its line endings and surrounding
+//! whitespace normalised, the language pair and the options that shape the
+//! result; [`KeyNormalization`] lets texts that differ in case, inner
+//! whitespace or placeholder names share an entry too. The least recently
+//! used entry goes once the cache is full, and
+//! with a TTL entries also go once they are that old. For a cache shared
+//! by a cluster, see [`Coordinated`](crate::cluster::Coordinated).
+//!
+//! [`TranslationCache`] is one [`CacheStore`]; [`StorageCache`] keeps
+//! translations in any [`Storage`], such as `SqliteStorage` or
+//! `RedisStorage`, so that they survive restarts.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    clock::Instant,
+    protect::unmask,
+    signing::sha256,
+    state::Stateful,
+    storage::{Storage, StorageResult, NS_CACHE},
+    translator::{BoxFuture, Translation},
+    validate::placeholder_spans,
+};
+
+/// How far two texts may differ and still share a cache entry. Line
+/// endings never count. A hit is the stored translation of whichever
+/// variant was translated first, as it was returned then, except that
+/// with `placeholders` the requested text's placeholders are put back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyNormalization {
+    /// Whitespace around the text does not count. On by default.
+    pub trim: bool,
+    /// A run of whitespace inside the text counts as one space.
+    pub collapse_whitespace: bool,
+    /// Upper and lower case do not count; with
+    /// [`Casing::PreserveSource`](crate::options::Casing::PreserveSource)
+    /// the client recases a hit to the requested text.
+    pub case_fold: bool,
+    /// Placeholders such as `{name}` or `%s` count by position, not by
+    /// name: `Hi {name}` is answered from `Hi {user}` with `{name}` in
+    /// place of `{user}`. Translations that lost or duplicated a
+    /// placeholder are not stored.
+    pub placeholders: bool,
+}
+
+impl Default for KeyNormalization {
+    fn default() -> Self {
+        KeyNormalization {
+            trim: true,
+            collapse_whitespace: false,
+            case_fold: false,
+            placeholders: false,
+        }
+    }
+}
+
+impl KeyNormalization {
+    /// Every normalization at once, for UI strings.
+    pub fn all() -> Self {
+        KeyNormalization {
+            trim: true,
+            collapse_whitespace: true,
+            case_fold: true,
+            placeholders: true,
+        }
+    }
+
+    /// The form of `text` its key is made from.
+    pub fn normalize(&self, text: &str) -> String {
+        self.apply(text).0
+    }
+
+    /// The placeholders of `text` that [`normalize`](Self::normalize)
+    /// replaced, in order; none without `placeholders`.
+    pub fn placeholders(&self, text: &str) -> Vec<String> {
+        self.apply(text).1
+    }
+
+    fn apply(&self, text: &str) -> (String, Vec<String>) {
+        let mut text = text.replace("\r\n", "\n");
+        if self.trim {
+            text = text.trim().to_string();
+        }
+        let mut placeholders = Vec::new();
+        if self.placeholders {
+            let mut out = String::with_capacity(text.len());
+            let mut last = 0;
+            for span in placeholder_spans(&text) {
+                out.push_str(&text[last..span.start]);
+                out.push_str(&sentinel(placeholders.len()));
+                placeholders.push(text[span.clone()].to_string());
+                last = span.end;
+            }
+            out.push_str(&text[last..]);
+            text = out;
+        }
+        if self.collapse_whitespace {
+            let mut out = String::with_capacity(text.len());
+            for c in text.chars() {
+                if !c.is_whitespace() {
+                    out.push(c);
+                } else if !out.ends_with(' ') {
+                    out.push(' ');
+                }
+            }
+            text = out;
+        }
+        if self.case_fold {
+            text = text.to_lowercase();
+        }
+        (text, placeholders)
+    }
+}
+
+fn sentinel(n: usize) -> String {
+    format!("⟦{}⟧", n)
+}
+
+/// `translation` with the `placeholders` of its source replaced by
+/// sentinels, to be filled in with those of the next text that hits;
+/// `None` if the main translation does not hold each of them.
+pub fn template(translation: &Translation, placeholders: &[String]) -> Option<Translation> {
+    let replace = |text: &str| {
+        let mut out = text.to_string();
+        for (n, placeholder) in placeholders.iter().enumerate() {
+            if !out.contains(placeholder.as_str()) {
+                return None;
+            }
+            out = out.replacen(placeholder.as_str(), &sentinel(n), 1);
+        }
+        Some(out)
+    };
+    Some(Translation {
+        text: replace(&translation.text)?,
+        alternatives: translation
+            .alternatives
+            .iter()
+            .filter_map(|a| replace(a))
+            .collect(),
+        ..translation.clone()
+    })
+}
+
+/// A [`template`] with `placeholders` put back, or `None` if they do not
+/// fit its sentinels.
+pub fn fill(mut translation: Translation, placeholders: &[String]) -> Option<Translation> {
+    translation.text = unmask(&translation.text, placeholders).ok()?;
+    translation
+        .alternatives
+        .retain_mut(|a| match unmask(a, placeholders) {
+            Ok(filled) => {
+                *a = filled;
+                true
+            }
+            Err(_) => false,
+        });
+    Some(translation)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey([u8; 32]);
+
+impl CacheKey {
+    /// `options` tells apart requests whose results differ for the same
+    /// text, such as the formality or glossary used.
+    pub fn new(text: &str, src_lang: &str, target_lang: &str, options: &str) -> Self {
+        Self::normalized(
+            text,
+            src_lang,
+            target_lang,
+            options,
+            &KeyNormalization::default(),
+        )
+    }
+
+    /// Like [`new`](Self::new), for `text` as `normalization` has it.
+    /// Keys normalized differently never match.
+    pub fn normalized(
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+        options: &str,
+        normalization: &KeyNormalization,
+    ) -> Self {
+        let text = normalization.normalize(text);
+        // The default leaves the keys stored before normalization was
+        // configurable as they were.
+        let options = if *normalization == KeyNormalization::default() {
+            options.to_string()
+        } else {
+            format!(
+                "{}|norm:{}{}{}{}",
+                options,
+                normalization.trim as u8,
+                normalization.collapse_whitespace as u8,
+                normalization.case_fold as u8,
+                normalization.placeholders as u8
+            )
+        };
+        let mut data = Vec::with_capacity(text.len() + options.len() + 16);
+        for part in [
+            src_lang.to_uppercase().as_bytes(),
+            target_lang.to_uppercase().as_bytes(),
+            options.as_bytes(),
+            text.as_bytes(),
+        ] {
+            data.extend_from_slice(&(part.len() as u64).to_le_bytes());
+            data.extend_from_slice(part);
+        }
+        CacheKey(sha256(&data))
+    }
+}
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+/// Parses the hex form [`Display`](fmt::Display) writes.
+impl FromStr for CacheKey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(());
+        }
+        let mut key = [0; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).map_err(|_| ())?;
+        }
+        Ok(CacheKey(key))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// 0 for stores that do not count them.
+    pub entries: usize,
+    /// Entries dropped to make room; expired ones are not counted.
+    pub evictions: u64,
+}
+
+/// Where a client keeps the translations it has already fetched.
+pub trait CacheStore: fmt::Debug + Send + Sync {
+    /// The translation stored for `key`, marked with the `cached`
+    /// extension.
+    fn get<'a>(&'a self, key: &'a CacheKey) -> BoxFuture<'a, StorageResult<Option<Translation>>>;
+
+    /// Like [`get`](Self::get), but also answers with entries past their
+    /// TTL, marked with the `stale` extension, while the store has them,
+    /// or with `max_staleness` only those that expired at most that long
+    /// ago.
+    fn get_stale<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        _max_staleness: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<Option<Translation>>> {
+        self.get(key)
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        translation: &'a Translation,
+    ) -> BoxFuture<'a, StorageResult<()>>;
+
+    fn stats(&self) -> CacheStats;
+}
+
+fn mark_cached(mut translation: Translation) -> Translation {
+    translation
+        .meta
+        .extensions
+        .insert("cached".to_string(), Value::Bool(true));
+    translation
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+struct Entry {
+    translation: Translation,
+    stored_at: Instant,
+    /// Its position in [`Lru::order`].
+    used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<CacheKey, Entry>,
+    /// Keys by when they were last used, oldest first.
+    order: BTreeMap<u64, CacheKey>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl Lru {
+    fn touch(&mut self, key: CacheKey) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            self.order.remove(&entry.used);
+            entry.used = self.clock;
+            self.order.insert(self.clock, key);
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.used);
+        }
+    }
+}
+
+pub struct TranslationCache {
+    capacity: usize,
+    ttl: Option<Duration>,
+    lru: Mutex<Lru>,
+}
+
+impl TranslationCache {
+    /// A cache of up to `capacity` translations, kept until evicted.
+    pub fn new(capacity: usize) -> Self {
+        TranslationCache {
+            capacity,
+            ttl: None,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// The cached translation for `key`, marked with the `cached`
+    /// extension. Expired entries are kept for
+    /// [`get_stale`](Self::get_stale) until they are evicted.
+    pub fn get(&self, key: &CacheKey) -> Option<Translation> {
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        let expired = lru
+            .entries
+            .get(key)
+            .map(|e| self.ttl.is_some_and(|ttl| e.stored_at.elapsed() >= ttl));
+        match expired {
+            Some(false) => {
+                lru.stats.hits += 1;
+                lru.touch(*key);
+                Some(mark_cached(lru.entries[key].translation.clone()))
+            }
+            Some(true) | None => {
+                lru.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Like [`get`](Self::get), but entries past the TTL are returned too,
+    /// marked with the `stale` extension, until they are evicted, or with
+    /// `max_staleness` only if they expired at most that long ago.
+    pub fn get_stale(
+        &self,
+        key: &CacheKey,
+        max_staleness: Option<Duration>,
+    ) -> Option<Translation> {
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        let age = lru.entries.get(key).map(|entry| entry.stored_at.elapsed());
+        let ttl = self.ttl.unwrap_or(Duration::MAX);
+        let Some(age) =
+            age.filter(|&age| max_staleness.map_or(true, |max| age < ttl.saturating_add(max)))
+        else {
+            lru.stats.misses += 1;
+            return None;
+        };
+        let entry = &lru.entries[key];
+        let expired = age >= ttl;
+        let mut translation = mark_cached(entry.translation.clone());
+        if expired {
+            translation
+                .meta
+                .extensions
+                .insert("stale".to_string(), Value::Bool(true));
+        }
+        lru.stats.hits += 1;
+        lru.touch(*key);
+        Some(translation)
+    }
+
+    pub fn insert(&self, key: CacheKey, translation: Translation) {
+        self.insert_at(key, translation, Instant::now());
+    }
+
+    fn insert_at(&self, key: CacheKey, translation: Translation, stored_at: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        lru.remove(&key);
+        while lru.entries.len() >= self.capacity {
+            let Some((_, oldest)) = lru.order.pop_first() else {
+                break;
+            };
+            lru.entries.remove(&oldest);
+            lru.stats.evictions += 1;
+        }
+        lru.entries.insert(
+            key,
+            Entry {
+                translation,
+                stored_at,
+                used: 0,
+            },
+        );
+        lru.touch(key);
+    }
+
+    pub fn clear(&self) {
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        lru.entries.clear();
+        lru.order.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        CacheStats {
+            entries: lru.entries.len(),
+            ..lru.stats
+        }
+    }
+}
+
+impl CacheStore for TranslationCache {
+    fn get<'a>(&'a self, key: &'a CacheKey) -> BoxFuture<'a, StorageResult<Option<Translation>>> {
+        Box::pin(async move { Ok(TranslationCache::get(self, key)) })
+    }
+
+    fn get_stale<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        max_staleness: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<Option<Translation>>> {
+        Box::pin(async move { Ok(TranslationCache::get_stale(self, key, max_staleness)) })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        translation: &'a Translation,
+    ) -> BoxFuture<'a, StorageResult<()>> {
+        Box::pin(async move {
+            self.insert(*key, translation.clone());
+            Ok(())
+        })
+    }
+
+    fn stats(&self) -> CacheStats {
+        TranslationCache::stats(self)
+    }
+}
+
+/// Translations kept in a [`Storage`] under [`NS_CACHE`], as JSON keyed by
+/// the hex [`CacheKey`]. Expiry and eviction are left to the storage.
+pub struct StorageCache {
+    storage: Arc<dyn Storage>,
+    ttl: Option<Duration>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl StorageCache {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        StorageCache {
+            storage,
+            ttl: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+impl fmt::Debug for StorageCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StorageCache")
+            .field("ttl", &self.ttl)
+            .field("stats", &self.stats())
+            .finish_non_exhaustive()
+    }
+}
+
+impl CacheStore for StorageCache {
+    fn get<'a>(&'a self, key: &'a CacheKey) -> BoxFuture<'a, StorageResult<Option<Translation>>> {
+        Box::pin(async move {
+            let found = self
+                .storage
+                .get(NS_CACHE, &key.to_string())
+                .await?
+                // An entry written by another version reads as a miss.
+                .and_then(|value| serde_json::from_slice::<Translation>(&value).ok());
+            let counter = if found.is_some() {
+                &self.hits
+            } else {
+                &self.misses
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+            Ok(found.map(mark_cached))
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        translation: &'a Translation,
+    ) -> BoxFuture<'a, StorageResult<()>> {
+        Box::pin(async move {
+            // Translations always serialize.
+            let value = serde_json::to_vec(translation).unwrap_or_default();
+            self.storage
+                .put(NS_CACHE, &key.to_string(), value, self.ttl)
+                .await
+        })
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ..CacheStats::default()
+        }
+    }
+}
+
+impl fmt::Debug for TranslationCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TranslationCache")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEntry {
+    key: String,
+    translation: Translation,
+    age_ms: u64,
+}
+
+/// Saves the entries with their age, least recently used first, so that
+/// they expire and are evicted after a restart as they would have been.
+impl Stateful for TranslationCache {
+    fn state_name(&self) -> &str {
+        "cache"
+    }
+
+    fn export_state(&self) -> Value {
+        let lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        let saved: Vec<SavedEntry> = lru
+            .order
+            .values()
+            .map(|key| {
+                let entry = &lru.entries[key];
+                SavedEntry {
+                    key: key.to_string(),
+                    translation: entry.translation.clone(),
+                    age_ms: entry.stored_at.elapsed().as_millis() as u64,
+                }
+            })
+            .collect();
+        serde_json::json!(saved)
+    }
+
+    fn import_state(&self, state: Value) -> Result<(), serde_json::Error> {
+        let saved: Vec<SavedEntry> = serde_json::from_value(state)?;
+        let now = Instant::now();
+        for entry in saved {
+            let key = entry.key.parse().map_err(|()| {
+                serde::de::Error::custom(format!("invalid cache key {}", entry.key))
+            })?;
+            let age = Duration::from_millis(entry.age_ms);
+            self.insert_at(key, entry.translation, now.checked_sub(age).unwrap_or(now));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(text: &str) -> Translation {
+        Translation {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_least_recently_used_goes_first() {
+        let cache = TranslationCache::new(2);
+        let key = |text| CacheKey::new(text, "en", "DE", "");
+        cache.insert(key("a"), translation("A"));
+        cache.insert(key("b"), translation("B"));
+        assert_eq!(cache.get(&key(" a\r\n")).unwrap().text, "A");
+        cache.insert(key("c"), translation("C"));
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).unwrap().extension("cached").is_some());
+        assert_ne!(key("a"), CacheKey::new("a", "EN", "DE", "formal"));
+        let stats = cache.stats();
+        assert_eq!(
+            (stats.hits, stats.misses, stats.entries, stats.evictions),
+            (2, 1, 2, 1)
+        );
+    }
+
+    #[test]
+    fn test_normalized_keys_share_entries() {
+        let all = KeyNormalization::all();
+        let key = |text, normalization| CacheKey::normalized(text, "EN", "DE", "", &normalization);
+        assert_eq!(key("Hello ", all), key("  hello", all));
+        assert_eq!(key("Save  the\tfile", all), key("save the file", all));
+        assert_ne!(
+            key("Hello", KeyNormalization::default()),
+            key("hello", KeyNormalization::default())
+        );
+        assert_eq!(
+            key("a", KeyNormalization::default()),
+            CacheKey::new(" a", "EN", "DE", "")
+        );
+        assert_ne!(key("a", all), CacheKey::new("a", "EN", "DE", ""));
+        let untrimmed = KeyNormalization {
+            trim: false,
+            ..KeyNormalization::default()
+        };
+        assert_ne!(key("a ", untrimmed), key("a", untrimmed));
+
+        assert_eq!(key("Hi {name}, %s", all), key("hi {user}, %d", all));
+        let stored = template(
+            &translation("%s, hallo {name}"),
+            &all.placeholders("Hi {name}, %s"),
+        );
+        assert_eq!(stored.as_ref().unwrap().text, "⟦1⟧, hallo ⟦0⟧");
+        let hit = fill(stored.unwrap(), &all.placeholders("hi {user}, %d")).unwrap();
+        assert_eq!(hit.text, "%d, hallo {user}");
+        assert!(template(&translation("hallo"), &all.placeholders("Hi {name}")).is_none());
+    }
+
+    #[test]
+    fn test_storage_cache_round_trip() {
+        let storage = Arc::new(crate::storage::MemoryStorage::new());
+        let cache = StorageCache::new(storage.clone());
+        let key = CacheKey::new("a", "EN", "DE", "");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert!(cache.get(&key).await.unwrap().is_none());
+            cache.put(&key, &translation("A")).await.unwrap();
+            // Another process opening the same storage sees it.
+            let reopened = StorageCache::new(storage);
+            let hit = reopened.get(&key).await.unwrap().unwrap();
+            assert_eq!(hit.text, "A");
+            assert!(hit.extension("cached").is_some());
+            assert_eq!((reopened.stats().hits, cache.stats().misses), (1, 1));
+        });
+    }
+
+    #[test]
+    fn test_entries_expire() {
+        let cache = TranslationCache::new(4).with_ttl(Duration::ZERO);
+        let key = CacheKey::new("a", "EN", "DE", "");
+        cache.insert(key, translation("A"));
+        assert!(cache.get(&key).is_none());
+        // Still there for when the upstream is down.
+        assert!(cache.get_stale(&key, None).unwrap().is_stale());
+        assert!(cache
+            .get_stale(&key, Some(Duration::from_secs(60)))
+            .is_some());
+        assert!(cache.get_stale(&key, Some(Duration::ZERO)).is_none());
+        assert_eq!(cache.stats().entries, 1);
+    }
+
+    #[test]
+    fn test_saved_entries_keep_their_age_and_order() {
+        let key = |text| CacheKey::new(text, "EN", "DE", "");
+        let cache = TranslationCache::new(4);
+        cache.insert(key("a"), translation("A"));
+        cache.insert(key("b"), translation("B"));
+        cache.get(&key("a"));
+        let state = cache.export_state();
+
+        let restored = TranslationCache::new(4).with_ttl(Duration::from_secs(60));
+        restored.import_state(state.clone()).unwrap();
+        assert_eq!(restored.get(&key("a")).unwrap().text, "A");
+        // "b" was used longest ago, so it goes first.
+        let small = TranslationCache::new(1);
+        small.import_state(state).unwrap();
+        assert!(small.get(&key("b")).is_none());
+        assert!(small.get(&key("a")).is_some());
+
+        let expired = TranslationCache::new(4).with_ttl(Duration::from_secs(60));
+        let old = serde_json::json!([{
+            "key": key("c").to_string(),
+            "translation": translation("C"),
+            "age_ms": 120_000,
+        }]);
+        expired.import_state(old).unwrap();
+        assert!(expired.get(&key("c")).is_none());
+        assert!(expired.get_stale(&key("c"), None).is_some());
+    }
+}
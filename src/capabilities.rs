@@ -0,0 +1,56 @@
+use crate::lang::is_auto;
+
+/// What a [`Translator`](crate::Translator) can do. Empty language lists
+/// mean the backend did not say, and any code is assumed to work.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub source_langs: Vec<String>,
+    pub target_langs: Vec<String>,
+    pub max_chars: Option<usize>,
+    pub formality: bool,
+    pub alternatives: bool,
+    pub context: bool,
+}
+
+impl Capabilities {
+    pub fn supports_source(&self, lang: &str) -> bool {
+        is_auto(lang) || contains(&self.source_langs, lang)
+    }
+
+    pub fn supports_target(&self, lang: &str) -> bool {
+        !is_auto(lang) && contains(&self.target_langs, lang)
+    }
+
+    pub fn supports_pair(&self, src_lang: &str, target_lang: &str) -> bool {
+        self.supports_source(src_lang) && self.supports_target(target_lang)
+    }
+
+    pub fn accepts_len(&self, chars: usize) -> bool {
+        self.max_chars.map_or(true, |max| chars <= max)
+    }
+}
+
+fn contains(langs: &[String], lang: &str) -> bool {
+    langs.is_empty() || langs.iter().any(|l| l.eq_ignore_ascii_case(lang))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supports_pair() {
+        let caps = Capabilities {
+            source_langs: vec!["EN".to_string(), "DE".to_string()],
+            target_langs: vec!["ZH".to_string()],
+            max_chars: Some(10),
+            ..Default::default()
+        };
+        assert!(caps.supports_pair("en", "ZH"));
+        assert!(caps.supports_pair("auto", "zh"));
+        assert!(!caps.supports_pair("FR", "ZH"));
+        assert!(!caps.supports_pair("EN", "auto"));
+        assert!(!caps.accepts_len(11));
+        assert!(Capabilities::default().supports_pair("XX", "YY"));
+    }
+}
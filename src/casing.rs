@@ -0,0 +1,241 @@
+//! Casing applied to translations once they come back, for UI strings:
+//! DeepL does not carry an all-capitals label or a title's capitals over
+//! reliably.
+//!
+//! Title case follows the target language. English capitalizes every word
+//! but short articles, conjunctions and prepositions; the other languages
+//! with letter case write titles in sentence case; scripts without case
+//! are left as they are. Sentence case lowercases the words after the
+//! first, names included, except for acronyms, words like `iPhone` and
+//! German, whose nouns keep their capitals. Turkish and Azerbaijani get
+//! their dotted and dotless I.
+
+use crate::{lang, options::Casing};
+
+const ENGLISH_MINOR_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "into", "nor", "of", "on",
+    "or", "over", "per", "so", "the", "to", "up", "via", "vs", "with", "yet",
+];
+
+fn turkic(lang: &str) -> bool {
+    lang::matches("TR", lang) || lang::matches("AZ", lang)
+}
+
+fn upper(s: &str, lang: &str) -> String {
+    if turkic(lang) {
+        s.replace('i', "İ").to_uppercase()
+    } else {
+        s.to_uppercase()
+    }
+}
+
+fn lower(s: &str, lang: &str) -> String {
+    if turkic(lang) {
+        s.replace('İ', "i").replace('I', "ı").to_lowercase()
+    } else {
+        s.to_lowercase()
+    }
+}
+
+/// `word` with its first letter in capitals.
+fn capitalize(word: &str, lang: &str) -> String {
+    match word.char_indices().find(|(_, c)| c.is_alphabetic()) {
+        Some((i, c)) => {
+            let end = i + c.len_utf8();
+            format!(
+                "{}{}{}",
+                &word[..i],
+                upper(&word[i..end], lang),
+                &word[end..]
+            )
+        }
+        None => word.to_string(),
+    }
+}
+
+fn has_letters(s: &str) -> bool {
+    s.chars().any(char::is_alphabetic)
+}
+
+fn starts_upper(word: &str) -> bool {
+    word.chars()
+        .find(|c| c.is_alphabetic())
+        .is_some_and(char::is_uppercase)
+}
+
+/// Whether `s` has letters with case and none of them is lower case.
+fn is_all_upper(s: &str) -> bool {
+    s.chars().any(char::is_uppercase) && !s.chars().any(char::is_lowercase)
+}
+
+/// Acronyms such as `PDF` and words such as `iPhone`: a capital after the
+/// first letter.
+fn keeps_case(word: &str) -> bool {
+    word.chars()
+        .filter(|c| c.is_alphabetic())
+        .skip(1)
+        .any(char::is_uppercase)
+}
+
+fn english_i(word: &str) -> bool {
+    let word = word.trim_end();
+    word == "I" || word.starts_with("I'") || word.starts_with("I’")
+}
+
+fn sentence(text: &str, lang: &str) -> String {
+    let all_upper = is_all_upper(text);
+    let nouns_capitalized = lang::matches("DE", lang);
+    let english = lang::matches("EN", lang);
+    let mut start = true;
+    let mut out = String::with_capacity(text.len());
+    for piece in text.split_inclusive(char::is_whitespace) {
+        if !has_letters(piece) {
+            out.push_str(piece);
+            continue;
+        }
+        let word = if all_upper {
+            lower(piece, lang)
+        } else if nouns_capitalized || keeps_case(piece) || (english && english_i(piece)) {
+            piece.to_string()
+        } else {
+            lower(piece, lang)
+        };
+        out.push_str(&if start { capitalize(&word, lang) } else { word });
+        start = piece.trim_end().ends_with(['.', '!', '?']);
+    }
+    out
+}
+
+fn title(text: &str, lang: &str) -> String {
+    if !lang::matches("EN", lang) {
+        return sentence(text, lang);
+    }
+    let all_upper = is_all_upper(text);
+    let pieces: Vec<&str> = text.split_inclusive(char::is_whitespace).collect();
+    let last = pieces.iter().rposition(|piece| has_letters(piece));
+    let mut start = true;
+    let mut out = String::with_capacity(text.len());
+    for (i, piece) in pieces.iter().enumerate() {
+        if !has_letters(piece) {
+            out.push_str(piece);
+            continue;
+        }
+        let word = if all_upper {
+            lower(piece, lang)
+        } else {
+            piece.to_string()
+        };
+        let bare: String = word.chars().filter(|c| c.is_alphabetic()).collect();
+        let minor = !start
+            && Some(i) != last
+            && ENGLISH_MINOR_WORDS.contains(&bare.to_lowercase().as_str());
+        out.push_str(&if keeps_case(&word) {
+            word
+        } else if minor {
+            lower(&word, lang)
+        } else {
+            capitalize(&word, lang)
+        });
+        start = piece.trim_end().ends_with(['.', '!', '?', ':']);
+    }
+    out
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pattern {
+    Upper,
+    Lower,
+    Title,
+    Capitalized,
+}
+
+fn pattern(source: &str) -> Option<Pattern> {
+    let cased = source
+        .chars()
+        .filter(|c| c.is_uppercase() || c.is_lowercase())
+        .count();
+    if cased == 0 {
+        return None;
+    }
+    if cased > 1 && is_all_upper(source) {
+        return Some(Pattern::Upper);
+    }
+    if !source.chars().any(char::is_uppercase) {
+        return Some(Pattern::Lower);
+    }
+    let words: Vec<&str> = source
+        .split_whitespace()
+        .filter(|w| has_letters(w))
+        .collect();
+    let capitalized = words.iter().filter(|w| starts_upper(w)).count();
+    // Short words such as `of` stay lower case in titles.
+    let short = |w: &str| w.chars().filter(|c| c.is_alphabetic()).count() <= 3;
+    if capitalized > 1 && words.iter().all(|w| starts_upper(w) || short(w)) {
+        Some(Pattern::Title)
+    } else if words.first().is_some_and(|w| starts_upper(w)) {
+        Some(Pattern::Capitalized)
+    } else {
+        None
+    }
+}
+
+/// `text`, translated from `source` into `target_lang`, in `casing`.
+pub fn recase(casing: Casing, source: &str, text: &str, target_lang: &str) -> String {
+    match casing {
+        Casing::Sentence => sentence(text, target_lang),
+        Casing::Title => title(text, target_lang),
+        Casing::PreserveSource => match pattern(source) {
+            Some(Pattern::Upper) => upper(text, target_lang),
+            Some(Pattern::Lower) => lower(text, target_lang),
+            Some(Pattern::Title) => title(text, target_lang),
+            Some(Pattern::Capitalized) => capitalize(text, target_lang),
+            None => text.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_casing_follows_the_target_language() {
+        let title = |text, lang| recase(Casing::Title, "", text, lang);
+        assert_eq!(
+            title("save and exit the PDF editor", "EN-US"),
+            "Save and Exit the PDF Editor"
+        );
+        assert_eq!(
+            title("SPEICHERN UND BEENDEN", "DE"),
+            "Speichern und beenden"
+        );
+        assert_eq!(
+            title("Enregistrer Et Quitter", "FR"),
+            "Enregistrer et quitter"
+        );
+        assert_eq!(title("保存并退出", "ZH"), "保存并退出");
+        let sentence = |text, lang| recase(Casing::Sentence, "", text, lang);
+        assert_eq!(
+            sentence("Open In iPhone. then Quit", "EN"),
+            "Open in iPhone. Then quit"
+        );
+        assert_eq!(sentence("Die Datei Öffnen", "DE"), "Die Datei Öffnen");
+
+        let preserve = |source, text, lang| recase(Casing::PreserveSource, source, text, lang);
+        assert_eq!(
+            preserve("SAVE CHANGES", "Änderungen speichern", "DE"),
+            "ÄNDERUNGEN SPEICHERN"
+        );
+        assert_eq!(
+            preserve("SETTINGS", "ayarlar listesi", "TR"),
+            "AYARLAR LİSTESİ"
+        );
+        assert_eq!(
+            preserve("Terms of Service", "terms of use", "EN-GB"),
+            "Terms of Use"
+        );
+        assert_eq!(preserve("settings", "Einstellungen", "DE"), "einstellungen");
+        assert_eq!(preserve("Hello world", "hallo Welt", "DE"), "Hallo Welt");
+        assert_eq!(preserve("你好", "hello", "EN"), "hello");
+    }
+}
@@ -0,0 +1,317 @@
+//! Translating chat logs: arrays of message objects such as
+//! `{"role": "user", "author": "ana", "text": "…"}`.
+//!
+//! Only the text field of each message changes; roles, authors, ids and
+//! timestamps are left as they were. Mentions, emoji shortcodes, URLs and
+//! inline code are masked before sending. Messages are short, so
+//! consecutive ones are sent together, one per line, up to
+//! [`ChatOptions::max_batch_chars`]; a batch whose lines do not survive
+//! the round trip is retried one message at a time.
+
+use std::{ops::Range, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    error::DeepLError,
+    protect::{mask_with, unmask, Masked},
+    translator::Translator,
+    validate::Issue,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatOptions {
+    /// Keys holding the message text; the first one that is a string is
+    /// translated.
+    pub text_fields: Vec<String>,
+    /// Messages with one of these roles are left untranslated.
+    pub skip_roles: Vec<String>,
+    pub max_batch_chars: usize,
+}
+
+impl Default for ChatOptions {
+    fn default() -> Self {
+        Self {
+            text_fields: vec!["text".to_string(), "content".to_string()],
+            skip_roles: Vec::new(),
+            max_batch_chars: 3000,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ChatReport {
+    pub translated: usize,
+    /// Messages without text or with a skipped role.
+    pub skipped: usize,
+    /// Messages left untranslated because of an error, by index.
+    pub failed: Vec<(usize, Arc<DeepLError>)>,
+}
+
+fn word_end(text: &str, start: usize, inner: impl Fn(char) -> bool) -> usize {
+    let len = text[start..]
+        .find(|c: char| !inner(c))
+        .unwrap_or(text.len() - start);
+    start + text[start..start + len].trim_end_matches(['.', '-']).len()
+}
+
+/// Byte ranges of the mentions (`@ana`, `<@123>`, `<#general>`), emoji
+/// shortcodes (`:tada:`), URLs and inline code in `text`.
+pub fn chat_spans(text: &str) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        let boundary = text[..i]
+            .chars()
+            .next_back()
+            .map_or(true, |p| !p.is_alphanumeric());
+        let end = match c {
+            '@' if boundary => Some(word_end(text, i + 1, |c| {
+                c.is_alphanumeric() || matches!(c, '_' | '.' | '-')
+            }))
+            .filter(|&end| end > i + 1),
+            '<' if rest[1..].starts_with(['@', '#', ':']) || rest.starts_with("<a:") => rest
+                .find('>')
+                .filter(|&e| !rest[..e].contains(char::is_whitespace))
+                .map(|e| i + e + 1),
+            ':' if boundary => {
+                let name = rest[1..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')))
+                    .unwrap_or(rest.len() - 1);
+                (name > 0 && rest[1 + name..].starts_with(':')).then(|| i + name + 2)
+            }
+            'h' if boundary && (rest.starts_with("https://") || rest.starts_with("http://")) => {
+                let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                Some(
+                    i + rest[..len]
+                        .trim_end_matches(['.', ',', ')', '!', '?', ';', ':'])
+                        .len(),
+                )
+            }
+            '`' => {
+                let fence = if rest.starts_with("```") { "```" } else { "`" };
+                rest[fence.len()..]
+                    .find(fence)
+                    .map(|e| i + fence.len() + e + fence.len())
+            }
+            _ => None,
+        };
+        match end {
+            Some(end) => {
+                out.push(i..end);
+                i = end;
+            }
+            None => i += c.len_utf8(),
+        }
+    }
+    out
+}
+
+fn mask(text: &str) -> Masked {
+    mask_with(text, chat_spans(text))
+}
+
+fn text_field<'a>(message: &Value, options: &'a ChatOptions) -> Option<&'a str> {
+    options
+        .text_fields
+        .iter()
+        .find(|f| message.get(f.as_str()).is_some_and(Value::is_string))
+        .map(String::as_str)
+}
+
+struct Pending {
+    index: usize,
+    field: String,
+    masked: Masked,
+}
+
+async fn translate_one(
+    provider: &dyn Translator,
+    pending: &Pending,
+    src_lang: &str,
+    target_lang: &str,
+) -> Result<String, Arc<DeepLError>> {
+    let translation = provider
+        .translate(&pending.masked.text, src_lang, target_lang)
+        .await
+        .map_err(Arc::new)?;
+    unmask(&translation.text, &pending.masked.tokens).map_err(|e| {
+        Arc::new(DeepLError::ValidationFailed {
+            segment: pending.index,
+            issues: vec![Issue::BrokenSentinel(e)],
+        })
+    })
+}
+
+/// Translates the text of every message in place.
+pub async fn translate_chat(
+    provider: &dyn Translator,
+    messages: &mut [Value],
+    src_lang: &str,
+    target_lang: &str,
+    options: &ChatOptions,
+) -> ChatReport {
+    let mut report = ChatReport::default();
+    let mut pending = Vec::new();
+    for (index, message) in messages.iter().enumerate() {
+        let role = message.get("role").and_then(Value::as_str);
+        let field = text_field(message, options);
+        let skip = role.is_some_and(|r| options.skip_roles.iter().any(|s| s == r));
+        match field {
+            Some(field) if !skip => {
+                let text = message[field].as_str().unwrap_or_default();
+                if text.trim().is_empty() {
+                    report.skipped += 1;
+                    continue;
+                }
+                pending.push(Pending {
+                    index,
+                    field: field.to_string(),
+                    masked: mask(text),
+                });
+            }
+            _ => report.skipped += 1,
+        }
+    }
+
+    let mut batches: Vec<Vec<Pending>> = Vec::new();
+    for item in pending {
+        let len = item.masked.text.len();
+        match batches.last_mut() {
+            Some(batch)
+                if batch.iter().map(|p| p.masked.text.len() + 1).sum::<usize>() + len
+                    <= options.max_batch_chars =>
+            {
+                batch.push(item)
+            }
+            _ => batches.push(vec![item]),
+        }
+    }
+
+    for batch in batches {
+        let mut results: Vec<Option<Result<String, Arc<DeepLError>>>> = vec![None; batch.len()];
+        if batch.len() > 1 {
+            let joined = batch
+                .iter()
+                .map(|p| p.masked.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let lines: Vec<usize> = batch
+                .iter()
+                .map(|p| p.masked.text.split('\n').count())
+                .collect();
+            match provider.translate(&joined, src_lang, target_lang).await {
+                Ok(translation) => {
+                    let parts: Vec<&str> = translation.text.split('\n').collect();
+                    if parts.len() == lines.iter().sum::<usize>() {
+                        let mut at = 0;
+                        for (slot, (p, n)) in results.iter_mut().zip(batch.iter().zip(&lines)) {
+                            *slot = unmask(&parts[at..at + n].join("\n"), &p.masked.tokens)
+                                .ok()
+                                .map(Ok);
+                            at += n;
+                        }
+                    }
+                }
+                Err(e) => {
+                    let e = Arc::new(e);
+                    results
+                        .iter_mut()
+                        .for_each(|slot| *slot = Some(Err(e.clone())));
+                }
+            }
+        }
+        for (p, result) in batch.iter().zip(results) {
+            let result = match result {
+                Some(result) => result,
+                None => translate_one(provider, p, src_lang, target_lang).await,
+            };
+            match result {
+                Ok(text) => {
+                    messages[p.index][p.field.as_str()] = Value::String(text);
+                    report.translated += 1;
+                }
+                Err(e) => report.failed.push((p.index, e)),
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::translator::{BoxFuture, Translation};
+
+    #[test]
+    fn test_chat_spans() {
+        let text = "@ana see https://x.io/a?b=1. :tada: <@123> `cargo run` mail@host";
+        let spans: Vec<_> = chat_spans(text).into_iter().map(|r| &text[r]).collect();
+        assert_eq!(
+            spans,
+            [
+                "@ana",
+                "https://x.io/a?b=1",
+                ":tada:",
+                "<@123>",
+                "`cargo run`"
+            ]
+        );
+    }
+
+    struct Upper(Mutex<usize>);
+
+    impl Translator for Upper {
+        fn name(&self) -> &str {
+            "upper"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            *self.0.lock().unwrap() += 1;
+            Box::pin(async move {
+                Ok(Translation {
+                    text: text.to_uppercase(),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_translate_chat_keeps_metadata() {
+        let mut messages: Vec<Value> = serde_json::from_str(
+            r#"[
+                {"role": "system", "content": "be nice"},
+                {"role": "user", "author": "ana", "text": "hi @bob :wave:", "ts": 1},
+                {"role": "assistant", "content": "see https://docs.rs/x"},
+                {"role": "user", "attachments": []}
+            ]"#,
+        )
+        .unwrap();
+        let upper = Upper(Mutex::new(0));
+        let options = ChatOptions {
+            skip_roles: vec!["system".to_string()],
+            ..ChatOptions::default()
+        };
+        let report = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(translate_chat(&upper, &mut messages, "EN", "DE", &options));
+        assert_eq!((report.translated, report.skipped), (2, 2));
+        assert!(report.failed.is_empty());
+        assert_eq!(*upper.0.lock().unwrap(), 1);
+        assert_eq!(messages[0]["content"], "be nice");
+        assert_eq!(messages[1]["text"], "HI @bob :wave:");
+        assert_eq!(messages[1]["author"], "ana");
+        assert_eq!(messages[2]["content"], "SEE https://docs.rs/x");
+    }
+}
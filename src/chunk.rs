@@ -0,0 +1,519 @@
+//! Splitting long documents into request-sized chunks.
+//!
+//! Markdown documents are cut at heading boundaries first, so every chunk
+//! belongs to exactly one chapter and progress can be reported per chapter.
+//! Concatenating all chunk texts in order reproduces the input exactly.
+//!
+//! [`Chunked`] applies the same splitting to any [`Translator`], so callers
+//! can hand it documents longer than one request allows.
+
+use std::{ops::Range, sync::Arc};
+
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde_json::Value;
+
+use crate::{
+    capabilities::Capabilities,
+    error::DeepLError,
+    options::TranslateOptions,
+    translator::{BoxFuture, Translation, Translator},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    pub chapter: usize,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TocEntry {
+    /// Heading text without the leading `#`s; empty for the preamble
+    /// before the first heading.
+    pub title: String,
+    /// Heading level 1–6, or 0 for the preamble.
+    pub level: usize,
+    pub chunks: Range<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Document {
+    pub chunks: Vec<Chunk>,
+    pub toc: Vec<TocEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChapterProgress<'a> {
+    pub title: &'a str,
+    pub done: usize,
+    pub total: usize,
+}
+
+impl ChapterProgress<'_> {
+    pub fn is_complete(&self) -> bool {
+        self.done == self.total
+    }
+}
+
+impl Document {
+    pub fn chapter_of(&self, chunk: usize) -> Option<&TocEntry> {
+        self.chunks.get(chunk).and_then(|c| self.toc.get(c.chapter))
+    }
+
+    /// Per-chapter progress given which chunk indices are finished.
+    pub fn progress(&self, is_done: impl Fn(usize) -> bool) -> Vec<ChapterProgress<'_>> {
+        self.toc
+            .iter()
+            .map(|entry| ChapterProgress {
+                title: &entry.title,
+                done: entry.chunks.clone().filter(|i| is_done(*i)).count(),
+                total: entry.chunks.len(),
+            })
+            .collect()
+    }
+
+    /// Joins translated chunks back together. Chapters with any missing
+    /// chunk are left out entirely, so a partial export never contains
+    /// half a chapter.
+    pub fn assemble_complete(&self, translated: &[Option<String>]) -> String {
+        let mut out = String::new();
+        for entry in &self.toc {
+            let parts: Option<Vec<&str>> = entry
+                .chunks
+                .clone()
+                .map(|i| translated.get(i).and_then(|t| t.as_deref()))
+                .collect();
+            if let Some(parts) = parts {
+                parts.iter().for_each(|p| out.push_str(p));
+            }
+        }
+        out
+    }
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start_matches('#');
+    let level = line.len() - trimmed.len();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    if !trimmed.is_empty() && !trimmed.starts_with([' ', '\t']) {
+        return None;
+    }
+    Some((level, trimmed.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Splits `text` into chapters at ATX headings (`#` … `######`), ignoring
+/// anything inside fenced code blocks. Returns `(title, level, text)`.
+pub fn split_chapters(text: &str) -> Vec<(String, usize, String)> {
+    let mut chapters: Vec<(String, usize, String)> = Vec::new();
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        let bare = line.trim_end_matches(['\r', '\n']);
+        if bare.trim_start().starts_with("```") || bare.trim_start().starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        match heading(bare).filter(|_| !in_fence) {
+            Some((level, title)) => chapters.push((title.to_string(), level, line.to_string())),
+            None => match chapters.last_mut() {
+                Some(chapter) => chapter.2.push_str(line),
+                None => chapters.push((String::new(), 0, line.to_string())),
+            },
+        }
+    }
+    chapters
+}
+
+/// Splits `text` into paragraphs, each keeping its trailing blank lines.
+fn paragraphs(text: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut seen_blank = false;
+    for line in text.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if seen_blank && !blank {
+            out.push(&text[start..offset]);
+            start = offset;
+        }
+        seen_blank = blank;
+        offset += line.len();
+    }
+    if start < text.len() {
+        out.push(&text[start..]);
+    }
+    out
+}
+
+/// Packs whole paragraphs into chunks of at most `max_chars` characters.
+/// A single paragraph longer than the limit becomes a chunk of its own.
+pub fn pack_paragraphs(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for para in paragraphs(text) {
+        let len = para.chars().count();
+        if current_len > 0 && current_len + len > max_chars {
+            chunks.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        current.push_str(para);
+        current_len += len;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Splits `text` after sentence-ending punctuation, each sentence keeping
+/// the whitespace that follows it.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let cjk = matches!(c, '。' | '！' | '？');
+        if !(cjk || matches!(c, '.' | '!' | '?')) {
+            continue;
+        }
+        let next = chars.peek().map(|(_, n)| *n);
+        if !cjk && !next.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let mut end = i + c.len_utf8();
+        while let Some((j, n)) = chars.peek().copied() {
+            if !n.is_whitespace() {
+                break;
+            }
+            end = j + n.len_utf8();
+            chars.next();
+        }
+        out.push(&text[start..end]);
+        start = end;
+    }
+    if start < text.len() {
+        out.push(&text[start..]);
+    }
+    out
+}
+
+/// Cuts `text` into pieces of at most `max_chars` characters, preferring
+/// to cut after whitespace.
+fn hard_split(text: &str, max_chars: usize) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut rest = text;
+    while rest.chars().count() > max_chars {
+        let limit = rest
+            .char_indices()
+            .nth(max_chars)
+            .map_or(rest.len(), |(i, _)| i);
+        let cut = rest[..limit]
+            .rfind(char::is_whitespace)
+            .map(|i| i + rest[i..].chars().next().map_or(1, char::len_utf8))
+            .filter(|&i| i > 0 && i < limit)
+            .unwrap_or(limit);
+        out.push(&rest[..cut]);
+        rest = &rest[cut..];
+    }
+    if !rest.is_empty() {
+        out.push(rest);
+    }
+    out
+}
+
+/// Splits `text` into chunks of at most `max_chars` characters: whole
+/// paragraphs where they fit, then whole sentences, then, for sentences
+/// too long on their own, cuts at whitespace.
+pub fn split_text(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut out = Vec::new();
+    for chunk in pack_paragraphs(text, max_chars) {
+        if chunk.chars().count() <= max_chars {
+            out.push(chunk);
+            continue;
+        }
+        let mut current = String::new();
+        let mut current_len = 0;
+        for piece in sentences(&chunk)
+            .into_iter()
+            .flat_map(|s| hard_split(s, max_chars))
+        {
+            let len = piece.chars().count();
+            if current_len > 0 && current_len + len > max_chars {
+                out.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+            current.push_str(piece);
+            current_len += len;
+        }
+        if !current.is_empty() {
+            out.push(current);
+        }
+    }
+    out
+}
+
+/// Splits texts longer than `max_chars` with [`split_text`], translates the
+/// chunks and joins the results, keeping the whitespace between chunks as
+/// in the source. Alternatives are dropped for split texts, and the number
+/// of chunks is recorded in the `chunks` extension.
+pub struct Chunked {
+    inner: Arc<dyn Translator>,
+    max_chars: usize,
+    concurrency: usize,
+}
+
+impl Chunked {
+    pub fn new(inner: Arc<dyn Translator>, max_chars: usize) -> Self {
+        Self {
+            inner,
+            max_chars,
+            concurrency: 1,
+        }
+    }
+
+    /// Chunks translated at the same time; one at a time by default.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// One request to the inner translator, with `options` if given.
+    fn send<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+        options: Option<&'a TranslateOptions>,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        match options {
+            Some(options) => {
+                self.inner
+                    .translate_with_options(text, src_lang, target_lang, options)
+            }
+            None => self.inner.translate(text, src_lang, target_lang),
+        }
+    }
+
+    async fn translate_chunks(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+        options: Option<&TranslateOptions>,
+    ) -> Result<Translation, DeepLError> {
+        if text.chars().count() <= self.max_chars {
+            return self.send(text, src_lang, target_lang, options).await;
+        }
+        let chunks = split_text(text, self.max_chars);
+        let count = chunks.len();
+        let parts: Vec<(String, Option<Translation>)> = stream::iter(chunks)
+            .map(|chunk| async move {
+                let body = chunk.trim();
+                if body.is_empty() {
+                    return Ok((chunk, None));
+                }
+                let t = self.send(body, src_lang, target_lang, options).await?;
+                Ok::<_, DeepLError>((chunk, Some(t)))
+            })
+            .buffered(self.concurrency)
+            .try_collect()
+            .await?;
+
+        let mut out: Option<Translation> = None;
+        let mut text = String::new();
+        for (chunk, translated) in parts {
+            let Some(translated) = translated else {
+                text.push_str(&chunk);
+                continue;
+            };
+            let leading = chunk.len() - chunk.trim_start().len();
+            text.push_str(&chunk[..leading]);
+            text.push_str(&translated.text);
+            text.push_str(&chunk[chunk.trim_end().len()..]);
+            out.get_or_insert(translated);
+        }
+        let mut out = out.ok_or(DeepLError::NoProvider)?;
+        out.text = text;
+        out.alternatives.clear();
+        out.meta
+            .extensions
+            .insert("chunks".to_string(), Value::from(count));
+        Ok(out)
+    }
+}
+
+impl Translator for Chunked {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            max_chars: None,
+            ..self.inner.capabilities()
+        }
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(self.translate_chunks(text, src_lang, target_lang, None))
+    }
+
+    /// Sends `options` along with every chunk.
+    fn translate_with_options<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+        options: &'a TranslateOptions,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(self.translate_chunks(text, src_lang, target_lang, Some(options)))
+    }
+}
+
+/// Chunks a Markdown document chapter by chapter.
+pub fn chunk_markdown(text: &str, max_chars: usize) -> Document {
+    let mut doc = Document::default();
+    for (chapter, (title, level, body)) in split_chapters(text).into_iter().enumerate() {
+        let start = doc.chunks.len();
+        doc.chunks.extend(
+            pack_paragraphs(&body, max_chars)
+                .into_iter()
+                .map(|text| Chunk { chapter, text }),
+        );
+        doc.toc.push(TocEntry {
+            title,
+            level,
+            chunks: start..doc.chunks.len(),
+        });
+    }
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = "Preface.\n\n# One\n\nFirst para.\n\nSecond para.\n\n```\n# not a heading\n```\n## One.A ##\nText.\n";
+
+    #[test]
+    fn test_chunk_markdown_round_trips() {
+        let doc = chunk_markdown(BOOK, 20);
+        let joined: String = doc.chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(joined, BOOK);
+        let titles: Vec<_> = doc.toc.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["", "One", "One.A"]);
+        assert_eq!(doc.toc[2].level, 2);
+        assert!(doc.toc[1].chunks.len() > 1);
+        assert!(doc.chunks.iter().all(|c| !c.text.starts_with("# not")));
+    }
+
+    #[test]
+    fn test_progress_and_partial_export() {
+        let doc = chunk_markdown(BOOK, 1000);
+        let mut translated: Vec<Option<String>> = doc.chunks.iter().map(|_| None).collect();
+        translated[0] = Some("P.\n\n".to_string());
+        translated[2] = Some("# 2\n".to_string());
+        let progress = doc.progress(|i| translated[i].is_some());
+        assert!(progress[0].is_complete());
+        assert!(!progress[1].is_complete());
+        assert_eq!(doc.assemble_complete(&translated), "P.\n\n# 2\n");
+    }
+
+    #[test]
+    fn test_split_text_respects_the_limit() {
+        let text = "Short one.\n\nA long paragraph. It has three sentences! Does it fit?\n\n长句子。第二句。";
+        let chunks = split_text(text, 24);
+        assert_eq!(chunks.concat(), text);
+        assert!(
+            chunks.iter().all(|c| c.chars().count() <= 24),
+            "{:?}",
+            chunks
+        );
+        assert_eq!(chunks[1], "A long paragraph. ");
+        assert_eq!(split_text("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+    }
+
+    struct Upper;
+
+    impl Translator for Upper {
+        fn name(&self) -> &str {
+            "upper"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                assert!(text.chars().count() <= 20 && text.trim() == text);
+                Ok(Translation {
+                    text: text.to_uppercase(),
+                    alternatives: vec!["alt".to_string()],
+                    ..Default::default()
+                })
+            })
+        }
+
+        /// Tags the translation with the formality asked for.
+        fn translate_with_options<'a>(
+            &'a self,
+            text: &'a str,
+            src_lang: &'a str,
+            target_lang: &'a str,
+            options: &'a TranslateOptions,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                let mut t = self.translate(text, src_lang, target_lang).await?;
+                t.text = format!("[{:?}]{}", options.formality, t.text);
+                Ok(t)
+            })
+        }
+    }
+
+    #[test]
+    fn test_chunked_reassembles_in_order() {
+        let text = "First para.\n\nSecond one is longer. It splits.\n\n  Third.\n";
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        for concurrency in [1, 4] {
+            let chunked = Chunked::new(Arc::new(Upper), 20).concurrency(concurrency);
+            let t = runtime
+                .block_on(chunked.translate(text, "EN", "DE"))
+                .unwrap();
+            assert_eq!(t.text, text.to_uppercase());
+            assert_eq!(t.extension("chunks"), Some(&Value::from(4)));
+            assert!(t.alternatives.is_empty());
+        }
+        let short = runtime
+            .block_on(Chunked::new(Arc::new(Upper), 20).translate("hi", "EN", "DE"))
+            .unwrap();
+        assert_eq!(short.alternatives, ["alt"]);
+    }
+
+    #[test]
+    fn test_chunked_sends_options_with_every_chunk() {
+        let text = "First para.\n\nSecond one is longer. It splits.\n\n  Third.\n";
+        let options = TranslateOptions {
+            formality: Some(crate::options::Formality::Formal),
+            ..Default::default()
+        };
+        let t = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(
+                Chunked::new(Arc::new(Upper), 20)
+                    .translate_with_options(text, "EN", "DE", &options),
+            )
+            .unwrap();
+        assert_eq!(t.text.matches("[Some(Formal)]").count(), 4, "{}", t.text);
+    }
+}
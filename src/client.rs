@@ -0,0 +1,1774 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
+};
+
+use reqwest::{
+    header::{
+        HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, COOKIE, RETRY_AFTER, SET_COOKIE,
+    },
+    StatusCode,
+};
+
+#[cfg(all(feature = "coordinator", unix))]
+use crate::coordinator::CoordinatorClient;
+use crate::{
+    anomaly::Thresholds,
+    batch::{self, BatchOptions, BatchResults},
+    breaker::{CircuitBreaker, CircuitState},
+    budget::{Budget, BudgetUsage},
+    build_batch_post_data_frozen,
+    cache::{self, CacheKey, CacheStats, CacheStore, KeyNormalization},
+    capabilities::Capabilities,
+    casing::recase,
+    clock::{self, Instant},
+    cookies::CookieJar,
+    default_headers, diag,
+    error::{parse_retry_after, DeepLError},
+    explain::{self, Api, Explanation},
+    fingerprint::{Fingerprint, FingerprintPool},
+    glossary::Glossary,
+    lang::{self, Detection, SOURCE_LANGS, TARGET_LANGS},
+    limiter::{RateLimiter, Tally},
+    maintenance::{Mode, Switch},
+    middleware::{self, Middleware},
+    official::{self, AuthKey, CreateGlossary, GlossaryInfo, GlossaryList, V2Request, V2Response},
+    options::{Casing, Formality, Splitting, TagHandling, TranslateOptions},
+    preflight::{self, Preflight, TranslateRequest},
+    protect::{mask_markup, unmask},
+    redact::Redaction,
+    retry::RetryPolicy,
+    schedule::Calendar,
+    schema::SchemaWatch,
+    session::SessionPool,
+    telemetry::{Telemetry, TelemetrySink},
+    translator::{BoxFuture, Translation, Translator},
+    validate::Issue,
+    DeepLResponse, DeeplResult, Frozen, JobOptions, RequestStrategy, DEEPL_API, DEEPL_PRO_API,
+};
+
+const MAX_CHARS: usize = 5000;
+/// Characters of the text [`DeepLClient::detect_language`] sends.
+const DETECT_SAMPLE_CHARS: usize = 200;
+/// Texts sent together by [`DeepLClient::translate_batch`].
+const MAX_BATCH_TEXTS: usize = 50;
+
+/// A snapshot of how loaded the client is, for shedding work before it
+/// piles up behind the rate limiter or an open circuit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pressure {
+    /// Permits the rate limiter could hand out right now; `None` without a
+    /// rate limiter.
+    pub available_permits: Option<u32>,
+    /// Requests waiting for a permit.
+    pub queue_depth: usize,
+    pub in_flight: usize,
+    pub circuit: CircuitState,
+}
+
+impl Pressure {
+    /// Time until requests are accepted again, if they are refused now.
+    pub fn cooldown(&self) -> Option<Duration> {
+        match self.circuit {
+            CircuitState::Open { remaining } => Some(remaining),
+            _ => None,
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.cooldown().is_some() || self.available_permits == Some(0)
+    }
+}
+
+/// Where requests to the endpoint go out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ProxyConfig {
+    /// As `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` say.
+    #[default]
+    Env,
+    /// Straight to the endpoint, whatever the environment says.
+    Direct,
+    /// Every request through `url`: `http://`, `https://` or, with the
+    /// `socks` feature, `socks5://` and `socks5h://` (names resolved by the
+    /// proxy). Credentials may also be given in the URL.
+    Url {
+        url: String,
+        auth: Option<(String, String)>,
+    },
+}
+
+impl ProxyConfig {
+    pub fn url(url: impl Into<String>) -> Self {
+        ProxyConfig::Url {
+            url: url.into(),
+            auth: None,
+        }
+    }
+
+    /// `env`, `direct`, or the URL of a proxy, as in the `proxy` setting
+    /// of the config file.
+    pub fn parse(spec: &str) -> Self {
+        match spec {
+            "env" => ProxyConfig::Env,
+            "direct" => ProxyConfig::Direct,
+            url => ProxyConfig::url(url),
+        }
+    }
+
+    /// Logs in to a [`ProxyConfig::Url`] proxy; ignored otherwise.
+    pub fn with_auth(self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        match self {
+            ProxyConfig::Url { url, .. } => ProxyConfig::Url {
+                url,
+                auth: Some((username.into(), password.into())),
+            },
+            other => other,
+        }
+    }
+}
+
+/// Rejects codes DeepL would not translate before a request is spent on
+/// them. Regional variants of a supported code (`ZH-TW`) are let through.
+fn check_langs(src_lang: &str, target_lang: &str) -> Result<(), DeepLError> {
+    let supported = |langs: &[&str], lang: &str| langs.iter().any(|l| lang::matches(l, lang));
+    if !lang::is_auto(src_lang) && !supported(SOURCE_LANGS, src_lang) {
+        return Err(DeepLError::InvalidLanguage {
+            lang: src_lang.to_string(),
+        });
+    }
+    if !supported(TARGET_LANGS, target_lang) {
+        return Err(DeepLError::InvalidLanguage {
+            lang: target_lang.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// A DeepL client meant to be built once and reused: it owns the HTTP
+/// connection pool, the request headers and the limiter, breaker and
+/// logging settings. Clones share all of them.
+#[derive(Clone, Debug)]
+pub struct DeepLClient {
+    pub(crate) http: reqwest::Client,
+    headers: HeaderMap,
+    /// How `http` was built, unless it was given.
+    proxy: Option<ProxyConfig>,
+    #[cfg(not(target_arch = "wasm32"))]
+    connect_timeout: Option<Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    timeout: Option<Duration>,
+    endpoint: String,
+    limiter: Option<Arc<RateLimiter>>,
+    #[cfg(all(feature = "coordinator", unix))]
+    coordinator: Option<Arc<CoordinatorClient>>,
+    breaker: Option<Arc<CircuitBreaker>>,
+    retry: Option<RetryPolicy>,
+    pub(crate) sessions: Option<Arc<SessionPool>>,
+    fingerprints: Option<Arc<FingerprintPool>>,
+    cookies: Option<Arc<CookieJar>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    strategy: RequestStrategy,
+    alternatives: u32,
+    formality: Option<Formality>,
+    auth_key: Option<AuthKey>,
+    glossary_id: Option<String>,
+    tag_handling: Option<TagHandling>,
+    casing: Option<Casing>,
+    splitting: Option<Splitting>,
+    switch: Option<Switch>,
+    budget: Option<Arc<Budget>>,
+    calendar: Option<(Arc<Calendar>, String)>,
+    cache: Option<Arc<dyn CacheStore>>,
+    cache_normalization: KeyNormalization,
+    max_staleness: Option<Duration>,
+    priority: bool,
+    in_flight: Arc<AtomicUsize>,
+    redaction: Redaction,
+    anomalies: Thresholds,
+    schema: Arc<SchemaWatch>,
+    telemetry: Telemetry,
+}
+
+impl Default for DeepLClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeepLClient {
+    pub fn new() -> Self {
+        Self::with_endpoint(DEEPL_API)
+    }
+
+    pub fn with_endpoint(endpoint: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            headers: default_headers(),
+            proxy: Some(ProxyConfig::Env),
+            #[cfg(not(target_arch = "wasm32"))]
+            connect_timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            timeout: None,
+            endpoint: endpoint.into(),
+            limiter: None,
+            #[cfg(all(feature = "coordinator", unix))]
+            coordinator: None,
+            breaker: None,
+            retry: None,
+            sessions: None,
+            fingerprints: None,
+            cookies: None,
+            middleware: Vec::new(),
+            strategy: RequestStrategy::default(),
+            alternatives: 0,
+            formality: None,
+            auth_key: None,
+            glossary_id: None,
+            tag_handling: None,
+            casing: None,
+            splitting: None,
+            switch: None,
+            budget: None,
+            calendar: None,
+            cache: None,
+            cache_normalization: KeyNormalization::default(),
+            max_staleness: None,
+            priority: false,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            redaction: Redaction::default(),
+            anomalies: Thresholds::default(),
+            schema: Arc::new(SchemaWatch::new()),
+            telemetry: Telemetry::default(),
+        }
+    }
+
+    /// A client as `config` says: its endpoint, proxy, timeouts and
+    /// retries, and an in-memory cache when it has a size. Fails like
+    /// [`with_proxy`](Self::with_proxy).
+    #[cfg(all(feature = "config", not(target_arch = "wasm32")))]
+    pub fn from_config(config: &crate::config::Config) -> Result<Self, DeepLError> {
+        let mut client = Self::with_endpoint(config.endpoint.as_deref().unwrap_or(DEEPL_API));
+        client.connect_timeout = config.timeouts.connect.map(Duration::from_secs);
+        client.timeout = config.timeouts.request.map(Duration::from_secs);
+        let proxy = config
+            .proxy
+            .as_deref()
+            .map_or(ProxyConfig::Env, ProxyConfig::parse);
+        let mut client = client.with_proxy(proxy)?;
+        if let Some(retries) = config.retries {
+            client = client.with_retry(RetryPolicy {
+                max_attempts: retries.saturating_add(1),
+                ..RetryPolicy::default()
+            });
+        }
+        if let Some(size) = config.cache.size.filter(|&size| size > 0) {
+            let mut cache = cache::TranslationCache::new(size);
+            if let Some(ttl) = config.cache.ttl {
+                cache = cache.with_ttl(Duration::from_secs(ttl));
+            }
+            client = client.with_cache(Arc::new(cache));
+        }
+        Ok(client)
+    }
+
+    /// The client behind [`deepl_translate`](crate::deepl_translate),
+    /// shared by the whole process.
+    pub(crate) fn shared() -> &'static Self {
+        static SHARED: OnceLock<DeepLClient> = OnceLock::new();
+        SHARED.get_or_init(Self::new)
+    }
+
+    /// Sends requests through `http`, for custom timeouts, proxies or
+    /// connection pool settings.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self.proxy = None;
+        self
+    }
+
+    /// Sends requests through `proxy`, replacing any client given to
+    /// [`with_http_client`](Self::with_http_client). Fails on a malformed
+    /// proxy URL or an unsupported scheme. Not on wasm32, where the
+    /// browser picks the proxy.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_proxy(self, proxy: ProxyConfig) -> Result<Self, DeepLError> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let builder = match proxy.clone() {
+            ProxyConfig::Env => builder,
+            ProxyConfig::Direct => builder.no_proxy(),
+            ProxyConfig::Url { url, auth } => {
+                let mut proxy = reqwest::Proxy::all(url)?;
+                if let Some((username, password)) = auth {
+                    proxy = proxy.basic_auth(&username, &password);
+                }
+                builder.proxy(proxy)
+            }
+        };
+        let mut client = self.with_http_client(builder.build()?);
+        client.proxy = Some(proxy);
+        Ok(client)
+    }
+
+    /// Gives up connecting after `connect` and on requests after
+    /// `request`, the response included, rather than waiting for as long
+    /// as the system does. Like [`with_proxy`](Self::with_proxy), whose
+    /// proxy it keeps, it replaces any client given to
+    /// [`with_http_client`](Self::with_http_client).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_timeouts(
+        mut self,
+        connect: Option<Duration>,
+        request: Option<Duration>,
+    ) -> Result<Self, DeepLError> {
+        self.connect_timeout = connect;
+        self.timeout = request;
+        let proxy = self.proxy.clone().unwrap_or_default();
+        self.with_proxy(proxy)
+    }
+
+    /// Waits for a permit from `limiter` before every request, retries
+    /// included. Clones of the client share it.
+    pub fn with_rate_limiter(self, limiter: RateLimiter) -> Self {
+        self.with_shared_rate_limiter(Arc::new(limiter))
+    }
+
+    /// Like [`with_rate_limiter`](Self::with_rate_limiter), for one
+    /// limiter shared by clients built separately, such as one per
+    /// session token.
+    pub fn with_shared_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Some(Arc::new(breaker));
+        self
+    }
+
+    /// Retries failed requests as `policy` says. Each attempt waits for the
+    /// rate limiter and counts towards the circuit breaker.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Sends a `dl_session` cookie from `pool` with every request, to
+    /// [`DEEPL_PRO_API`] unless the client was built
+    /// [`with_endpoint`](Self::with_endpoint) another one. A token
+    /// rejected with 401 is taken out of rotation and the request is sent
+    /// again with the next one.
+    pub fn with_session_pool(mut self, pool: Arc<SessionPool>) -> Self {
+        if self.endpoint == DEEPL_API {
+            self.endpoint = DEEPL_PRO_API.to_string();
+        }
+        self.sessions = Some(pool);
+        self
+    }
+
+    /// Sends each web request with the app, OS and device headers of the
+    /// next fingerprint from `pool` instead of always the same ones.
+    /// Clones of the client share the pool's rotation.
+    pub fn with_fingerprints(mut self, pool: Arc<FingerprintPool>) -> Self {
+        self.fingerprints = Some(pool);
+        self
+    }
+
+    /// Keeps the cookies web responses set in `jar` and sends them back
+    /// with later requests, as the app does within a session. Clones of
+    /// the client share the jar; [`CookieJar::save`] keeps it for the next
+    /// run.
+    pub fn with_cookie_jar(mut self, jar: Arc<CookieJar>) -> Self {
+        self.cookies = Some(jar);
+        self
+    }
+
+    /// Runs `middleware` around every translation request, inside any
+    /// registered before it; see [`middleware`](crate::middleware).
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Uses the Pro web account behind the `dl_session` cookie `token`,
+    /// like DeepLX does given one.
+    pub fn with_dl_session(self, token: impl Into<String>) -> Self {
+        self.with_session_pool(Arc::new(SessionPool::new([token.into()])))
+    }
+
+    /// The tone of every translation into languages that have a formal
+    /// and an informal register, such as German, French and Japanese.
+    /// Overridden per request through
+    /// [`translate_with_options`](Translator::translate_with_options).
+    pub fn with_formality(mut self, formality: Option<Formality>) -> Self {
+        self.formality = formality;
+        self
+    }
+
+    /// Translates through the official DeepL API with `auth_key` instead
+    /// of the web endpoint: `api-free.deepl.com` for Free plan keys and
+    /// `api.deepl.com` otherwise, unless the client was built
+    /// [`with_endpoint`](Self::with_endpoint) another one. Session tokens,
+    /// request strategies and alternatives do not apply there.
+    pub fn with_auth_key(mut self, auth_key: impl Into<String>) -> Self {
+        let auth_key = auth_key.into();
+        if self.endpoint == DEEPL_API {
+            self.endpoint = official::endpoint_for(&auth_key).to_string();
+        }
+        self.auth_key = Some(AuthKey(auth_key.trim().to_string()));
+        self
+    }
+
+    /// Applies the glossary DeepL stores as `glossary_id`, from
+    /// [`create_glossary`](Self::create_glossary), to translations
+    /// through the official API. The web endpoint has no glossaries; wrap
+    /// the client in [`Enforced`](crate::glossary::Enforced) there.
+    pub fn with_glossary_id(mut self, glossary_id: Option<String>) -> Self {
+        self.glossary_id = glossary_id;
+        self
+    }
+
+    /// Treats texts as HTML or XML, so translations keep their markup. See
+    /// [`TagHandling`] for how the two backends differ.
+    pub fn with_tag_handling(mut self, tag_handling: Option<TagHandling>) -> Self {
+        self.tag_handling = tag_handling;
+        self
+    }
+
+    /// Recases every translation, and its alternatives, after it comes
+    /// back; see [`recase`].
+    pub fn with_casing(mut self, casing: Option<Casing>) -> Self {
+        self.casing = casing;
+        self
+    }
+
+    /// Where DeepL splits every text into segments; line breaks and
+    /// sentence ends when `None`. The official API has no paragraph mode
+    /// and splits those texts at sentence ends and line breaks too.
+    pub fn with_splitting(mut self, splitting: Option<Splitting>) -> Self {
+        self.splitting = splitting;
+        self
+    }
+
+    /// Stops sending anything upstream while `switch` is not in
+    /// [`Mode::Normal`], answering only from the cache; see
+    /// [`maintenance`](crate::maintenance).
+    pub fn with_switch(mut self, switch: Switch) -> Self {
+        self.switch = Some(switch);
+        self
+    }
+
+    fn mode(&self) -> Mode {
+        self.switch.as_ref().map_or(Mode::Normal, Switch::mode)
+    }
+
+    /// Refuses upstream calls while the switch is off.
+    pub(crate) fn upstream_open(&self) -> Result<(), DeepLError> {
+        match self.mode() {
+            Mode::Normal => Ok(()),
+            mode => Err(DeepLError::Paused { mode }),
+        }
+    }
+
+    /// Books every request's characters against `budget`, which clients
+    /// and servers of one deployment can share.
+    pub fn with_budget(mut self, budget: Arc<Budget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Sends requests only when `calendar` admits them for `identity`, the
+    /// host, proxy or session this client goes through: in its quiet
+    /// hours they fail with [`DeepLError::RateLimited`] and past its
+    /// character budget with [`DeepLError::BudgetExceeded`]. A
+    /// [`Scheduler`](crate::schedule::Scheduler) given the same calendar
+    /// defers its jobs instead.
+    pub fn with_calendar(mut self, calendar: Arc<Calendar>, identity: impl Into<String>) -> Self {
+        self.calendar = Some((calendar, identity.into()));
+        self
+    }
+
+    /// Lets requests through past the budget's hard limit, for the work
+    /// that must go on when batch jobs have spent the budget.
+    pub fn with_priority(mut self, priority: bool) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn budget_usage(&self) -> Option<BudgetUsage> {
+        self.budget.as_ref().map(|b| b.usage())
+    }
+
+    /// Answers repeated [`translate`](Translator::translate) calls from
+    /// `cache`, a [`TranslationCache`](crate::cache::TranslationCache) in
+    /// memory or a persistent [`StorageCache`](crate::cache::StorageCache),
+    /// instead of sending them again. Clients with different settings can
+    /// share one cache; their entries are kept apart. A cache that fails
+    /// is skipped with a warning.
+    pub fn with_cache(mut self, cache: Arc<dyn CacheStore>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Lets texts that differ only as `normalization` allows share a
+    /// cache entry; see [`KeyNormalization`] for what a hit returns then.
+    pub fn with_cache_normalization(mut self, normalization: KeyNormalization) -> Self {
+        self.cache_normalization = normalization;
+        self
+    }
+
+    /// Answers from a cache entry that expired at most `max_staleness`
+    /// ago, marked with the `stale` extension, when the upstream cannot
+    /// be reached, times out, refuses to serve or the circuit breaker is
+    /// open, instead of failing. Has no effect without a cache.
+    pub fn serve_stale(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+
+    /// Waits for a permit from the [`coordinator`](crate::coordinator)
+    /// before every request, like a rate limiter shared with other
+    /// processes, and uses it as the cache, replacing any given to
+    /// [`with_cache`](Self::with_cache). Requests go ahead with a warning
+    /// while the coordinator cannot be reached.
+    #[cfg(all(feature = "coordinator", unix))]
+    pub fn with_coordinator(mut self, coordinator: Arc<CoordinatorClient>) -> Self {
+        self.cache = Some(coordinator.clone());
+        self.coordinator = Some(coordinator);
+        self
+    }
+
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|c| c.stats())
+    }
+
+    /// Reports upstream requests, their latency, outcome and response
+    /// status, translated characters, cache hits, misses and hit rate, requests
+    /// past the budget's soft limit, budget refusals and rotated session tokens to `sink`.
+    pub fn with_telemetry(mut self, sink: Arc<dyn TelemetrySink>) -> Self {
+        self.telemetry = Telemetry::new(sink);
+        self
+    }
+
+    pub fn session_pool(&self) -> Option<&Arc<SessionPool>> {
+        self.sessions.as_ref()
+    }
+
+    /// How request bodies are spaced; see [`experiment`](crate::experiment)
+    /// for finding out which works best at the moment.
+    pub fn with_strategy(mut self, strategy: RequestStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Asks DeepL for up to `n` alternative translations of every text,
+    /// besides the main one. Defaults to none.
+    pub fn with_alternatives(mut self, n: u32) -> Self {
+        self.alternatives = n;
+        self
+    }
+
+    /// How source texts and translations appear in log events. Defaults
+    /// to [`Redaction::Full`].
+    pub fn with_redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    /// When successful responses are logged as warnings for being slow,
+    /// oddly sized or coming with low detection confidence.
+    pub fn with_anomaly_thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.anomalies = thresholds;
+        self
+    }
+
+    pub fn pressure(&self) -> Pressure {
+        Pressure {
+            available_permits: self.limiter.as_ref().map(|l| l.available()),
+            queue_depth: self.limiter.as_ref().map_or(0, |l| l.waiting()),
+            in_flight: self.in_flight.load(Ordering::SeqCst),
+            circuit: self
+                .breaker
+                .as_ref()
+                .map_or(CircuitState::Closed, |b| b.state()),
+        }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Responses whose fields differed from what this crate expects.
+    pub fn schema_drifts(&self) -> u64 {
+        self.schema.drifted()
+    }
+
+    /// Sends a translation request, retried under the client's
+    /// [`RetryPolicy`], and returns the JSON-RPC response as DeepL sent it.
+    pub async fn translate_raw(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<DeepLResponse, DeepLError> {
+        self.request(&[text], src_lang, target_lang).await
+    }
+
+    /// Translates `text` asking for up to `n` alternatives, whatever this
+    /// client was configured with. [`Translation::candidates`] lists the
+    /// main translation followed by the alternatives, best first.
+    pub async fn translate_with_alternatives(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+        n: u32,
+    ) -> Result<Translation, DeepLError> {
+        self.clone()
+            .with_alternatives(n)
+            .translate_cased(text, src_lang, target_lang)
+            .await
+    }
+
+    /// [`translate_cached`](Self::translate_cached) in the client's casing.
+    async fn translate_cased(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Translation, DeepLError> {
+        let mut translation = self.translate_cached(text, src_lang, target_lang).await?;
+        self.recase(text, &mut translation, target_lang);
+        Ok(translation)
+    }
+
+    /// Applies the client's [`Casing`], if any, to the translation of
+    /// `text`.
+    fn recase(&self, text: &str, translation: &mut Translation, target_lang: &str) {
+        if let Some(casing) = self.casing {
+            translation.text = recase(casing, text, &translation.text, target_lang);
+            for alternative in &mut translation.alternatives {
+                *alternative = recase(casing, text, alternative, target_lang);
+            }
+        }
+    }
+
+    /// [`translate_raw`](Self::translate_raw) through the cache, if the
+    /// client has one.
+    async fn translate_cached(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Translation, DeepLError> {
+        let Some(cache) = &self.cache else {
+            return self
+                .translate_raw(text, src_lang, target_lang)
+                .await
+                .map(Translation::from);
+        };
+        let (key, placeholders) = self.cache_key(text, src_lang, target_lang);
+        if let Some(hit) = self.cache_lookup(cache, &key, &placeholders).await {
+            return Ok(hit);
+        }
+        let translation = match self.translate_raw(text, src_lang, target_lang).await {
+            Ok(response) => Translation::from(response),
+            Err(e) if e.is_unavailable() && self.max_staleness.is_some() => {
+                let stale = match cache.get_stale(&key, self.max_staleness).await {
+                    Ok(Some(hit)) if placeholders.is_empty() => Some(hit),
+                    Ok(Some(hit)) => cache::fill(hit, &placeholders),
+                    Ok(None) => None,
+                    Err(e) => {
+                        diag::log_warn!("translation cache lookup failed: {}", e);
+                        None
+                    }
+                };
+                let Some(stale) = stale else {
+                    return Err(e);
+                };
+                diag::log_warn!("upstream unavailable ({}), serving a stale result", e);
+                self.telemetry.counter("deeplx_cache_stale_total", 1, &[]);
+                return Ok(stale);
+            }
+            Err(e) => return Err(e),
+        };
+        Self::cache_store(cache, &key, &translation, &placeholders).await;
+        Ok(translation)
+    }
+
+    /// The cache key of `text` under the client's current settings, and
+    /// the placeholders the key no longer names.
+    fn cache_key(&self, text: &str, src_lang: &str, target_lang: &str) -> (CacheKey, Vec<String>) {
+        // Everything that can change the result for the same text.
+        let options = format!(
+            "{}|{:?}|{}|{:?}|{:?}|{:?}",
+            self.auth_key.is_some(),
+            self.formality,
+            self.alternatives,
+            self.glossary_id,
+            self.tag_handling,
+            self.splitting
+        );
+        let normalization = &self.cache_normalization;
+        let key = CacheKey::normalized(text, src_lang, target_lang, &options, normalization);
+        (key, normalization.placeholders(text))
+    }
+
+    /// The cached translation under `key`, with `placeholders` filled in.
+    async fn cache_lookup(
+        &self,
+        cache: &Arc<dyn CacheStore>,
+        key: &CacheKey,
+        placeholders: &[String],
+    ) -> Option<Translation> {
+        let lookup = match self.mode() {
+            Mode::Maintenance => cache.get_stale(key, None).await,
+            _ => cache.get(key).await,
+        };
+        // Entries that do not fit the placeholders and failed lookups are
+        // misses too: the text goes upstream either way.
+        let found = match lookup {
+            Ok(Some(hit)) if placeholders.is_empty() => Some(hit),
+            Ok(Some(hit)) => {
+                let filled = cache::fill(hit, placeholders);
+                if filled.is_none() {
+                    diag::log_debug!("cached translation does not fit the placeholders");
+                }
+                filled
+            }
+            Ok(None) => None,
+            Err(e) => {
+                diag::log_warn!("translation cache lookup failed: {}", e);
+                None
+            }
+        };
+        let (result, counter) = match found {
+            Some(_) => ("hit", "deeplx_cache_hits_total"),
+            None => ("miss", "deeplx_cache_misses_total"),
+        };
+        self.telemetry
+            .counter("deeplx_cache_lookups_total", 1, &[("result", result)]);
+        self.telemetry.counter(counter, 1, &[]);
+        self.telemetry
+            .gauge("deeplx_cache_hit_ratio", cache.stats().hit_rate(), &[]);
+        found
+    }
+
+    /// Keeps `translation` under `key`, as a template if the key stands
+    /// for `placeholders`.
+    async fn cache_store(
+        cache: &Arc<dyn CacheStore>,
+        key: &CacheKey,
+        translation: &Translation,
+        placeholders: &[String],
+    ) {
+        let templated;
+        let stored = if placeholders.is_empty() {
+            Some(translation)
+        } else {
+            templated = cache::template(translation, placeholders);
+            templated.as_ref()
+        };
+        if let Some(stored) = stored {
+            if let Err(e) = cache.put(key, stored).await {
+                diag::log_warn!("translation cache write failed: {}", e);
+            }
+        }
+    }
+
+    /// Checks `request` against the languages and length limit of DeepL
+    /// without sending it.
+    pub fn validate(&self, request: &TranslateRequest) -> Preflight {
+        preflight::check(request, &self.capabilities())
+    }
+
+    /// The request [`translate_with_options`](Translator::translate_with_options)
+    /// would send for `request` now, and why it looks as it does; nothing
+    /// is sent, so [`Middleware`] does not see it. The fingerprint
+    /// rotation moves on as if it had been.
+    pub fn explain(&self, request: &TranslateRequest) -> Result<Explanation, DeepLError> {
+        check_langs(&request.source_lang, &request.target_lang)?;
+        let mut client = self.clone();
+        if let Some(formality) = request.formality {
+            client = client.with_formality(Some(formality));
+        }
+        if let Some(tag_handling) = &request.tag_handling {
+            client = client.with_tag_handling(Some(tag_handling.clone()));
+        }
+        if let Some(splitting) = request.splitting {
+            client = client.with_splitting(Some(splitting));
+        }
+        client.explain_request(&request.text, &request.source_lang, &request.target_lang)
+    }
+
+    /// The proxy URL every request goes through, if one was given,
+    /// credentials left out.
+    pub(crate) fn proxy_url(&self) -> Option<String> {
+        let Some(ProxyConfig::Url { url, auth }) = &self.proxy else {
+            return None;
+        };
+        let mut shown = match reqwest::Url::parse(url) {
+            Ok(mut url) => {
+                let _ = url.set_password(None);
+                url.to_string()
+            }
+            Err(_) => url.clone(),
+        };
+        if let Some((username, _)) = auth {
+            shown = format!("{} as {}", shown, username);
+        }
+        Some(shown)
+    }
+
+    fn explain_request(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Explanation, DeepLError> {
+        let proxy = match &self.proxy {
+            Some(ProxyConfig::Env) => "from the environment".to_string(),
+            Some(ProxyConfig::Direct) => "none".to_string(),
+            Some(ProxyConfig::Url { .. }) => self.proxy_url().unwrap_or_default(),
+            None => "as the given HTTP client decides".to_string(),
+        };
+        let headers = |map: &HeaderMap| {
+            map.iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes());
+                    (
+                        name.to_string(),
+                        explain::redact_header(name.as_str(), &value),
+                    )
+                })
+                .collect()
+        };
+        let texts = [text];
+        if let Some(AuthKey(key)) = &self.auth_key {
+            let (map, body) = self.official_request(key, &texts, src_lang, target_lang)?;
+            return Ok(Explanation {
+                api: Api::Official,
+                url: self.endpoint.clone(),
+                headers: headers(&map),
+                body,
+                id: None,
+                timestamp: None,
+                strategy: None,
+                spacing: None,
+                proxy,
+                session: None,
+                fingerprint: None,
+                masked_markup: false,
+            });
+        }
+        let session = match &self.sessions {
+            Some(pool) => Some(pool.pick().ok_or(DeepLError::NoProvider)?),
+            None => None,
+        };
+        let masked = self
+            .tag_handling
+            .as_ref()
+            .map(|handling| mask_markup(text, &handling.ignore_tags).text);
+        let fingerprint = self.fingerprints.as_ref().map(|pool| pool.pick());
+        let frozen = Frozen::now();
+        let (map, body) = self.web_request(
+            session.as_ref().map(|(_, token)| token.as_str()),
+            fingerprint,
+            frozen,
+            &[masked.as_deref().unwrap_or(text)],
+            src_lang,
+            target_lang,
+        );
+        let timestamp =
+            serde_json::from_str::<serde_json::Value>(&body)?["params"]["timestamp"].as_u64();
+        Ok(Explanation {
+            api: Api::Web,
+            url: self.endpoint.clone(),
+            headers: headers(&map),
+            body,
+            id: Some(frozen.id),
+            timestamp,
+            strategy: Some(self.strategy),
+            spacing: Some(self.strategy.resolve(frozen.id)),
+            proxy,
+            session: session.map(|(index, _)| index),
+            fingerprint: fingerprint.cloned(),
+            masked_markup: masked.is_some(),
+        })
+    }
+
+    /// Detects the language of `text`. DeepL has no call for detection
+    /// alone, so the first 200 characters are translated into English and
+    /// the translation is thrown away.
+    pub async fn detect_language(&self, text: &str) -> Result<Detection, DeepLError> {
+        let sample = match text.char_indices().nth(DETECT_SAMPLE_CHARS) {
+            Some((end, _)) => &text[..end],
+            None => text,
+        };
+        let result = self.translate_raw(sample, "auto", "EN").await?.result;
+        if result.lang.is_empty() {
+            return Err(DeepLError::Deserialize(serde::de::Error::custom(
+                "no language detected",
+            )));
+        }
+        Ok(Detection {
+            lang: result.lang,
+            confident: result.lang_is_confident,
+            scores: result.detected_languages,
+        })
+    }
+
+    /// Translates each of `texts` with a request of its own,
+    /// [`BatchOptions::concurrency`] at a time, keeping every text's result
+    /// or failure in input order.
+    pub async fn translate_many<I>(&self, texts: I, options: &BatchOptions) -> BatchResults
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        batch::translate_many(self, texts, options).await
+    }
+
+    /// Translates every text in `texts` with as few requests as the size
+    /// limit allows, returning the results in input order. With a cache,
+    /// only the texts it cannot answer are sent, and their translations
+    /// are kept as [`translate`](Translator::translate) keeps them. The
+    /// client's casing applies to each text as it does there.
+    pub async fn translate_batch(
+        &self,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Vec<Translation>, DeepLError> {
+        let mut out = match &self.cache {
+            Some(cache) => {
+                self.batch_cached(cache, texts, src_lang, target_lang)
+                    .await?
+            }
+            None => self.send_batch(texts, src_lang, target_lang).await?,
+        };
+        for (text, translation) in texts.iter().zip(&mut out) {
+            self.recase(text, translation, target_lang);
+        }
+        Ok(out)
+    }
+
+    async fn batch_cached(
+        &self,
+        cache: &Arc<dyn CacheStore>,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Vec<Translation>, DeepLError> {
+        let mut keys = Vec::with_capacity(texts.len());
+        let mut out = Vec::with_capacity(texts.len());
+        for text in texts {
+            let (key, placeholders) = self.cache_key(text, src_lang, target_lang);
+            out.push(self.cache_lookup(cache, &key, &placeholders).await);
+            keys.push((key, placeholders));
+        }
+        let misses: Vec<&str> = texts
+            .iter()
+            .zip(&out)
+            .filter(|(_, hit)| hit.is_none())
+            .map(|(text, _)| *text)
+            .collect();
+        if !misses.is_empty() {
+            let mut fresh = self
+                .send_batch(&misses, src_lang, target_lang)
+                .await?
+                .into_iter();
+            for (slot, (key, placeholders)) in out.iter_mut().zip(&keys) {
+                if slot.is_some() {
+                    continue;
+                }
+                let Some(translation) = fresh.next() else {
+                    break;
+                };
+                Self::cache_store(cache, key, &translation, placeholders).await;
+                *slot = Some(translation);
+            }
+        }
+        Ok(out.into_iter().flatten().collect())
+    }
+
+    /// [`translate_batch`](Self::translate_batch) without the cache.
+    async fn send_batch(
+        &self,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Vec<Translation>, DeepLError> {
+        let mut out = Vec::with_capacity(texts.len());
+        let mut start = 0;
+        while start < texts.len() {
+            let mut end = start + 1;
+            let mut chars = texts[start].chars().count();
+            while end < texts.len() && end - start < MAX_BATCH_TEXTS {
+                chars += texts[end].chars().count();
+                if chars > MAX_CHARS {
+                    break;
+                }
+                end += 1;
+            }
+            let chunk = &texts[start..end];
+            let resp = self.request(chunk, src_lang, target_lang).await?;
+            if resp.result.texts.len() != chunk.len() {
+                return Err(DeepLError::Deserialize(serde::de::Error::custom(format!(
+                    "expected {} texts, got {}",
+                    chunk.len(),
+                    resp.result.texts.len()
+                ))));
+            }
+            let DeepLResponse {
+                jsonrpc,
+                id,
+                result,
+            } = resp;
+            for text in result.texts {
+                out.push(Translation::from(DeepLResponse {
+                    jsonrpc: jsonrpc.clone(),
+                    id,
+                    result: DeeplResult {
+                        texts: vec![text],
+                        lang: result.lang.clone(),
+                        lang_is_confident: result.lang_is_confident,
+                        detected_languages: result.detected_languages.clone(),
+                    },
+                }));
+            }
+            start = end;
+        }
+        Ok(out)
+    }
+
+    /// One request for `texts`, retried under the client's [`RetryPolicy`].
+    async fn request(
+        &self,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<DeepLResponse, DeepLError> {
+        self.upstream_open()?;
+        let chars = texts.iter().map(|t| t.chars().count() as u64).sum();
+        if let Some((calendar, identity)) = &self.calendar {
+            calendar.admit(identity, chars)?;
+        }
+        if let Some(budget) = &self.budget {
+            match budget.book(chars, self.priority) {
+                Ok(false) => {}
+                Ok(true) => self
+                    .telemetry
+                    .counter("deeplx_budget_over_soft_limit_total", 1, &[]),
+                Err(e) => {
+                    // Nothing went out, so the identity keeps its characters.
+                    if let Some((calendar, identity)) = &self.calendar {
+                        calendar.release(identity, chars);
+                    }
+                    self.telemetry.event("deeplx_budget_exceeded", &[]);
+                    return Err(e);
+                }
+            }
+        }
+        let mut attempt = 1;
+        loop {
+            let result = self.attempt(texts, src_lang, target_lang).await;
+            let delay = match (&result, &self.retry) {
+                (Err(e), Some(policy)) => policy.delay(attempt, e),
+                _ => None,
+            };
+            let Some(delay) = delay else {
+                return result;
+            };
+            diag::log_warn!("deepl attempt {} failed, retrying in {:?}", attempt, delay);
+            clock::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn attempt(
+        &self,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<DeepLResponse, DeepLError> {
+        if let Some(breaker) = &self.breaker {
+            if let CircuitState::Open { remaining } = breaker.state() {
+                return Err(DeepLError::RateLimited {
+                    retry_after: Some(remaining),
+                });
+            }
+        }
+        check_langs(src_lang, target_lang)?;
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+        #[cfg(all(feature = "coordinator", unix))]
+        if let Some(coordinator) = &self.coordinator {
+            if let Err(e) = coordinator.permit().await {
+                diag::log_warn!(
+                    "coordinator at {} unreachable, sending without a permit: {}",
+                    coordinator.path().display(),
+                    e
+                );
+            }
+        }
+
+        diag::log_debug!(
+            "deepl request {}->{}: {}",
+            src_lang,
+            target_lang,
+            self.redaction.apply(&texts.join("\n"))
+        );
+        let in_flight = Tally::new(&self.in_flight);
+        let started = Instant::now();
+        let result = self.send(texts, src_lang, target_lang).await;
+        let elapsed = started.elapsed();
+        drop(in_flight);
+        let outcome = result.as_ref().map_or_else(DeepLError::kind, |_| "ok");
+        self.telemetry
+            .counter("deeplx_upstream_requests_total", 1, &[("outcome", outcome)]);
+        self.telemetry.histogram(
+            "deeplx_upstream_request_seconds",
+            elapsed.as_secs_f64(),
+            &[],
+        );
+        if result.is_ok() {
+            let chars = texts.iter().map(|t| t.chars().count() as u64).sum();
+            self.telemetry
+                .counter("deeplx_upstream_chars_total", chars, &[]);
+        }
+        match &result {
+            Ok(resp) => {
+                let translated: Vec<&str> =
+                    resp.result.texts.iter().map(|t| t.text.as_str()).collect();
+                diag::log_debug!(
+                    "deepl response ({} texts, lang {}): {}",
+                    resp.result.texts.len(),
+                    resp.result.lang,
+                    self.redaction.apply(&translated.join("\n"))
+                );
+                let confidence = resp.result.detected_languages.get(&resp.result.lang);
+                for (text, translated) in texts.iter().zip(translated) {
+                    for anomaly in
+                        self.anomalies
+                            .check(text, translated, confidence.copied(), elapsed)
+                    {
+                        diag::log_warn!("deepl {}->{}: {}", src_lang, target_lang, anomaly);
+                    }
+                }
+            }
+            Err(e) => {
+                diag::log_warn!("deepl request failed: {}", e);
+                if let Some(anomaly) = self.anomalies.check_latency(elapsed) {
+                    diag::log_warn!("deepl {}->{}: {}", src_lang, target_lang, anomaly);
+                }
+            }
+        }
+
+        if let Some(breaker) = &self.breaker {
+            match &result {
+                Err(DeepLError::Network(_))
+                | Err(DeepLError::RateLimited { .. })
+                | Err(DeepLError::Blocked { .. })
+                | Err(DeepLError::ChallengeRequired { .. }) => breaker.record_failure(),
+                Err(DeepLError::Status { status, .. }) if *status >= 500 => {
+                    breaker.record_failure()
+                }
+                _ => breaker.record_success(),
+            }
+        }
+        result
+    }
+
+    async fn send(
+        &self,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<DeepLResponse, DeepLError> {
+        if let Some(AuthKey(key)) = &self.auth_key {
+            return self.send_official(key, texts, src_lang, target_lang).await;
+        }
+        let Some(handling) = &self.tag_handling else {
+            return self.send_web(texts, src_lang, target_lang).await;
+        };
+        // The web endpoint would translate markup like text, so it only
+        // ever sees sentinels in its place.
+        let masked: Vec<_> = texts
+            .iter()
+            .map(|text| mask_markup(text, &handling.ignore_tags))
+            .collect();
+        let masked_texts: Vec<&str> = masked.iter().map(|m| m.text.as_str()).collect();
+        let mut resp = self.send_web(&masked_texts, src_lang, target_lang).await?;
+        for (segment, (text, masked)) in resp.result.texts.iter_mut().zip(&masked).enumerate() {
+            text.text =
+                unmask(&text.text, &masked.tokens).map_err(|e| DeepLError::ValidationFailed {
+                    segment,
+                    issues: vec![Issue::BrokenSentinel(e)],
+                })?;
+            text.alternatives
+                .retain_mut(|a| match unmask(&a.text, &masked.tokens) {
+                    Ok(unmasked) => {
+                        a.text = unmasked;
+                        true
+                    }
+                    Err(_) => false,
+                });
+        }
+        Ok(resp)
+    }
+
+    /// [`send`](Self::send) to the web endpoint, rotating through the
+    /// session pool if there is one.
+    async fn send_web(
+        &self,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<DeepLResponse, DeepLError> {
+        let Some(pool) = &self.sessions else {
+            return self.send_as(None, texts, src_lang, target_lang).await;
+        };
+        let mut rejected = None;
+        for _ in 0..pool.len() {
+            let Some((index, token)) = pool.pick() else {
+                break;
+            };
+            let result = self
+                .send_as(Some(&token), texts, src_lang, target_lang)
+                .await;
+            match &result {
+                Ok(_) => pool.record_use(index, texts.iter().map(|t| t.chars().count()).sum()),
+                Err(DeepLError::Status { status: 401, .. })
+                | Err(DeepLError::ChallengeRequired { .. }) => {
+                    diag::log_warn!("deepl rejected dl_session {}, rotating", index);
+                    pool.invalidate(index);
+                    self.telemetry.event(
+                        "deeplx_session_invalidated",
+                        &[("session", &index.to_string())],
+                    );
+                    rejected = Some(result);
+                    continue;
+                }
+                Err(_) => pool.record_failure(index),
+            }
+            return result;
+        }
+        // Every token was rejected, now or recently.
+        rejected.unwrap_or(Err(DeepLError::NoProvider))
+    }
+
+    pub(crate) async fn send_as(
+        &self,
+        session: Option<&str>,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<DeepLResponse, DeepLError> {
+        let fingerprint = self.fingerprints.as_ref().map(|pool| pool.pick());
+        let (headers, body) = self.web_request(
+            session,
+            fingerprint,
+            Frozen::now(),
+            texts,
+            src_lang,
+            target_lang,
+        );
+        let resp = self.exchange(headers, body).await?;
+        let status = resp.status;
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(rate_limited(&resp.headers));
+        }
+        let html = resp
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("text/html"));
+        let body = resp.body;
+        if let Some(marker) = html.then(|| challenge_marker(&body)).flatten() {
+            return Err(DeepLError::ChallengeRequired {
+                status,
+                marker: marker.to_string(),
+                body,
+            });
+        }
+        if status == StatusCode::FORBIDDEN {
+            return Err(DeepLError::Blocked { body });
+        }
+        if status != StatusCode::OK {
+            return Err(DeepLError::Status { status, body });
+        }
+        let value: serde_json::Value = serde_json::from_str(&body)?;
+        if let Some(drift) = self.schema.observe(&value) {
+            diag::log_warn!("deepl response schema drift: {}", drift);
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Sends a request to the endpoint through the client's middleware,
+    /// keeping the cookies of the response.
+    async fn exchange(
+        &self,
+        headers: HeaderMap,
+        body: String,
+    ) -> Result<middleware::Response, DeepLError> {
+        let mut request = middleware::Request {
+            url: self.endpoint.clone(),
+            headers,
+            body,
+        };
+        for layer in &self.middleware {
+            layer.on_request(&mut request).await?;
+        }
+        let resp = self
+            .http
+            .post(&request.url)
+            .headers(request.headers.clone())
+            .body(request.body.clone())
+            .send()
+            .await?;
+        if let (Some(jar), Some(host)) = (&self.cookies, resp.url().host_str()) {
+            for value in resp.headers().get_all(SET_COOKIE) {
+                if let Ok(value) = value.to_str() {
+                    jar.store(host, resp.url().path(), value);
+                }
+            }
+        }
+        let mut response = middleware::Response {
+            status: resp.status().as_u16(),
+            headers: resp.headers().clone(),
+            body: resp.text().await?,
+        };
+        self.telemetry.counter(
+            "deeplx_upstream_responses_total",
+            1,
+            &[("status", &response.status.to_string())],
+        );
+        for layer in self.middleware.iter().rev() {
+            layer.on_response(&request, &mut response).await?;
+        }
+        Ok(response)
+    }
+
+    /// The headers and the body of a web request for `texts`, as
+    /// [`send_as`](Self::send_as) sends and [`explain`](Self::explain)
+    /// describes it.
+    fn web_request(
+        &self,
+        session: Option<&str>,
+        fingerprint: Option<&Fingerprint>,
+        frozen: Frozen,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> (HeaderMap, String) {
+        let mut headers = self.headers.clone();
+        for (name, value) in fingerprint.map(Fingerprint::headers).into_iter().flatten() {
+            // Custom fingerprints may hold what no header can.
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+        if let Some(cookie) = self.cookie_header(session) {
+            match HeaderValue::from_str(&cookie) {
+                Ok(cookie) => {
+                    headers.insert(COOKIE, cookie);
+                }
+                Err(_) => diag::log_warn!("cookies not sendable as a header, left out"),
+            }
+        }
+        let body = build_batch_post_data_frozen(
+            frozen,
+            self.strategy,
+            JobOptions {
+                alternatives: self.alternatives,
+                formality: self.formality,
+                splitting: self.splitting.unwrap_or_default(),
+            },
+            texts,
+            src_lang,
+            target_lang,
+        );
+        (headers, body)
+    }
+
+    /// The jar's cookies for the endpoint, with `dl_session` set to
+    /// `session` in place of any the jar holds.
+    fn cookie_header(&self, session: Option<&str>) -> Option<String> {
+        let mut pairs: Vec<String> = session
+            .map(|token| format!("dl_session={}", token))
+            .into_iter()
+            .collect();
+        let url = reqwest::Url::parse(&self.endpoint).ok();
+        let stored = url
+            .as_ref()
+            .zip(self.cookies.as_ref())
+            .and_then(|(url, jar)| {
+                jar.header(url.host_str()?, url.path(), url.scheme() == "https")
+            });
+        if let Some(stored) = &stored {
+            pairs.extend(
+                stored
+                    .split("; ")
+                    .filter(|pair| session.is_none() || !pair.starts_with("dl_session="))
+                    .map(str::to_string),
+            );
+        }
+        (!pairs.is_empty()).then(|| pairs.join("; "))
+    }
+
+    /// [`send_as`](Self::send_as) for the official API. A 403 there means
+    /// a bad key rather than a ban, and 456 an exhausted quota, so both
+    /// come back as [`DeepLError::Status`].
+    async fn send_official(
+        &self,
+        auth_key: &str,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<DeepLResponse, DeepLError> {
+        let (headers, body) = self.official_request(auth_key, texts, src_lang, target_lang)?;
+        let resp = self.exchange(headers, body).await?;
+        if resp.status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(rate_limited(&resp.headers));
+        }
+        if !(200..300).contains(&resp.status) {
+            return Err(DeepLError::Status {
+                status: resp.status,
+                body: resp.body,
+            });
+        }
+        let resp: V2Response = serde_json::from_str(&resp.body)?;
+        Ok(resp.into())
+    }
+
+    /// The headers and the body of an official API request for `texts`.
+    fn official_request(
+        &self,
+        auth_key: &str,
+        texts: &[&str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<(HeaderMap, String), DeepLError> {
+        let mut headers = HeaderMap::new();
+        if let Ok(value) = HeaderValue::from_str(&format!("DeepL-Auth-Key {}", auth_key)) {
+            headers.insert(AUTHORIZATION, value);
+        }
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let body = serde_json::to_string(&self.v2_request(texts, src_lang, target_lang))?;
+        Ok((headers, body))
+    }
+
+    fn v2_request<'a>(
+        &'a self,
+        texts: &'a [&'a str],
+        src_lang: &str,
+        target_lang: &str,
+    ) -> V2Request<'a> {
+        V2Request::new(texts, src_lang, target_lang)
+            .with_formality(self.formality)
+            .with_glossary_id(self.glossary_id.as_deref())
+            .with_tag_handling(self.tag_handling.as_ref())
+            .with_splitting(self.splitting)
+    }
+
+    pub(crate) fn official_key(&self) -> Result<&str, DeepLError> {
+        match &self.auth_key {
+            Some(AuthKey(key)) => Ok(key),
+            None => Err(DeepLError::NoProvider),
+        }
+    }
+
+    /// Stores the decided entries of `glossary` with DeepL under `name`,
+    /// for translations from `src_lang` into `target_lang`. Needs the
+    /// official API: without [`with_auth_key`](Self::with_auth_key) this
+    /// fails with [`DeepLError::NoProvider`].
+    pub async fn create_glossary(
+        &self,
+        name: &str,
+        src_lang: &str,
+        target_lang: &str,
+        glossary: &Glossary,
+    ) -> Result<GlossaryInfo, DeepLError> {
+        self.upstream_open()?;
+        let key = self.official_key()?;
+        let resp = self
+            .http
+            .post(official::glossaries_endpoint(&self.endpoint))
+            .header(AUTHORIZATION, format!("DeepL-Auth-Key {}", key))
+            .json(&CreateGlossary::new(name, src_lang, target_lang, glossary))
+            .send()
+            .await?;
+        Ok(serde_json::from_str(
+            &official_ok(resp).await?.text().await?,
+        )?)
+    }
+
+    /// The glossaries stored with DeepL for the auth key.
+    pub async fn list_glossaries(&self) -> Result<Vec<GlossaryInfo>, DeepLError> {
+        self.upstream_open()?;
+        let key = self.official_key()?;
+        let resp = self
+            .http
+            .get(official::glossaries_endpoint(&self.endpoint))
+            .header(AUTHORIZATION, format!("DeepL-Auth-Key {}", key))
+            .send()
+            .await?;
+        let list: GlossaryList = serde_json::from_str(&official_ok(resp).await?.text().await?)?;
+        Ok(list.glossaries)
+    }
+}
+
+/// `resp` if the official API answered with success, otherwise the
+/// error it reports.
+pub(crate) async fn official_ok(resp: reqwest::Response) -> Result<reqwest::Response, DeepLError> {
+    let status = resp.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(rate_limited(resp.headers()));
+    }
+    if !status.is_success() {
+        return Err(DeepLError::Status {
+            status: status.as_u16(),
+            body: resp.text().await?,
+        });
+    }
+    Ok(resp)
+}
+
+fn rate_limited(headers: &HeaderMap) -> DeepLError {
+    let retry_after = headers
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_retry_after(v, clock::now()));
+    DeepLError::RateLimited { retry_after }
+}
+
+/// Signs of a bot check in an HTML page, with the name reported for each.
+const CHALLENGE_MARKERS: &[(&str, &str)] = &[
+    ("cf-chl", "cloudflare"),
+    ("challenge-platform", "cloudflare"),
+    ("cf-turnstile", "turnstile"),
+    ("h-captcha", "hcaptcha"),
+    ("hcaptcha.com", "hcaptcha"),
+    ("g-recaptcha", "recaptcha"),
+    ("recaptcha/api", "recaptcha"),
+    ("captcha", "captcha"),
+];
+
+fn challenge_marker(body: &str) -> Option<&'static str> {
+    let body = body.to_ascii_lowercase();
+    CHALLENGE_MARKERS
+        .iter()
+        .find(|(needle, _)| body.contains(needle))
+        .map(|(_, name)| *name)
+}
+
+impl Translator for DeepLClient {
+    fn name(&self) -> &str {
+        "deepl"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            source_langs: SOURCE_LANGS.iter().map(|l| l.to_string()).collect(),
+            target_langs: TARGET_LANGS.iter().map(|l| l.to_string()).collect(),
+            max_chars: Some(MAX_CHARS),
+            formality: true,
+            alternatives: true,
+            context: false,
+        }
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move { self.translate_cased(text, src_lang, target_lang).await })
+    }
+
+    fn translate_batch<'a>(
+        &'a self,
+        texts: &'a [&'a str],
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Translation>, DeepLError>> {
+        Box::pin(DeepLClient::translate_batch(
+            self,
+            texts,
+            src_lang,
+            target_lang,
+        ))
+    }
+
+    fn translate_with_options<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+        options: &'a TranslateOptions,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move {
+            let mut client = self.clone();
+            if let Some(formality) = options.formality {
+                client = client.with_formality(Some(formality));
+            }
+            if let Some(tag_handling) = &options.tag_handling {
+                client = client.with_tag_handling(Some(tag_handling.clone()));
+            }
+            if let Some(casing) = options.casing {
+                client = client.with_casing(Some(casing));
+            }
+            if let Some(splitting) = options.splitting {
+                client = client.with_splitting(Some(splitting));
+            }
+            client.translate_cased(text, src_lang, target_lang).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clients_share_a_rate_limiter() {
+        let limiter = Arc::new(RateLimiter::new(0.0, 3));
+        let a = DeepLClient::new().with_shared_rate_limiter(limiter.clone());
+        let b = DeepLClient::with_endpoint("http://127.0.0.1:1")
+            .with_shared_rate_limiter(limiter.clone());
+        let c = a.clone();
+        assert!(limiter.try_acquire());
+        for client in [&a, &b, &c] {
+            assert_eq!(client.pressure().available_permits, Some(2));
+        }
+    }
+
+    #[test]
+    fn test_pressure_reports_limiter_and_breaker() {
+        let client = DeepLClient::new()
+            .with_rate_limiter(RateLimiter::new(0.0, 2))
+            .with_circuit_breaker(CircuitBreaker::new(1, Duration::from_secs(60)));
+        let pressure = client.pressure();
+        assert_eq!(pressure.available_permits, Some(2));
+        assert_eq!(pressure.queue_depth, 0);
+        assert!(!pressure.is_busy());
+
+        client.breaker.as_ref().unwrap().record_failure();
+        assert!(client.pressure().cooldown().is_some());
+        assert!(client.pressure().is_busy());
+    }
+
+    #[test]
+    fn test_calendar_refusals_leave_the_budget_alone() {
+        use crate::{
+            budget::Period,
+            schedule::{QuietHours, UpstreamPolicy},
+        };
+
+        let calendar = Arc::new(Calendar::new());
+        calendar.set_policy(
+            "night",
+            UpstreamPolicy {
+                quiet_hours: vec![QuietHours::new(0, 0, 12, 0), QuietHours::new(12, 0, 0, 0)],
+                ..Default::default()
+            },
+        );
+        let budget = Arc::new(Budget::new(Period::Day));
+        let client = DeepLClient::with_endpoint("http://127.0.0.1:1")
+            .with_budget(budget.clone())
+            .with_calendar(calendar.clone(), "night");
+        let result = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(client.translate("Hello", "EN", "DE"));
+        assert!(matches!(result, Err(DeepLError::RateLimited { .. })));
+        assert_eq!(budget.usage().used, 0);
+
+        // And the other way round: the budget refuses, the calendar is
+        // not charged.
+        calendar.set_policy(
+            "day",
+            UpstreamPolicy {
+                char_budget: Some(100),
+                ..Default::default()
+            },
+        );
+        let client = DeepLClient::with_endpoint("http://127.0.0.1:1")
+            .with_budget(Arc::new(Budget::new(Period::Day).with_hard_limit(1)))
+            .with_calendar(calendar.clone(), "day");
+        let result = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(client.translate("Hello", "EN", "DE"));
+        assert!(matches!(result, Err(DeepLError::BudgetExceeded { .. })));
+        assert_eq!(calendar.used("day", clock::now()), 0);
+    }
+
+    #[test]
+    fn test_shared_client_is_reused() {
+        assert!(std::ptr::eq(DeepLClient::shared(), DeepLClient::shared()));
+        assert_eq!(DeepLClient::shared().endpoint(), DEEPL_API);
+        assert_eq!(DeepLClient::new().headers.len(), crate::HEADERS.len());
+    }
+
+    #[test]
+    fn test_dl_session_uses_pro_endpoint() {
+        let client = DeepLClient::new().with_dl_session("token");
+        assert_eq!(client.endpoint(), DEEPL_PRO_API);
+        assert_eq!(client.sessions.as_ref().map(|p| p.len()), Some(1));
+        let custom = DeepLClient::with_endpoint("http://127.0.0.1:1188").with_dl_session("t");
+        assert_eq!(custom.endpoint(), "http://127.0.0.1:1188");
+    }
+
+    #[test]
+    fn test_explain_shows_the_request_without_secrets() {
+        let request: TranslateRequest = serde_json::from_str(
+            r#"{"text": "<b>hi</b>", "target_lang": "DE", "tag_handling": {"mode": "html"}}"#,
+        )
+        .unwrap();
+        let jar = Arc::new(CookieJar::new());
+        jar.store("api.deepl.com", "/", "LMTBID=v2|abc");
+        let client = DeepLClient::new()
+            .with_dl_session("secret-token")
+            .with_cookie_jar(jar)
+            .with_fingerprints(Arc::new(FingerprintPool::builtin()));
+        let explanation = client.explain(&request).unwrap();
+        assert_eq!(explanation.api, Api::Web);
+        assert_eq!(explanation.url, DEEPL_PRO_API);
+        assert_eq!(explanation.session, Some(0));
+        assert!(explanation.masked_markup);
+        assert!(!explanation.body.contains("<b>"));
+        let body: serde_json::Value = serde_json::from_str(&explanation.body).unwrap();
+        assert_eq!(Some(body["id"].as_i64().unwrap()), explanation.id);
+        let (_, cookie) = explanation
+            .headers
+            .iter()
+            .find(|(n, _)| n == "cookie")
+            .unwrap();
+        assert!(cookie.starts_with("dl_session=<12 chars") && cookie.contains("; LMTBID=<"));
+        assert!(!explanation.to_string().contains("secret-token"));
+        let agent = explanation.fingerprint.unwrap().user_agent();
+        assert!(explanation
+            .headers
+            .contains(&("user-agent".to_string(), agent)));
+
+        let official = DeepLClient::new().with_auth_key("k3y:fx");
+        let explanation = official.explain(&request).unwrap();
+        assert_eq!(explanation.api, Api::Official);
+        assert!(explanation.body.contains("\"tag_handling\":\"html\""));
+        assert!(!explanation.to_string().contains("k3y"));
+    }
+}
@@ -0,0 +1,506 @@
+//! State shared between gateway replicas through a common [`Storage`],
+//! normally [`RedisStorage`](crate::storage::RedisStorage), so that
+//! replicas agree on rate limits, upstream cooldowns and cached results
+//! instead of each exhausting the same upstream identity on its own.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    capabilities::Capabilities,
+    clock, diag,
+    error::DeepLError,
+    storage::{
+        unix_millis, Storage, StorageResult, NS_CACHE, NS_COOLDOWN, NS_LEADER, NS_RATE_LIMIT,
+    },
+    telemetry::{Telemetry, TelemetrySink},
+    translator::{BoxFuture, Translation, Translator},
+};
+
+const PREWARM_ROLE: &str = "prewarm";
+
+pub struct Cluster {
+    storage: Arc<dyn Storage>,
+    node_id: String,
+}
+
+impl Cluster {
+    pub fn new(storage: Arc<dyn Storage>, node_id: impl Into<String>) -> Self {
+        Self {
+            storage,
+            node_id: node_id.into(),
+        }
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn storage(&self) -> &Arc<dyn Storage> {
+        &self.storage
+    }
+
+    /// Takes one request from a fixed-window budget of `limit` requests per
+    /// `window`, counted across every replica.
+    pub async fn try_acquire(
+        &self,
+        identity: &str,
+        limit: u64,
+        window: Duration,
+    ) -> StorageResult<bool> {
+        let window_ms = window.as_millis().max(1) as u64;
+        let slot = unix_millis(clock::now()) / window_ms;
+        let key = format!("{}:{}", identity, slot);
+        let count = self
+            .storage
+            .incr(NS_RATE_LIMIT, &key, 1, Some(window))
+            .await?;
+        Ok(count <= limit as i64)
+    }
+
+    pub async fn set_cooldown(&self, identity: &str, duration: Duration) -> StorageResult<()> {
+        let until = clock::now()
+            .checked_add(duration)
+            .map_or(u64::MAX, unix_millis);
+        self.storage
+            .put(
+                NS_COOLDOWN,
+                identity,
+                until.to_string().into_bytes(),
+                Some(duration),
+            )
+            .await
+    }
+
+    /// When the upstream identity may be used again, if some replica put it
+    /// on cooldown.
+    pub async fn cooldown(&self, identity: &str) -> StorageResult<Option<SystemTime>> {
+        let value = self.storage.get(NS_COOLDOWN, identity).await?;
+        Ok(value
+            .and_then(|v| String::from_utf8(v).ok())
+            .and_then(|v| v.parse().ok())
+            .and_then(|ms| SystemTime::UNIX_EPOCH.checked_add(Duration::from_millis(ms)))
+            .filter(|until| *until > clock::now()))
+    }
+
+    /// Acquires or renews the lease for `role`. Only one node holds a given
+    /// role until its lease lapses without renewal.
+    pub async fn acquire_leadership(&self, role: &str, ttl: Duration) -> StorageResult<bool> {
+        let holder = self.storage.get(NS_LEADER, role).await?;
+        if holder.as_deref() == Some(self.node_id.as_bytes()) {
+            self.storage
+                .put(
+                    NS_LEADER,
+                    role,
+                    self.node_id.clone().into_bytes(),
+                    Some(ttl),
+                )
+                .await?;
+            return Ok(true);
+        }
+        self.storage
+            .put_if_absent(
+                NS_LEADER,
+                role,
+                self.node_id.clone().into_bytes(),
+                Some(ttl),
+            )
+            .await
+    }
+
+    /// Runs `warm` (typically opening upstream sessions) only on the node
+    /// that currently leads prewarming, so replicas don't all hit the
+    /// upstream at startup. Returns whether this node ran it.
+    pub async fn prewarm<F, Fut>(&self, lease: Duration, warm: F) -> StorageResult<bool>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        if !self.acquire_leadership(PREWARM_ROLE, lease).await? {
+            return Ok(false);
+        }
+        warm().await;
+        Ok(true)
+    }
+}
+
+/// Time left in the current fixed window of length `window`.
+pub fn window_remaining(window: Duration) -> Duration {
+    let window_ms = window.as_millis().max(1) as u64;
+    Duration::from_millis(window_ms - unix_millis(clock::now()) % window_ms)
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    stored_at: u64,
+    translation: Translation,
+}
+
+/// Wraps a translator so every call goes through the cluster's shared
+/// cache, cooldowns and rate limit for one upstream identity.
+pub struct Coordinated {
+    inner: Arc<dyn Translator>,
+    cluster: Arc<Cluster>,
+    identity: String,
+    rate_limit: Option<(u64, Duration)>,
+    cooldown: Duration,
+    cache_ttl: Option<Duration>,
+    max_staleness: Option<Duration>,
+    telemetry: Telemetry,
+}
+
+impl Coordinated {
+    pub fn new(
+        inner: Arc<dyn Translator>,
+        cluster: Arc<Cluster>,
+        identity: impl Into<String>,
+    ) -> Self {
+        Self {
+            inner,
+            cluster,
+            identity: identity.into(),
+            rate_limit: None,
+            cooldown: Duration::from_secs(60),
+            cache_ttl: None,
+            max_staleness: None,
+            telemetry: Telemetry::default(),
+        }
+    }
+
+    /// Counts cache lookups in `deeplx_cache_lookups_total`, by `result`
+    /// `hit` or `miss`, and in `deeplx_cache_hits_total` or
+    /// `deeplx_cache_misses_total`, and misses answered with a stale entry in
+    /// `deeplx_cache_stale_total`.
+    pub fn telemetry(mut self, sink: Arc<dyn TelemetrySink>) -> Self {
+        self.telemetry = Telemetry::new(sink);
+        self
+    }
+
+    fn count_lookup(&self, result: &str) {
+        self.telemetry
+            .counter("deeplx_cache_lookups_total", 1, &[("result", result)]);
+        let counter = match result {
+            "hit" => "deeplx_cache_hits_total",
+            _ => "deeplx_cache_misses_total",
+        };
+        self.telemetry.counter(counter, 1, &[]);
+    }
+
+    pub fn rate_limit(mut self, limit: u64, window: Duration) -> Self {
+        self.rate_limit = Some((limit, window));
+        self
+    }
+
+    /// How long the identity is benched for every replica after upstream
+    /// rate-limits it.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Keeps cache entries for up to `max_staleness` past their TTL and
+    /// answers with them, flagged with the `stale` extension, when the
+    /// upstream is unavailable. Has no effect without a cache TTL.
+    pub fn serve_stale(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+
+    fn cache_key(&self, text: &str, src_lang: &str, target_lang: &str) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            self.inner.name(),
+            src_lang.to_ascii_uppercase(),
+            target_lang.to_ascii_uppercase(),
+            text
+        )
+    }
+
+    async fn run(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Translation, DeepLError> {
+        let key = self.cache_key(text, src_lang, target_lang);
+        let mut stale = None;
+        if let Some(ttl) = self.cache_ttl {
+            if let Some(hit) = self.cluster.storage.get(NS_CACHE, &key).await? {
+                if let Ok(entry) = serde_json::from_slice::<CacheEntry>(&hit) {
+                    let age = Duration::from_millis(
+                        unix_millis(clock::now()).saturating_sub(entry.stored_at),
+                    );
+                    let mut translation = entry.translation;
+                    translation
+                        .meta
+                        .extensions
+                        .insert("cached".to_string(), Value::Bool(true));
+                    if age <= ttl {
+                        self.count_lookup("hit");
+                        return Ok(translation);
+                    }
+                    if self
+                        .max_staleness
+                        .is_some_and(|max| age <= ttl.saturating_add(max))
+                    {
+                        stale = Some((translation, age));
+                    }
+                }
+            }
+        }
+
+        if self.cache_ttl.is_some() {
+            self.count_lookup("miss");
+        }
+        let result = self.fetch(text, src_lang, target_lang).await;
+        match result {
+            Ok(translation) => {
+                if let Some(ttl) = self.cache_ttl {
+                    let entry = CacheEntry {
+                        stored_at: unix_millis(clock::now()),
+                        translation,
+                    };
+                    let value = serde_json::to_vec(&entry)?;
+                    let keep = ttl.saturating_add(self.max_staleness.unwrap_or_default());
+                    self.cluster
+                        .storage
+                        .put(NS_CACHE, &key, value, Some(keep))
+                        .await?;
+                    return Ok(entry.translation);
+                }
+                Ok(translation)
+            }
+            Err(e) if e.is_unavailable() => match stale {
+                Some((mut translation, age)) => {
+                    diag::log_warn!(
+                        "{} unavailable ({}), serving a cached result {}s old",
+                        self.identity,
+                        e,
+                        age.as_secs()
+                    );
+                    self.telemetry.counter("deeplx_cache_stale_total", 1, &[]);
+                    translation
+                        .meta
+                        .extensions
+                        .insert("stale".to_string(), Value::Bool(true));
+                    Ok(translation)
+                }
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Asks the upstream, honouring the shared cooldown and rate limit.
+    async fn fetch(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Translation, DeepLError> {
+        if let Some(until) = self.cluster.cooldown(&self.identity).await? {
+            return Err(DeepLError::RateLimited {
+                retry_after: Some(until.duration_since(clock::now()).unwrap_or_default()),
+            });
+        }
+        if let Some((limit, window)) = self.rate_limit {
+            if !self
+                .cluster
+                .try_acquire(&self.identity, limit, window)
+                .await?
+            {
+                return Err(DeepLError::RateLimited {
+                    retry_after: Some(window_remaining(window)),
+                });
+            }
+        }
+
+        let result = self.inner.translate(text, src_lang, target_lang).await;
+        if let Err(DeepLError::RateLimited { retry_after }) = &result {
+            self.cluster
+                .set_cooldown(
+                    &self.identity,
+                    self.cooldown.max(retry_after.unwrap_or_default()),
+                )
+                .await?;
+        }
+        result
+    }
+}
+
+impl Translator for Coordinated {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(self.run(text, src_lang, target_lang))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    struct Counting(AtomicUsize);
+
+    impl Translator for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                Ok(Translation {
+                    text: text.chars().rev().collect(),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn test_replicas_share_leadership_and_limits() {
+        block_on(async {
+            let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+            let a = Cluster::new(storage.clone(), "a");
+            let b = Cluster::new(storage, "b");
+            let lease = Duration::from_secs(30);
+
+            assert!(a.prewarm(lease, || async {}).await.unwrap());
+            assert!(!b.prewarm(lease, || async {}).await.unwrap());
+            assert!(a.acquire_leadership(PREWARM_ROLE, lease).await.unwrap());
+
+            let window = Duration::from_secs(3600);
+            assert!(a.try_acquire("proxy", 2, window).await.unwrap());
+            assert!(b.try_acquire("proxy", 2, window).await.unwrap());
+            assert!(!a.try_acquire("proxy", 2, window).await.unwrap());
+
+            b.set_cooldown("session", lease).await.unwrap();
+            assert!(a.cooldown("session").await.unwrap().is_some());
+        });
+    }
+
+    #[test]
+    fn test_coordinated_uses_shared_cache() {
+        block_on(async {
+            let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+            let inner = Arc::new(Counting(AtomicUsize::new(0)));
+            let replica = |id: &str| {
+                Coordinated::new(
+                    inner.clone(),
+                    Arc::new(Cluster::new(storage.clone(), id)),
+                    "proxy",
+                )
+                .cache_ttl(Duration::from_secs(60))
+            };
+            let (a, b) = (replica("a"), replica("b"));
+            assert_eq!(a.translate("abc", "EN", "DE").await.unwrap().text, "cba");
+            let hit = b.translate("abc", "en", "de").await.unwrap();
+            assert_eq!(hit.text, "cba");
+            assert!(hit.is_cached());
+            assert_eq!(inner.0.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    struct Flaky(std::sync::atomic::AtomicBool);
+
+    impl Translator for Flaky {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            let up = self.0.load(Ordering::SeqCst);
+            Box::pin(async move {
+                if !up {
+                    return Err(DeepLError::Status {
+                        status: 503,
+                        body: String::new(),
+                    });
+                }
+                Ok(Translation {
+                    text: text.to_uppercase(),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_serves_stale_results_while_upstream_is_down() {
+        block_on(async {
+            let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+            let inner = Arc::new(Flaky(true.into()));
+            let coordinated = |max_staleness| {
+                Coordinated::new(
+                    inner.clone(),
+                    Arc::new(Cluster::new(storage.clone(), "a")),
+                    "proxy",
+                )
+                .cache_ttl(Duration::ZERO)
+                .serve_stale(max_staleness)
+            };
+            let fresh = coordinated(Duration::from_secs(60))
+                .translate("abc", "EN", "DE")
+                .await
+                .unwrap();
+            assert!(!fresh.is_stale());
+
+            inner.0.store(false, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(5));
+            let stale = coordinated(Duration::from_secs(60))
+                .translate("abc", "EN", "DE")
+                .await
+                .unwrap();
+            assert_eq!(stale.text, "ABC");
+            assert!(stale.is_stale());
+            assert!(coordinated(Duration::ZERO)
+                .translate("abc", "EN", "DE")
+                .await
+                .is_err());
+        });
+    }
+}
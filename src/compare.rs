@@ -0,0 +1,92 @@
+//! Side-by-side results from several providers for the same input.
+
+use std::{sync::Arc, time::Duration};
+
+use futures_util::future::join_all;
+
+use crate::{clock::Instant, error::DeepLError, translator::Translation, Translator};
+
+#[derive(Debug)]
+pub struct Comparison {
+    pub provider: String,
+    pub result: Result<Translation, DeepLError>,
+    pub elapsed: Duration,
+}
+
+/// Sends `text` to every provider concurrently and returns one
+/// [`Comparison`] per provider, in the order they were given.
+pub async fn compare(
+    text: &str,
+    src_lang: &str,
+    target_lang: &str,
+    providers: &[Arc<dyn Translator>],
+) -> Vec<Comparison> {
+    join_all(providers.iter().map(|provider| async move {
+        let start = Instant::now();
+        let result = provider.translate(text, src_lang, target_lang).await;
+        Comparison {
+            provider: provider.name().to_string(),
+            result,
+            elapsed: start.elapsed(),
+        }
+    }))
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::BoxFuture;
+
+    struct Upper;
+    struct Failing;
+
+    impl Translator for Upper {
+        fn name(&self) -> &str {
+            "upper"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                Ok(Translation {
+                    text: text.to_uppercase(),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    impl Translator for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            _text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async { Err(DeepLError::NoProvider) })
+        }
+    }
+
+    #[test]
+    fn test_compare_keeps_provider_order() {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let providers: Vec<Arc<dyn Translator>> = vec![Arc::new(Failing), Arc::new(Upper)];
+        let results = rt.block_on(compare("hello", "EN", "DE", &providers));
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].provider, "failing");
+        assert!(results[0].result.is_err());
+        assert_eq!(results[1].result.as_ref().unwrap().text, "HELLO");
+    }
+}
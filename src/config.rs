@@ -0,0 +1,483 @@
+//! Settings for the client and for `deeplx` and its server, read from a
+//! TOML file and overridden by `DEEPLX_*` environment variables.
+//!
+//! ```toml
+//! endpoint = "https://www2.deepl.com/jsonrpc"
+//! proxy = "socks5h://127.0.0.1:1080"
+//! retries = 3
+//!
+//! [timeouts]
+//! connect = 10
+//! request = 30
+//!
+//! [cache]
+//! size = 1000
+//! ttl = 86400
+//!
+//! [server]
+//! listen = "127.0.0.1:1188"
+//! ```
+//!
+//! Every setting is optional. `DEEPLX_ENDPOINT`, `DEEPLX_PROXY`,
+//! `DEEPLX_RETRIES`, `DEEPLX_CONNECT_TIMEOUT`, `DEEPLX_TIMEOUT`,
+//! `DEEPLX_CACHE_SIZE`, `DEEPLX_CACHE_TTL` and `DEEPLX_LISTEN` override
+//! the file; empty variables are ignored. Times are in seconds.
+//!
+//! The parser reads the TOML these settings need: tables, strings,
+//! integers, floats, booleans, arrays and inline tables on one line.
+//! Multi-line strings and arrays, dates and arrays of tables are
+//! reported as unsupported.
+
+use std::{fmt, fs, io, net::SocketAddr, path::Path, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub endpoint: Option<String>,
+    /// A proxy URL as for [`ProxyConfig::url`](crate::ProxyConfig::url),
+    /// `direct` to ignore the proxy environment variables, or `env`.
+    pub proxy: Option<String>,
+    /// Times to retry a rate-limited or failed request.
+    pub retries: Option<u32>,
+    pub timeouts: Timeouts,
+    pub cache: CacheConfig,
+    pub server: ServerConfig,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// Seconds to wait for a connection.
+    pub connect: Option<u64>,
+    /// Seconds to wait for a whole request, the response included.
+    pub request: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Recent translations kept in memory; 0 turns the cache off.
+    pub size: Option<usize>,
+    /// Seconds a cached translation is used for.
+    pub ttl: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: Option<SocketAddr>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    /// Not TOML, or TOML the parser does not read.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    /// An unknown setting, or one of the wrong type.
+    Invalid(String),
+    /// A `DEEPLX_*` variable whose value is not valid for its setting.
+    Env {
+        name: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "config: {}", e),
+            ConfigError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            ConfigError::Invalid(message) => write!(f, "invalid config: {}", message),
+            ConfigError::Env { name, value } => write!(f, "{}: invalid value {:?}", name, value),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl Config {
+    /// The settings in the TOML `input`.
+    pub fn parse(input: &str) -> Result<Self, ConfigError> {
+        let table = parse_toml(input)?;
+        serde_json::from_value(Value::Object(table))
+            .map_err(|e| ConfigError::Invalid(e.to_string()))
+    }
+
+    /// The settings in the TOML file at `path`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// The settings overridden by the `DEEPLX_*` variables among `vars`.
+    pub fn with_vars(
+        mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        for (name, value) in vars {
+            if value.is_empty() {
+                continue;
+            }
+            let Some(setting) = name.strip_prefix("DEEPLX_") else {
+                continue;
+            };
+            match setting {
+                "ENDPOINT" => self.endpoint = Some(value),
+                "PROXY" => self.proxy = Some(value),
+                "RETRIES" => self.retries = Some(env_value(&name, &value)?),
+                "CONNECT_TIMEOUT" => self.timeouts.connect = Some(env_value(&name, &value)?),
+                "TIMEOUT" => self.timeouts.request = Some(env_value(&name, &value)?),
+                "CACHE_SIZE" => self.cache.size = Some(env_value(&name, &value)?),
+                "CACHE_TTL" => self.cache.ttl = Some(env_value(&name, &value)?),
+                "LISTEN" => self.server.listen = Some(env_value(&name, &value)?),
+                // `DEEPLX_CONFIG`, `DEEPLX_COORDINATOR` and the like.
+                _ => {}
+            }
+        }
+        Ok(self)
+    }
+
+    /// [`with_vars`](Self::with_vars) with the process environment.
+    pub fn with_env(self) -> Result<Self, ConfigError> {
+        let vars = std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        });
+        self.with_vars(vars)
+    }
+}
+
+fn env_value<T: FromStr>(name: &str, value: &str) -> Result<T, ConfigError> {
+    value.trim().parse().map_err(|_| ConfigError::Env {
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// The TOML `input` as JSON, tables as objects.
+fn parse_toml(input: &str) -> Result<Map<String, Value>, ConfigError> {
+    let mut root = Map::new();
+    let mut table = Vec::new();
+    for (n, line) in input.lines().enumerate() {
+        let syntax = |message: String| ConfigError::Syntax {
+            line: n + 1,
+            message,
+        };
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            if header.starts_with('[') {
+                return Err(syntax("arrays of tables are not supported".to_string()));
+            }
+            let (path, rest) = key(header).map_err(syntax)?;
+            let rest = rest.strip_prefix(']').ok_or("expected `]`".to_string());
+            end(rest.map_err(syntax)?).map_err(syntax)?;
+            entry(&mut root, &path, None).map_err(syntax)?;
+            table = path;
+            continue;
+        }
+        let (path, rest) = key(line).map_err(syntax)?;
+        let rest = rest.strip_prefix('=').ok_or("expected `=`".to_string());
+        let (parsed, rest) = value(rest.map_err(syntax)?.trim_start()).map_err(syntax)?;
+        end(rest).map_err(syntax)?;
+        let path: Vec<String> = table.iter().cloned().chain(path).collect();
+        entry(&mut root, &path, Some(parsed)).map_err(syntax)?;
+    }
+    Ok(root)
+}
+
+/// Sets `path` in `root` to `value`, or makes sure it is a table for
+/// `None`.
+fn entry(
+    root: &mut Map<String, Value>,
+    path: &[String],
+    value: Option<Value>,
+) -> Result<(), String> {
+    let mut table = root;
+    let (last, parents) = path.split_last().ok_or("empty key")?;
+    for name in parents {
+        let next = table
+            .entry(name.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        table = next
+            .as_object_mut()
+            .ok_or_else(|| format!("`{}` is not a table", name))?;
+    }
+    match value {
+        Some(_) if table.contains_key(last) => Err(format!("`{}` is set twice", last)),
+        Some(value) => {
+            table.insert(last.clone(), value);
+            Ok(())
+        }
+        None => match table
+            .entry(last.clone())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            Value::Object(_) => Ok(()),
+            _ => Err(format!("`{}` is not a table", last)),
+        },
+    }
+}
+
+/// Only whitespace and a comment may follow.
+fn end(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected {:?}", rest))
+    }
+}
+
+/// A dotted key at the start of `s`, and what follows it.
+fn key(s: &str) -> Result<(Vec<String>, &str), String> {
+    let mut path = Vec::new();
+    let mut rest = s.trim_start();
+    loop {
+        let (part, after) = if rest.starts_with(['"', '\'']) {
+            quoted(rest)?
+        } else {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                return Err("expected a key".to_string());
+            }
+            (rest[..len].to_string(), &rest[len..])
+        };
+        path.push(part);
+        rest = after.trim_start();
+        match rest.strip_prefix('.') {
+            Some(after) => rest = after.trim_start(),
+            None => return Ok((path, rest)),
+        }
+    }
+}
+
+/// The value at the start of `s`, and what follows it.
+fn value(s: &str) -> Result<(Value, &str), String> {
+    if s.starts_with("\"\"\"") || s.starts_with("'''") {
+        return Err("multi-line strings are not supported".to_string());
+    }
+    if s.starts_with(['"', '\'']) {
+        let (string, rest) = quoted(s)?;
+        return Ok((Value::String(string), rest));
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            if rest.is_empty() || rest.starts_with('#') {
+                return Err("arrays must be on one line".to_string());
+            }
+            let (item, after) = value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => {}
+                None => return Err("expected `,` or `]`".to_string()),
+            }
+        }
+    }
+    if let Some(mut rest) = s.strip_prefix('{') {
+        let mut table = Map::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix('}') {
+                return Ok((Value::Object(table), after));
+            }
+            let (path, after) = key(rest)?;
+            let after = after.strip_prefix('=').ok_or("expected `=`")?;
+            let (item, after) = value(after.trim_start())?;
+            entry(&mut table, &path, Some(item))?;
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with('}') => {}
+                None => return Err("expected `,` or `}`".to_string()),
+            }
+        }
+    }
+    let len = s
+        .find(|c: char| c.is_whitespace() || matches!(c, ',' | ']' | '}' | '#'))
+        .unwrap_or(s.len());
+    let (token, rest) = s.split_at(len);
+    Ok((scalar(token)?, rest))
+}
+
+fn scalar(token: &str) -> Result<Value, String> {
+    match token {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        "" => return Err("expected a value".to_string()),
+        _ => {}
+    }
+    let digits = token.replace('_', "");
+    let (sign, unsigned) = match digits.strip_prefix('-') {
+        Some(unsigned) => (-1, unsigned),
+        None => (1, digits.strip_prefix('+').unwrap_or(&digits)),
+    };
+    let radix = [("0x", 16), ("0o", 8), ("0b", 2)]
+        .into_iter()
+        .find_map(|(prefix, radix)| Some((unsigned.strip_prefix(prefix)?, radix)));
+    let integer = match radix {
+        Some((digits, radix)) => i64::from_str_radix(digits, radix).ok(),
+        None => unsigned.parse::<i64>().ok(),
+    };
+    if let Some(integer) = integer {
+        return Ok(Value::from(sign * integer));
+    }
+    let float = match unsigned {
+        "inf" | "nan" => None,
+        _ => digits.parse::<f64>().ok(),
+    };
+    match float.and_then(serde_json::Number::from_f64) {
+        Some(number) => Ok(Value::Number(number)),
+        None if token.contains(':') || token.get(1..).is_some_and(|t| t.contains('-')) => {
+            Err("dates are not supported".to_string())
+        }
+        None => Err(format!("invalid value {:?}", token)),
+    }
+}
+
+/// The string quoted at the start of `s`, and what follows it.
+fn quoted(s: &str) -> Result<(String, &str), String> {
+    if let Some(rest) = s.strip_prefix('\'') {
+        // Literal strings have no escapes.
+        let close = rest.find('\'').ok_or("unterminated string")?;
+        return Ok((rest[..close].to_string(), &rest[close + 1..]));
+    }
+    let s = &s[1..];
+    let mut out = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((out, &s[i + 1..])),
+            '\\' => {
+                let escaped = match chars.next().map(|(_, c)| c) {
+                    Some('b') => '\u{8}',
+                    Some('t') => '\t',
+                    Some('n') => '\n',
+                    Some('f') => '\u{c}',
+                    Some('r') => '\r',
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some(u @ ('u' | 'U')) => {
+                        let len = if u == 'u' { 4 } else { 8 };
+                        let hex: String = chars.by_ref().take(len).map(|(_, c)| c).collect();
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape \\{}{}", u, hex))?
+                    }
+                    other => return Err(format!("invalid escape {:?}", other)),
+                };
+                out.push(escaped);
+            }
+            c => out.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_settings_are_overridden_by_env() {
+        let config = Config::parse(
+            "# deeplx settings\n\
+             endpoint = \"https://www2.deepl.com/jsonrpc\" # the web API\n\
+             proxy = 'socks5h://127.0.0.1:1080'\n\
+             retries = 2\n\
+             timeouts = { connect = 10 }\n\
+             \n\
+             [cache]\n\
+             size = 1_000\n\
+             \n\
+             [server]\n\
+             listen = \"127.0.0.1:1188\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.endpoint.as_deref(),
+            Some("https://www2.deepl.com/jsonrpc")
+        );
+        assert_eq!(config.retries, Some(2));
+        assert_eq!(config.timeouts.connect, Some(10));
+        assert_eq!(config.cache.size, Some(1000));
+        assert_eq!(
+            config.server.listen,
+            Some("127.0.0.1:1188".parse().unwrap())
+        );
+
+        let vars = [
+            ("DEEPLX_RETRIES", "5"),
+            ("DEEPLX_TIMEOUT", "30"),
+            ("DEEPLX_PROXY", ""),
+            ("DEEPLX_COORDINATOR", "/tmp/deeplx.sock"),
+            ("HOME", "/root"),
+        ];
+        let vars = vars.map(|(name, value)| (name.to_string(), value.to_string()));
+        let config = config.with_vars(vars).unwrap();
+        assert_eq!(config.retries, Some(5));
+        assert_eq!(
+            config.timeouts,
+            Timeouts {
+                connect: Some(10),
+                request: Some(30)
+            }
+        );
+        assert_eq!(config.proxy.as_deref(), Some("socks5h://127.0.0.1:1080"));
+
+        let bad = [("DEEPLX_CACHE_SIZE".to_string(), "lots".to_string())];
+        assert!(matches!(
+            Config::default().with_vars(bad),
+            Err(ConfigError::Env { name, .. }) if name == "DEEPLX_CACHE_SIZE"
+        ));
+    }
+
+    #[test]
+    fn test_errors_name_the_line_or_the_setting() {
+        let error = |input: &str| Config::parse(input).unwrap_err().to_string();
+        assert_eq!(
+            error("retries = 3\nretries = 4"),
+            "line 2: `retries` is set twice"
+        );
+        assert_eq!(error("[cache\nsize = 1"), "line 1: expected `]`");
+        assert_eq!(
+            error("endpoint = \"https://\n"),
+            "line 1: unterminated string"
+        );
+        assert_eq!(
+            error("[server]\nstarted = 2024-05-01"),
+            "line 2: dates are not supported"
+        );
+        assert!(error("[cache]\nsise = 10").starts_with("invalid config: unknown field `sise`"));
+        assert!(error("retries = \"three\"").starts_with("invalid config: invalid type"));
+    }
+}
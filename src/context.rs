@@ -0,0 +1,169 @@
+//! Translating related segments with their neighbours as context, so that
+//! pronouns and terminology stay consistent across one document.
+//!
+//! Each segment is sent together with up to `before` preceding and `after`
+//! following segments, one per line; DeepL splits on newlines but
+//! translates the lines as one text. The segment's own lines are then cut
+//! back out of the result. When the line structure does not survive, the
+//! segment is translated on its own instead and the translation carries
+//! the `context_fallback` extension.
+//!
+//! Context is paid for in characters: a window of one segment on each side
+//! roughly triples the volume sent upstream.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::{
+    error::DeepLError,
+    translator::{Translation, Translator},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Window {
+    pub before: usize,
+    pub after: usize,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self {
+            before: 1,
+            after: 1,
+        }
+    }
+}
+
+pub(crate) fn line_count(s: &str) -> usize {
+    s.split('\n').count()
+}
+
+/// Lines `start..start + len` of `text`, if it has exactly `total` lines.
+pub(crate) fn extract(text: &str, total: usize, start: usize, len: usize) -> Option<String> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    (lines.len() == total).then(|| lines[start..start + len].join("\n"))
+}
+
+pub async fn translate_with_context(
+    provider: &dyn Translator,
+    segments: &[&str],
+    src_lang: &str,
+    target_lang: &str,
+    window: Window,
+) -> Vec<Result<Translation, Arc<DeepLError>>> {
+    let mut results = Vec::with_capacity(segments.len());
+    for (i, segment) in segments.iter().enumerate() {
+        let first = i.saturating_sub(window.before);
+        let last = i.saturating_add(window.after).min(segments.len() - 1);
+        if first == last || segment.trim().is_empty() {
+            results.push(
+                provider
+                    .translate(segment, src_lang, target_lang)
+                    .await
+                    .map_err(Arc::new),
+            );
+            continue;
+        }
+
+        let text = segments[first..=last].join("\n");
+        let total = line_count(&text);
+        let start: usize = segments[first..i].iter().map(|s| line_count(s)).sum();
+        let len = line_count(segment);
+        let result = match provider.translate(&text, src_lang, target_lang).await {
+            Ok(mut translation) => match extract(&translation.text, total, start, len) {
+                Some(own) => {
+                    translation.text = own;
+                    translation.alternatives = translation
+                        .alternatives
+                        .iter()
+                        .filter_map(|a| extract(a, total, start, len))
+                        .collect();
+                    Ok(translation)
+                }
+                None => provider
+                    .translate(segment, src_lang, target_lang)
+                    .await
+                    .map(|mut translation| {
+                        translation
+                            .meta
+                            .extensions
+                            .insert("context_fallback".to_string(), Value::Bool(true));
+                        translation
+                    }),
+            },
+            Err(e) => Err(e),
+        };
+        results.push(result.map_err(Arc::new));
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::translator::BoxFuture;
+
+    /// Upper-cases, but joins the lines of a context window mentioning
+    /// "merge".
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Translator for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            if let Ok(mut sent) = self.0.lock() {
+                sent.push(text.to_string());
+            }
+            let text = if text.contains("merge") && text.contains('\n') {
+                text.replace('\n', " ")
+            } else {
+                text.to_uppercase()
+            };
+            Box::pin(async move {
+                Ok(Translation {
+                    text,
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_segments_are_cut_out_of_their_context() {
+        let recorder = Recorder(Mutex::new(Vec::new()));
+        let segments = ["Anna called.", "She said\nhi.", "merge", "Bye."];
+        let results = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(translate_with_context(
+                &recorder,
+                &segments,
+                "EN",
+                "DE",
+                Window {
+                    before: 1,
+                    after: 0,
+                },
+            ));
+        let texts: Vec<_> = results
+            .iter()
+            .map(|r| r.as_ref().unwrap().text.as_str())
+            .collect();
+        assert_eq!(texts, ["ANNA CALLED.", "SHE SAID\nHI.", "MERGE", "BYE."]);
+        let fell_back = |i: usize| results[i].as_ref().unwrap().extension("context_fallback");
+        assert!(fell_back(1).is_none());
+        assert!(fell_back(2).is_some());
+        assert_eq!(recorder.0.lock().unwrap()[1], "Anna called.\nShe said\nhi.");
+    }
+}
@@ -0,0 +1,262 @@
+//! Two-way translation between two people who write in different
+//! languages, as chat-translation bots need it.
+//!
+//! A [`Conversation`] knows its pair of languages, such as `EN` and `JA`,
+//! and translates each message into the one it is not written in. The
+//! message is first sent with its language left to detection, into the
+//! language of the message before it, since replies usually alternate;
+//! when it turns out to be in that language already, it is translated
+//! again the other way. A message in neither language is taken to be in
+//! the language expected next.
+//!
+//! With a context of `n` turns, the last `n` messages are sent along with
+//! each one, in its language, one per line, as
+//! [`translate_with_context`](crate::context::translate_with_context)
+//! does for documents; that second request is paid for in characters.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::{
+    context::{extract, line_count},
+    error::DeepLError,
+    lang,
+    translator::Translator,
+};
+
+/// One message of a conversation and its translation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Turn {
+    pub text: String,
+    pub translation: String,
+    /// The conversation's language `text` is in.
+    pub source_lang: String,
+    /// The other one, which `translation` is in.
+    pub target_lang: String,
+}
+
+pub struct Conversation {
+    provider: Arc<dyn Translator>,
+    langs: [String; 2],
+    context: usize,
+    history: VecDeque<Turn>,
+    /// Which of `langs` the last message was in.
+    last: Option<usize>,
+}
+
+/// Whether the detected language `detected` is the conversation language
+/// `lang`, either way round: `EN` is `EN-GB`.
+fn same(detected: &str, lang: &str) -> bool {
+    lang::matches(detected, lang) || lang::matches(lang, detected)
+}
+
+/// `lang` as a source language, without its region.
+fn source(lang: &str) -> &str {
+    lang.split('-').next().unwrap_or(lang)
+}
+
+impl Conversation {
+    /// A conversation between `first` and `second`, whose first message
+    /// is expected in `first`, with two turns of context.
+    pub fn new(
+        provider: Arc<dyn Translator>,
+        first: impl Into<String>,
+        second: impl Into<String>,
+    ) -> Self {
+        Self {
+            provider,
+            langs: [first.into(), second.into()],
+            context: 2,
+            history: VecDeque::new(),
+            last: None,
+        }
+    }
+
+    /// Sends the last `turns` messages along with each one; 0 sends every
+    /// message on its own.
+    pub fn with_context(mut self, turns: usize) -> Self {
+        self.context = turns;
+        while self.history.len() > turns {
+            self.history.pop_front();
+        }
+        self
+    }
+
+    /// The turns kept as context, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &Turn> {
+        self.history.iter()
+    }
+
+    /// Forgets the conversation so far, as for a new one.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.last = None;
+    }
+
+    /// Translates `text` into whichever of the two languages it is not
+    /// in, and remembers it as the latest turn.
+    pub async fn translate(&mut self, text: &str) -> Result<Turn, DeepLError> {
+        let expected = self.last.map_or(0, |last| 1 - last);
+        let guess = 1 - expected;
+        let first = self
+            .provider
+            .translate(text, "auto", &self.langs[guess])
+            .await?;
+        let side = match first.detected_source.as_deref() {
+            Some(detected) if same(detected, &self.langs[guess]) => guess,
+            _ => expected,
+        };
+        let target = 1 - side;
+        let mut translation = (side == expected).then_some(first.text);
+        if !self.history.is_empty() && self.context > 0 {
+            let mut lines: Vec<&str> = self
+                .history
+                .iter()
+                .map(|turn| {
+                    if turn.source_lang == self.langs[side] {
+                        turn.text.as_str()
+                    } else {
+                        turn.translation.as_str()
+                    }
+                })
+                .collect();
+            let start = lines.iter().map(|l| line_count(l)).sum();
+            lines.push(text);
+            let joined = lines.join("\n");
+            let total = line_count(&joined);
+            let with_context = self
+                .provider
+                .translate(&joined, source(&self.langs[side]), &self.langs[target])
+                .await?;
+            // Else the translation without context stands.
+            if let Some(own) = extract(&with_context.text, total, start, line_count(text)) {
+                translation = Some(own);
+            }
+        }
+        let translation = match translation {
+            Some(translation) => translation,
+            None => {
+                self.provider
+                    .translate(text, source(&self.langs[side]), &self.langs[target])
+                    .await?
+                    .text
+            }
+        };
+
+        let turn = Turn {
+            text: text.to_string(),
+            translation,
+            source_lang: self.langs[side].clone(),
+            target_lang: self.langs[target].clone(),
+        };
+        self.last = Some(side);
+        if self.context > 0 {
+            if self.history.len() == self.context {
+                self.history.pop_front();
+            }
+            self.history.push_back(turn.clone());
+        }
+        Ok(turn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::translator::{BoxFuture, Translation};
+
+    /// Takes texts with kana to be Japanese and everything else English,
+    /// tags each line with the target language and records the requests.
+    struct Tagger(Mutex<Vec<(String, String, String)>>);
+
+    impl Translator for Tagger {
+        fn name(&self) -> &str {
+            "tagger"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            src_lang: &'a str,
+            target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            if let Ok(mut sent) = self.0.lock() {
+                sent.push((
+                    text.to_string(),
+                    src_lang.to_string(),
+                    target_lang.to_string(),
+                ));
+            }
+            let japanese = text.chars().any(|c| ('\u{3040}'..='\u{30ff}').contains(&c));
+            let lines: Vec<String> = text
+                .split('\n')
+                .map(|line| format!("{}:{}", target_lang, line))
+                .collect();
+            Box::pin(async move {
+                Ok(Translation {
+                    text: lines.join("\n"),
+                    detected_source: Some(if japanese { "JA" } else { "EN" }.to_string()),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_messages_go_to_the_other_language() {
+        let tagger = Arc::new(Tagger(Mutex::new(Vec::new())));
+        let mut conversation = Conversation::new(tagger.clone(), "EN-US", "JA").with_context(0);
+        run(async {
+            let turn = conversation.translate("Hello!").await.unwrap();
+            assert_eq!(
+                (turn.translation.as_str(), turn.target_lang.as_str()),
+                ("JA:Hello!", "JA")
+            );
+            let turn = conversation.translate("こんにちは").await.unwrap();
+            assert_eq!(turn.translation, "EN-US:こんにちは");
+            // The same speaker again: the first guess is wrong.
+            let turn = conversation.translate("さようなら").await.unwrap();
+            assert_eq!(
+                (turn.translation.as_str(), turn.source_lang.as_str()),
+                ("EN-US:さようなら", "JA")
+            );
+        });
+        let sent = tagger.0.lock().unwrap();
+        assert_eq!(sent.len(), 4);
+        assert_eq!(
+            sent[3],
+            (
+                "さようなら".to_string(),
+                "JA".to_string(),
+                "EN-US".to_string()
+            )
+        );
+        assert_eq!(conversation.history().count(), 0);
+    }
+
+    #[test]
+    fn test_context_is_sent_in_the_message_language() {
+        let tagger = Arc::new(Tagger(Mutex::new(Vec::new())));
+        let mut conversation = Conversation::new(tagger.clone(), "EN", "JA").with_context(1);
+        run(async {
+            conversation.translate("Is it ready?").await.unwrap();
+            let turn = conversation.translate("はい\nできました").await.unwrap();
+            assert_eq!(turn.translation, "EN:はい\nEN:できました");
+        });
+        let sent = tagger.0.lock().unwrap();
+        // The English question goes along in its Japanese translation.
+        assert_eq!(sent.last().unwrap().0, "JA:Is it ready?\nはい\nできました");
+        let history: Vec<_> = conversation.history().map(|t| t.text.as_str()).collect();
+        assert_eq!(history, ["はい\nできました"]);
+        conversation.clear();
+        assert_eq!(conversation.history().count(), 0);
+    }
+}
@@ -0,0 +1,281 @@
+//! The cookies DeepL sets, kept and sent back like the app does, so that a
+//! client carries on one session instead of making a fresh anonymous hit
+//! with every request.
+//!
+//! A [`CookieJar`] handles the `Domain`, `Path`, `Expires`, `Max-Age` and
+//! `Secure` attributes and ignores the rest. It is [`Stateful`], and
+//! [`save`](CookieJar::save) and [`load`](CookieJar::load) keep it in a
+//! file of its own, so that the session survives restarts.
+
+use std::{
+    fs, io,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{clock, state::Stateful, storage::unix_millis, sync::write_atomic};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Lower case, without a leading dot.
+    pub domain: String,
+    /// Sent to `domain` only, not to its subdomains: set without a
+    /// `Domain` attribute.
+    pub host_only: bool,
+    pub path: String,
+    pub secure: bool,
+    /// Milliseconds since the epoch; `None` for a cookie that lasts the
+    /// session.
+    pub expires_ms: Option<u64>,
+}
+
+impl Cookie {
+    fn expired(&self, now_ms: u64) -> bool {
+        self.expires_ms.is_some_and(|at| at <= now_ms)
+    }
+
+    fn matches(&self, host: &str, path: &str, secure: bool) -> bool {
+        let domain = host == self.domain
+            || (!self.host_only
+                && host
+                    .strip_suffix(&self.domain)
+                    .is_some_and(|sub| sub.ends_with('.')));
+        let path = path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+        domain && path && (secure || !self.secure)
+    }
+}
+
+/// The directory of `path`, where a cookie set without `Path` applies.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(end) => path[..end].to_string(),
+    }
+}
+
+/// Parses the `Set-Cookie` value `header`, sent by `host` for `path`.
+fn parse(header: &str, host: &str, path: &str, now: SystemTime) -> Option<Cookie> {
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let host = host.to_ascii_lowercase();
+    let mut cookie = Cookie {
+        name: name.to_string(),
+        value: value.trim().trim_matches('"').to_string(),
+        domain: host.clone(),
+        host_only: true,
+        path: default_path(path),
+        secure: false,
+        expires_ms: None,
+    };
+    let mut max_age = None;
+    for part in parts {
+        let (key, value) = part.split_once('=').unwrap_or((part, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                let allowed = host == domain
+                    || host
+                        .strip_suffix(&domain)
+                        .is_some_and(|sub| sub.ends_with('.'));
+                // A host may not set cookies for a sibling or a parent
+                // other than its own domain.
+                if !allowed {
+                    return None;
+                }
+                cookie.domain = domain;
+                cookie.host_only = false;
+            }
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "secure" => cookie.secure = true,
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "expires" if max_age.is_none() => {
+                if let Ok(at) = httpdate::parse_http_date(value) {
+                    cookie.expires_ms = Some(unix_millis(at));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(secs) = max_age {
+        cookie.expires_ms = Some(match u64::try_from(secs) {
+            Ok(secs) => unix_millis(now + Duration::from_secs(secs)),
+            // Zero or negative: delete it now.
+            Err(_) => 0,
+        });
+    }
+    Some(cookie)
+}
+
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Cookie>> {
+        self.cookies.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Keeps the cookie in the `Set-Cookie` value `header` of a response
+    /// from `host` to a request for `path`, replacing the one with the
+    /// same name, domain and path. Malformed cookies and cookies for
+    /// another domain are ignored.
+    pub fn store(&self, host: &str, path: &str, header: &str) {
+        let now = clock::now();
+        let Some(cookie) = parse(header, host, path, now) else {
+            return;
+        };
+        let mut cookies = self.lock();
+        cookies.retain(|c| {
+            !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+        });
+        if !cookie.expired(unix_millis(now)) {
+            cookies.push(cookie);
+        }
+    }
+
+    /// The `Cookie` header for a request to `host` and `path`, or `None`
+    /// without a cookie to send. `secure` is whether it goes over HTTPS.
+    pub fn header(&self, host: &str, path: &str, secure: bool) -> Option<String> {
+        let host = host.to_ascii_lowercase();
+        let now_ms = unix_millis(clock::now());
+        let mut cookies = self.lock();
+        cookies.retain(|c| !c.expired(now_ms));
+        let mut matching: Vec<&Cookie> = cookies
+            .iter()
+            .filter(|c| c.matches(&host, path, secure))
+            .collect();
+        // The most specific path first, as browsers send them.
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        let pairs: Vec<String> = matching
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        (!pairs.is_empty()).then(|| pairs.join("; "))
+    }
+
+    /// The cookies that have not expired.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let now_ms = unix_millis(clock::now());
+        self.lock()
+            .iter()
+            .filter(|c| !c.expired(now_ms))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Writes the cookies to `path` as JSON.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.export_state())?;
+        write_atomic(path, &json, false)
+    }
+
+    /// The jar saved at `path`, or an empty one if nothing was saved there.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let jar = CookieJar::new();
+        match fs::read(path) {
+            Ok(bytes) => {
+                let state = serde_json::from_slice(&bytes)?;
+                jar.import_state(state)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(jar)
+    }
+}
+
+impl Stateful for CookieJar {
+    fn state_name(&self) -> &str {
+        "cookies"
+    }
+
+    fn export_state(&self) -> Value {
+        serde_json::json!(self.cookies())
+    }
+
+    fn import_state(&self, state: Value) -> Result<(), serde_json::Error> {
+        let cookies: Vec<Cookie> = serde_json::from_value(state)?;
+        let now_ms = unix_millis(clock::now());
+        *self.lock() = cookies.into_iter().filter(|c| !c.expired(now_ms)).collect();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookies_follow_domain_path_and_expiry() {
+        let jar = CookieJar::new();
+        jar.store(
+            "www2.deepl.com",
+            "/jsonrpc",
+            "LMTBID=v2|abc; Path=/; Secure",
+        );
+        jar.store(
+            "www2.deepl.com",
+            "/jsonrpc",
+            "privacySettings=%7B%7D; Domain=.deepl.com; Max-Age=3600",
+        );
+        jar.store(
+            "www2.deepl.com",
+            "/jsonrpc",
+            "tracker=1; Domain=example.com",
+        );
+        jar.store("www2.deepl.com", "/a/b", "scoped=1");
+        jar.store(
+            "www2.deepl.com",
+            "/",
+            "old=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+        );
+
+        assert_eq!(
+            jar.header("www2.deepl.com", "/jsonrpc", true).as_deref(),
+            Some("LMTBID=v2|abc; privacySettings=%7B%7D")
+        );
+        assert_eq!(
+            jar.header("api.deepl.com", "/jsonrpc", false).as_deref(),
+            Some("privacySettings=%7B%7D")
+        );
+        assert_eq!(
+            jar.header("www2.deepl.com", "/a/c", true).as_deref(),
+            Some("scoped=1; LMTBID=v2|abc; privacySettings=%7B%7D")
+        );
+        assert_eq!(jar.header("example.com", "/", true), None);
+
+        jar.store(
+            "www2.deepl.com",
+            "/jsonrpc",
+            "LMTBID=gone; Path=/; Max-Age=0",
+        );
+        let names: Vec<_> = jar.cookies().into_iter().map(|c| c.name).collect();
+        assert_eq!(names, ["privacySettings", "scoped"]);
+
+        let restored = CookieJar::new();
+        restored.import_state(jar.export_state()).unwrap();
+        assert_eq!(restored.cookies(), jar.cookies());
+    }
+}
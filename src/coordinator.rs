@@ -0,0 +1,371 @@
+//! A local daemon that short-lived processes on one machine, such as CLI
+//! invocations under `make -j` or parallel CI steps, consult for rate
+//! permits and cached translations, so that together they stay within one
+//! rate limit instead of each spending its own.
+//!
+//! [`Coordinator`] listens on a Unix socket and holds the token bucket and
+//! the cache; [`CoordinatorClient`] is its [`CacheStore`], and
+//! [`DeepLClient::with_coordinator`](crate::DeepLClient::with_coordinator)
+//! also waits for one of its permits before every request. Each call is
+//! one JSON line each way over a connection of its own:
+//! `{"op": "permit"}`, `{"op": "get", "key", "stale"}` and
+//! `{"op": "put", "key", "translation"}`.
+//!
+//! Only the user running the coordinator may use it: the socket is made
+//! readable and writable by its owner alone, and connections from
+//! processes of other users are closed unanswered, so nobody else can
+//! fill the cache with translations of their own.
+
+use std::{
+    fs, io,
+    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::{
+    cache::{CacheKey, CacheStats, CacheStore, TranslationCache},
+    diag,
+    limiter::RateLimiter,
+    storage::{StorageError, StorageResult},
+    translator::{BoxFuture, Translation},
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    Permit,
+    Get {
+        key: String,
+        #[serde(default)]
+        stale: bool,
+        /// Seconds past the TTL a stale entry may be.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_staleness: Option<u64>,
+    },
+    Put {
+        key: String,
+        translation: Translation,
+    },
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Reply {
+    ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    translation: Option<Translation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The daemon: one token bucket and one cache for every process that
+/// connects.
+pub struct Coordinator {
+    limiter: Option<RateLimiter>,
+    cache: TranslationCache,
+}
+
+impl Default for Coordinator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Coordinator {
+    /// Permits without limit and a cache of 10,000 translations.
+    pub fn new() -> Self {
+        Coordinator {
+            limiter: None,
+            cache: TranslationCache::new(10_000),
+        }
+    }
+
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    pub fn with_cache(mut self, cache: TranslationCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Listens at `path`, replacing a socket left behind by a coordinator
+    /// that is gone, and lets only its owner connect. Fails if one is
+    /// still answering there.
+    pub fn bind(path: &Path) -> io::Result<UnixListener> {
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("a coordinator is already listening at {}", path.display()),
+                ))
+            }
+            Err(e)
+                if e.kind() == io::ErrorKind::ConnectionRefused
+                    && fs::symlink_metadata(path)?.file_type().is_socket() =>
+            {
+                fs::remove_file(path)?
+            }
+            Err(_) => {}
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    /// Answers connections on `listener` until accepting one fails,
+    /// from processes of the user owning its socket file only.
+    pub async fn serve(self, listener: UnixListener) -> io::Result<()> {
+        let owner = match listener.local_addr()?.as_pathname() {
+            Some(path) => fs::metadata(path)?.uid(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the coordinator needs a socket with a path",
+                ))
+            }
+        };
+        let coordinator = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            match stream.peer_cred() {
+                Ok(peer) if peer.uid() == owner => {}
+                Ok(peer) => {
+                    diag::log_warn!("coordinator refused a process of uid {}", peer.uid());
+                    continue;
+                }
+                Err(e) => {
+                    diag::log_warn!("coordinator cannot identify a peer: {}", e);
+                    continue;
+                }
+            }
+            let coordinator = coordinator.clone();
+            tokio::spawn(async move {
+                if let Err(e) = coordinator.answer(stream).await {
+                    diag::log_warn!("coordinator connection failed: {}", e);
+                }
+            });
+        }
+    }
+
+    async fn answer(&self, stream: UnixStream) -> io::Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        while let Some(line) = lines.next_line().await? {
+            let reply = match serde_json::from_str(&line) {
+                Ok(request) => self.handle(request).await,
+                Err(e) => Reply {
+                    error: Some(e.to_string()),
+                    ..Reply::default()
+                },
+            };
+            let mut reply = serde_json::to_string(&reply)?;
+            reply.push('\n');
+            write.write_all(reply.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle(&self, request: Request) -> Reply {
+        let ok = Reply {
+            ok: true,
+            ..Reply::default()
+        };
+        match request {
+            Request::Permit => {
+                if let Some(limiter) = &self.limiter {
+                    limiter.acquire().await;
+                }
+                ok
+            }
+            Request::Get {
+                key,
+                stale,
+                max_staleness,
+            } => match key.parse::<CacheKey>() {
+                Ok(key) if stale => Reply {
+                    translation: self
+                        .cache
+                        .get_stale(&key, max_staleness.map(Duration::from_secs)),
+                    ..ok
+                },
+                Ok(key) => Reply {
+                    translation: self.cache.get(&key),
+                    ..ok
+                },
+                Err(()) => Reply {
+                    error: Some(format!("invalid cache key {:?}", key)),
+                    ..Reply::default()
+                },
+            },
+            Request::Put { key, translation } => match key.parse::<CacheKey>() {
+                Ok(key) => {
+                    self.cache.insert(key, translation);
+                    ok
+                }
+                Err(()) => Reply {
+                    error: Some(format!("invalid cache key {:?}", key)),
+                    ..Reply::default()
+                },
+            },
+        }
+    }
+}
+
+/// The side of a process consulting the [`Coordinator`] at a socket.
+#[derive(Debug)]
+pub struct CoordinatorClient {
+    path: PathBuf,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CoordinatorClient {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CoordinatorClient {
+            path: path.into(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn call(&self, request: &Request) -> io::Result<Reply> {
+        let stream = UnixStream::connect(&self.path).await?;
+        let (read, mut write) = stream.into_split();
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        write.write_all(line.as_bytes()).await?;
+        let mut reply = String::new();
+        BufReader::new(read).read_line(&mut reply).await?;
+        let reply: Reply = serde_json::from_str(&reply)?;
+        match reply.error {
+            Some(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            None => Ok(reply),
+        }
+    }
+
+    /// Waits until the coordinator hands out a permit.
+    pub async fn permit(&self) -> io::Result<()> {
+        self.call(&Request::Permit).await.map(|_| ())
+    }
+
+    async fn lookup(
+        &self,
+        key: &CacheKey,
+        stale: bool,
+        max_staleness: Option<Duration>,
+    ) -> StorageResult<Option<Translation>> {
+        let request = Request::Get {
+            key: key.to_string(),
+            stale,
+            max_staleness: max_staleness.map(|max| max.as_secs()),
+        };
+        let translation = self
+            .call(&request)
+            .await
+            .map_err(StorageError::Io)?
+            .translation;
+        let counter = match translation {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(translation)
+    }
+}
+
+impl CacheStore for CoordinatorClient {
+    fn get<'a>(&'a self, key: &'a CacheKey) -> BoxFuture<'a, StorageResult<Option<Translation>>> {
+        Box::pin(self.lookup(key, false, None))
+    }
+
+    fn get_stale<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        max_staleness: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<Option<Translation>>> {
+        Box::pin(self.lookup(key, true, max_staleness))
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        translation: &'a Translation,
+    ) -> BoxFuture<'a, StorageResult<()>> {
+        Box::pin(async move {
+            let request = Request::Put {
+                key: key.to_string(),
+                translation: translation.clone(),
+            };
+            self.call(&request)
+                .await
+                .map(|_| ())
+                .map_err(StorageError::Io)
+        })
+    }
+
+    /// This process's lookups only.
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ..CacheStats::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_processes_share_permits_and_cache() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("deeplx-coord-{}.sock", std::process::id()));
+        runtime.block_on(async {
+            let listener = Coordinator::bind(&path).unwrap();
+            assert!(Coordinator::bind(&path).is_err());
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            let coordinator = Coordinator::new().with_rate_limiter(RateLimiter::new(0.5, 2));
+            tokio::spawn(coordinator.serve(listener));
+
+            let (first, second) = (CoordinatorClient::new(&path), CoordinatorClient::new(&path));
+            first.permit().await.unwrap();
+            second.permit().await.unwrap();
+            let third = tokio::time::timeout(Duration::from_millis(100), first.permit()).await;
+            assert!(third.is_err(), "the bucket is shared");
+
+            let key = CacheKey::new("hello", "EN", "DE", "");
+            let translation = Translation {
+                text: "hallo".to_string(),
+                ..Default::default()
+            };
+            assert_eq!(second.get(&key).await.unwrap(), None);
+            first.put(&key, &translation).await.unwrap();
+            let hit = second.get(&key).await.unwrap().unwrap();
+            assert_eq!(hit.text, "hallo");
+            assert_eq!((second.stats().hits, second.stats().misses), (1, 1));
+        });
+        drop(runtime);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,115 @@
+//! Translate repeated segments once and fan the result out to every copy.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{error::DeepLError, translator::Translation, Translator};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    pub total: usize,
+    pub unique: usize,
+    pub duplicates: usize,
+    /// Characters that did not have to be sent because they repeat an
+    /// earlier segment.
+    pub saved_chars: usize,
+}
+
+#[derive(Debug)]
+pub struct Dedup<'a> {
+    pub unique: Vec<&'a str>,
+    /// For every input segment, the index of its text in `unique`.
+    pub index: Vec<usize>,
+    pub stats: DedupStats,
+}
+
+impl<'a> Dedup<'a> {
+    pub fn new(segments: &[&'a str]) -> Self {
+        let mut seen: HashMap<&str, usize> = HashMap::with_capacity(segments.len());
+        let mut unique = Vec::new();
+        let mut index = Vec::with_capacity(segments.len());
+        let mut saved_chars = 0;
+        for segment in segments {
+            let next = unique.len();
+            let i = *seen.entry(segment).or_insert(next);
+            if i == next {
+                unique.push(*segment);
+            } else {
+                saved_chars += segment.chars().count();
+            }
+            index.push(i);
+        }
+        let stats = DedupStats {
+            total: segments.len(),
+            unique: unique.len(),
+            duplicates: segments.len() - unique.len(),
+            saved_chars,
+        };
+        Self {
+            unique,
+            index,
+            stats,
+        }
+    }
+
+    /// Expands one result per unique segment into one result per input
+    /// segment. Returns `None` if `results` is shorter than `unique`.
+    pub fn fan_out<T: Clone>(&self, results: &[T]) -> Option<Vec<T>> {
+        self.index
+            .iter()
+            .map(|i| results.get(*i).cloned())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct DedupOutcome {
+    pub results: Vec<Result<Translation, Arc<DeepLError>>>,
+    pub stats: DedupStats,
+}
+
+pub async fn translate_deduplicated(
+    provider: &dyn Translator,
+    segments: &[&str],
+    src_lang: &str,
+    target_lang: &str,
+) -> DedupOutcome {
+    let dedup = Dedup::new(segments);
+    let mut unique_results = Vec::with_capacity(dedup.unique.len());
+    for text in &dedup.unique {
+        unique_results.push(
+            provider
+                .translate(text, src_lang, target_lang)
+                .await
+                .map_err(Arc::new),
+        );
+    }
+    DedupOutcome {
+        results: dedup.fan_out(&unique_results).unwrap_or_default(),
+        stats: dedup.stats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_stats_and_fan_out() {
+        let dedup = Dedup::new(&["OK", "Cancel", "OK", "OK", "Save"]);
+        assert_eq!(dedup.unique, ["OK", "Cancel", "Save"]);
+        assert_eq!(
+            dedup.stats,
+            DedupStats {
+                total: 5,
+                unique: 3,
+                duplicates: 2,
+                saved_chars: 4,
+            }
+        );
+        assert_eq!(
+            dedup.fan_out(&["好", "取消", "保存"]).unwrap(),
+            ["好", "取消", "好", "好", "保存"]
+        );
+        assert_eq!(dedup.fan_out(&["好"]), None);
+    }
+}
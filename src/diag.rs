@@ -0,0 +1,37 @@
+//! Crate-internal logging macros. With the `tracing` feature they forward
+//! to `tracing`, otherwise with the `log` feature to `log`; the crate
+//! never logs through both. Without either they compile to nothing, though
+//! the arguments are still type-checked. Pass user text through
+//! [`Redaction`](crate::redact::Redaction) before logging it.
+
+// Not every feature combination logs.
+#[allow(unused_macros)]
+macro_rules! log_debug {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)+);
+        #[cfg(all(feature = "log", not(feature = "tracing")))]
+        log::debug!($($arg)+);
+        #[cfg(not(any(feature = "tracing", feature = "log")))]
+        {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+#[allow(unused_macros)]
+macro_rules! log_warn {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)+);
+        #[cfg(all(feature = "log", not(feature = "tracing")))]
+        log::warn!($($arg)+);
+        #[cfg(not(any(feature = "tracing", feature = "log")))]
+        {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+#[allow(unused_imports)]
+pub(crate) use {log_debug, log_warn};
@@ -0,0 +1,218 @@
+//! Self-diagnostics for when translations suddenly fail: `deeplx doctor`
+//! and [`DeepLClient::self_test`].
+
+use std::{fmt, time::Duration};
+
+use crate::{breaker::CircuitState, client::DeepLClient, clock::Instant, error::DeepLError};
+
+/// What every probe request translates.
+const PROBE: &str = "Hello, world!";
+
+/// Round trips slower than this get a warning even when they succeed.
+const SLOW: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelfTest {
+    pub checks: Vec<Check>,
+    /// Round trip of the probe request, if it got a response at all.
+    pub latency: Option<Duration>,
+}
+
+impl SelfTest {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Fail)
+    }
+}
+
+impl fmt::Display for SelfTest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let tag = match check.status {
+                CheckStatus::Pass => "ok",
+                CheckStatus::Warn => "warn",
+                CheckStatus::Fail => "FAIL",
+            };
+            writeln!(f, "[{:>4}] {:<12} {}", tag, check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+fn hint(error: &DeepLError) -> String {
+    match error {
+        DeepLError::Network(e) => format!(
+            "cannot reach the endpoint ({}); check DNS, firewall and proxy settings",
+            e
+        ),
+        DeepLError::RateLimited { .. } => format!(
+            "upstream is rate limiting this IP; wait {}s or route through a proxy",
+            error.retry_after().unwrap_or_default().as_secs().max(1)
+        ),
+        DeepLError::Blocked { .. } => {
+            "403 Forbidden: the IP or headers are being blocked".to_string()
+        }
+        DeepLError::ChallengeRequired { marker, .. } => format!(
+            "upstream sent a {} challenge page; use another dl_session or proxy",
+            marker
+        ),
+        DeepLError::Status { status, .. } if *status >= 500 => {
+            format!("upstream error {}; usually transient, retry later", status)
+        }
+        DeepLError::Deserialize(e) => format!(
+            "unexpected response body ({}); the endpoint may be wrong or DeepL changed its API",
+            e
+        ),
+        other => other.to_string(),
+    }
+}
+
+impl DeepLClient {
+    /// Checks the local limiter and circuit breaker, sends a probe
+    /// sentence through the proxy and with each dl_session on its own,
+    /// then translates it as usual and reports what went wrong, with
+    /// suggestions.
+    pub async fn self_test(&self) -> SelfTest {
+        let mut report = SelfTest::default();
+        let pressure = self.pressure();
+        report.checks.push(match pressure.circuit {
+            CircuitState::Open { remaining } => Check::new(
+                "circuit",
+                CheckStatus::Warn,
+                format!(
+                    "open after repeated failures; requests resume in {}s",
+                    remaining.as_secs()
+                ),
+            ),
+            _ => Check::new("circuit", CheckStatus::Pass, "closed"),
+        });
+        if let Some(permits) = pressure.available_permits {
+            report.checks.push(match permits {
+                0 => Check::new(
+                    "rate limit",
+                    CheckStatus::Warn,
+                    "no permits left; requests will wait for the limiter",
+                ),
+                n => Check::new("rate limit", CheckStatus::Pass, format!("{} permits", n)),
+            });
+        }
+        if let Some(proxy) = self.proxy_url() {
+            let started = Instant::now();
+            let result = self.send_as(None, &[PROBE], "EN", "DE").await;
+            let elapsed = started.elapsed().as_millis();
+            report.checks.push(match result {
+                Err(e @ DeepLError::Network(_)) => Check::new(
+                    "proxy",
+                    CheckStatus::Fail,
+                    format!("{}: {}", proxy, hint(&e)),
+                ),
+                Err(DeepLError::Status { status: 407, .. }) => Check::new(
+                    "proxy",
+                    CheckStatus::Fail,
+                    format!("{} wants credentials; add them to its URL", proxy),
+                ),
+                // Whatever upstream made of the probe, it got there.
+                _ => Check::new(
+                    "proxy",
+                    CheckStatus::Pass,
+                    format!("{} relayed the probe in {} ms", proxy, elapsed),
+                ),
+            });
+        }
+        if let Some(pool) = &self.sessions {
+            // Each token on its own, so one rejected token does not hide
+            // behind the next; the pool learns what the probes find.
+            for (index, (token, usage)) in pool.tokens().iter().zip(pool.usage()).enumerate() {
+                let result = self.send_as(Some(token), &[PROBE], "EN", "DE").await;
+                report.checks.push(match result {
+                    Ok(_) => {
+                        pool.record_use(index, PROBE.chars().count());
+                        Check::new(
+                            "dl_session",
+                            CheckStatus::Pass,
+                            format!("{} accepted", usage.hint),
+                        )
+                    }
+                    Err(DeepLError::Status { status: 401, .. })
+                    | Err(DeepLError::ChallengeRequired { .. }) => {
+                        pool.invalidate(index);
+                        Check::new(
+                            "dl_session",
+                            CheckStatus::Warn,
+                            format!("{} rejected; renew it from a logged-in browser", usage.hint),
+                        )
+                    }
+                    Err(e) => {
+                        pool.record_failure(index);
+                        Check::new(
+                            "dl_session",
+                            CheckStatus::Warn,
+                            format!("{} untested: {}", usage.hint, hint(&e)),
+                        )
+                    }
+                });
+            }
+        }
+
+        let started = Instant::now();
+        let result = self.translate_raw(PROBE, "EN", "DE").await;
+        let elapsed = started.elapsed();
+        match result {
+            Ok(resp) => {
+                report.latency = Some(elapsed);
+                let status = if elapsed > SLOW {
+                    CheckStatus::Warn
+                } else {
+                    CheckStatus::Pass
+                };
+                report.checks.push(Check::new(
+                    "connectivity",
+                    status,
+                    format!("{} answered in {} ms", self.endpoint(), elapsed.as_millis()),
+                ));
+                let text = resp.result.texts.first().map_or("", |t| t.text.as_str());
+                report.checks.push(if text.trim().is_empty() {
+                    Check::new(
+                        "response",
+                        CheckStatus::Warn,
+                        "empty translation; the response format may have changed",
+                    )
+                } else {
+                    Check::new("response", CheckStatus::Pass, format!("EN→DE: {}", text))
+                });
+            }
+            Err(e) => {
+                if !matches!(e, DeepLError::Network(_)) {
+                    report.latency = Some(elapsed);
+                }
+                report
+                    .checks
+                    .push(Check::new("connectivity", CheckStatus::Fail, hint(&e)));
+            }
+        }
+        report
+    }
+}
@@ -0,0 +1,326 @@
+//! Whole documents (`.docx`, `.pptx`, `.pdf`, `.html` and the other
+//! formats DeepL accepts) translated by the official API, which keeps
+//! their layout.
+//!
+//! [`DeepLClient::translate_document`] uploads the file, polls its status
+//! until DeepL is done, reporting each one to the progress callback, and
+//! downloads the result.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    client::official_ok, clock, error::DeepLError, lang, official, options::Formality,
+    signing::sha256, DeepLClient,
+};
+
+/// The longest wait between two status checks, whatever DeepL estimates.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What identifies an upload: DeepL needs both to report on it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentHandle {
+    pub document_id: String,
+    pub document_key: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentState {
+    Queued,
+    Translating,
+    Done,
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentStatus {
+    pub document_id: String,
+    pub status: DocumentState,
+    /// DeepL's estimate while translating.
+    #[serde(default)]
+    pub seconds_remaining: Option<u64>,
+    /// Set once done.
+    #[serde(default)]
+    pub billed_characters: Option<u64>,
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+type Progress = Arc<dyn Fn(&DocumentStatus) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct DocumentOptions {
+    pub source_lang: String,
+    pub target_lang: String,
+    pub formality: Option<Formality>,
+    /// A glossary from
+    /// [`create_glossary`](DeepLClient::create_glossary); needs a source
+    /// language.
+    pub glossary_id: Option<String>,
+    /// Where to write the translation; it is returned as bytes otherwise.
+    pub output: Option<PathBuf>,
+    /// The shortest wait between two status checks.
+    pub poll_interval: Duration,
+    progress: Option<Progress>,
+}
+
+impl DocumentOptions {
+    pub fn new(target_lang: impl Into<String>) -> Self {
+        DocumentOptions {
+            source_lang: "auto".to_string(),
+            target_lang: target_lang.into(),
+            formality: None,
+            glossary_id: None,
+            output: None,
+            poll_interval: Duration::from_secs(1),
+            progress: None,
+        }
+    }
+
+    pub fn with_source(mut self, source_lang: impl Into<String>) -> Self {
+        self.source_lang = source_lang.into();
+        self
+    }
+
+    pub fn with_formality(mut self, formality: Option<Formality>) -> Self {
+        self.formality = formality;
+        self
+    }
+
+    pub fn with_glossary_id(mut self, glossary_id: Option<String>) -> Self {
+        self.glossary_id = glossary_id;
+        self
+    }
+
+    pub fn with_output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Calls `progress` with every status DeepL reports.
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(&DocumentStatus) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+}
+
+impl fmt::Debug for DocumentOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DocumentOptions")
+            .field("source_lang", &self.source_lang)
+            .field("target_lang", &self.target_lang)
+            .field("formality", &self.formality)
+            .field("glossary_id", &self.glossary_id)
+            .field("output", &self.output)
+            .field("poll_interval", &self.poll_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DocumentOutput {
+    /// Written to [`DocumentOptions::output`].
+    Written(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+/// A `multipart/form-data` body of text `fields` and one file.
+fn multipart(boundary: &str, fields: &[(&str, &str)], file_name: &str, file: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(file.len() + 512);
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    let file_name = file_name.replace(['"', '\r', '\n'], "_");
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            boundary, file_name
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+impl DeepLClient {
+    /// Translates the document at `path` through the official API and
+    /// writes it to `options.output`, or returns it. Needs
+    /// [`with_auth_key`](Self::with_auth_key); fails with
+    /// [`DeepLError::DocumentFailed`] when DeepL cannot translate the
+    /// file.
+    pub async fn translate_document(
+        &self,
+        path: &Path,
+        options: &DocumentOptions,
+    ) -> Result<DocumentOutput, DeepLError> {
+        let file = std::fs::read(path)?;
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "document".to_string());
+        let handle = self.upload_document(&file_name, &file, options).await?;
+        loop {
+            let status = self.document_status(&handle).await?;
+            if let Some(progress) = &options.progress {
+                progress(&status);
+            }
+            match status.status {
+                DocumentState::Done => break,
+                DocumentState::Error => {
+                    return Err(DeepLError::DocumentFailed {
+                        document_id: handle.document_id,
+                        message: status.error_message.unwrap_or_default(),
+                    })
+                }
+                DocumentState::Queued | DocumentState::Translating => {
+                    let wait = status
+                        .seconds_remaining
+                        .map(Duration::from_secs)
+                        .unwrap_or_default()
+                        .clamp(
+                            options.poll_interval,
+                            MAX_POLL_INTERVAL.max(options.poll_interval),
+                        );
+                    clock::sleep(wait).await;
+                }
+            }
+        }
+        let bytes = self.download_document(&handle).await?;
+        match &options.output {
+            Some(output) => {
+                std::fs::write(output, &bytes)?;
+                Ok(DocumentOutput::Written(output.clone()))
+            }
+            None => Ok(DocumentOutput::Bytes(bytes)),
+        }
+    }
+
+    /// Starts translating `file`; [`translate_document`](Self::translate_document)
+    /// does this and waits for the result.
+    pub async fn upload_document(
+        &self,
+        file_name: &str,
+        file: &[u8],
+        options: &DocumentOptions,
+    ) -> Result<DocumentHandle, DeepLError> {
+        self.upstream_open()?;
+        let key = self.official_key()?;
+        let target_lang = options.target_lang.to_uppercase();
+        let source_lang = options.source_lang.to_uppercase();
+        let mut fields = vec![("target_lang", target_lang.as_str())];
+        if !lang::is_auto(&source_lang) {
+            fields.push(("source_lang", &source_lang));
+        }
+        match options.formality {
+            Some(Formality::Formal) => fields.push(("formality", "prefer_more")),
+            Some(Formality::Informal) => fields.push(("formality", "prefer_less")),
+            None => {}
+        }
+        if let Some(id) = &options.glossary_id {
+            fields.push(("glossary_id", id));
+        }
+        // Derived from the file, so the file cannot contain it.
+        let boundary: String = sha256(file)[..12]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let resp = self
+            .http
+            .post(official::documents_endpoint(self.endpoint()))
+            .header(AUTHORIZATION, format!("DeepL-Auth-Key {}", key))
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(multipart(&boundary, &fields, file_name, file))
+            .send()
+            .await?;
+        Ok(serde_json::from_str(
+            &official_ok(resp).await?.text().await?,
+        )?)
+    }
+
+    pub async fn document_status(
+        &self,
+        handle: &DocumentHandle,
+    ) -> Result<DocumentStatus, DeepLError> {
+        self.upstream_open()?;
+        let key = self.official_key()?;
+        let url = format!(
+            "{}/{}",
+            official::documents_endpoint(self.endpoint()),
+            handle.document_id
+        );
+        let resp = self
+            .http
+            .post(url)
+            .header(AUTHORIZATION, format!("DeepL-Auth-Key {}", key))
+            .json(&json!({"document_key": handle.document_key}))
+            .send()
+            .await?;
+        Ok(serde_json::from_str(
+            &official_ok(resp).await?.text().await?,
+        )?)
+    }
+
+    /// The translated file, once [`document_status`](Self::document_status)
+    /// reports it done. DeepL lets it be downloaded once.
+    pub async fn download_document(&self, handle: &DocumentHandle) -> Result<Vec<u8>, DeepLError> {
+        self.upstream_open()?;
+        let key = self.official_key()?;
+        let url = format!(
+            "{}/{}/result",
+            official::documents_endpoint(self.endpoint()),
+            handle.document_id
+        );
+        let resp = self
+            .http
+            .post(url)
+            .header(AUTHORIZATION, format!("DeepL-Auth-Key {}", key))
+            .json(&json!({"document_key": handle.document_key}))
+            .send()
+            .await?;
+        Ok(official_ok(resp).await?.bytes().await?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart_body() {
+        let body = multipart("b", &[("target_lang", "DE")], "a \"b\".docx", b"PK");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--b\r\nContent-Disposition: form-data; name=\"target_lang\"\r\n\r\nDE\r\n\
+             --b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a _b_.docx\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\nPK\r\n--b--\r\n"
+        );
+    }
+}
@@ -0,0 +1,789 @@
+//! Email messages (`.eml`), as RFC 5322 text with MIME parts.
+//!
+//! [`translate_email`] translates the `text/plain` parts as
+//! [plain text](crate::formats::plain_text) and the body of the
+//! `text/html` parts with HTML tag handling. Everything else is written
+//! back as it was: the headers, attachments, inline images and text parts
+//! whose charset or transfer encoding cannot be decoded. Translated parts
+//! are written in UTF-8 and keep their transfer encoding, except 7-bit
+//! parts that are no longer ASCII, which become quoted-printable.
+
+use std::{ops::Range, sync::Arc};
+
+use crate::{
+    formats::{translate_file_with, FileError, LineEnding, OnFailure, PlainTextFile},
+    options::{TagHandling, TagMode, TranslateOptions},
+    Translator,
+};
+
+/// The most HTML sent in one request, in bytes; longer bodies are split
+/// before a tag.
+const HTML_CHUNK: usize = 4000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmailOptions {
+    /// Keep the original text of each part below its translation.
+    pub keep_original: bool,
+    /// Written between the translation and the original.
+    pub separator: String,
+}
+
+impl Default for EmailOptions {
+    fn default() -> Self {
+        Self {
+            keep_original: false,
+            separator: "-------- Original message --------".to_string(),
+        }
+    }
+}
+
+impl EmailOptions {
+    pub fn with_keep_original(mut self, keep: bool) -> Self {
+        self.keep_original = keep;
+        self
+    }
+
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EmailOutcome {
+    pub output: String,
+    /// Text parts translated.
+    pub translated: usize,
+    /// Text parts left untranslated because their charset or transfer
+    /// encoding could not be decoded.
+    pub skipped: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Part {
+    /// The header fields and the blank line after them, as written.
+    head: String,
+    body: Body,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Body {
+    Single(String),
+    Multipart {
+        preamble: String,
+        /// Each part with the delimiter line in front of it.
+        parts: Vec<(String, Part)>,
+        /// The closing delimiter and the epilogue after it; `None` when
+        /// the message ends without one.
+        close: Option<String>,
+    },
+}
+
+impl Part {
+    fn parse(text: &str) -> Part {
+        let (head, body) = if text.starts_with('\n') {
+            text.split_at(1)
+        } else {
+            match text.find("\n\n") {
+                Some(i) => text.split_at(i + 2),
+                None => (text, ""),
+            }
+        };
+        let content_type = content_type(head);
+        let multipart = media_type(&content_type)
+            .starts_with("multipart/")
+            .then(|| param(&content_type, "boundary"))
+            .flatten()
+            .and_then(|boundary| parse_multipart(body, &boundary));
+        Part {
+            head: head.to_string(),
+            body: multipart.unwrap_or_else(|| Body::Single(body.to_string())),
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        out.push_str(&self.head);
+        match &self.body {
+            Body::Single(body) => out.push_str(body),
+            Body::Multipart {
+                preamble,
+                parts,
+                close,
+            } => {
+                out.push_str(preamble);
+                for (n, (delimiter, part)) in parts.iter().enumerate() {
+                    out.push_str(delimiter);
+                    out.push('\n');
+                    part.render(out);
+                    if n + 1 < parts.len() || close.is_some() {
+                        out.push('\n');
+                    }
+                }
+                if let Some(close) = close {
+                    out.push_str(close);
+                }
+            }
+        }
+    }
+}
+
+fn parse_multipart(body: &str, boundary: &str) -> Option<Body> {
+    let delimiter = format!("--{}", boundary);
+    // The byte range of each delimiter line, and whether it closes.
+    let mut lines: Vec<(Range<usize>, bool)> = Vec::new();
+    let mut pos = 0;
+    for line in body.split_inclusive('\n') {
+        let range = pos..pos + line.len();
+        pos += line.len();
+        match line.trim_end().strip_prefix(delimiter.as_str()) {
+            Some("") => lines.push((range, false)),
+            Some("--") => {
+                lines.push((range, true));
+                break;
+            }
+            _ => {}
+        }
+    }
+    let preamble = body[..lines.first()?.0.start].to_string();
+    let mut parts = Vec::new();
+    let mut close = None;
+    for (n, (range, closing)) in lines.iter().enumerate() {
+        if *closing {
+            close = Some(body[range.start..].to_string());
+            break;
+        }
+        let line = &body[range.clone()];
+        // The line break before a delimiter belongs to the delimiter.
+        let content = match lines.get(n + 1) {
+            Some((next, _)) => {
+                let content = &body[range.end..next.start];
+                content.strip_suffix('\n').unwrap_or(content)
+            }
+            None => &body[range.end..],
+        };
+        parts.push((
+            line.strip_suffix('\n').unwrap_or(line).to_string(),
+            Part::parse(content),
+        ));
+    }
+    Some(Body::Multipart {
+        preamble,
+        parts,
+        close,
+    })
+}
+
+/// The header fields in `head`: the byte range each spans, its name and
+/// its unfolded value.
+fn fields(head: &str) -> Vec<(Range<usize>, &str, String)> {
+    let mut out: Vec<(Range<usize>, &str, String)> = Vec::new();
+    let mut pos = 0;
+    for line in head.split_inclusive('\n') {
+        let range = pos..pos + line.len();
+        pos += line.len();
+        let text = line.trim_end_matches('\n');
+        if text.starts_with([' ', '\t']) {
+            if let Some(field) = out.last_mut() {
+                field.0.end = range.end;
+                field.2.push_str(text);
+            }
+        } else if let Some((name, value)) = text.split_once(':') {
+            out.push((range, name.trim_end(), value.to_string()));
+        }
+    }
+    out
+}
+
+fn field(head: &str, name: &str) -> Option<String> {
+    fields(head)
+        .into_iter()
+        .find(|(_, n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, _, value)| value.trim().to_string())
+}
+
+/// `head` with the field `name` replaced by, or added as, `value`.
+fn set_field(head: &str, name: &str, value: &str) -> String {
+    let line = format!("{}: {}\n", name, value);
+    let existing = fields(head)
+        .into_iter()
+        .find(|(_, n, _)| n.eq_ignore_ascii_case(name));
+    let range = match existing {
+        Some((range, _, _)) => range,
+        None if head == "\n" => 0..0,
+        None if head.ends_with("\n\n") => head.len() - 1..head.len() - 1,
+        None => head.len()..head.len(),
+    };
+    let mut out = head.to_string();
+    if range.start == out.len() && !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+        out.push_str(&line);
+    } else {
+        out.replace_range(range, &line);
+    }
+    out
+}
+
+/// The `Content-Type` of a part, `text/plain` when it has none.
+fn content_type(head: &str) -> String {
+    field(head, "content-type").unwrap_or_else(|| "text/plain".to_string())
+}
+
+/// `value` split at the semicolons outside quotes: the media type, then
+/// the parameters as written.
+fn split_params(value: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                out.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(value[start..].trim());
+    out
+}
+
+fn media_type(value: &str) -> String {
+    split_params(value)[0].to_ascii_lowercase()
+}
+
+fn param_name(param: &str) -> &str {
+    param.split('=').next().unwrap_or("").trim()
+}
+
+fn param(value: &str, name: &str) -> Option<String> {
+    split_params(value).into_iter().skip(1).find_map(|p| {
+        let (_, v) = p.split_once('=')?;
+        param_name(p)
+            .eq_ignore_ascii_case(name)
+            .then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+/// `value` with its charset replaced by UTF-8.
+fn with_utf8(value: &str) -> String {
+    let mut params = split_params(value);
+    let media = params.remove(0);
+    let mut out = vec![media];
+    out.extend(
+        params
+            .into_iter()
+            .filter(|p| !p.is_empty() && !param_name(p).eq_ignore_ascii_case("charset")),
+    );
+    out.push("charset=utf-8");
+    out.join("; ")
+}
+
+fn is_attachment(head: &str) -> bool {
+    field(head, "content-disposition").is_some_and(|d| media_type(&d) == "attachment")
+}
+
+/// The text parts of `part` to translate, attachments left out.
+fn text_parts<'p>(part: &'p mut Part, out: &mut Vec<&'p mut Part>) {
+    let media = media_type(&content_type(&part.head));
+    let text = (media == "text/plain" || media == "text/html") && !is_attachment(&part.head);
+    if let Body::Single(_) = part.body {
+        if text {
+            out.push(part);
+        }
+    } else if let Body::Multipart { parts, .. } = &mut part.body {
+        for (_, part) in parts {
+            text_parts(part, out);
+        }
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            break;
+        }
+        let value = BASE64.iter().position(|&b| b == c)? as u32;
+        acc = (acc << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// `bytes` in base64, in lines of 76 characters.
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() / 3 * 4 + bytes.len() / 57 + 4);
+    for (n, chunk) in bytes.chunks(3).enumerate() {
+        if n > 0 && n % 19 == 0 {
+            out.push('\n');
+        }
+        let byte = |i: usize| u32::from(chunk.get(i).copied().unwrap_or(0));
+        let group = byte(0) << 16 | byte(1) << 8 | byte(2);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(group >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn decode_quoted_printable(text: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    let lines: Vec<&str> = text.split('\n').collect();
+    for (n, line) in lines.iter().enumerate() {
+        let line = line.trim_end_matches([' ', '\t', '\r']);
+        let (line, soft) = match line.strip_suffix('=') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let bytes = line.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let hex = line
+                .get(i + 1..i + 3)
+                .filter(|h| h.bytes().all(|b| b.is_ascii_hexdigit()));
+            match hex {
+                Some(hex) if bytes[i] == b'=' => {
+                    out.push(u8::from_str_radix(hex, 16).unwrap_or(b'='));
+                    i += 3;
+                }
+                _ => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        if !soft && n + 1 < lines.len() {
+            out.push(b'\n');
+        }
+    }
+    out
+}
+
+/// `text` in quoted-printable, with soft line breaks keeping lines to 76
+/// characters.
+fn encode_quoted_printable(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() * 2);
+    for (n, line) in text.split('\n').enumerate() {
+        if n > 0 {
+            out.push('\n');
+        }
+        let bytes = line.as_bytes();
+        let mut used = 0;
+        for (i, &b) in bytes.iter().enumerate() {
+            let trailing = i + 1 == bytes.len();
+            let literal =
+                matches!(b, b'!'..=b'<' | b'>'..=b'~') || (matches!(b, b' ' | b'\t') && !trailing);
+            let encoded = if literal {
+                char::from(b).to_string()
+            } else {
+                format!("={:02X}", b)
+            };
+            // One column stays free for the `=` of a soft break.
+            if used + encoded.len() > 75 {
+                out.push_str("=\n");
+                used = 0;
+            }
+            out.push_str(&encoded);
+            used += encoded.len();
+        }
+    }
+    out
+}
+
+fn decode_charset(bytes: Vec<u8>, charset: &str) -> Option<String> {
+    match charset.to_ascii_lowercase().as_str() {
+        "" | "utf-8" | "utf8" | "us-ascii" | "ascii" => String::from_utf8(bytes).ok(),
+        "iso-8859-1" | "latin1" | "latin-1" => Some(bytes.iter().map(|&b| char::from(b)).collect()),
+        #[cfg(feature = "encoding")]
+        label => crate::encoding::Encoding::for_label(label.as_bytes())?
+            .decode_without_bom_handling_and_without_replacement(&bytes)
+            .map(|text| text.into_owned()),
+        #[cfg(not(feature = "encoding"))]
+        _ => None,
+    }
+}
+
+/// The byte range of the content of the `<body>` element in `html`, or
+/// all of it when there is none.
+fn body_range(html: &str) -> Range<usize> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower
+        .find("<body")
+        .and_then(|i| lower[i..].find('>').map(|j| i + j + 1))
+        .unwrap_or(0);
+    let end = lower[start..]
+        .rfind("</body")
+        .map_or(html.len(), |i| start + i);
+    start..end
+}
+
+/// `html` split before tags into pieces of at most `max` bytes, where a
+/// tag allows it.
+fn html_chunks(html: &str, max: usize) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut rest = html;
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let cut = rest[..end]
+            .rfind('<')
+            .filter(|&i| i > 0)
+            .or_else(|| rest[1..].find('<').map(|i| i + 1));
+        let Some(cut) = cut else {
+            break;
+        };
+        out.push(&rest[..cut]);
+        rest = &rest[cut..];
+    }
+    out.push(rest);
+    out
+}
+
+/// Whether `html` has letters or digits outside its tags.
+fn has_text(html: &str) -> bool {
+    let mut in_tag = false;
+    html.chars().any(|c| {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ => return !in_tag && c.is_alphanumeric(),
+        }
+        false
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+async fn translate_html(
+    provider: &dyn Translator,
+    html: &str,
+    src_lang: &str,
+    target_lang: &str,
+    options: &EmailOptions,
+) -> Result<String, FileError> {
+    let request = TranslateOptions {
+        tag_handling: Some(TagHandling::new(TagMode::Html).with_ignore_tags(["script", "style"])),
+        ..TranslateOptions::default()
+    };
+    let range = body_range(html);
+    let mut out = html[..range.start].to_string();
+    for chunk in html_chunks(&html[range.clone()], HTML_CHUNK) {
+        let text = chunk.trim();
+        if !has_text(text) {
+            out.push_str(chunk);
+            continue;
+        }
+        let translation = provider
+            .translate_with_options(text, src_lang, target_lang, &request)
+            .await
+            .map_err(|e| FileError::Translate(Arc::new(e)))?;
+        let start = chunk.len() - chunk.trim_start().len();
+        out.push_str(&chunk[..start]);
+        out.push_str(&translation.text);
+        out.push_str(&chunk[chunk.trim_end().len()..]);
+    }
+    if options.keep_original {
+        out.push_str(&format!(
+            "\n<hr>\n<p>{}</p>\n{}",
+            escape(&options.separator),
+            &html[range.clone()]
+        ));
+    }
+    out.push_str(&html[range.end..]);
+    Ok(out)
+}
+
+/// Translates the text part `part` in place; `false` if it could not be
+/// decoded.
+async fn translate_part(
+    part: &mut Part,
+    provider: &dyn Translator,
+    src_lang: &str,
+    target_lang: &str,
+    options: &EmailOptions,
+) -> Result<bool, FileError> {
+    let Body::Single(body) = &part.body else {
+        return Ok(false);
+    };
+    let content_type = content_type(&part.head);
+    let transfer = field(&part.head, "content-transfer-encoding")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let bytes = match transfer.as_str() {
+        "base64" => match decode_base64(body) {
+            Some(bytes) => bytes,
+            None => return Ok(false),
+        },
+        "quoted-printable" => decode_quoted_printable(body),
+        "" | "7bit" | "8bit" | "binary" => body.as_bytes().to_vec(),
+        _ => return Ok(false),
+    };
+    let charset = param(&content_type, "charset").unwrap_or_default();
+    let Some(decoded) = decode_charset(bytes, &charset) else {
+        return Ok(false);
+    };
+    // Only base64 hides line endings from the rest of the message.
+    let ending = LineEnding::detect(&decoded);
+    let text = LineEnding::Lf.apply(&decoded);
+
+    let translated = if media_type(&content_type) == "text/html" {
+        translate_html(provider, &text, src_lang, target_lang, options).await?
+    } else {
+        let outcome = translate_file_with::<PlainTextFile>(
+            provider,
+            &text,
+            src_lang,
+            target_lang,
+            &OnFailure::Fail,
+        )
+        .await?;
+        if options.keep_original {
+            format!(
+                "{}\n\n{}\n\n{}",
+                outcome.output.trim_end(),
+                options.separator,
+                text
+            )
+        } else {
+            outcome.output
+        }
+    };
+
+    let mut head = part.head.clone();
+    let body = match transfer.as_str() {
+        "base64" => {
+            let mut encoded = encode_base64(ending.apply(&translated).as_bytes());
+            if body.ends_with('\n') {
+                encoded.push('\n');
+            }
+            encoded
+        }
+        "quoted-printable" => encode_quoted_printable(&translated),
+        "8bit" | "binary" => translated,
+        _ if translated.is_ascii() => translated,
+        _ => {
+            head = set_field(&head, "Content-Transfer-Encoding", "quoted-printable");
+            encode_quoted_printable(&translated)
+        }
+    };
+    if !charset.eq_ignore_ascii_case("utf-8") {
+        head = set_field(&head, "Content-Type", &with_utf8(&content_type));
+    }
+    part.head = head;
+    part.body = Body::Single(body);
+    Ok(true)
+}
+
+/// Translates the text parts of the message `input`, keeping its headers,
+/// its attachments and its line endings.
+pub async fn translate_email(
+    provider: &dyn Translator,
+    input: &str,
+    src_lang: &str,
+    target_lang: &str,
+    options: &EmailOptions,
+) -> Result<EmailOutcome, FileError> {
+    let mut message = Part::parse(&LineEnding::Lf.apply(input));
+    let mut parts = Vec::new();
+    text_parts(&mut message, &mut parts);
+    let mut outcome = EmailOutcome::default();
+    for part in parts {
+        if translate_part(part, provider, src_lang, target_lang, options).await? {
+            outcome.translated += 1;
+        } else {
+            outcome.skipped += 1;
+        }
+    }
+    let mut output = String::with_capacity(input.len() * 2);
+    message.render(&mut output);
+    outcome.output = LineEnding::detect(input).apply(&output);
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::DeepLError,
+        translator::{BoxFuture, Translation},
+    };
+
+    /// Uppercases the text outside tags.
+    struct Shout;
+
+    impl Translator for Shout {
+        fn name(&self) -> &str {
+            "shout"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                let mut in_tag = false;
+                let text = text
+                    .chars()
+                    .map(|c| {
+                        in_tag = (in_tag || c == '<') && c != '>';
+                        if in_tag {
+                            c
+                        } else {
+                            c.to_ascii_uppercase()
+                        }
+                    })
+                    .collect();
+                Ok(Translation {
+                    text,
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    fn translate(input: &str, options: &EmailOptions) -> EmailOutcome {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime
+            .block_on(translate_email(&Shout, input, "EN", "DE", options))
+            .unwrap()
+    }
+
+    const MESSAGE: &str = "From: Ana <ana@example.com>\r
+Subject: Refund request\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"outer\"\r
+\r
+This is a multi-part message.\r
+--outer\r
+Content-Type: multipart/alternative;\r
+ boundary=inner\r
+\r
+--inner\r
+Content-Type: text/plain; charset=iso-8859-1\r
+Content-Transfer-Encoding: quoted-printable\r
+\r
+Hello, my order arrived broken. Caf=E9 au lait=\r
+ spilled everywhere.\r
+--inner\r
+Content-Type: text/html; charset=utf-8\r
+Content-Transfer-Encoding: base64\r
+\r
+PGh0bWw+PGhlYWQ+PHRpdGxlPlJlZnVuZDwvdGl0bGU+PC9oZWFkPjxib2R5PjxwPk15IDxiPm9y\r
+ZGVyPC9iPiBhcnJpdmVkIGJyb2tlbi48L3A+PC9ib2R5PjwvaHRtbD4=\r
+--inner--\r
+--outer\r
+Content-Type: image/jpeg; name=photo.jpg\r
+Content-Disposition: attachment; filename=photo.jpg\r
+Content-Transfer-Encoding: base64\r
+\r
+/9j/4AAQSkZJRgABAQ==\r
+--outer--\r
+";
+
+    #[test]
+    fn test_text_parts_are_translated_in_place() {
+        let outcome = translate(MESSAGE, &EmailOptions::default());
+        assert_eq!((outcome.translated, outcome.skipped), (2, 0));
+        let output = &outcome.output;
+        assert!(output.starts_with(&MESSAGE[..MESSAGE.find("--outer\r\n").unwrap()]));
+        assert!(
+            output.ends_with(&MESSAGE[MESSAGE.find("--outer\r\nContent-Type: image").unwrap()..])
+        );
+        assert!(!output.replace("\r\n", "").contains('\n'));
+
+        let message = Part::parse(&LineEnding::Lf.apply(output));
+        let Body::Multipart { parts, .. } = &message.body else {
+            panic!("{:?}", message);
+        };
+        let Body::Multipart { parts, .. } = &parts[0].1.body else {
+            panic!("{:?}", parts);
+        };
+        let (plain, html) = (&parts[0].1, &parts[1].1);
+        assert_eq!(content_type(&plain.head), "text/plain; charset=utf-8");
+        let Body::Single(body) = &plain.body else {
+            unreachable!()
+        };
+        assert_eq!(
+            String::from_utf8(decode_quoted_printable(body)).unwrap(),
+            "HELLO, MY ORDER ARRIVED BROKEN. CAFé AU LAIT SPILLED EVERYWHERE."
+        );
+        let Body::Single(body) = &html.body else {
+            unreachable!()
+        };
+        assert_eq!(
+            String::from_utf8(decode_base64(body).unwrap()).unwrap(),
+            "<html><head><title>Refund</title></head><body><p>MY <b>ORDER</b> ARRIVED \
+             BROKEN.</p></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_original_is_kept_below_and_undecodable_parts_skipped() {
+        let input = "Subject: Hi\n\
+                     Content-Type: multipart/mixed; boundary=b\n\
+                     \n\
+                     --b\n\
+                     \n\
+                     Thanks for the quick reply, Jürgen.\n\
+                     --b\n\
+                     Content-Type: text/plain; charset=x-unknown\n\
+                     \n\
+                     Unreadable.\n\
+                     --b--\n";
+        let options = EmailOptions::default().with_keep_original(true);
+        let outcome = translate(input, &options);
+        assert_eq!((outcome.translated, outcome.skipped), (1, 1));
+        assert_eq!(
+            outcome.output,
+            "Subject: Hi\n\
+             Content-Type: multipart/mixed; boundary=b\n\
+             \n\
+             --b\n\
+             Content-Transfer-Encoding: quoted-printable\n\
+             Content-Type: text/plain; charset=utf-8\n\
+             \n\
+             THANKS FOR THE QUICK REPLY, J=C3=BCRGEN.\n\
+             \n\
+             -------- Original message --------\n\
+             \n\
+             Thanks for the quick reply, J=C3=BCrgen.\n\
+             --b\n\
+             Content-Type: text/plain; charset=x-unknown\n\
+             \n\
+             Unreadable.\n\
+             --b--\n"
+        );
+        assert_eq!(
+            encode_quoted_printable(&"a".repeat(80)),
+            format!("{}=\n{}", "a".repeat(75), "a".repeat(5))
+        );
+        assert_eq!(
+            decode_base64(&encode_base64(b"any carnal pleas")).unwrap(),
+            b"any carnal pleas"
+        );
+    }
+}
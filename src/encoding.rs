@@ -0,0 +1,148 @@
+//! Reading and writing files that are not UTF-8.
+//!
+//! Legacy subtitle and locale files are often in GBK, Shift-JIS,
+//! Windows-1252 or UTF-16. [`decode`] finds the encoding from the BOM, or
+//! guesses it with chardetng when the bytes are not valid UTF-8, and
+//! [`encode`] writes the translation back in the same encoding.
+
+use std::fmt;
+
+use chardetng::EncodingDetector;
+pub use encoding_rs::Encoding;
+use encoding_rs::{UTF_16BE, UTF_16LE, UTF_8};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decoded {
+    /// The text without its BOM.
+    pub text: String,
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodingError {
+    pub encoding: &'static Encoding,
+    /// The first character the encoding cannot represent.
+    pub unmappable: char,
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} cannot be written in {}",
+            self.unmappable,
+            self.encoding.name()
+        )
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+/// Decodes `bytes`, detecting their encoding. Invalid sequences become
+/// U+FFFD.
+pub fn decode(bytes: &[u8]) -> Decoded {
+    if let Some((encoding, len)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[len..]);
+        return Decoded {
+            text: text.into_owned(),
+            encoding,
+            bom: true,
+        };
+    }
+    let encoding = match std::str::from_utf8(bytes) {
+        Ok(_) => UTF_8,
+        Err(_) => {
+            let mut detector = EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(None, true)
+        }
+    };
+    let (text, _) = encoding.decode_without_bom_handling(bytes);
+    Decoded {
+        text: text.into_owned(),
+        encoding,
+        bom: false,
+    }
+}
+
+/// Encodes `text` in `encoding`, with a BOM if `bom` is set and the
+/// encoding has one.
+pub fn encode(
+    text: &str,
+    encoding: &'static Encoding,
+    bom: bool,
+) -> Result<Vec<u8>, EncodingError> {
+    let mut out = Vec::with_capacity(text.len() + 3);
+    // encoding_rs only decodes UTF-16; its encoder would produce UTF-8.
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let le = encoding == UTF_16LE;
+        let units = bom.then_some('\u{feff}').into_iter().chain(text.chars());
+        for unit in units.flat_map(|c| {
+            let mut buf = [0; 2];
+            c.encode_utf16(&mut buf).to_vec()
+        }) {
+            out.extend(if le {
+                unit.to_le_bytes()
+            } else {
+                unit.to_be_bytes()
+            });
+        }
+        return Ok(out);
+    }
+    if bom && encoding == UTF_8 {
+        out.extend(b"\xef\xbb\xbf");
+    }
+    let (bytes, _, had_errors) = encoding.encode(text);
+    if had_errors {
+        let unmappable = text
+            .chars()
+            .find(|c| encoding.encode(c.encode_utf8(&mut [0; 4])).2)
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        return Err(EncodingError {
+            encoding,
+            unmappable,
+        });
+    }
+    out.extend_from_slice(&bytes);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_detects_legacy_encodings() {
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS
+            .encode("1\n00:00:01,000 --> 00:00:02,000\nこんにちは、世界。今日はいい天気ですね。\n");
+        let decoded = decode(&sjis);
+        assert_eq!(decoded.encoding, encoding_rs::SHIFT_JIS);
+        assert!(decoded.text.contains("こんにちは"));
+
+        let utf16 = encode("msgid \"Hi\"\n", UTF_16LE, true).unwrap();
+        assert_eq!(&utf16[..4], b"\xff\xfem\0");
+        let decoded = decode(&utf16);
+        assert_eq!(
+            (decoded.text.as_str(), decoded.encoding, decoded.bom),
+            ("msgid \"Hi\"\n", UTF_16LE, true)
+        );
+        assert_eq!(decode("plain é".as_bytes()).encoding, UTF_8);
+    }
+
+    #[test]
+    fn test_encode_round_trips_and_rejects_unmappable() {
+        let gbk = encoding_rs::GBK;
+        let text = "打开文件\n保存为新的文件名\n";
+        let bytes = encode(text, gbk, false).unwrap();
+        assert_eq!(decode(&bytes).text, text);
+        assert_eq!(
+            encode("日本語 ☃", encoding_rs::WINDOWS_1252, false),
+            Err(EncodingError {
+                encoding: encoding_rs::WINDOWS_1252,
+                unmappable: '日',
+            })
+        );
+        assert_eq!(encode("a", UTF_8, true).unwrap(), b"\xef\xbb\xbfa");
+    }
+}
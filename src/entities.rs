@@ -0,0 +1,272 @@
+//! Names that must survive translation untouched: companies, people,
+//! products, SKUs.
+//!
+//! A project lists them in an entities file,
+//!
+//! ```json
+//! {"exact": ["Acme Corp", "Jane Doe"], "patterns": ["SKU-\\d{4,}"]}
+//! ```
+//!
+//! where `patterns` are regular expressions and need the `regex` feature.
+//! [`Protected`] masks every occurrence before translating and rejects
+//! results that lost one; [`Entities::verify`] checks a translation
+//! produced some other way.
+
+use std::{fmt, ops::Range, path::Path, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{
+    capabilities::Capabilities,
+    error::DeepLError,
+    protect::{mask_with, unmask},
+    translator::{BoxFuture, Translation, Translator},
+    validate::Issue,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEntities {
+    #[serde(default)]
+    exact: Vec<String>,
+    #[serde(default)]
+    patterns: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum EntityError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Pattern { index: usize, message: String },
+}
+
+impl fmt::Display for EntityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntityError::Io(e) => write!(f, "cannot read entities: {}", e),
+            EntityError::Parse(e) => write!(f, "invalid entities file: {}", e),
+            EntityError::Pattern { index, message } => {
+                write!(f, "entity pattern {}: {}", index, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EntityError {}
+
+#[derive(Clone, Debug, Default)]
+pub struct Entities {
+    exact: Vec<String>,
+    #[cfg(feature = "regex")]
+    patterns: Vec<regex::Regex>,
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_exact(mut self, entity: impl Into<String>) -> Self {
+        let entity = entity.into();
+        if !entity.is_empty() {
+            self.exact.push(entity);
+        }
+        self
+    }
+
+    #[cfg(feature = "regex")]
+    pub fn with_pattern(mut self, pattern: &str) -> Result<Self, EntityError> {
+        let regex = regex::Regex::new(pattern).map_err(|e| EntityError::Pattern {
+            index: self.patterns.len(),
+            message: e.to_string(),
+        })?;
+        self.patterns.push(regex);
+        Ok(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, EntityError> {
+        let raw: RawEntities = serde_json::from_str(json).map_err(EntityError::Parse)?;
+        let entities = raw
+            .exact
+            .into_iter()
+            .fold(Self::new(), |entities, e| entities.with_exact(e));
+        #[cfg(feature = "regex")]
+        let entities = raw
+            .patterns
+            .iter()
+            .try_fold(entities, |entities, p| entities.with_pattern(p))?;
+        #[cfg(not(feature = "regex"))]
+        if !raw.patterns.is_empty() {
+            return Err(EntityError::Pattern {
+                index: 0,
+                message: "patterns need the `regex` feature".to_string(),
+            });
+        }
+        Ok(entities)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EntityError> {
+        Self::from_json(&std::fs::read_to_string(path).map_err(EntityError::Io)?)
+    }
+
+    /// Byte ranges of every entity occurrence in `text`, sorted by start.
+    /// Occurrences may overlap.
+    pub fn spans(&self, text: &str) -> Vec<Range<usize>> {
+        let mut spans: Vec<_> = self
+            .exact
+            .iter()
+            .flat_map(|e| text.match_indices(e.as_str()).map(|(i, m)| i..i + m.len()))
+            .collect();
+        #[cfg(feature = "regex")]
+        spans.extend(
+            self.patterns
+                .iter()
+                .flat_map(|p| p.find_iter(text).map(|m| m.range()))
+                .filter(|span| !span.is_empty()),
+        );
+        spans.sort_by_key(|s| (s.start, std::cmp::Reverse(s.end)));
+        spans
+    }
+
+    /// The entities in `text`, left to right, taking the longest one where
+    /// occurrences overlap.
+    fn occurrences<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        let mut found = Vec::new();
+        let mut last = 0;
+        for span in self.spans(text) {
+            if span.start >= last {
+                last = span.end;
+                found.push(&text[span]);
+            }
+        }
+        found
+    }
+
+    /// Entities of `source` that `translated` has fewer copies of.
+    pub fn verify(&self, source: &str, translated: &str) -> Vec<Issue> {
+        let wanted = self.occurrences(source);
+        let have = self.occurrences(translated);
+        let mut issues = Vec::new();
+        for (i, entity) in wanted.iter().enumerate() {
+            if wanted[..i].contains(entity) {
+                continue;
+            }
+            let count = |found: &[&str]| found.iter().filter(|e| *e == entity).count();
+            if count(&have) < count(&wanted) {
+                issues.push(Issue::MissingEntity(entity.to_string()));
+            }
+        }
+        issues
+    }
+}
+
+/// Masks the [`Entities`], placeholders and tags of every text before
+/// handing it to `inner`, and fails with
+/// [`DeepLError::ValidationFailed`] when the translation broke the masking.
+/// Alternatives that broke it are dropped.
+pub struct Protected {
+    inner: Arc<dyn Translator>,
+    entities: Arc<Entities>,
+}
+
+impl Protected {
+    pub fn new(inner: Arc<dyn Translator>, entities: Arc<Entities>) -> Self {
+        Self { inner, entities }
+    }
+}
+
+impl Translator for Protected {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move {
+            let masked = mask_with(text, self.entities.spans(text));
+            let mut translation = self
+                .inner
+                .translate(&masked.text, src_lang, target_lang)
+                .await?;
+            translation.text = unmask(&translation.text, &masked.tokens).map_err(|e| {
+                DeepLError::ValidationFailed {
+                    segment: 0,
+                    issues: vec![Issue::BrokenSentinel(e)],
+                }
+            })?;
+            translation.alternatives = translation
+                .alternatives
+                .iter()
+                .filter_map(|a| unmask(a, &masked.tokens).ok())
+                .collect();
+            Ok(translation)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Shout;
+
+    impl Translator for Shout {
+        fn name(&self) -> &str {
+            "shout"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                Ok(Translation {
+                    text: text.to_uppercase(),
+                    alternatives: vec![String::new()],
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_entities_are_masked_and_verified() {
+        let entities = Entities::from_json(r#"{"exact": ["Acme Corp", "Acme"]}"#).unwrap();
+        assert_eq!(entities.spans("Acme Corp and Acme"), [0..9, 0..4, 14..18]);
+        assert_eq!(
+            entities.verify("Acme Corp and Acme", "Acme Corp et ACME"),
+            [Issue::MissingEntity("Acme".to_string())]
+        );
+
+        let protected = Protected::new(Arc::new(Shout), Arc::new(entities));
+        let translation = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(protected.translate("{n} orders at Acme Corp", "EN", "DE"))
+            .unwrap();
+        assert_eq!(translation.text, "{n} ORDERS AT Acme Corp");
+        assert!(translation.alternatives.is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_entity_patterns() {
+        let entities = Entities::from_json(r#"{"patterns": ["SKU-\\d{4,}"]}"#).unwrap();
+        assert_eq!(entities.spans("SKU-12 or SKU-12345"), vec![10..19]);
+        assert!(matches!(
+            Entities::from_json(r#"{"patterns": ["("]}"#),
+            Err(EntityError::Pattern { index: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,253 @@
+use std::{fmt, time::Duration};
+
+use crate::{maintenance::Mode, storage::StorageError, validate::Issue};
+
+/// Used when upstream rate-limits us without saying for how long.
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum DeepLError {
+    #[cfg(feature = "client")]
+    Network(reqwest::Error),
+    Status {
+        status: u16,
+        body: String,
+    },
+    Deserialize(serde_json::Error),
+    NoProvider,
+    /// Upstream or a local limiter refused the request; it may be retried
+    /// after `retry_after`, or after backing off when upstream did not say.
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// Upstream refused to serve this IP or these headers at all.
+    Blocked {
+        body: String,
+    },
+    /// Upstream answered with an HTML challenge or captcha page, which
+    /// `marker` identifies, instead of JSON.
+    ChallengeRequired {
+        status: u16,
+        marker: String,
+        body: String,
+    },
+    /// A language code the provider does not support.
+    InvalidLanguage {
+        lang: String,
+    },
+    Storage(StorageError),
+    /// Reading or writing a local file, such as a document to translate.
+    Io(std::io::Error),
+    /// An output filter refused the translation.
+    Rejected {
+        filter: String,
+        reason: String,
+    },
+    ValidationFailed {
+        segment: usize,
+        issues: Vec<Issue>,
+    },
+    /// The deployment's character budget for the period is spent.
+    BudgetExceeded {
+        used: u64,
+        limit: u64,
+        resets_in: Duration,
+    },
+    /// DeepL gave up translating an uploaded document.
+    DocumentFailed {
+        document_id: String,
+        message: String,
+    },
+    /// Upstream traffic is switched off; see [`crate::maintenance`].
+    Paused {
+        mode: Mode,
+    },
+}
+
+impl fmt::Display for DeepLError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "client")]
+            DeepLError::Network(e) => write!(f, "network error: {}", e),
+            DeepLError::Status { status, .. } => write!(f, "unexpected status: {}", status),
+            DeepLError::Deserialize(e) => write!(f, "invalid response: {}", e),
+            DeepLError::NoProvider => write!(f, "no translation provider configured"),
+            DeepLError::RateLimited { retry_after } => write!(
+                f,
+                "rate limited, retry after {:?}",
+                retry_after.unwrap_or(DEFAULT_RETRY_AFTER)
+            ),
+            DeepLError::Blocked { .. } => write!(f, "blocked by upstream"),
+            DeepLError::ChallengeRequired { status, marker, .. } => {
+                write!(
+                    f,
+                    "upstream requires a challenge ({}, status {})",
+                    marker, status
+                )
+            }
+            DeepLError::InvalidLanguage { lang } => write!(f, "unsupported language: {}", lang),
+            DeepLError::Storage(e) => write!(f, "storage error: {}", e),
+            DeepLError::Io(e) => write!(f, "io error: {}", e),
+            DeepLError::Rejected { filter, reason } => {
+                write!(f, "translation rejected by {}: {}", filter, reason)
+            }
+            DeepLError::ValidationFailed { segment, issues } => {
+                write!(f, "segment {} failed validation", segment)?;
+                for (i, issue) in issues.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, issue)?;
+                }
+                Ok(())
+            }
+            DeepLError::BudgetExceeded {
+                used,
+                limit,
+                resets_in,
+            } => write!(
+                f,
+                "character budget exhausted ({} of {}), resets in {:?}",
+                used, limit, resets_in
+            ),
+            DeepLError::DocumentFailed {
+                document_id,
+                message,
+            } => write!(f, "document {} failed: {}", document_id, message),
+            DeepLError::Paused {
+                mode: Mode::Maintenance,
+            } => {
+                write!(f, "in maintenance, only cached results are served")
+            }
+            DeepLError::Paused { .. } => write!(f, "upstream traffic is paused"),
+        }
+    }
+}
+
+impl std::error::Error for DeepLError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "client")]
+            DeepLError::Network(e) => Some(e),
+            DeepLError::Deserialize(e) => Some(e),
+            DeepLError::Storage(e) => Some(e),
+            DeepLError::Io(e) => Some(e),
+            DeepLError::Status { .. }
+            | DeepLError::NoProvider
+            | DeepLError::RateLimited { .. }
+            | DeepLError::Blocked { .. }
+            | DeepLError::ChallengeRequired { .. }
+            | DeepLError::InvalidLanguage { .. }
+            | DeepLError::Rejected { .. }
+            | DeepLError::ValidationFailed { .. }
+            | DeepLError::BudgetExceeded { .. }
+            | DeepLError::DocumentFailed { .. }
+            | DeepLError::Paused { .. } => None,
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+impl From<reqwest::Error> for DeepLError {
+    fn from(e: reqwest::Error) -> Self {
+        DeepLError::Network(e)
+    }
+}
+
+impl From<std::io::Error> for DeepLError {
+    fn from(e: std::io::Error) -> Self {
+        DeepLError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for DeepLError {
+    fn from(e: serde_json::Error) -> Self {
+        DeepLError::Deserialize(e)
+    }
+}
+
+impl From<StorageError> for DeepLError {
+    fn from(e: StorageError) -> Self {
+        DeepLError::Storage(e)
+    }
+}
+
+impl DeepLError {
+    /// A short name for the variant, for metrics and logs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "client")]
+            DeepLError::Network(_) => "network",
+            DeepLError::Status { .. } => "status",
+            DeepLError::Deserialize(_) => "deserialize",
+            DeepLError::NoProvider => "no_provider",
+            DeepLError::RateLimited { .. } => "rate_limited",
+            DeepLError::Blocked { .. } => "blocked",
+            DeepLError::ChallengeRequired { .. } => "challenge_required",
+            DeepLError::InvalidLanguage { .. } => "invalid_language",
+            DeepLError::Storage(_) => "storage",
+            DeepLError::Io(_) => "io",
+            DeepLError::Rejected { .. } => "rejected",
+            DeepLError::ValidationFailed { .. } => "validation_failed",
+            DeepLError::BudgetExceeded { .. } => "budget_exceeded",
+            DeepLError::DocumentFailed { .. } => "document_failed",
+            DeepLError::Paused { .. } => "paused",
+        }
+    }
+
+    /// How long to tell a caller to wait, [`DEFAULT_RETRY_AFTER`] when
+    /// upstream rate-limited without saying.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            DeepLError::RateLimited { retry_after } => {
+                Some(retry_after.unwrap_or(DEFAULT_RETRY_AFTER))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the upstream could not be reached or refused to serve us
+    /// for now, as opposed to rejecting the request itself.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            #[cfg(feature = "client")]
+            DeepLError::Network(_) => true,
+            DeepLError::RateLimited { .. }
+            | DeepLError::Blocked { .. }
+            | DeepLError::ChallengeRequired { .. }
+            | DeepLError::Paused { .. } => true,
+            DeepLError::Status { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+/// Parses a `Retry-After` header value, either delay-seconds or an
+/// HTTP-date, relative to `now`.
+pub fn parse_retry_after(value: &str, now: std::time::SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:49:47 GMT", now),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
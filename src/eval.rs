@@ -0,0 +1,411 @@
+//! Provider evaluation against reference translations.
+//!
+//! Input is a TSV file of `source<TAB>reference` lines, each optionally
+//! followed by `<TAB>source_lang<TAB>target_lang` for a case in another
+//! language pair than the run's. The sources are translated by the
+//! provider under test, those of a pair in one batch, and scored with
+//! corpus-level chrF and BLEU, alongside latency statistics.
+
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
+
+use crate::{clock::Instant, error::DeepLError, Translator};
+
+const CHRF_ORDER: usize = 6;
+const CHRF_BETA: f64 = 2.0;
+const BLEU_ORDER: usize = 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalCase {
+    pub source: String,
+    pub reference: String,
+    /// The source and target language of this case, in place of the
+    /// run's.
+    pub langs: Option<(String, String)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TsvError {
+    pub line: usize,
+}
+
+impl fmt::Display for TsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: expected `source<TAB>reference[<TAB>source_lang<TAB>target_lang]`",
+            self.line
+        )
+    }
+}
+
+impl std::error::Error for TsvError {}
+
+/// Parses `source<TAB>reference` lines, with or without the language
+/// pair after them. Blank lines and lines starting with `#` are skipped.
+pub fn parse_tsv(input: &str) -> Result<Vec<EvalCase>, TsvError> {
+    let mut cases = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let langs = match fields[..] {
+            [_, _] => None,
+            [_, _, src, target] => Some((src.to_string(), target.to_string())),
+            _ => return Err(TsvError { line: i + 1 }),
+        };
+        cases.push(EvalCase {
+            source: fields[0].to_string(),
+            reference: fields[1].to_string(),
+            langs,
+        });
+    }
+    Ok(cases)
+}
+
+#[derive(Debug)]
+pub struct CaseResult {
+    pub case: EvalCase,
+    /// The translation, or why there is none; cases sent in one batch
+    /// share its error.
+    pub hypothesis: Result<String, Arc<DeepLError>>,
+    pub chrf: f64,
+    /// How long the batch this case was sent in took.
+    pub elapsed: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LatencyStats {
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let pick = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize];
+        Self {
+            mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p50: pick(0.5),
+            p95: pick(0.95),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EvalReport {
+    pub provider: String,
+    pub cases: Vec<CaseResult>,
+    pub chrf: f64,
+    pub bleu: f64,
+    pub latency: LatencyStats,
+    pub failures: usize,
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "provider: {}", self.provider)?;
+        writeln!(
+            f,
+            "segments: {} ({} failed)",
+            self.cases.len(),
+            self.failures
+        )?;
+        writeln!(f, "chrF:     {:.2}", self.chrf)?;
+        writeln!(f, "BLEU:     {:.2}", self.bleu)?;
+        write!(
+            f,
+            "latency:  mean {:?}, p50 {:?}, p95 {:?}, max {:?}",
+            self.latency.mean, self.latency.p50, self.latency.p95, self.latency.max
+        )
+    }
+}
+
+/// Translates the cases with `provider`, those of each language pair
+/// together with [`Translator::translate_batch`], and scores the results.
+/// The pairs go one at a time so latencies are not skewed by concurrency;
+/// cases without a pair of their own are in `src_lang` and `target_lang`.
+pub async fn evaluate(
+    provider: &dyn Translator,
+    cases: &[EvalCase],
+    src_lang: &str,
+    target_lang: &str,
+) -> EvalReport {
+    let mut groups: Vec<((&str, &str), Vec<usize>)> = Vec::new();
+    for (index, case) in cases.iter().enumerate() {
+        let pair = case
+            .langs
+            .as_ref()
+            .map_or((src_lang, target_lang), |(src, target)| {
+                (src.as_str(), target.as_str())
+            });
+        match groups.iter_mut().find(|(p, _)| *p == pair) {
+            Some((_, group)) => group.push(index),
+            None => groups.push((pair, vec![index])),
+        }
+    }
+
+    let mut results: Vec<Option<CaseResult>> = cases.iter().map(|_| None).collect();
+    for ((src, target), group) in groups {
+        let texts: Vec<&str> = group.iter().map(|&i| cases[i].source.as_str()).collect();
+        let start = Instant::now();
+        let translated = provider
+            .translate_batch(&texts, src, target)
+            .await
+            .map_err(Arc::new);
+        let elapsed = start.elapsed();
+        for (n, &index) in group.iter().enumerate() {
+            let case = &cases[index];
+            let hypothesis = match &translated {
+                Ok(translations) => translations.get(n).map(|t| t.text.clone()).ok_or_else(|| {
+                    Arc::new(DeepLError::Deserialize(serde::de::Error::custom(
+                        "missing from the batch",
+                    )))
+                }),
+                Err(e) => Err(e.clone()),
+            };
+            let chrf = hypothesis
+                .as_ref()
+                .map_or(0.0, |h| chrf(&[(h.as_str(), case.reference.as_str())]));
+            results[index] = Some(CaseResult {
+                case: case.clone(),
+                hypothesis,
+                chrf,
+                elapsed,
+            });
+        }
+    }
+    let results = results.into_iter().flatten().collect();
+    report(provider.name(), results)
+}
+
+fn report(provider: &str, cases: Vec<CaseResult>) -> EvalReport {
+    // Failed segments score as empty output so they drag the corpus
+    // scores down instead of silently disappearing from them.
+    let pairs: Vec<(&str, &str)> = cases
+        .iter()
+        .map(|c| {
+            (
+                c.hypothesis.as_deref().unwrap_or(""),
+                c.case.reference.as_str(),
+            )
+        })
+        .collect();
+    let latencies: Vec<Duration> = cases.iter().map(|c| c.elapsed).collect();
+    EvalReport {
+        provider: provider.to_string(),
+        chrf: chrf(&pairs),
+        bleu: bleu(&pairs),
+        latency: LatencyStats::from_samples(&latencies),
+        failures: cases.iter().filter(|c| c.hypothesis.is_err()).count(),
+        cases,
+    }
+}
+
+fn ngram_counts<T: Eq + std::hash::Hash>(items: &[T], n: usize) -> HashMap<&[T], usize> {
+    let mut counts = HashMap::new();
+    if items.len() >= n {
+        for gram in items.windows(n) {
+            *counts.entry(gram).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// `(clipped matches, hypothesis n-grams, reference n-grams)`.
+fn ngram_overlap<T: Eq + std::hash::Hash>(
+    hyp: &[T],
+    reference: &[T],
+    n: usize,
+) -> (usize, usize, usize) {
+    let hyp_counts = ngram_counts(hyp, n);
+    let ref_counts = ngram_counts(reference, n);
+    let matches = hyp_counts
+        .iter()
+        .map(|(gram, count)| (*count).min(*ref_counts.get(gram).unwrap_or(&0)))
+        .sum();
+    (
+        matches,
+        hyp.len().saturating_sub(n - 1),
+        reference.len().saturating_sub(n - 1),
+    )
+}
+
+/// Corpus chrF (character 6-grams, beta = 2, whitespace ignored), 0–100.
+pub fn chrf(pairs: &[(&str, &str)]) -> f64 {
+    let mut totals = [(0usize, 0usize, 0usize); CHRF_ORDER];
+    for (hyp, reference) in pairs {
+        let hyp: Vec<char> = hyp.chars().filter(|c| !c.is_whitespace()).collect();
+        let reference: Vec<char> = reference.chars().filter(|c| !c.is_whitespace()).collect();
+        for (n, total) in totals.iter_mut().enumerate() {
+            let (m, h, r) = ngram_overlap(&hyp, &reference, n + 1);
+            total.0 += m;
+            total.1 += h;
+            total.2 += r;
+        }
+    }
+    let orders: Vec<_> = totals.iter().filter(|t| t.1 > 0 || t.2 > 0).collect();
+    if orders.is_empty() {
+        return 0.0;
+    }
+    let ratio = |a: usize, b: usize| if b == 0 { 0.0 } else { a as f64 / b as f64 };
+    let precision = orders.iter().map(|t| ratio(t.0, t.1)).sum::<f64>() / orders.len() as f64;
+    let recall = orders.iter().map(|t| ratio(t.0, t.2)).sum::<f64>() / orders.len() as f64;
+    if precision + recall == 0.0 {
+        return 0.0;
+    }
+    let beta2 = CHRF_BETA * CHRF_BETA;
+    100.0 * (1.0 + beta2) * precision * recall / (beta2 * precision + recall)
+}
+
+/// Corpus BLEU over whitespace tokens with add-one smoothing for n > 1,
+/// 0–100. Scripts written without spaces are better judged by [`chrf`].
+pub fn bleu(pairs: &[(&str, &str)]) -> f64 {
+    let mut matches = [0usize; BLEU_ORDER];
+    let mut totals = [0usize; BLEU_ORDER];
+    let (mut hyp_len, mut ref_len) = (0, 0);
+    for (hyp, reference) in pairs {
+        let hyp: Vec<&str> = hyp.split_whitespace().collect();
+        let reference: Vec<&str> = reference.split_whitespace().collect();
+        hyp_len += hyp.len();
+        ref_len += reference.len();
+        for n in 0..BLEU_ORDER {
+            let (m, h, _) = ngram_overlap(&hyp, &reference, n + 1);
+            matches[n] += m;
+            totals[n] += h;
+        }
+    }
+    if hyp_len == 0 || matches[0] == 0 {
+        return 0.0;
+    }
+    let log_precision = (0..BLEU_ORDER)
+        .map(|n| {
+            let smooth = if n == 0 { 0.0 } else { 1.0 };
+            ((matches[n] as f64 + smooth) / (totals[n] as f64 + smooth)).ln()
+        })
+        .sum::<f64>()
+        / BLEU_ORDER as f64;
+    let brevity = if hyp_len >= ref_len {
+        1.0
+    } else {
+        (1.0 - ref_len as f64 / hyp_len as f64).exp()
+    };
+    100.0 * brevity * log_precision.exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tsv() {
+        let cases = parse_tsv("# comment\nhello\t你好\n\nworld\t世界\r\n").unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[1].reference, "世界");
+        assert_eq!(parse_tsv("ok\tfine\nbroken"), Err(TsvError { line: 2 }));
+        let cases = parse_tsv("hallo\thello\tDE\tEN-US\n").unwrap();
+        assert_eq!(cases[0].langs, Some(("DE".into(), "EN-US".into())));
+        assert_eq!(parse_tsv("a\tb\tDE\n"), Err(TsvError { line: 1 }));
+    }
+
+    #[test]
+    fn test_cases_are_batched_by_language_pair() {
+        use std::sync::Mutex;
+
+        use crate::{
+            error::DeepLError,
+            translator::{BoxFuture, Translation},
+        };
+
+        /// Echoes texts, recording each batch's pair and size.
+        struct Echo(Mutex<Vec<(String, usize)>>);
+
+        impl Translator for Echo {
+            fn name(&self) -> &str {
+                "echo"
+            }
+
+            fn translate<'a>(
+                &'a self,
+                text: &'a str,
+                _src_lang: &'a str,
+                _target_lang: &'a str,
+            ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+                Box::pin(async move {
+                    Ok(Translation {
+                        text: text.to_string(),
+                        ..Default::default()
+                    })
+                })
+            }
+
+            fn translate_batch<'a>(
+                &'a self,
+                texts: &'a [&'a str],
+                src_lang: &'a str,
+                target_lang: &'a str,
+            ) -> BoxFuture<'a, Result<Vec<Translation>, DeepLError>> {
+                if let Ok(mut batches) = self.0.lock() {
+                    batches.push((format!("{}>{}", src_lang, target_lang), texts.len()));
+                }
+                Box::pin(async move {
+                    if target_lang == "XX" {
+                        return Err(DeepLError::NoProvider);
+                    }
+                    let mut out = Vec::new();
+                    for text in texts {
+                        out.push(self.translate(text, src_lang, target_lang).await?);
+                    }
+                    Ok(out)
+                })
+            }
+        }
+
+        let cases = parse_tsv("a b\ta b\nc\tc\tDE\tXX\nd e\td f\n").unwrap();
+        let echo = Echo(Mutex::new(Vec::new()));
+        let report = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(evaluate(&echo, &cases, "EN", "DE"));
+        assert_eq!(
+            *echo.0.lock().unwrap(),
+            [("EN>DE".to_string(), 2), ("DE>XX".to_string(), 1)]
+        );
+        let sources: Vec<_> = report
+            .cases
+            .iter()
+            .map(|c| c.case.source.as_str())
+            .collect();
+        assert_eq!(sources, ["a b", "c", "d e"]);
+        assert_eq!(report.failures, 1);
+        assert!(report.cases[1].hypothesis.is_err());
+    }
+
+    #[test]
+    fn test_scores() {
+        let exact = [("the cat sat on the mat", "the cat sat on the mat")];
+        assert!((chrf(&exact) - 100.0).abs() < 1e-9);
+        assert!((bleu(&exact) - 100.0).abs() < 1e-9);
+
+        let partial = [("the cat sat on a mat", "the cat sat on the mat")];
+        assert!(chrf(&partial) < 100.0 && chrf(&partial) > 50.0);
+        assert!(bleu(&partial) < 100.0 && bleu(&partial) > 0.0);
+        assert_eq!(chrf(&[("", "")]), 0.0);
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        let samples: Vec<_> = (1..=20).map(Duration::from_millis).collect();
+        let stats = LatencyStats::from_samples(&samples);
+        assert_eq!(stats.max, Duration::from_millis(20));
+        assert_eq!(stats.p95, Duration::from_millis(19));
+    }
+}
@@ -0,0 +1,207 @@
+//! A/B comparison of [`RequestStrategy`] variants against the live
+//! upstream.
+//!
+//! [`Experiment::run`] sends every sample text once per strategy,
+//! interleaving the strategies so that upstream changing its behaviour
+//! mid-run affects all of them alike, and counts how each one fared.
+
+use std::{fmt, time::Duration};
+
+use serde::Serialize;
+
+use crate::{
+    client::DeepLClient,
+    clock::{self, Instant},
+    error::DeepLError,
+    RequestStrategy,
+};
+
+#[derive(Clone, Debug)]
+pub struct Experiment {
+    samples: Vec<String>,
+    strategies: Vec<RequestStrategy>,
+    rounds: usize,
+    interval: Duration,
+    src_lang: String,
+    target_lang: String,
+}
+
+impl Experiment {
+    /// Every strategy, one round of `samples` from English to German.
+    pub fn new(samples: impl IntoIterator<Item = String>) -> Self {
+        Experiment {
+            samples: samples.into_iter().collect(),
+            strategies: RequestStrategy::ALL.to_vec(),
+            rounds: 1,
+            interval: Duration::ZERO,
+            src_lang: "EN".to_string(),
+            target_lang: "DE".to_string(),
+        }
+    }
+
+    pub fn strategies(mut self, strategies: impl IntoIterator<Item = RequestStrategy>) -> Self {
+        self.strategies = strategies.into_iter().collect();
+        self
+    }
+
+    pub fn rounds(mut self, rounds: usize) -> Self {
+        self.rounds = rounds;
+        self
+    }
+
+    /// Pause between requests, to stay under upstream's rate limit.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn langs(mut self, src_lang: impl Into<String>, target_lang: impl Into<String>) -> Self {
+        self.src_lang = src_lang.into();
+        self.target_lang = target_lang.into();
+        self
+    }
+
+    /// Sends the workload through clones of `client`, one per strategy.
+    /// Give it a client without retries, or they hide failures.
+    pub async fn run(&self, client: &DeepLClient) -> ExperimentReport {
+        let clients: Vec<DeepLClient> = self
+            .strategies
+            .iter()
+            .map(|s| client.clone().with_strategy(*s))
+            .collect();
+        let mut stats: Vec<StrategyStats> = self
+            .strategies
+            .iter()
+            .map(|s| StrategyStats::new(*s))
+            .collect();
+        let mut first = true;
+        for _ in 0..self.rounds {
+            for sample in &self.samples {
+                for (client, stats) in clients.iter().zip(&mut stats) {
+                    if !first && !self.interval.is_zero() {
+                        clock::sleep(self.interval).await;
+                    }
+                    first = false;
+                    let started = Instant::now();
+                    let result = client
+                        .translate_raw(sample, &self.src_lang, &self.target_lang)
+                        .await;
+                    stats.record(result.err().as_ref(), started.elapsed());
+                }
+            }
+        }
+        ExperimentReport { strategies: stats }
+    }
+}
+
+/// How one strategy fared.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StrategyStats {
+    pub strategy: RequestStrategy,
+    pub requests: u64,
+    pub succeeded: u64,
+    pub rate_limited: u64,
+    /// Refused with 403 or a challenge page.
+    pub banned: u64,
+    /// Any other failure: network errors, unexpected statuses or bodies.
+    pub failed: u64,
+    /// Time spent on successful requests.
+    pub success_ms: u64,
+}
+
+impl StrategyStats {
+    fn new(strategy: RequestStrategy) -> Self {
+        StrategyStats {
+            strategy,
+            requests: 0,
+            succeeded: 0,
+            rate_limited: 0,
+            banned: 0,
+            failed: 0,
+            success_ms: 0,
+        }
+    }
+
+    fn record(&mut self, error: Option<&DeepLError>, elapsed: Duration) {
+        self.requests += 1;
+        match error {
+            None => {
+                self.succeeded += 1;
+                self.success_ms += elapsed.as_millis() as u64;
+            }
+            Some(DeepLError::RateLimited { .. }) => self.rate_limited += 1,
+            Some(DeepLError::Blocked { .. } | DeepLError::ChallengeRequired { .. }) => {
+                self.banned += 1
+            }
+            Some(_) => self.failed += 1,
+        }
+    }
+
+    fn rate(&self, count: u64) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            count as f64 / self.requests as f64
+        }
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        self.rate(self.succeeded)
+    }
+
+    pub fn ban_rate(&self) -> f64 {
+        self.rate(self.banned)
+    }
+
+    /// Mean latency of successful requests.
+    pub fn mean_ms(&self) -> Option<u64> {
+        (self.succeeded > 0).then(|| self.success_ms / self.succeeded)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ExperimentReport {
+    pub strategies: Vec<StrategyStats>,
+}
+
+impl ExperimentReport {
+    /// The strategy with the highest success rate, the faster one on a tie.
+    pub fn best(&self) -> Option<RequestStrategy> {
+        self.strategies
+            .iter()
+            .filter(|s| s.succeeded > 0)
+            .max_by(|a, b| {
+                a.success_rate()
+                    .total_cmp(&b.success_rate())
+                    .then(b.mean_ms().cmp(&a.mean_ms()))
+            })
+            .map(|s| s.strategy)
+    }
+}
+
+impl fmt::Display for ExperimentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            "strategy", "requests", "success", "banned", "limited", "failed", "mean ms"
+        )?;
+        for s in &self.strategies {
+            writeln!(
+                f,
+                "{:<12} {:>8} {:>7.0}% {:>7.0}% {:>8} {:>8} {:>8}",
+                s.strategy.to_string(),
+                s.requests,
+                s.success_rate() * 100.0,
+                s.ban_rate() * 100.0,
+                s.rate_limited,
+                s.failed,
+                s.mean_ms().map_or("-".to_string(), |ms| ms.to_string())
+            )?;
+        }
+        match self.best() {
+            Some(best) => writeln!(f, "best: {}", best),
+            None => writeln!(f, "no strategy succeeded"),
+        }
+    }
+}
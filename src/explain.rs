@@ -0,0 +1,124 @@
+//! What a client would send for a request, for working out why upstream
+//! blocks some requests and not others.
+//!
+//! [`DeepLClient::explain`](crate::DeepLClient::explain) builds the request
+//! the way a translation does, without sending it, and returns the URL,
+//! headers and body together with the choices made along the way. Secrets
+//! in the headers, session tokens, auth keys and cookie values, are
+//! replaced by their length and a hash, so that two explanations show
+//! whether the same one was used.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{fingerprint::Fingerprint, redact::Redaction, RequestStrategy};
+
+/// Which DeepL API a request goes to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Api {
+    Web,
+    Official,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Explanation {
+    pub api: Api,
+    pub url: String,
+    /// In the order they are sent, secrets redacted.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// The JSON-RPC id, for the web API.
+    pub id: Option<i64>,
+    /// `params.timestamp`, for the web API.
+    pub timestamp: Option<u64>,
+    /// The client's strategy, for the web API.
+    pub strategy: Option<RequestStrategy>,
+    /// The spacing `strategy` gives this request's id.
+    pub spacing: Option<RequestStrategy>,
+    /// The proxy the request goes through, credentials left out.
+    pub proxy: String,
+    /// The index of the pooled `dl_session` token used.
+    pub session: Option<usize>,
+    pub fingerprint: Option<Fingerprint>,
+    /// Whether markup was replaced by sentinels for the web API.
+    pub masked_markup: bool,
+}
+
+/// `value` as [`Redaction::Hashed`] shows it.
+fn secret(value: &str) -> String {
+    Redaction::Hashed.apply(value).to_string()
+}
+
+/// The value of header `name`, with the secrets it may carry redacted.
+pub(crate) fn redact_header(name: &str, value: &str) -> String {
+    if name.eq_ignore_ascii_case("cookie") {
+        value
+            .split("; ")
+            .map(|pair| match pair.split_once('=') {
+                Some((name, value)) => format!("{}={}", name, secret(value)),
+                None => secret(pair),
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    } else if name.eq_ignore_ascii_case("authorization") {
+        match value.rsplit_once(' ') {
+            Some((scheme, key)) => format!("{} {}", scheme, secret(key)),
+            None => secret(value),
+        }
+    } else {
+        value.to_string()
+    }
+}
+
+/// Like `curl -v`: the request line and headers, the body, then the
+/// choices made.
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "POST {}", self.url)?;
+        for (name, value) in &self.headers {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{}", self.body)?;
+        writeln!(f)?;
+        if let Some(id) = self.id {
+            writeln!(f, "id: {}", id)?;
+        }
+        if let (Some(strategy), Some(spacing)) = (self.strategy, self.spacing) {
+            writeln!(f, "spacing: {} (strategy {})", spacing, strategy)?;
+        }
+        if let Some(timestamp) = self.timestamp {
+            writeln!(f, "timestamp: {}", timestamp)?;
+        }
+        writeln!(f, "proxy: {}", self.proxy)?;
+        match self.session {
+            Some(index) => writeln!(f, "session: #{}", index)?,
+            None => writeln!(f, "session: none")?,
+        }
+        if let Some(fingerprint) = &self.fingerprint {
+            writeln!(f, "fingerprint: {}", fingerprint.user_agent())?;
+        }
+        if self.masked_markup {
+            writeln!(f, "markup: masked")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_are_redacted_from_headers() {
+        let cookie = redact_header("Cookie", "dl_session=abc-123; LMTBID=v2");
+        assert!(cookie.starts_with("dl_session=<7 chars, fnv1a "));
+        assert!(cookie.contains("; LMTBID=<2 chars, fnv1a "));
+        assert!(!cookie.contains("abc-123"));
+        let auth = redact_header("authorization", "DeepL-Auth-Key k3y:fx");
+        assert!(auth.starts_with("DeepL-Auth-Key <6 chars"));
+        assert_eq!(redact_header("x-app-device", "iPhone13,2"), "iPhone13,2");
+    }
+}
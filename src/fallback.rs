@@ -0,0 +1,190 @@
+//! Retry low-confidence translations with the next provider in line.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use crate::{
+    capabilities::Capabilities,
+    error::DeepLError,
+    translator::{BoxFuture, Translation, Translator},
+};
+
+/// Tries each provider in order until one returns a result whose
+/// confidence reaches `threshold`. Results without a confidence score are
+/// accepted as they are. When nobody reaches the threshold the
+/// highest-scoring result wins.
+///
+/// Every attempt is recorded under the `fallback_attempts` extension of the
+/// returned translation.
+pub struct Fallback {
+    providers: Vec<Arc<dyn Translator>>,
+    threshold: f64,
+}
+
+impl Fallback {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            providers: Vec::new(),
+            threshold,
+        }
+    }
+
+    pub fn with_provider(mut self, provider: Arc<dyn Translator>) -> Self {
+        self.providers.push(provider);
+        self
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    async fn run(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<Translation, DeepLError> {
+        let mut attempts = Vec::with_capacity(self.providers.len());
+        let mut best: Option<Translation> = None;
+        let mut last_err = None;
+
+        for provider in &self.providers {
+            match provider.translate(text, src_lang, target_lang).await {
+                Ok(translation) => {
+                    attempts.push(json!({
+                        "provider": provider.name(),
+                        "text": translation.text,
+                        "confidence": translation.meta.confidence,
+                    }));
+                    let passed = translation
+                        .meta
+                        .confidence
+                        .map_or(true, |c| c >= self.threshold);
+                    if passed || score(&translation) > best.as_ref().map_or(-1.0, score) {
+                        best = Some(translation);
+                    }
+                    if passed {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    attempts.push(json!({
+                        "provider": provider.name(),
+                        "error": e.to_string(),
+                    }));
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        match best {
+            Some(mut translation) => {
+                translation
+                    .meta
+                    .extensions
+                    .insert("fallback_attempts".to_string(), Value::Array(attempts));
+                Ok(translation)
+            }
+            None => Err(last_err.unwrap_or(DeepLError::NoProvider)),
+        }
+    }
+}
+
+fn score(translation: &Translation) -> f64 {
+    translation.meta.confidence.unwrap_or(0.0)
+}
+
+impl Translator for Fallback {
+    fn name(&self) -> &str {
+        "fallback"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.providers
+            .first()
+            .map(|p| p.capabilities())
+            .unwrap_or_default()
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(self.run(text, src_lang, target_lang))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::TranslationMeta;
+
+    struct Fixed(&'static str, Option<f64>);
+
+    impl Translator for Fixed {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn translate<'a>(
+            &'a self,
+            _text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                Ok(Translation {
+                    text: self.0.to_string(),
+                    meta: TranslationMeta {
+                        provider: self.0.to_string(),
+                        confidence: self.1,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn test_fallback_picks_higher_score() {
+        let fallback = Fallback::new(0.8)
+            .with_provider(Arc::new(Fixed("primary", Some(0.5))))
+            .with_provider(Arc::new(Fixed("secondary", Some(0.7))));
+        let translation = block_on(fallback.translate("hi", "EN", "ZH")).unwrap();
+        assert_eq!(translation.text, "secondary");
+        assert_eq!(
+            translation.extension("fallback_attempts").unwrap()[0]["provider"],
+            "primary"
+        );
+    }
+
+    #[test]
+    fn test_fallback_stops_at_confident_result() {
+        let fallback = Fallback::new(0.8)
+            .with_provider(Arc::new(Fixed("primary", Some(0.9))))
+            .with_provider(Arc::new(Fixed("secondary", Some(1.0))));
+        let translation = block_on(fallback.translate("hi", "EN", "ZH")).unwrap();
+        assert_eq!(translation.text, "primary");
+        assert_eq!(
+            translation
+                .extension("fallback_attempts")
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Output filters that catch machine translation garbage before it is
+//! handed to users: runaway lengths, banned words and broken
+//! placeholders.
+//!
+//! Each filter in [`Filters`] either flags a translation, listing the
+//! reason in the `quality_flags` extension, or rejects it with
+//! [`DeepLError::Rejected`].
+
+use serde_json::Value;
+
+use crate::{error::DeepLError, translator::Translation, validate};
+
+pub trait OutputFilter: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Why `translation` of `source` should not be trusted, if it should
+    /// not.
+    fn check(&self, source: &str, translation: &str) -> Option<String>;
+}
+
+/// Translations more than `max` times as long as their source, in
+/// characters. Sources shorter than `min_source_chars` are let through,
+/// since a word can legitimately become a phrase.
+#[derive(Clone, Debug)]
+pub struct LengthRatio {
+    pub max: f64,
+    pub min_source_chars: usize,
+}
+
+impl LengthRatio {
+    pub fn new(max: f64) -> Self {
+        LengthRatio {
+            max,
+            min_source_chars: 10,
+        }
+    }
+}
+
+impl OutputFilter for LengthRatio {
+    fn name(&self) -> &str {
+        "length_ratio"
+    }
+
+    fn check(&self, source: &str, translation: &str) -> Option<String> {
+        let source_chars = source.chars().count();
+        if source_chars < self.min_source_chars {
+            return None;
+        }
+        let ratio = translation.chars().count() as f64 / source_chars as f64;
+        (ratio > self.max).then(|| format!("{:.1} times as long as the source", ratio))
+    }
+}
+
+/// Translations containing any of a list of substrings, ignoring case.
+/// Substrings the source contains too are allowed.
+#[derive(Clone, Debug, Default)]
+pub struct BannedSubstrings {
+    banned: Vec<String>,
+}
+
+impl BannedSubstrings {
+    pub fn new(banned: impl IntoIterator<Item = String>) -> Self {
+        BannedSubstrings {
+            banned: banned
+                .into_iter()
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        }
+    }
+
+    /// One substring per line; blank lines and `#` comments are skipped.
+    pub fn parse(text: &str) -> Self {
+        Self::new(
+            text.lines()
+                .filter(|l| !l.trim_start().starts_with('#'))
+                .map(str::to_string),
+        )
+    }
+}
+
+impl OutputFilter for BannedSubstrings {
+    fn name(&self) -> &str {
+        "banned_substrings"
+    }
+
+    fn check(&self, source: &str, translation: &str) -> Option<String> {
+        let source = source.to_lowercase();
+        let translation = translation.to_lowercase();
+        self.banned
+            .iter()
+            .find(|b| translation.contains(b.as_str()) && !source.contains(b.as_str()))
+            .map(|b| format!("contains `{}`", b))
+    }
+}
+
+/// Translations that lost or invented placeholders or tags, as
+/// [`validate::validate`] finds them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Placeholders;
+
+impl OutputFilter for Placeholders {
+    fn name(&self) -> &str {
+        "placeholders"
+    }
+
+    fn check(&self, source: &str, translation: &str) -> Option<String> {
+        let issues = validate::validate(source, translation);
+        (!issues.is_empty()).then(|| {
+            issues
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join("; ")
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Action {
+    /// Pass the translation on with the reason in `quality_flags`.
+    #[default]
+    Flag,
+    Reject,
+}
+
+#[derive(Default)]
+pub struct Filters {
+    filters: Vec<(Box<dyn OutputFilter>, Action)>,
+}
+
+impl Filters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, filter: impl OutputFilter + 'static, action: Action) -> Self {
+        self.filters.push((Box::new(filter), action));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Runs every filter over `translation`, recording flags in its
+    /// `quality_flags` extension. Fails on the first rejecting filter that
+    /// objects.
+    pub fn apply(&self, source: &str, translation: &mut Translation) -> Result<(), DeepLError> {
+        let mut flags = Vec::new();
+        for (filter, action) in &self.filters {
+            let Some(reason) = filter.check(source, &translation.text) else {
+                continue;
+            };
+            if *action == Action::Reject {
+                return Err(DeepLError::Rejected {
+                    filter: filter.name().to_string(),
+                    reason,
+                });
+            }
+            flags.push(Value::from(format!("{}: {}", filter.name(), reason)));
+        }
+        if !flags.is_empty() {
+            translation
+                .meta
+                .extensions
+                .insert("quality_flags".to_string(), Value::Array(flags));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(text: &str) -> Translation {
+        Translation {
+            text: text.to_string(),
+            detected_source: None,
+            alternatives: Vec::new(),
+            meta: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_filters_flag_or_reject() {
+        let filters = Filters::new()
+            .with(LengthRatio::new(3.0), Action::Flag)
+            .with(
+                BannedSubstrings::parse("# spam\nlorem ipsum\n"),
+                Action::Flag,
+            )
+            .with(Placeholders, Action::Reject);
+
+        let mut t = translation("Lorem ipsum, lorem ipsum, lorem ipsum, lorem ipsum {n}");
+        filters.apply("Hello {n} there", &mut t).unwrap();
+        assert_eq!(
+            t.extension("quality_flags").unwrap(),
+            &serde_json::json!([
+                "length_ratio: 3.6 times as long as the source",
+                "banned_substrings: contains `lorem ipsum`"
+            ])
+        );
+
+        let mut t = translation("Bonjour là");
+        match filters.apply("Hello {n} there", &mut t) {
+            Err(DeepLError::Rejected { filter, reason }) => {
+                assert_eq!(filter, "placeholders");
+                assert_eq!(reason, "missing placeholder `{n}`");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut t = translation("Bonjour {n}");
+        filters.apply("Hi {n}", &mut t).unwrap();
+        assert!(t.extension("quality_flags").is_none());
+    }
+}
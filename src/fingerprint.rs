@@ -0,0 +1,174 @@
+//! The app, OS and device a request claims to come from, rotated so that
+//! a busy client does not send every request with one static fingerprint.
+//!
+//! A [`Fingerprint`] fills in the headers of [`HEADERS`](crate::HEADERS)
+//! that describe the DeepL iOS app; the others stay as they are. The
+//! built-in pool varies the device and the iOS version and keeps the one
+//! app release whose build number is known; fingerprints of other
+//! releases can be registered with [`FingerprintPool::with_fingerprint`].
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{clock, payload::splitmix64};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub app_version: String,
+    pub app_build: String,
+    pub os_version: String,
+    /// A model identifier such as `iPhone13,2`.
+    pub device: String,
+}
+
+impl Default for Fingerprint {
+    /// The fingerprint of [`HEADERS`](crate::HEADERS).
+    fn default() -> Self {
+        Fingerprint::ios("2.9.1", "510265", "16.3.0", "iPhone13,2")
+    }
+}
+
+impl Fingerprint {
+    pub fn ios(app_version: &str, app_build: &str, os_version: &str, device: &str) -> Self {
+        Fingerprint {
+            app_version: app_version.to_string(),
+            app_build: app_build.to_string(),
+            os_version: os_version.to_string(),
+            device: device.to_string(),
+        }
+    }
+
+    pub fn user_agent(&self) -> String {
+        format!(
+            "DeepL-iOS/{} iOS {} ({})",
+            self.app_version, self.os_version, self.device
+        )
+    }
+
+    /// The headers this fingerprint decides, by name as in
+    /// [`HEADERS`](crate::HEADERS).
+    pub fn headers(&self) -> [(&'static str, String); 6] {
+        [
+            ("x-app-os-name", "iOS".to_string()),
+            ("x-app-os-version", self.os_version.clone()),
+            ("x-app-device", self.device.clone()),
+            ("User-Agent", self.user_agent()),
+            ("x-app-build", self.app_build.clone()),
+            ("x-app-version", self.app_version.clone()),
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Selection {
+    /// Each fingerprint in turn, from a random one, so that separate
+    /// processes do not all start with the same.
+    #[default]
+    RoundRobin,
+    /// Any fingerprint, independently for each request.
+    Random,
+}
+
+fn random(salt: usize) -> usize {
+    let seed = clock::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    (splitmix64(seed ^ salt as u64) >> 1) as usize
+}
+
+#[derive(Debug)]
+pub struct FingerprintPool {
+    fingerprints: Vec<Fingerprint>,
+    selection: Selection,
+    next: AtomicUsize,
+}
+
+impl Default for FingerprintPool {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl FingerprintPool {
+    /// A pool of `fingerprints` only, or of the default one if there are
+    /// none.
+    pub fn new(fingerprints: impl IntoIterator<Item = Fingerprint>) -> Self {
+        let mut fingerprints: Vec<_> = fingerprints.into_iter().collect();
+        if fingerprints.is_empty() {
+            fingerprints.push(Fingerprint::default());
+        }
+        FingerprintPool {
+            fingerprints,
+            selection: Selection::default(),
+            next: AtomicUsize::new(random(0)),
+        }
+    }
+
+    /// iPhones from the 11 to the 15 on iOS 16 and 17.
+    pub fn builtin() -> Self {
+        Self::new([
+            Fingerprint::default(),
+            Fingerprint::ios("2.9.1", "510265", "16.1.2", "iPhone12,1"),
+            Fingerprint::ios("2.9.1", "510265", "16.5.0", "iPhone14,2"),
+            Fingerprint::ios("2.9.1", "510265", "16.6.1", "iPhone14,5"),
+            Fingerprint::ios("2.9.1", "510265", "17.0.3", "iPhone15,4"),
+            Fingerprint::ios("2.9.1", "510265", "17.1.2", "iPhone15,2"),
+        ])
+    }
+
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Adds `fingerprint` to those the pool picks from.
+    pub fn with_fingerprint(mut self, fingerprint: Fingerprint) -> Self {
+        self.fingerprints.push(fingerprint);
+        self
+    }
+
+    pub fn fingerprints(&self) -> &[Fingerprint] {
+        &self.fingerprints
+    }
+
+    /// The fingerprint for the next request.
+    pub fn pick(&self) -> &Fingerprint {
+        let count = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = match self.selection {
+            Selection::RoundRobin => count,
+            Selection::Random => random(count),
+        };
+        &self.fingerprints[index % self.fingerprints.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_fingerprint_matches_the_static_headers() {
+        for (name, value) in Fingerprint::default().headers() {
+            let header = crate::HEADERS.iter().find(|(n, _)| *n == name).unwrap();
+            assert_eq!(header.1, value);
+        }
+
+        let custom = Fingerprint::ios("3.0.0", "600000", "17.2.0", "iPhone16,1");
+        let pool = FingerprintPool::new([]).with_fingerprint(custom.clone());
+        let picked: Vec<_> = (0..3).map(|_| pool.pick().device.clone()).collect();
+        assert_ne!(picked[0], picked[1]);
+        assert_eq!(picked[0], picked[2]);
+        assert!(picked.contains(&custom.device));
+
+        let pool = FingerprintPool::builtin().with_selection(Selection::Random);
+        for _ in 0..20 {
+            assert!(pool.fingerprints().contains(pool.pick()));
+        }
+    }
+}
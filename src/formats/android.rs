@@ -0,0 +1,199 @@
+//! Android `res/values/strings.xml` resources.
+//!
+//! `<string>`, `<plurals>` items and `<string-array>` items are segments,
+//! except where `translatable="false"`. As with XLIFF, segments are the
+//! raw inner markup, so `<xliff:g>` spans, `\'` escapes and entities come
+//! through untouched and the file is otherwise written back byte for byte.
+
+use std::ops::Range;
+
+use super::{
+    check_count,
+    xml::{attr, is_tag, line_of},
+    Format, FormatError,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resource {
+    pub name: String,
+    /// `quantity` of a plurals item, or the index of a string-array item.
+    pub item: Option<String>,
+    pub text: String,
+    range: Range<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AndroidStrings {
+    input: String,
+    pub resources: Vec<Resource>,
+    /// Comment written after the XML declaration.
+    note: Option<String>,
+}
+
+struct Parent {
+    name: String,
+    translatable: bool,
+    items: usize,
+}
+
+fn element(
+    input: &str,
+    start: usize,
+    name: &str,
+) -> Result<(String, Range<usize>, usize), FormatError> {
+    let unclosed = || FormatError::new(line_of(input, start), format!("unclosed <{}>", name));
+    let open_end = start + input[start..].find('>').ok_or_else(unclosed)? + 1;
+    let tag = input[start..open_end].to_string();
+    if tag.ends_with("/>") {
+        return Ok((tag, open_end..open_end, open_end));
+    }
+    let close_tag = format!("</{}>", name);
+    let close = open_end + input[open_end..].find(&close_tag).ok_or_else(unclosed)?;
+    Ok((tag, open_end..close, close + close_tag.len()))
+}
+
+impl Format for AndroidStrings {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let mut resources = Vec::new();
+        let mut parent: Option<Parent> = None;
+        let mut pos = 0;
+        while let Some(found) = input[pos..].find('<') {
+            let start = pos + found;
+            let rest = &input[start..];
+            pos = start + 1;
+            if rest.starts_with("<!--") || rest.starts_with("<![CDATA[") {
+                let close = if rest.starts_with("<!--") {
+                    "-->"
+                } else {
+                    "]]>"
+                };
+                pos = start
+                    + rest.find(close).ok_or_else(|| {
+                        FormatError::new(line_of(input, start), "unclosed comment")
+                    })?
+                    + close.len();
+            } else if is_tag(rest, "string") {
+                let (tag, range, end) = element(input, start, "string")?;
+                pos = end;
+                if tag.ends_with("/>") || attr(&tag, "translatable").as_deref() == Some("false") {
+                    continue;
+                }
+                resources.push(Resource {
+                    name: attr(&tag, "name").unwrap_or_default(),
+                    item: None,
+                    text: input[range.clone()].to_string(),
+                    range,
+                });
+            } else if is_tag(rest, "plurals") || is_tag(rest, "string-array") {
+                let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+                parent = Some(Parent {
+                    name: attr(tag, "name").unwrap_or_default(),
+                    translatable: attr(tag, "translatable").as_deref() != Some("false"),
+                    items: 0,
+                });
+            } else if is_tag(rest, "item") {
+                let (tag, range, end) = element(input, start, "item")?;
+                pos = end;
+                let Some(parent) = parent.as_mut().filter(|p| p.translatable) else {
+                    continue;
+                };
+                if tag.ends_with("/>") {
+                    continue;
+                }
+                let item = attr(&tag, "quantity").unwrap_or_else(|| parent.items.to_string());
+                parent.items += 1;
+                resources.push(Resource {
+                    name: parent.name.clone(),
+                    item: Some(item),
+                    text: input[range.clone()].to_string(),
+                    range,
+                });
+            } else if rest.starts_with("</plurals") || rest.starts_with("</string-array") {
+                parent = None;
+            }
+        }
+        Ok(Self {
+            input: input.to_string(),
+            resources,
+            note: None,
+        })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.resources.iter().map(|r| r.text.as_str()).collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.resources.len(), translated.len())?;
+        for (resource, text) in self.resources.iter_mut().zip(translated) {
+            resource.text = text.clone();
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::with_capacity(self.input.len());
+        let mut last = 0;
+        if let Some(note) = &self.note {
+            if self.input.starts_with("<?xml") {
+                last = self.input.find("?>").map_or(0, |i| i + "?>".len());
+                out.push_str(&self.input[..last]);
+                out.push('\n');
+            }
+            out.push_str(&format!("<!-- {} -->", note));
+            if last == 0 {
+                out.push('\n');
+            }
+        }
+        for resource in &self.resources {
+            out.push_str(&self.input[last..resource.range.start]);
+            out.push_str(&resource.text);
+            last = resource.range.end;
+        }
+        out.push_str(&self.input[last..]);
+        out
+    }
+
+    fn mark_machine_translated(&mut self, note: &str) {
+        if !note.is_empty() {
+            self.note = Some(note.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_android_resources() {
+        let input = r#"<resources xmlns:xliff="urn:oasis:names:tc:xliff:document:1.2">
+    <!-- <string name="commented">No</string> -->
+    <string name="app" translatable="false">Deeplx</string>
+    <string name="hello">Hello <xliff:g id="user">%1$s</xliff:g>!</string>
+    <plurals name="files">
+        <item quantity="one">%d file</item>
+        <item quantity="other">%d files</item>
+    </plurals>
+    <string-array name="days"><item>Mon</item><item>Tue</item></string-array>
+</resources>
+"#;
+        let mut file = AndroidStrings::parse(input).unwrap();
+        assert_eq!(
+            file.segments(),
+            [
+                "Hello <xliff:g id=\"user\">%1$s</xliff:g>!",
+                "%d file",
+                "%d files",
+                "Mon",
+                "Tue"
+            ]
+        );
+        assert_eq!(file.resources[2].item.as_deref(), Some("other"));
+        assert_eq!(file.resources[4].name, "days");
+        assert_eq!(file.resources[4].item.as_deref(), Some("1"));
+        let same: Vec<String> = file.segments().iter().map(|s| s.to_string()).collect();
+        file.replace_segments(&same).unwrap();
+        assert_eq!(file.render(), input);
+    }
+}
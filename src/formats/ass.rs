@@ -0,0 +1,159 @@
+//! Advanced SubStation Alpha (`.ass`/`.ssa`) subtitles.
+//!
+//! Only the `Text` field of `Dialogue:` lines in `[Events]` is a segment.
+//! Override blocks such as `{\i1}` and `\N` line breaks stay in the text
+//! and must be protected by the caller.
+
+use super::{check_count, Format, FormatError};
+
+/// Fields in a v4+ `Format:` line; `Text` is always last.
+const DEFAULT_FIELDS: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Line {
+    Raw(String),
+    Dialogue {
+        /// Everything up to and including the comma before `Text`.
+        prefix: String,
+        text: String,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssFile {
+    pub bom: bool,
+    pub lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+impl Format for AssFile {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let (bom, input) = match input.strip_prefix('\u{feff}') {
+            Some(rest) => (true, rest),
+            None => (false, input),
+        };
+        let mut lines = Vec::new();
+        let mut in_events = false;
+        let mut fields = DEFAULT_FIELDS;
+        for (i, line) in input.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') {
+                in_events = trimmed.eq_ignore_ascii_case("[events]");
+            }
+            if in_events {
+                if let Some(format) = trimmed.strip_prefix("Format:") {
+                    fields = format.split(',').count();
+                    if !format.trim_end().ends_with("Text") {
+                        return Err(FormatError::new(i + 1, "`Text` must be the last field"));
+                    }
+                }
+                if let Some(rest) = line.strip_prefix("Dialogue:") {
+                    let split = match fields.checked_sub(2) {
+                        Some(n) => rest.match_indices(',').nth(n).map(|(at, _)| at + 1),
+                        None => Some(0),
+                    }
+                    .ok_or_else(|| FormatError::new(i + 1, "too few dialogue fields"))?;
+                    let split = "Dialogue:".len() + split;
+                    lines.push(Line::Dialogue {
+                        prefix: line[..split].to_string(),
+                        text: line[split..].to_string(),
+                    });
+                    continue;
+                }
+            }
+            lines.push(Line::Raw(line.to_string()));
+        }
+        Ok(Self {
+            bom,
+            lines,
+            trailing_newline: input.ends_with('\n'),
+        })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|l| match l {
+                Line::Dialogue { text, .. } => Some(text.as_str()),
+                Line::Raw(_) => None,
+            })
+            .collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.segments().len(), translated.len())?;
+        let mut texts = translated.iter();
+        for line in &mut self.lines {
+            if let Line::Dialogue { text, .. } = line {
+                // Newlines would split the event; ASS spells them `\N`.
+                *text = texts
+                    .next()
+                    .map_or(String::new(), |t| t.replace('\n', "\\N"));
+            }
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        if self.bom {
+            out.push('\u{feff}');
+        }
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            match line {
+                Line::Raw(raw) => out.push_str(raw),
+                Line::Dialogue { prefix, text } => {
+                    out.push_str(prefix);
+                    out.push_str(text);
+                }
+            }
+        }
+        if self.trailing_newline {
+            out.push('\n');
+        }
+        out
+    }
+
+    /// `note` becomes `;` comment lines at the top of `[Script Info]`.
+    fn mark_machine_translated(&mut self, note: &str) {
+        let at = self
+            .lines
+            .iter()
+            .position(
+                |l| matches!(l, Line::Raw(raw) if raw.trim().eq_ignore_ascii_case("[script info]")),
+            )
+            .map_or(0, |i| i + 1);
+        let lines = note
+            .lines()
+            .map(|l| Line::Raw(format!("; {}", l).trim_end().to_string()));
+        self.lines.splice(at..at, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "[Script Info]\nTitle: Dialogue: not an event\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\i1}Hello, world{\\i0}\nComment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,note\n";
+
+    #[test]
+    fn test_ass_translates_dialogue_text_only() {
+        let mut ass = AssFile::parse(SCRIPT).unwrap();
+        assert_eq!(ass.segments(), ["{\\i1}Hello, world{\\i0}"]);
+        ass.replace_segments(&["{\\i1}Hallo,\nWelt{\\i0}".to_string()])
+            .unwrap();
+        assert_eq!(
+            ass.render(),
+            SCRIPT.replace("Hello, world", "Hallo,\\NWelt")
+        );
+    }
+
+    #[test]
+    fn test_ass_rejects_short_dialogue() {
+        let err = AssFile::parse("[Events]\nDialogue: 0,1,2\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
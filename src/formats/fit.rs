@@ -0,0 +1,356 @@
+//! Keeping translated subtitles readable within their original timing.
+//!
+//! Translations are often longer than the source, and a cue that was
+//! comfortable to read in two short lines can become three long ones.
+//! [`fit`] rewraps a cue's text into balanced lines within the limits and,
+//! when the text is still too long for the cue's duration, condenses it.
+
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CueLimits {
+    /// Visible characters per line; markup tags don't count.
+    pub max_line_chars: usize,
+    pub max_lines: usize,
+    /// Characters per second of cue duration.
+    pub max_cps: Option<f64>,
+    /// Drop bracketed annotations such as `[door slams]` or `(laughs)` when
+    /// the text is over budget.
+    pub drop_annotations: bool,
+    /// As a last resort, cut the text at a word boundary and end it with
+    /// `…`.
+    pub truncate: bool,
+}
+
+impl Default for CueLimits {
+    fn default() -> Self {
+        Self {
+            max_line_chars: 42,
+            max_lines: 2,
+            max_cps: Some(17.0),
+            drop_annotations: false,
+            truncate: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fit {
+    /// Already within the limits.
+    Unchanged,
+    Rewrapped,
+    Condensed,
+    /// Still over the limits after everything allowed was tried.
+    OverBudget {
+        cps: f64,
+        lines: usize,
+    },
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30ff}' | '\u{3400}'..='\u{9fff}' | '\u{ff00}'..='\u{ffef}')
+}
+
+/// A wrappable piece of text: a word, or a single CJK character.
+struct Piece<'a> {
+    text: &'a str,
+    width: usize,
+    /// Whether a space separated it from the previous piece.
+    spaced: bool,
+}
+
+fn visible_len(s: &str) -> usize {
+    let mut in_tag = false;
+    s.chars()
+        .filter(|&c| match c {
+            '<' | '{' => {
+                in_tag = true;
+                false
+            }
+            '>' | '}' => !std::mem::replace(&mut in_tag, false),
+            _ => !in_tag,
+        })
+        .count()
+}
+
+fn pieces(text: &str) -> Vec<Piece<'_>> {
+    let mut out = Vec::new();
+    let mut start = None;
+    let mut spaced = false;
+    let mut in_tag = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' | '{' => in_tag = true,
+            '>' | '}' => in_tag = false,
+            _ => {}
+        }
+        if c.is_whitespace() && !in_tag {
+            if let Some(s) = start.take() {
+                out.push(Piece {
+                    text: &text[s..i],
+                    width: visible_len(&text[s..i]),
+                    spaced,
+                });
+            }
+            spaced = true;
+            continue;
+        }
+        if is_cjk(c) && !in_tag {
+            if let Some(s) = start.take() {
+                out.push(Piece {
+                    text: &text[s..i],
+                    width: visible_len(&text[s..i]),
+                    spaced,
+                });
+                spaced = false;
+            }
+            let end = i + c.len_utf8();
+            out.push(Piece {
+                text: &text[i..end],
+                width: 1,
+                spaced,
+            });
+            spaced = false;
+            continue;
+        }
+        if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        out.push(Piece {
+            text: &text[s..],
+            width: visible_len(&text[s..]),
+            spaced,
+        });
+    }
+    out
+}
+
+/// Greedy wrap at `width`, returning the lines.
+fn wrap_at(pieces: &[Piece<'_>], width: usize) -> Vec<String> {
+    let mut lines: Vec<(String, usize)> = Vec::new();
+    for piece in pieces {
+        match lines.last_mut() {
+            Some((line, len)) if *len + usize::from(piece.spaced) + piece.width <= width => {
+                if piece.spaced {
+                    line.push(' ');
+                    *len += 1;
+                }
+                line.push_str(piece.text);
+                *len += piece.width;
+            }
+            _ => lines.push((piece.text.to_string(), piece.width)),
+        }
+    }
+    lines.into_iter().map(|(line, _)| line).collect()
+}
+
+/// Ending a line on a clause boundary is worth this many characters of
+/// imbalance.
+const CLAUSE_BONUS: usize = 6;
+
+/// The most balanced two-line split that fits, preferring a break after
+/// punctuation.
+fn split_in_two(pieces: &[Piece<'_>], max_line_chars: usize) -> Vec<String> {
+    let widths: Vec<usize> = pieces
+        .iter()
+        .map(|p| p.width + usize::from(p.spaced))
+        .collect();
+    let total: usize = widths.iter().sum();
+    let mut best: Option<(usize, usize)> = None;
+    let mut first = 0;
+    for split in 1..pieces.len() {
+        first += widths[split - 1];
+        let second = total - first - usize::from(pieces[split].spaced);
+        let first_len = first - usize::from(pieces[0].spaced);
+        if first_len > max_line_chars || second > max_line_chars {
+            continue;
+        }
+        let clause = pieces[split - 1]
+            .text
+            .ends_with([',', '.', ';', ':', '!', '?', '…', '，', '。', '！', '？']);
+        let cost = first_len.max(second) + CLAUSE_BONUS - if clause { CLAUSE_BONUS } else { 0 };
+        if best.map_or(true, |(c, _)| cost < c || (cost == c && clause)) {
+            best = Some((cost, split));
+        }
+    }
+    match best {
+        Some((_, split)) => {
+            let mut out = wrap_at(&pieces[..split], usize::MAX);
+            out.extend(wrap_at(&pieces[split..], usize::MAX));
+            out
+        }
+        None => wrap_at(pieces, max_line_chars),
+    }
+}
+
+/// Wraps into as few lines as `max_line_chars` allows, keeping the lines
+/// about equally long.
+fn wrap(text: &str, max_line_chars: usize) -> Vec<String> {
+    let pieces = pieces(text);
+    let max_line_chars = max_line_chars.max(1);
+    let lines = wrap_at(&pieces, max_line_chars).len();
+    if lines <= 1 {
+        return wrap_at(&pieces, max_line_chars);
+    }
+    let total: usize = pieces.iter().map(|p| p.width + usize::from(p.spaced)).sum();
+    if lines == 2 {
+        return split_in_two(&pieces, max_line_chars);
+    }
+    let mut width = (total + lines - 1) / lines;
+    loop {
+        let wrapped = wrap_at(&pieces, width);
+        if wrapped.len() <= lines || width >= max_line_chars {
+            return wrapped;
+        }
+        width += 1;
+    }
+}
+
+fn drop_annotations(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' if depth > 0 => depth -= 1,
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn truncate(text: &str, budget: usize) -> String {
+    let pieces = pieces(text);
+    let mut out = String::new();
+    let mut len = 0;
+    for piece in &pieces {
+        let extra = usize::from(piece.spaced && !out.is_empty()) + piece.width;
+        if len + extra + 1 > budget {
+            break;
+        }
+        if piece.spaced && !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(piece.text);
+        len += extra;
+    }
+    let out = out.trim_end_matches([',', ';', ':', '.', ' ']);
+    format!("{}…", out)
+}
+
+/// Rewraps and, if allowed, condenses `text` for a cue lasting `duration`.
+/// Lines in the result are joined with `\n`.
+pub fn fit(text: &str, duration: Duration, limits: &CueLimits) -> (String, Fit) {
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let line_budget = limits.max_lines.max(1) * limits.max_line_chars.max(1);
+    let cps_budget = limits
+        .max_cps
+        .map(|cps| (cps * duration.as_secs_f64()).floor() as usize);
+    let budget = cps_budget.map_or(line_budget, |b| b.min(line_budget));
+
+    let mut condensed = false;
+    let mut body = flat.clone();
+    if visible_len(&body) > budget && body.contains("...") {
+        body = body.replace("...", "…");
+        condensed = true;
+    }
+    if visible_len(&body) > budget && limits.drop_annotations {
+        let dropped = drop_annotations(&body);
+        if !dropped.is_empty() && dropped != body {
+            body = dropped;
+            condensed = true;
+        }
+    }
+    if visible_len(&body) > budget && limits.truncate && budget > 1 {
+        body = truncate(&body, budget);
+        condensed = true;
+    }
+
+    let lines = wrap(&body, limits.max_line_chars);
+    let wrapped = lines.join("\n");
+    let chars = visible_len(&body);
+    let cps = match duration.as_secs_f64() {
+        secs if secs > 0.0 => chars as f64 / secs,
+        _ => f64::INFINITY,
+    };
+    let over_lines = lines.len() > limits.max_lines.max(1)
+        || lines.iter().any(|l| visible_len(l) > limits.max_line_chars);
+    let over_cps = limits.max_cps.is_some_and(|max| cps > max);
+    let outcome = if over_lines || over_cps {
+        Fit::OverBudget {
+            cps,
+            lines: lines.len(),
+        }
+    } else if condensed {
+        Fit::Condensed
+    } else if wrapped == text {
+        Fit::Unchanged
+    } else {
+        Fit::Rewrapped
+    };
+    (wrapped, outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewraps_into_balanced_lines() {
+        let limits = CueLimits::default();
+        let (text, outcome) = fit(
+            "Ich habe dir doch gesagt, dass wir heute\nAbend nicht kommen können.",
+            Duration::from_secs(5),
+            &limits,
+        );
+        assert_eq!(
+            text,
+            "Ich habe dir doch gesagt, dass wir\nheute Abend nicht kommen können."
+        );
+        assert_eq!(outcome, Fit::Rewrapped);
+        assert_eq!(
+            fit("<i>Kurz.</i>", Duration::from_secs(1), &limits),
+            ("<i>Kurz.</i>".to_string(), Fit::Unchanged)
+        );
+        let (text, _) = fit(
+            "我告诉过你我们今天晚上不能来，这件事真的非常重要。",
+            Duration::from_secs(5),
+            &CueLimits {
+                max_line_chars: 16,
+                ..limits
+            },
+        );
+        assert_eq!(text, "我告诉过你我们今天晚上不能来，\n这件事真的非常重要。");
+    }
+
+    #[test]
+    fn test_condenses_to_the_reading_speed() {
+        let text = "[door slams] Wait... I didn't mean it like that, please come back!";
+        let limits = CueLimits {
+            drop_annotations: true,
+            ..CueLimits::default()
+        };
+        let (fitted, outcome) = fit(text, Duration::from_secs(3), &limits);
+        assert_eq!(outcome, Fit::Condensed);
+        assert_eq!(
+            fitted,
+            "Wait… I didn't mean it like that,\nplease come back!"
+        );
+        let (_, outcome) = fit(text, Duration::from_secs(2), &limits);
+        assert!(matches!(outcome, Fit::OverBudget { .. }), "{:?}", outcome);
+
+        let (fitted, outcome) = fit(
+            text,
+            Duration::from_secs(2),
+            &CueLimits {
+                truncate: true,
+                ..limits
+            },
+        );
+        assert_eq!(outcome, Fit::Condensed);
+        assert_eq!(fitted, "Wait… I didn't mean it like that…");
+    }
+}
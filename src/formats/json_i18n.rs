@@ -0,0 +1,140 @@
+//! Nested JSON message catalogs (i18next, vue-i18n, Chrome `messages.json`
+//! style). Every string leaf is a segment; keys, numbers and booleans are
+//! left alone and key order is preserved.
+
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Serializer, Value};
+
+use super::{check_count, Format, FormatError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonFile {
+    pub root: Value,
+    indent: String,
+    trailing_newline: bool,
+}
+
+fn leaves<'a>(value: &'a Value, path: &mut Vec<String>, out: &mut Vec<(String, &'a str)>) {
+    match value {
+        Value::String(s) => out.push((path.join("."), s.as_str())),
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                path.push(i.to_string());
+                leaves(item, path, out);
+                path.pop();
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map {
+                path.push(key.clone());
+                leaves(item, path, out);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn leaves_mut<'a>(value: &'a mut Value, out: &mut Vec<&'a mut String>) {
+    match value {
+        Value::String(s) => out.push(s),
+        Value::Array(items) => items.iter_mut().for_each(|item| leaves_mut(item, out)),
+        Value::Object(map) => map.values_mut().for_each(|item| leaves_mut(item, out)),
+        _ => {}
+    }
+}
+
+/// The whitespace used for the first indented line, so output keeps the
+/// project's indentation.
+pub(super) fn detect_indent(input: &str) -> String {
+    input
+        .lines()
+        .skip(1)
+        .map(|l| {
+            l.chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect::<String>()
+        })
+        .find(|indent| !indent.is_empty())
+        .unwrap_or_else(|| "  ".to_string())
+}
+
+impl JsonFile {
+    /// `(dotted.key.path, text)` for every string leaf, in document order.
+    /// Array elements use their index as the key.
+    pub fn entries(&self) -> Vec<(String, &str)> {
+        let mut out = Vec::new();
+        leaves(&self.root, &mut Vec::new(), &mut out);
+        out
+    }
+}
+
+impl Format for JsonFile {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+        let root =
+            serde_json::from_str(input).map_err(|e| FormatError::new(e.line(), e.to_string()))?;
+        Ok(Self {
+            root,
+            indent: detect_indent(input),
+            trailing_newline: input.ends_with('\n'),
+        })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.entries().into_iter().map(|(_, text)| text).collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        let mut slots = Vec::new();
+        leaves_mut(&mut self.root, &mut slots);
+        check_count(slots.len(), translated.len())?;
+        for (slot, text) in slots.into_iter().zip(translated) {
+            *slot = text.clone();
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut buf = Vec::new();
+        let formatter = PrettyFormatter::with_indent(self.indent.as_bytes());
+        let mut serializer = Serializer::with_formatter(&mut buf, formatter);
+        // Serializing a `Value` into memory cannot fail.
+        let _ = self.root.serialize(&mut serializer);
+        let mut out = String::from_utf8(buf).unwrap_or_default();
+        if self.trailing_newline {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_keeps_order_and_indent() {
+        let input = "{\n    \"zeta\": \"Last\",\n    \"menu\": {\"open\": \"Open {file}\", \"count\": 3},\n    \"list\": [\"a\", true]\n}\n";
+        let mut file = JsonFile::parse(input).unwrap();
+        assert_eq!(
+            file.entries(),
+            [
+                ("zeta".to_string(), "Last"),
+                ("menu.open".to_string(), "Open {file}"),
+                ("list.0".to_string(), "a")
+            ]
+        );
+        file.replace_segments(&["Letzte".into(), "{file} öffnen".into(), "ä".into()])
+            .unwrap();
+        assert_eq!(
+            file.render(),
+            "{\n    \"zeta\": \"Letzte\",\n    \"menu\": {\n        \"open\": \"{file} öffnen\",\n        \"count\": 3\n    },\n    \"list\": [\n        \"ä\",\n        true\n    ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_json_reports_parse_line() {
+        assert_eq!(JsonFile::parse("{\n\"a\": }").unwrap_err().line, 2);
+    }
+}
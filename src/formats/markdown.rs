@@ -0,0 +1,333 @@
+//! Markdown (`.md`) documents.
+//!
+//! Paragraphs, headings, list items, blockquote lines and table cells are
+//! segments. Front matter, fenced and indented code, HTML lines, link
+//! definitions and the markers around the text are kept verbatim. A
+//! paragraph hard-wrapped over several lines is one segment and is written
+//! back as a single line.
+
+use super::{check_count, Format, FormatError};
+use crate::validate::tag_spans;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    Raw(String),
+    Text(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MarkdownFile {
+    pieces: Vec<Piece>,
+    trailing_newline: bool,
+}
+
+fn is_break(body: &str) -> bool {
+    let marks: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ["-", "*", "_", "="]
+            .iter()
+            .any(|m| marks.chars().all(|c| c.to_string() == *m))
+}
+
+/// The length of a list marker such as `- `, `1. ` or `- [x] `.
+fn list_marker(body: &str) -> Option<usize> {
+    let digits = body.find(|c: char| !c.is_ascii_digit()).unwrap_or(0);
+    let len = match body.as_bytes().get(digits)? {
+        b'-' | b'*' | b'+' if digits == 0 => 1,
+        b'.' | b')' if (1..=9).contains(&digits) => digits + 1,
+        _ => return None,
+    };
+    if !body[len..].starts_with(' ') {
+        return None;
+    }
+    let len = len + 1;
+    let task = ["[ ] ", "[x] ", "[X] "]
+        .iter()
+        .find(|t| body[len..].starts_with(**t))
+        .map_or(0, |t| t.len());
+    Some(len + task)
+}
+
+fn fence(body: &str) -> Option<&str> {
+    ["```", "~~~"].into_iter().find(|f| body.starts_with(f))
+}
+
+/// Lines that never continue a paragraph.
+fn starts_block(body: &str) -> bool {
+    body.starts_with(['#', '>', '|', '<'])
+        || fence(body).is_some()
+        || list_marker(body).is_some()
+        || is_break(body)
+}
+
+/// Length of the front matter that `raw`, the first piece, starts with.
+fn front_matter_len(raw: &str) -> usize {
+    let Some(rest) = raw.strip_prefix("---\n") else {
+        return 0;
+    };
+    let mut at = "---\n".len();
+    for line in rest.split_inclusive('\n') {
+        at += line.len();
+        if matches!(line.trim_end(), "---" | "...") {
+            return at;
+        }
+    }
+    0
+}
+
+struct Builder {
+    pieces: Vec<Piece>,
+    paragraph: Option<String>,
+}
+
+impl Builder {
+    fn raw(&mut self, s: &str) {
+        self.flush();
+        match self.pieces.last_mut() {
+            Some(Piece::Raw(raw)) => raw.push_str(s),
+            _ => self.pieces.push(Piece::Raw(s.to_string())),
+        }
+    }
+
+    fn text(&mut self, s: &str) {
+        self.flush();
+        match s.trim() {
+            "" => self.raw(s),
+            text => {
+                let end = s.len() - s.trim_start().len();
+                self.raw(&s[..end]);
+                self.pieces.push(Piece::Text(text.to_string()));
+                self.raw(&s[s.trim_end().len()..]);
+            }
+        }
+    }
+
+    /// Starts or continues a paragraph. A hard line break ends the segment
+    /// but not the paragraph.
+    fn prose(&mut self, s: &str) {
+        let text = s.trim();
+        match &mut self.paragraph {
+            Some(p) => {
+                p.push(' ');
+                p.push_str(text);
+            }
+            None => self.paragraph = Some(text.to_string()),
+        }
+        let hard = match (s.ends_with("  "), text.ends_with('\\')) {
+            (true, _) => "  \n",
+            (false, true) => "\n",
+            (false, false) => return,
+        };
+        if let Some(p) = self.paragraph.take() {
+            self.pieces.push(Piece::Text(p));
+            self.pieces.push(Piece::Raw(hard.to_string()));
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(p) = self.paragraph.take() {
+            self.pieces.push(Piece::Text(p));
+            self.pieces.push(Piece::Raw("\n".to_string()));
+        }
+    }
+}
+
+impl Format for MarkdownFile {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let mut b = Builder {
+            pieces: Vec::new(),
+            paragraph: None,
+        };
+        let lines: Vec<&str> = input.lines().map(|l| l.trim_end_matches('\r')).collect();
+        let mut i = 0;
+        if lines.first() == Some(&"---") {
+            if let Some(end) = lines[1..].iter().position(|l| *l == "---" || *l == "...") {
+                for line in &lines[..end + 2] {
+                    b.raw(line);
+                    b.raw("\n");
+                }
+                i = end + 2;
+            }
+        }
+        while i < lines.len() {
+            let line = lines[i];
+            i += 1;
+            let indent = line.len() - line.trim_start().len();
+            let (lead, body) = line.split_at(indent);
+            if body.is_empty() {
+                b.raw(line);
+                b.raw("\n");
+                continue;
+            }
+            if let (Some(_), false) = (&b.paragraph, starts_block(body)) {
+                b.prose(body);
+                continue;
+            }
+            if let Some(mark) = fence(body).filter(|_| indent < 4) {
+                b.raw(line);
+                b.raw("\n");
+                while i < lines.len() {
+                    let inner = lines[i];
+                    i += 1;
+                    b.raw(inner);
+                    b.raw("\n");
+                    if inner.trim_start().starts_with(mark) {
+                        break;
+                    }
+                }
+            } else if body.starts_with("<!--") {
+                b.raw(line);
+                b.raw("\n");
+                let mut closed = body.contains("-->");
+                while !closed && i < lines.len() {
+                    closed = lines[i].contains("-->");
+                    b.raw(lines[i]);
+                    b.raw("\n");
+                    i += 1;
+                }
+            } else if indent >= 4
+                || line.starts_with('\t')
+                || is_break(body)
+                || (body.starts_with('<')
+                    && tag_spans(body).first().is_some_and(|t| t.0.start == 0))
+                || (body.starts_with('[') && body.contains("]:"))
+            {
+                b.raw(line);
+                b.raw("\n");
+            } else if let Some(level) = body
+                .find(|c| c != '#')
+                .filter(|&n| body.starts_with('#') && n <= 6 && body[n..].starts_with(' '))
+            {
+                b.raw(lead);
+                b.raw(&body[..level]);
+                let title = &body[level..];
+                let closing = title.trim_end().trim_end_matches('#');
+                let closing = match closing.ends_with(' ') || closing.trim().is_empty() {
+                    true => closing.len(),
+                    false => title.trim_end().len(),
+                };
+                b.text(&title[..closing]);
+                b.raw(&title[closing..]);
+                b.raw("\n");
+            } else if body.starts_with('|') {
+                b.raw(lead);
+                if body.chars().all(|c| matches!(c, '|' | '-' | ':' | ' ')) {
+                    b.raw(body);
+                } else {
+                    for (n, cell) in body.split('|').enumerate() {
+                        if n > 0 {
+                            b.raw("|");
+                        }
+                        b.text(cell);
+                    }
+                }
+                b.raw("\n");
+            } else if body.starts_with('>') {
+                let depth = body.len() - body.trim_start_matches(['>', ' ']).len();
+                b.raw(lead);
+                b.raw(&body[..depth]);
+                b.text(&body[depth..]);
+                b.raw("\n");
+            } else if let Some(marker) = list_marker(body) {
+                b.raw(lead);
+                b.raw(&body[..marker]);
+                b.prose(&body[marker..]);
+            } else {
+                b.raw(lead);
+                b.prose(body);
+            }
+        }
+        b.flush();
+        Ok(Self {
+            pieces: b.pieces,
+            trailing_newline: input.ends_with('\n'),
+        })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.pieces
+            .iter()
+            .filter_map(|p| match p {
+                Piece::Text(text) => Some(text.as_str()),
+                Piece::Raw(_) => None,
+            })
+            .collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.segments().len(), translated.len())?;
+        let slots = self.pieces.iter_mut().filter_map(|p| match p {
+            Piece::Text(text) => Some(text),
+            Piece::Raw(_) => None,
+        });
+        for (slot, text) in slots.zip(translated) {
+            // A line break inside a segment would end the block early.
+            *slot = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out: String = self
+            .pieces
+            .iter()
+            .map(|p| match p {
+                Piece::Raw(s) | Piece::Text(s) => s.as_str(),
+            })
+            .collect();
+        if !self.trailing_newline && out.ends_with('\n') {
+            out.pop();
+        }
+        out
+    }
+
+    /// `note` becomes an HTML comment at the top, after any front matter.
+    fn mark_machine_translated(&mut self, note: &str) {
+        if note.is_empty() {
+            return;
+        }
+        let comment = format!("<!-- {} -->\n\n", note);
+        match self.pieces.first_mut() {
+            Some(Piece::Raw(raw)) => raw.insert_str(front_matter_len(raw), &comment),
+            _ => self.pieces.insert(0, Piece::Raw(comment)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "---\ntitle: Guide\n---\n# Getting started #\n\nInstall the tool\nwith `cargo`.\n\n```sh\ncargo install deeplx\n```\n\n- [ ] Read the docs\n- Run it\n  twice\n\n> Note: keep it <b>short</b>.\n\n| Key | Meaning |\n|-----|---------|\n| `q` | Quit |\n\n<img src=\"logo.png\">\n[docs]: https://example.com\n";
+
+    #[test]
+    fn test_markdown_segments() {
+        let md = MarkdownFile::parse(DOC).unwrap();
+        assert_eq!(
+            md.segments(),
+            [
+                "Getting started",
+                "Install the tool with `cargo`.",
+                "Read the docs",
+                "Run it twice",
+                "Note: keep it <b>short</b>.",
+                "Key",
+                "Meaning",
+                "`q`",
+                "Quit"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markdown_round_trip() {
+        let mut md = MarkdownFile::parse(DOC).unwrap();
+        let upper: Vec<String> = md.segments().iter().map(|s| s.to_uppercase()).collect();
+        md.replace_segments(&upper).unwrap();
+        let rendered = md.render();
+        assert!(rendered.starts_with("---\ntitle: Guide\n---\n# GETTING STARTED #\n\nINSTALL THE TOOL WITH `CARGO`.\n\n```sh\ncargo install deeplx\n```\n"));
+        assert!(rendered.contains("- [ ] READ THE DOCS\n- RUN IT TWICE\n\n> NOTE"));
+        assert!(rendered.ends_with("| KEY | MEANING |\n|-----|---------|\n| `Q` | QUIT |\n\n<img src=\"logo.png\">\n[docs]: https://example.com\n"));
+        assert_eq!(MarkdownFile::parse("plain").unwrap().render(), "plain");
+    }
+}
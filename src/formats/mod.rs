@@ -0,0 +1,590 @@
+//! Parsers and writers for translatable file formats.
+//!
+//! Every format exposes its translatable text as an ordered list of
+//! segments and is written back with the same structure after the
+//! segments have been replaced. Everything that is not a segment (cue
+//! timings, comments, keys, markup around the text) is kept as it was.
+//!
+//! These parsers consume untrusted uploads, so malformed input must come
+//! back as a [`FormatError`], never as a panic.
+
+use std::{fmt, path::Path, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{dedup::translate_deduplicated, error::DeepLError, Translator};
+
+pub mod android;
+pub mod ass;
+pub mod fit;
+pub mod json_i18n;
+pub mod markdown;
+pub mod pdf_text;
+pub mod plain_text;
+pub mod po;
+pub mod srt;
+pub mod vtt;
+pub mod whisper;
+pub mod xliff;
+mod xml;
+
+pub use android::AndroidStrings;
+pub use ass::AssFile;
+pub use json_i18n::JsonFile;
+pub use markdown::MarkdownFile;
+pub use pdf_text::PdfText;
+pub use plain_text::PlainTextFile;
+pub use po::PoFile;
+pub use srt::SrtFile;
+pub use vtt::VttFile;
+pub use whisper::WhisperJson;
+pub use xliff::XliffFile;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatError {
+    /// 1-based line of the offending input; 0 when not tied to a line.
+    pub line: usize,
+    pub message: String,
+}
+
+impl FormatError {
+    pub(crate) fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => f.write_str(&self.message),
+            line => write!(f, "line {}: {}", line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+pub trait Format: Sized {
+    fn parse(input: &str) -> Result<Self, FormatError>;
+
+    /// Translatable texts in document order.
+    fn segments(&self) -> Vec<&str>;
+
+    /// Replaces every segment, in the order returned by
+    /// [`segments`](Format::segments).
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError>;
+
+    fn render(&self) -> String;
+
+    /// Flags a translated file as machine translated for reviewers, with
+    /// `note` as a comment where the format has comments. Formats with
+    /// neither flags nor comments are left alone.
+    fn mark_machine_translated(&mut self, _note: &str) {}
+}
+
+pub(crate) fn check_count(expected: usize, got: usize) -> Result<(), FormatError> {
+    if expected != got {
+        return Err(FormatError::new(
+            0,
+            format!("expected {} segments, got {}", expected, got),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// The line ending of the first line of `text`; LF for a single line.
+    pub fn detect(text: &str) -> Self {
+        match text.find('\n') {
+            Some(i) if text[..i].ends_with('\r') => LineEnding::Crlf,
+            _ => LineEnding::Lf,
+        }
+    }
+
+    /// `text` with every line ending replaced by this one.
+    pub fn apply(self, text: &str) -> String {
+        let lf = text.replace("\r\n", "\n");
+        match self {
+            LineEnding::Lf => lf,
+            LineEnding::Crlf => lf.replace('\n', "\r\n"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FileError {
+    Format(FormatError),
+    Translate(Arc<DeepLError>),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Format(e) => write!(f, "format error: {}", e),
+            FileError::Translate(e) => write!(f, "translation failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::Format(e) => Some(e),
+            FileError::Translate(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl From<FormatError> for FileError {
+    fn from(e: FormatError) -> Self {
+        FileError::Format(e)
+    }
+}
+
+/// Counts from translating one file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStats {
+    pub segments: usize,
+    pub chars: usize,
+    /// Segments repeating an earlier one in the file, sent only once.
+    pub duplicates: usize,
+    /// Segments answered from a provider's cache.
+    pub cached: usize,
+    /// Segments left in the source language under
+    /// [`OnFailure::KeepSource`].
+    #[serde(default)]
+    pub failed: usize,
+}
+
+/// What to do with a file when some of its segments cannot be translated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OnFailure {
+    /// The whole file fails with the first error.
+    #[default]
+    Fail,
+    /// The file is written with failed segments left in the source
+    /// language, prefixed with `marker`, and the failures are returned.
+    KeepSource { marker: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct SegmentFailure {
+    /// Position in [`Format::segments`].
+    pub index: usize,
+    pub source: String,
+    pub error: Arc<DeepLError>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FileOutcome {
+    pub output: String,
+    pub stats: FileStats,
+    pub failures: Vec<SegmentFailure>,
+}
+
+/// Parses `input` as `F`, translates every segment (repeated segments are
+/// sent once) and renders the translated file with the line endings of
+/// `input`.
+pub async fn translate_file<F: Format>(
+    provider: &dyn Translator,
+    input: &str,
+    src_lang: &str,
+    target_lang: &str,
+) -> Result<String, FileError> {
+    translate_file_with::<F>(provider, input, src_lang, target_lang, &OnFailure::Fail)
+        .await
+        .map(|outcome| outcome.output)
+}
+
+/// [`translate_file`] with a choice of what to do about failed segments,
+/// also counting what was translated.
+pub async fn translate_file_with<F: Format>(
+    provider: &dyn Translator,
+    input: &str,
+    src_lang: &str,
+    target_lang: &str,
+    on_failure: &OnFailure,
+) -> Result<FileOutcome, FileError> {
+    let mut file = F::parse(input)?;
+    let segments = file.segments();
+    let mut stats = FileStats {
+        segments: segments.len(),
+        chars: segments.iter().map(|s| s.chars().count()).sum(),
+        ..FileStats::default()
+    };
+    let outcome = translate_deduplicated(provider, &segments, src_lang, target_lang).await;
+    stats.duplicates = outcome.stats.duplicates;
+    let mut translated = Vec::with_capacity(outcome.results.len());
+    let mut failures = Vec::new();
+    for (index, (result, source)) in outcome.results.into_iter().zip(&segments).enumerate() {
+        match (result, on_failure) {
+            (Ok(translation), _) => {
+                stats.cached += usize::from(translation.is_cached());
+                translated.push(translation.text)
+            }
+            (Err(e), OnFailure::Fail) => return Err(FileError::Translate(e)),
+            (Err(error), OnFailure::KeepSource { marker }) => {
+                translated.push(format!("{}{}", marker, source));
+                failures.push(SegmentFailure {
+                    index,
+                    source: source.to_string(),
+                    error,
+                });
+            }
+        }
+    }
+    stats.failed = failures.len();
+    file.replace_segments(&translated)?;
+    Ok(FileOutcome {
+        output: LineEnding::detect(input).apply(&file.render()),
+        stats,
+        failures,
+    })
+}
+
+/// Re-parses the translated `output` of [`translate_file`] as `F` and
+/// marks it with [`Format::mark_machine_translated`].
+pub fn mark_machine_translated<F: Format>(output: &str, note: &str) -> Result<String, FormatError> {
+    let mut file = F::parse(output)?;
+    file.mark_machine_translated(note);
+    Ok(LineEnding::detect(output).apply(&file.render()))
+}
+
+/// A string of a locale file, by key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyedEntry {
+    pub key: String,
+    /// The text in the file's own language; empty when untranslated.
+    pub text: String,
+    /// What `text` translates, for bilingual formats (PO, XLIFF).
+    pub source: Option<String>,
+    /// Marked as needing review, as fuzzy PO entries are.
+    pub stale: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Android,
+    Ass,
+    Json,
+    Markdown,
+    PlainText,
+    Po,
+    Srt,
+    Vtt,
+    Whisper,
+    Xliff,
+}
+
+impl FileKind {
+    /// Picks the format from the file extension. JSON files are sniffed:
+    /// a top-level `segments` array marks a Whisper transcript.
+    pub fn detect(path: &Path, input: &str) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        Some(match ext.as_str() {
+            "xml" => FileKind::Android,
+            "ass" | "ssa" => FileKind::Ass,
+            "json" if WhisperJson::parse(input).is_ok() => FileKind::Whisper,
+            "json" => FileKind::Json,
+            "md" | "markdown" => FileKind::Markdown,
+            "txt" => FileKind::PlainText,
+            "po" | "pot" => FileKind::Po,
+            "srt" => FileKind::Srt,
+            "vtt" => FileKind::Vtt,
+            "xlf" | "xliff" => FileKind::Xliff,
+            _ => return None,
+        })
+    }
+
+    /// The keyed entries of a locale file, or `None` for formats whose
+    /// segments have no keys, such as subtitles and documents.
+    pub fn entries(self, input: &str) -> Result<Option<Vec<KeyedEntry>>, FormatError> {
+        let entry = |key: String, text: &str, source: Option<&str>, stale: bool| KeyedEntry {
+            key,
+            text: text.to_string(),
+            source: source.map(str::to_string),
+            stale,
+        };
+        Ok(Some(match self {
+            FileKind::Android => AndroidStrings::parse(input)?
+                .resources
+                .iter()
+                .map(|r| match &r.item {
+                    Some(item) => entry(format!("{}[{}]", r.name, item), &r.text, None, false),
+                    None => entry(r.name.clone(), &r.text, None, false),
+                })
+                .collect(),
+            FileKind::Json => JsonFile::parse(input)?
+                .entries()
+                .into_iter()
+                .map(|(key, text)| entry(key, text, None, false))
+                .collect(),
+            FileKind::Po => PoFile::parse(input)?
+                .entries
+                .iter()
+                .filter(|e| !e.is_header())
+                .map(|e| {
+                    let key = match &e.msgctxt {
+                        Some(ctxt) => format!("{}|{}", ctxt, e.msgid),
+                        None => e.msgid.clone(),
+                    };
+                    let text = e.msgstr.first().map_or("", String::as_str);
+                    entry(key, text, Some(&e.msgid), e.is_fuzzy())
+                })
+                .collect(),
+            FileKind::Xliff => XliffFile::parse(input)?
+                .units
+                .iter()
+                .map(|u| {
+                    let key = u.id.clone().unwrap_or_else(|| u.source.clone());
+                    entry(
+                        key,
+                        u.target.as_deref().unwrap_or(""),
+                        Some(&u.source),
+                        false,
+                    )
+                })
+                .collect(),
+            FileKind::Ass
+            | FileKind::Markdown
+            | FileKind::PlainText
+            | FileKind::Srt
+            | FileKind::Vtt
+            | FileKind::Whisper => return Ok(None),
+        }))
+    }
+
+    /// [`translate_file`] with the format picked at run time.
+    pub async fn translate(
+        self,
+        provider: &dyn Translator,
+        input: &str,
+        src_lang: &str,
+        target_lang: &str,
+    ) -> Result<String, FileError> {
+        self.translate_with(provider, input, src_lang, target_lang, &OnFailure::Fail)
+            .await
+            .map(|outcome| outcome.output)
+    }
+
+    /// [`mark_machine_translated`] with the format picked at run time.
+    pub fn mark_machine_translated(self, output: &str, note: &str) -> Result<String, FormatError> {
+        match self {
+            FileKind::Android => mark_machine_translated::<AndroidStrings>(output, note),
+            FileKind::Ass => mark_machine_translated::<AssFile>(output, note),
+            FileKind::Markdown => mark_machine_translated::<MarkdownFile>(output, note),
+            FileKind::Po => mark_machine_translated::<PoFile>(output, note),
+            FileKind::Vtt => mark_machine_translated::<VttFile>(output, note),
+            FileKind::Xliff => mark_machine_translated::<XliffFile>(output, note),
+            // No flags or comments to add; not rendered again either.
+            FileKind::Json | FileKind::PlainText | FileKind::Srt | FileKind::Whisper => {
+                Ok(output.to_string())
+            }
+        }
+    }
+
+    /// [`translate_file_with`] with the format picked at run time.
+    pub async fn translate_with(
+        self,
+        provider: &dyn Translator,
+        input: &str,
+        src_lang: &str,
+        target_lang: &str,
+        on_failure: &OnFailure,
+    ) -> Result<FileOutcome, FileError> {
+        match self {
+            FileKind::Android => {
+                translate_file_with::<AndroidStrings>(
+                    provider,
+                    input,
+                    src_lang,
+                    target_lang,
+                    on_failure,
+                )
+                .await
+            }
+            FileKind::Ass => {
+                translate_file_with::<AssFile>(provider, input, src_lang, target_lang, on_failure)
+                    .await
+            }
+            FileKind::Json => {
+                translate_file_with::<JsonFile>(provider, input, src_lang, target_lang, on_failure)
+                    .await
+            }
+            FileKind::Markdown => {
+                translate_file_with::<MarkdownFile>(
+                    provider,
+                    input,
+                    src_lang,
+                    target_lang,
+                    on_failure,
+                )
+                .await
+            }
+            FileKind::PlainText => {
+                translate_file_with::<PlainTextFile>(
+                    provider,
+                    input,
+                    src_lang,
+                    target_lang,
+                    on_failure,
+                )
+                .await
+            }
+            FileKind::Po => {
+                translate_file_with::<PoFile>(provider, input, src_lang, target_lang, on_failure)
+                    .await
+            }
+            FileKind::Srt => {
+                translate_file_with::<SrtFile>(provider, input, src_lang, target_lang, on_failure)
+                    .await
+            }
+            FileKind::Vtt => {
+                translate_file_with::<VttFile>(provider, input, src_lang, target_lang, on_failure)
+                    .await
+            }
+            FileKind::Whisper => {
+                translate_file_with::<WhisperJson>(
+                    provider,
+                    input,
+                    src_lang,
+                    target_lang,
+                    on_failure,
+                )
+                .await
+            }
+            FileKind::Xliff => {
+                translate_file_with::<XliffFile>(provider, input, src_lang, target_lang, on_failure)
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::{BoxFuture, Translation};
+
+    #[test]
+    fn test_line_endings() {
+        let crlf = "1\r\n00:00:01,000 --> 00:00:02,000\r\nHi\r\n";
+        assert_eq!(LineEnding::detect(crlf), LineEnding::Crlf);
+        assert_eq!(LineEnding::detect("a\nb\r\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("one line"), LineEnding::Lf);
+        assert_eq!(LineEnding::Crlf.apply("a\nb\r\nc"), "a\r\nb\r\nc");
+        assert_eq!(LineEnding::Lf.apply(crlf), crlf.replace('\r', ""));
+    }
+
+    /// Uppercases, but refuses anything mentioning "secret".
+    struct Picky;
+
+    impl Translator for Picky {
+        fn name(&self) -> &str {
+            "picky"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                if text.contains("secret") {
+                    return Err(DeepLError::Status {
+                        status: 400,
+                        body: String::new(),
+                    });
+                }
+                Ok(Translation {
+                    text: text.to_uppercase(),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_on_failure_keeps_source() {
+        let input = "{\n  \"a\": \"open\",\n  \"b\": \"secret\"\n}\n";
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let fail = runtime.block_on(FileKind::Json.translate(&Picky, input, "EN", "DE"));
+        assert!(matches!(fail, Err(FileError::Translate(_))));
+
+        let keep = OnFailure::KeepSource {
+            marker: "[!] ".to_string(),
+        };
+        let outcome = runtime
+            .block_on(FileKind::Json.translate_with(&Picky, input, "EN", "DE", &keep))
+            .unwrap();
+        assert!(outcome.output.contains("\"b\": \"[!] secret\""));
+        assert_eq!((outcome.stats.segments, outcome.stats.failed), (2, 1));
+        assert_eq!(outcome.failures[0].index, 1);
+        assert_eq!(outcome.failures[0].source, "secret");
+    }
+
+    #[test]
+    fn test_mark_machine_translated() {
+        let note = "Machine translated";
+        let po = "msgid \"\"\nmsgstr \"\"\n\"Language: de\\n\"\n\n#, c-format\nmsgid \"%d files\"\nmsgstr \"%d Dateien\"\n\nmsgid \"Open\"\nmsgstr \"\"\n";
+        let po = FileKind::Po.mark_machine_translated(po, note).unwrap();
+        assert!(po.starts_with("# Machine translated\nmsgid \"\"\n"));
+        assert!(po.contains("#, c-format, fuzzy\nmsgid \"%d files\""));
+        assert!(po.contains("\n\nmsgid \"Open\""));
+
+        let xliff = "<xliff version=\"1.2\"><file><body>\r\n<trans-unit id=\"a\">\r\n  <source>Hi</source>\r\n  <target state=\"new\">Hallo</target>\r\n</trans-unit>\r\n</body></file></xliff>\r\n";
+        let xliff = FileKind::Xliff
+            .mark_machine_translated(xliff, note)
+            .unwrap();
+        assert!(xliff.contains(
+            "<target state=\"needs-review-translation\" state-qualifier=\"mt-suggestion\">Hallo</target>\r\n"
+        ));
+        let xliff2 = "<xliff version=\"2.0\"><file><unit id=\"a\"><segment id=\"1\"><source>Hi</source><target>Hallo</target></segment></unit></file></xliff>";
+        assert!(FileKind::Xliff
+            .mark_machine_translated(xliff2, note)
+            .unwrap()
+            .contains("<segment id=\"1\" state=\"translated\" subState=\"deeplx:mt\"><source>"));
+
+        let md = FileKind::Markdown
+            .mark_machine_translated("---\ntitle: Guide\n---\n# Hallo\n", note)
+            .unwrap();
+        assert_eq!(
+            md,
+            "---\ntitle: Guide\n---\n<!-- Machine translated -->\n\n# Hallo\n"
+        );
+        let vtt = FileKind::Vtt
+            .mark_machine_translated("WEBVTT\n\n00:01.000 --> 00:02.000\nHallo\n", note)
+            .unwrap();
+        assert!(vtt.starts_with("WEBVTT\n\nNOTE Machine translated\n\n00:01.000"));
+        let ass = FileKind::Ass
+            .mark_machine_translated("[Script Info]\nTitle: x\n", note)
+            .unwrap();
+        assert_eq!(ass, "[Script Info]\n; Machine translated\nTitle: x\n");
+        let android = FileKind::Android
+            .mark_machine_translated(
+                "<?xml version=\"1.0\"?>\n<resources><string name=\"a\">Hallo</string></resources>\n",
+                note,
+            )
+            .unwrap();
+        assert!(android
+            .starts_with("<?xml version=\"1.0\"?>\n<!-- Machine translated -->\n<resources>"));
+        let json = "{\"a\":\"Hallo\"}";
+        assert_eq!(
+            FileKind::Json.mark_machine_translated(json, note).unwrap(),
+            json
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Plain text extracted from PDFs, as `pdftotext` writes it.
+//!
+//! The text is [reflowed](crate::reflow) on parsing, so each paragraph is
+//! one segment however it was wrapped, and rendered with the paragraphs
+//! separated by blank lines. The original line breaks are not kept: they
+//! belonged to the PDF's layout, not to the text.
+
+use super::{check_count, Format, FormatError};
+use crate::reflow::{paragraphs, Reflow};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PdfText {
+    pub paragraphs: Vec<String>,
+}
+
+impl PdfText {
+    /// Parses `input` reflowed with `options` instead of the defaults.
+    pub fn with_reflow(input: &str, options: &Reflow) -> Self {
+        PdfText {
+            paragraphs: paragraphs(input, options),
+        }
+    }
+}
+
+impl Format for PdfText {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        Ok(Self::with_reflow(input, &Reflow::default()))
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.paragraphs.iter().map(String::as_str).collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.paragraphs.len(), translated.len())?;
+        self.paragraphs = translated.to_vec();
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = self.paragraphs.join("\n\n");
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paragraphs_are_segments() {
+        let input = "Results\nThe model was trained on a large cor-\npus of parallel text.\n\n\u{c}Page two starts here.\n";
+        let mut file = PdfText::parse(input).unwrap();
+        assert_eq!(
+            file.segments(),
+            [
+                "Results",
+                "The model was trained on a large corpus of parallel text.",
+                "Page two starts here.",
+            ]
+        );
+        file.replace_segments(&[
+            "Ergebnisse".into(),
+            "Das Modell.".into(),
+            "Seite zwei.".into(),
+        ])
+        .unwrap();
+        assert_eq!(file.render(), "Ergebnisse\n\nDas Modell.\n\nSeite zwei.\n");
+        assert!(file.replace_segments(&[]).is_err());
+    }
+}
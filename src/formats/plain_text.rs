@@ -0,0 +1,240 @@
+//! Plain-text (`.txt`) documents such as READMEs and mail drafts,
+//! hard-wrapped at a fixed column.
+//!
+//! Each paragraph is one segment, however many lines it was wrapped over,
+//! and the translation is wrapped again at the column the file was
+//! wrapped at. The indentation, `>` quote markers and list markers in
+//! front of the lines are kept. A paragraph ends at a blank line, where
+//! the indentation or the quoting changes, before a list item and after a
+//! line much shorter than the column, such as a greeting or a heading.
+//! Lines without letters or digits, such as rules, are kept verbatim.
+//! Files whose lines were never wrapped are written back unwrapped.
+
+use super::{check_count, Format, FormatError};
+use crate::reflow::{join, list_marker, width, wrap, Reflow};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Paragraph {
+    /// Written in front of the first line, list marker included.
+    pub first_prefix: String,
+    /// Written in front of the lines after the first.
+    pub prefix: String,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Block {
+    Raw(String),
+    Paragraph(Paragraph),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlainTextFile {
+    blocks: Vec<Block>,
+    /// The column the file was wrapped at, prefixes included; `None` when
+    /// no paragraph spans more than one line.
+    pub width: Option<usize>,
+    trailing_newline: bool,
+}
+
+/// `line` split into the indentation and quote markers in front of it and
+/// the text.
+fn split_prefix(line: &str) -> (&str, &str) {
+    let body = line.trim_start_matches(|c: char| c == '>' || c.is_whitespace());
+    line.split_at(line.len() - body.len())
+}
+
+/// The prefix of the lines continuing a paragraph that starts with
+/// `line`: a list item's continues under its text.
+fn continuation(line: &str) -> String {
+    let (prefix, body) = split_prefix(line);
+    match list_marker(body) {
+        Some(marker) => format!("{}{}", prefix, " ".repeat(width(&body[..marker]))),
+        None => prefix.to_string(),
+    }
+}
+
+fn is_text(body: &str) -> bool {
+    body.chars().any(char::is_alphanumeric)
+}
+
+impl PlainTextFile {
+    pub fn paragraphs(&self) -> impl Iterator<Item = &Paragraph> {
+        self.blocks.iter().filter_map(|b| match b {
+            Block::Paragraph(p) => Some(p),
+            Block::Raw(_) => None,
+        })
+    }
+}
+
+impl Format for PlainTextFile {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let lines: Vec<&str> = input.lines().map(str::trim_end).collect();
+        let continues = |i: usize| {
+            let (prefix, body) = split_prefix(lines[i]);
+            is_text(body) && list_marker(body).is_none() && {
+                let (_, above) = split_prefix(lines[i - 1]);
+                is_text(above) && continuation(lines[i - 1]) == prefix
+            }
+        };
+        // The widest line followed by one continuing its paragraph is as
+        // wide as the wrapping allowed; lines standing alone, such as
+        // long links, may be wider.
+        let wrapped = (1..lines.len()).filter(|&i| continues(i));
+        let column = wrapped.map(|i| width(lines[i - 1])).max();
+        let short = column.map_or(0, |w| (w as f64 * Reflow::default().short_line) as usize);
+        let joining = Reflow {
+            dehyphenate: false,
+            ..Reflow::default()
+        };
+
+        let mut blocks = Vec::new();
+        for (i, &line) in lines.iter().enumerate() {
+            let (prefix, body) = split_prefix(line);
+            if !is_text(body) {
+                blocks.push(Block::Raw(line.to_string()));
+                continue;
+            }
+            if i > 0 && continues(i) && width(lines[i - 1]) >= short {
+                if let Some(Block::Paragraph(p)) = blocks.last_mut() {
+                    join(&mut p.text, body, &joining);
+                    continue;
+                }
+            }
+            let marker = list_marker(body).unwrap_or(0);
+            blocks.push(Block::Paragraph(Paragraph {
+                first_prefix: format!("{}{}", prefix, &body[..marker]),
+                prefix: continuation(line),
+                text: body[marker..].to_string(),
+            }));
+        }
+        Ok(Self {
+            blocks,
+            width: column,
+            trailing_newline: input.ends_with('\n'),
+        })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.paragraphs().map(|p| p.text.as_str()).collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.segments().len(), translated.len())?;
+        let slots = self.blocks.iter_mut().filter_map(|b| match b {
+            Block::Paragraph(p) => Some(p),
+            Block::Raw(_) => None,
+        });
+        for (p, text) in slots.zip(translated) {
+            p.text = text.clone();
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut lines = Vec::new();
+        for block in &self.blocks {
+            match block {
+                Block::Raw(line) => lines.push(line.clone()),
+                Block::Paragraph(p) => {
+                    let wrapped = match self.width {
+                        Some(w) => wrap(&p.text, w.saturating_sub(width(&p.prefix)).max(1)),
+                        None => vec![p.text.clone()],
+                    };
+                    for (n, line) in wrapped.iter().enumerate() {
+                        let prefix = if n == 0 { &p.first_prefix } else { &p.prefix };
+                        lines.push(format!("{}{}", prefix, line).trim_end().to_string());
+                    }
+                }
+            }
+        }
+        let mut out = lines.join("\n");
+        if self.trailing_newline {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAFT: &str = "Hi Sam,
+
+thanks for the notes on the release plan. I went
+through them and agree with most of the points
+you made.
+
+> Can we ship the installer before the docs are
+> done, or do they have to go out together?
+
+  - the installer can go first, as long as the
+    download page links to the old docs
+  - the docs follow a week later
+----
+";
+
+    #[test]
+    fn test_paragraphs_keep_prefixes_and_column() {
+        let mut file = PlainTextFile::parse(DRAFT).unwrap();
+        assert_eq!(file.width, Some(48));
+        assert_eq!(
+            file.segments(),
+            [
+                "Hi Sam,",
+                "thanks for the notes on the release plan. I went through them and agree \
+                 with most of the points you made.",
+                "Can we ship the installer before the docs are done, or do they have to go \
+                 out together?",
+                "the installer can go first, as long as the download page links to the old docs",
+                "the docs follow a week later",
+            ]
+        );
+        assert_eq!(file.render(), DRAFT);
+
+        file.replace_segments(&[
+            "Hallo Sam,".into(),
+            "danke für die Anmerkungen zum Veröffentlichungsplan. Ich bin sie durchgegangen \
+             und stimme den meisten Punkten zu."
+                .into(),
+            "Können wir das Installationsprogramm vor der Dokumentation ausliefern?".into(),
+            "das Installationsprogramm kann zuerst erscheinen".into(),
+            "die Dokumentation folgt eine Woche später".into(),
+        ])
+        .unwrap();
+        assert_eq!(
+            file.render(),
+            "Hallo Sam,
+
+danke für die Anmerkungen zum
+Veröffentlichungsplan. Ich bin sie durchgegangen
+und stimme den meisten Punkten zu.
+
+> Können wir das Installationsprogramm vor der
+> Dokumentation ausliefern?
+
+  - das Installationsprogramm kann zuerst
+    erscheinen
+  - die Dokumentation folgt eine Woche später
+----
+"
+        );
+    }
+
+    #[test]
+    fn test_unwrapped_files_stay_unwrapped() {
+        let input = "A line that was never wrapped at any column at all.\n\nAnother.";
+        let mut file = PlainTextFile::parse(input).unwrap();
+        assert_eq!(file.width, None);
+        file.replace_segments(&[
+            "Eine Zeile, die nie umbrochen wurde, an keiner Spalte.".into(),
+            "Noch eine.".into(),
+        ])
+        .unwrap();
+        assert_eq!(
+            file.render(),
+            "Eine Zeile, die nie umbrochen wurde, an keiner Spalte.\n\nNoch eine."
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! GNU gettext `.po` catalogs.
+//!
+//! The header entry (`msgid ""`) is kept but never translated. Plural
+//! entries contribute two segments, `msgid` and `msgid_plural`; the plural
+//! translation fills every `msgstr[n]` after the first.
+
+use super::{check_count, Format, FormatError};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoEntry {
+    /// Comment lines (`#`, `#.`, `#:`, `#,`, `#|`, `#~`) without their
+    /// line endings.
+    pub comments: Vec<String>,
+    pub msgctxt: Option<String>,
+    pub msgid: String,
+    pub msgid_plural: Option<String>,
+    /// One string, or one per plural form.
+    pub msgstr: Vec<String>,
+}
+
+impl PoEntry {
+    pub fn is_header(&self) -> bool {
+        self.msgid.is_empty() && self.msgctxt.is_none()
+    }
+
+    pub fn is_fuzzy(&self) -> bool {
+        self.comments
+            .iter()
+            .any(|c| c.starts_with("#,") && c[2..].split(',').any(|f| f.trim() == "fuzzy"))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoFile {
+    pub entries: Vec<PoEntry>,
+}
+
+pub fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn quoted(s: &str, line: usize) -> Result<String, FormatError> {
+    let s = s.trim();
+    match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(inner) => Ok(unescape(inner)),
+        _ => Err(FormatError::new(line, "expected a quoted string")),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Ctxt,
+    Id,
+    Plural,
+    Str(usize),
+}
+
+fn field_mut(entry: &mut PoEntry, field: Field) -> &mut String {
+    match field {
+        Field::Ctxt => entry.msgctxt.get_or_insert_with(String::new),
+        Field::Id => &mut entry.msgid,
+        Field::Plural => entry.msgid_plural.get_or_insert_with(String::new),
+        Field::Str(i) => {
+            if entry.msgstr.len() <= i {
+                entry.msgstr.resize(i + 1, String::new());
+            }
+            &mut entry.msgstr[i]
+        }
+    }
+}
+
+fn keyword(line: &str, n: usize) -> Result<(Field, &str), FormatError> {
+    let (key, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let field = match key {
+        "msgctxt" => Field::Ctxt,
+        "msgid" => Field::Id,
+        "msgid_plural" => Field::Plural,
+        "msgstr" => Field::Str(0),
+        _ => {
+            let index = key
+                .strip_prefix("msgstr[")
+                .and_then(|k| k.strip_suffix(']'))
+                .and_then(|k| k.parse::<usize>().ok())
+                .filter(|i| *i < MAX_PLURAL_FORMS)
+                .ok_or_else(|| FormatError::new(n, format!("unknown keyword `{}`", key)))?;
+            Field::Str(index)
+        }
+    };
+    Ok((field, rest))
+}
+
+/// No language has more than six plural forms; anything above is garbage.
+const MAX_PLURAL_FORMS: usize = 16;
+
+/// Writes `key "value"`, or msgcat's multi-line form with one quoted line
+/// per `\n` when the value spans several lines.
+fn write_field(out: &mut String, key: &str, value: &str) {
+    let lines: Vec<&str> = value.split_inclusive('\n').collect();
+    out.push_str(key);
+    if lines.len() > 1 {
+        out.push_str(" \"\"\n");
+    } else {
+        out.push(' ');
+    }
+    for line in lines.iter().copied().chain(value.is_empty().then_some("")) {
+        out.push('"');
+        out.push_str(&escape(line));
+        out.push_str("\"\n");
+    }
+}
+
+impl Format for PoFile {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+        let mut entries = Vec::new();
+        let mut current = PoEntry::default();
+        let mut started = false;
+        let mut field = None;
+        for (i, line) in input.lines().enumerate() {
+            let n = i + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('#') {
+                if started {
+                    entries.push(std::mem::take(&mut current));
+                    started = false;
+                    field = None;
+                }
+                current.comments.push(line.to_string());
+                continue;
+            }
+            if line.starts_with('"') {
+                let field = field
+                    .ok_or_else(|| FormatError::new(n, "string continuation without keyword"))?;
+                let value = quoted(line, n)?;
+                field_mut(&mut current, field).push_str(&value);
+                continue;
+            }
+            let (next, rest) = keyword(line, n)?;
+            let starts_entry = match next {
+                Field::Ctxt => true,
+                Field::Id => !matches!(field, Some(Field::Ctxt)),
+                _ => false,
+            };
+            if started && starts_entry {
+                entries.push(std::mem::take(&mut current));
+            }
+            started = true;
+            field = Some(next);
+            let value = quoted(rest, n)?;
+            *field_mut(&mut current, next) = value;
+        }
+        if started || !current.comments.is_empty() {
+            entries.push(current);
+        }
+        Ok(Self { entries })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        let mut out = Vec::new();
+        for entry in self.entries.iter().filter(|e| !e.is_header()) {
+            out.push(entry.msgid.as_str());
+            if let Some(plural) = &entry.msgid_plural {
+                out.push(plural.as_str());
+            }
+        }
+        out
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.segments().len(), translated.len())?;
+        let mut texts = translated.iter();
+        for entry in self.entries.iter_mut().filter(|e| !e.is_header()) {
+            let singular = texts.next().cloned().unwrap_or_default();
+            match entry.msgid_plural {
+                Some(_) => {
+                    let plural = texts.next().cloned().unwrap_or_default();
+                    let forms = entry.msgstr.len().max(2);
+                    entry.msgstr = std::iter::once(singular)
+                        .chain(std::iter::repeat(plural).take(forms - 1))
+                        .collect();
+                }
+                None => entry.msgstr = vec![singular],
+            }
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            for comment in &entry.comments {
+                out.push_str(comment);
+                out.push('\n');
+            }
+            if entry.msgid.is_empty() && entry.msgstr.is_empty() && entry.msgctxt.is_none() {
+                // A trailing comment block with no message attached.
+                continue;
+            }
+            if let Some(ctxt) = &entry.msgctxt {
+                write_field(&mut out, "msgctxt", ctxt);
+            }
+            write_field(&mut out, "msgid", &entry.msgid);
+            match &entry.msgid_plural {
+                Some(plural) => {
+                    write_field(&mut out, "msgid_plural", plural);
+                    for (n, s) in entry.msgstr.iter().enumerate() {
+                        write_field(&mut out, &format!("msgstr[{}]", n), s);
+                    }
+                }
+                None => {
+                    let msgstr = entry.msgstr.first().map_or("", |s| s.as_str());
+                    write_field(&mut out, "msgstr", msgstr);
+                }
+            }
+        }
+        out
+    }
+
+    /// Translated entries get the `fuzzy` flag, so gettext tools treat
+    /// them as needing review, and `note` becomes a header comment.
+    fn mark_machine_translated(&mut self, note: &str) {
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            if entry.is_header() {
+                if i == 0 && !entry.msgstr.is_empty() {
+                    let lines = note
+                        .lines()
+                        .map(|l| format!("# {}", l).trim_end().to_string());
+                    entry.comments.splice(0..0, lines);
+                }
+                continue;
+            }
+            if entry.is_fuzzy() || entry.msgstr.iter().all(String::is_empty) {
+                continue;
+            }
+            match entry.comments.iter_mut().find(|c| c.starts_with("#,")) {
+                Some(flags) => flags.push_str(", fuzzy"),
+                None => {
+                    // Flags go before the `#|` previous-msgid lines.
+                    let at = entry
+                        .comments
+                        .iter()
+                        .position(|c| c.starts_with("#|") || c.starts_with("#~"))
+                        .unwrap_or(entry.comments.len());
+                    entry.comments.insert(at, "#, fuzzy".to_string());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CATALOG: &str = r#"msgid ""
+msgstr ""
+"Language: de\n"
+
+#: src/main.rs:10
+msgid "Hello \"world\""
+msgstr ""
+
+#, fuzzy
+msgctxt "menu"
+msgid "Open"
+msgstr "Öffnen"
+
+msgid "%d file"
+msgid_plural "%d files"
+msgstr[0] ""
+msgstr[1] ""
+msgstr[2] ""
+"#;
+
+    #[test]
+    fn test_po_segments_and_plurals() {
+        let mut po = PoFile::parse(CATALOG).unwrap();
+        assert_eq!(po.entries.len(), 4);
+        assert!(po.entries[0].is_header());
+        assert!(po.entries[2].is_fuzzy());
+        assert_eq!(po.entries[2].msgctxt.as_deref(), Some("menu"));
+        assert_eq!(
+            po.segments(),
+            ["Hello \"world\"", "Open", "%d file", "%d files"]
+        );
+        let translated: Vec<String> = ["Hallo \"Welt\"", "Öffnen", "%d Datei", "%d Dateien"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        po.replace_segments(&translated).unwrap();
+        assert_eq!(
+            po.entries[3].msgstr,
+            ["%d Datei", "%d Dateien", "%d Dateien"]
+        );
+        let rendered = po.render();
+        assert!(rendered.contains("msgstr \"Hallo \\\"Welt\\\"\"\n"));
+        assert_eq!(PoFile::parse(&rendered).unwrap(), po);
+    }
+
+    #[test]
+    fn test_po_rejects_garbage() {
+        assert_eq!(PoFile::parse("msgid \"a\nmsgstr \"\"").unwrap_err().line, 1);
+        assert!(PoFile::parse("\"orphan\"").is_err());
+        assert!(PoFile::parse("msgstr[99999999999999999999] \"\"").is_err());
+    }
+}
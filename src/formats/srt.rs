@@ -0,0 +1,194 @@
+//! SubRip (`.srt`) subtitles.
+
+use std::time::Duration;
+
+use super::{
+    check_count,
+    fit::{fit, CueLimits, Fit},
+    Format, FormatError,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cue {
+    /// The cue number as written; not required to be sequential.
+    pub index: String,
+    pub start: Duration,
+    pub end: Duration,
+    /// Anything after the end timestamp, such as `X1:… Y1:…` positions.
+    pub settings: String,
+    /// Cue text, lines joined with `\n`.
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SrtFile {
+    pub bom: bool,
+    pub cues: Vec<Cue>,
+}
+
+impl SrtFile {
+    /// Rewraps and condenses every cue to `limits`, returning how each one
+    /// fared, in cue order.
+    pub fn fit(&mut self, limits: &CueLimits) -> Vec<Fit> {
+        self.cues
+            .iter_mut()
+            .map(|cue| {
+                let (text, outcome) = fit(&cue.text, cue.end.saturating_sub(cue.start), limits);
+                cue.text = text;
+                outcome
+            })
+            .collect()
+    }
+}
+
+/// Parses `HH:MM:SS,mmm`; a `.` is accepted in place of the comma.
+pub fn parse_timestamp(s: &str) -> Option<Duration> {
+    let (hms, millis) = s.trim().split_once([',', '.'])?;
+    let mut parts = hms.split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: u64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || minutes >= 60 || seconds >= 60 || millis.len() != 3 {
+        return None;
+    }
+    let millis: u64 = millis.parse().ok()?;
+    let secs = hours
+        .checked_mul(3600)?
+        .checked_add(minutes * 60 + seconds)?;
+    Some(Duration::from_secs(secs) + Duration::from_millis(millis))
+}
+
+pub fn format_timestamp(at: Duration) -> String {
+    let secs = at.as_secs();
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        at.subsec_millis()
+    )
+}
+
+fn parse_timing(line: &str) -> Option<(Duration, Duration, String)> {
+    let (start, rest) = line.split_once("-->")?;
+    let rest = rest.trim_start();
+    let end_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (end, settings) = rest.split_at(end_len);
+    Some((
+        parse_timestamp(start)?,
+        parse_timestamp(end)?,
+        settings.trim().to_string(),
+    ))
+}
+
+impl Format for SrtFile {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let (bom, input) = match input.strip_prefix('\u{feff}') {
+            Some(rest) => (true, rest),
+            None => (false, input),
+        };
+        let mut cues = Vec::new();
+        let mut lines = input.lines().map(|l| l.trim_end_matches('\r')).enumerate();
+        while let Some((n, line)) = lines.next() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let index = line.trim().to_string();
+            let (start, end, settings) = lines
+                .next()
+                .and_then(|(_, timing)| parse_timing(timing))
+                .ok_or_else(|| FormatError::new(n + 2, "expected `start --> end` timing"))?;
+            let mut text = Vec::new();
+            for (_, line) in lines.by_ref() {
+                if line.trim().is_empty() {
+                    break;
+                }
+                text.push(line);
+            }
+            cues.push(Cue {
+                index,
+                start,
+                end,
+                settings,
+                text: text.join("\n"),
+            });
+        }
+        Ok(Self { bom, cues })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.cues.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.cues.len(), translated.len())?;
+        for (cue, text) in self.cues.iter_mut().zip(translated) {
+            cue.text = text.clone();
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        if self.bom {
+            out.push('\u{feff}');
+        }
+        for (i, cue) in self.cues.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(&cue.index);
+            out.push('\n');
+            out.push_str(&format_timestamp(cue.start));
+            out.push_str(" --> ");
+            out.push_str(&format_timestamp(cue.end));
+            if !cue.settings.is_empty() {
+                out.push(' ');
+                out.push_str(&cue.settings);
+            }
+            out.push('\n');
+            // A blank line inside the text would end the cue early.
+            for line in cue.text.lines().filter(|l| !l.trim().is_empty()) {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srt_round_trip() {
+        let input = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\nHello\r\nworld\r\n\r\n2\r\n00:01:00.000 --> 01:00:00,001 X1:10\r\nBye\r\n";
+        let mut srt = SrtFile::parse(input).unwrap();
+        assert!(srt.bom);
+        assert_eq!(srt.segments(), ["Hello\nworld", "Bye"]);
+        assert_eq!(srt.cues[1].end, Duration::from_millis(3_600_001));
+        srt.replace_segments(&["你好\n世界".to_string(), "再见".to_string()])
+            .unwrap();
+        assert_eq!(
+            srt.render(),
+            "\u{feff}1\n00:00:01,000 --> 00:00:02,500\n你好\n世界\n\n2\n00:01:00,000 --> 01:00:00,001 X1:10\n再见\n"
+        );
+    }
+
+    #[test]
+    fn test_srt_fit() {
+        let mut srt = SrtFile::parse(
+            "1\n00:00:01,000 --> 00:00:04,000\nDas ist ein\nziemlich langer\nUntertitel.\n",
+        )
+        .unwrap();
+        assert_eq!(srt.fit(&CueLimits::default()), [Fit::Rewrapped]);
+        assert_eq!(srt.cues[0].text, "Das ist ein ziemlich langer Untertitel.");
+    }
+
+    #[test]
+    fn test_srt_rejects_bad_timing() {
+        assert_eq!(SrtFile::parse("1\nnot a timing\n").unwrap_err().line, 2);
+        assert!(SrtFile::parse("1\n00:00:99,000 --> 00:00:01,000\n").is_err());
+    }
+}
@@ -0,0 +1,357 @@
+//! WebVTT (`.vtt`) subtitles and transcripts.
+//!
+//! Speaker labels are `<v Name>` voice spans. Each span is its own
+//! segment, so a cue shared by two speakers is translated as two
+//! utterances and the voice tags never reach the translator. `NOTE`,
+//! `STYLE` and `REGION` blocks are kept verbatim.
+
+use std::time::Duration;
+
+use super::{check_count, Format, FormatError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Utterance {
+    /// The opening voice tag as written, such as `<v.loud Esme>`.
+    pub voice: Option<String>,
+    /// Utterance text, lines joined with `\n`.
+    pub text: String,
+    /// Whether the span was closed with `</v>`.
+    pub closed: bool,
+}
+
+impl Utterance {
+    /// The speaker named by the voice tag.
+    pub fn speaker(&self) -> Option<&str> {
+        let voice = self.voice.as_deref()?;
+        let inner = voice.strip_prefix("<v")?.strip_suffix('>')?;
+        let (_, name) = inner.split_once([' ', '\t'])?;
+        Some(name.trim())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cue {
+    pub id: Option<String>,
+    pub start: Duration,
+    pub end: Duration,
+    /// Cue settings after the end timestamp, such as `align:start`.
+    pub settings: String,
+    pub utterances: Vec<Utterance>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Block {
+    Cue(Cue),
+    /// A `NOTE`, `STYLE` or `REGION` block, lines joined with `\n`.
+    Other(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VttFile {
+    pub bom: bool,
+    /// The `WEBVTT` line and any header lines after it.
+    pub header: String,
+    pub blocks: Vec<Block>,
+    /// Write `HH:MM:SS.mmm` timestamps even below one hour, as the input
+    /// did.
+    pub hours: bool,
+}
+
+impl VttFile {
+    pub fn cues(&self) -> impl Iterator<Item = &Cue> {
+        self.blocks.iter().filter_map(|b| match b {
+            Block::Cue(cue) => Some(cue),
+            Block::Other(_) => None,
+        })
+    }
+}
+
+/// Parses `[HH:]MM:SS.mmm`, also returning whether the hours were written.
+pub fn parse_timestamp(s: &str) -> Option<(Duration, bool)> {
+    let (hms, millis) = s.trim().split_once('.')?;
+    let parts: Vec<&str> = hms.split(':').collect();
+    let (hours, minutes, seconds) = match parts[..] {
+        [m, s] => ("0", m, s),
+        [h, m, s] => (h, m, s),
+        _ => return None,
+    };
+    let hours: u64 = hours.parse().ok()?;
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    if minutes >= 60 || seconds >= 60 || millis.len() != 3 {
+        return None;
+    }
+    let millis: u64 = millis.parse().ok()?;
+    let secs = hours
+        .checked_mul(3600)?
+        .checked_add(minutes * 60 + seconds)?;
+    Some((
+        Duration::from_secs(secs) + Duration::from_millis(millis),
+        parts.len() == 3,
+    ))
+}
+
+pub fn format_timestamp(at: Duration, hours: bool) -> String {
+    let secs = at.as_secs();
+    if hours || secs >= 3600 {
+        format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            at.subsec_millis()
+        )
+    } else {
+        format!(
+            "{:02}:{:02}.{:03}",
+            secs / 60,
+            secs % 60,
+            at.subsec_millis()
+        )
+    }
+}
+
+fn parse_timing(line: &str) -> Option<(Duration, Duration, String, bool)> {
+    let (start, rest) = line.split_once("-->")?;
+    let rest = rest.trim_start();
+    let end_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (end, settings) = rest.split_at(end_len);
+    let (start, start_hours) = parse_timestamp(start)?;
+    let (end, end_hours) = parse_timestamp(end)?;
+    Some((
+        start,
+        end,
+        settings.trim().to_string(),
+        start_hours || end_hours,
+    ))
+}
+
+/// Splits a cue payload into voice spans; a line opening with `<v` starts a
+/// new one.
+fn utterances(lines: &[&str]) -> Vec<Utterance> {
+    let mut out: Vec<Utterance> = Vec::new();
+    for line in lines {
+        let voice = line
+            .strip_prefix("<v")
+            .filter(|rest| rest.starts_with([' ', '\t', '.']))
+            .and_then(|_| line.find('>'));
+        match (voice, out.last_mut()) {
+            (Some(end), _) => out.push(Utterance {
+                voice: Some(line[..=end].to_string()),
+                text: line[end + 1..].to_string(),
+                closed: false,
+            }),
+            (None, Some(last)) => {
+                last.text.push('\n');
+                last.text.push_str(line);
+            }
+            (None, None) => out.push(Utterance {
+                voice: None,
+                text: line.to_string(),
+                closed: false,
+            }),
+        }
+    }
+    for utterance in &mut out {
+        if let Some(text) = utterance.text.strip_suffix("</v>") {
+            utterance.text = text.to_string();
+            utterance.closed = true;
+        }
+    }
+    out
+}
+
+impl Format for VttFile {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let (bom, input) = match input.strip_prefix('\u{feff}') {
+            Some(rest) => (true, rest),
+            None => (false, input),
+        };
+        let lines: Vec<&str> = input.lines().map(|l| l.trim_end_matches('\r')).collect();
+        let signature = lines.first().copied().unwrap_or_default();
+        if signature.split([' ', '\t']).next() != Some("WEBVTT") {
+            return Err(FormatError::new(1, "expected a `WEBVTT` signature"));
+        }
+
+        // Blocks of consecutive non-blank lines, with the 1-based line each
+        // starts on.
+        let mut groups: Vec<(usize, Vec<&str>)> = Vec::new();
+        let mut blank = true;
+        for (n, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                blank = true;
+                continue;
+            }
+            match groups.last_mut() {
+                Some((_, group)) if !blank => group.push(line),
+                _ => groups.push((n + 1, vec![line])),
+            }
+            blank = false;
+        }
+
+        let mut groups = groups.into_iter();
+        let header = groups.next().map(|(_, g)| g.join("\n")).unwrap_or_default();
+        let mut hours = false;
+        let mut blocks = Vec::new();
+        for (line, group) in groups {
+            let first = group[0];
+            let keyword = first.split([' ', '\t']).next().unwrap_or_default();
+            if matches!(keyword, "NOTE" | "STYLE" | "REGION") {
+                blocks.push(Block::Other(group.join("\n")));
+                continue;
+            }
+            let (id, timing_at) = match first.contains("-->") {
+                true => (None, 0),
+                false => (Some(first.to_string()), 1),
+            };
+            let (start, end, settings, long) = group
+                .get(timing_at)
+                .and_then(|timing| parse_timing(timing))
+                .ok_or_else(|| {
+                    FormatError::new(line + timing_at, "expected `start --> end` timing")
+                })?;
+            hours |= long;
+            blocks.push(Block::Cue(Cue {
+                id,
+                start,
+                end,
+                settings,
+                utterances: utterances(&group[timing_at + 1..]),
+            }));
+        }
+        Ok(Self {
+            bom,
+            header,
+            blocks,
+            hours,
+        })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.cues()
+            .flat_map(|c| c.utterances.iter().map(|u| u.text.as_str()))
+            .collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.segments().len(), translated.len())?;
+        let mut translated = translated.iter();
+        for block in &mut self.blocks {
+            if let Block::Cue(cue) = block {
+                for (utterance, text) in cue.utterances.iter_mut().zip(translated.by_ref()) {
+                    utterance.text = text.clone();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        if self.bom {
+            out.push('\u{feff}');
+        }
+        out.push_str(&self.header);
+        out.push('\n');
+        for block in &self.blocks {
+            out.push('\n');
+            let cue = match block {
+                Block::Other(text) => {
+                    out.push_str(text);
+                    out.push('\n');
+                    continue;
+                }
+                Block::Cue(cue) => cue,
+            };
+            if let Some(id) = &cue.id {
+                out.push_str(id);
+                out.push('\n');
+            }
+            out.push_str(&format_timestamp(cue.start, self.hours));
+            out.push_str(" --> ");
+            out.push_str(&format_timestamp(cue.end, self.hours));
+            if !cue.settings.is_empty() {
+                out.push(' ');
+                out.push_str(&cue.settings);
+            }
+            out.push('\n');
+            for utterance in &cue.utterances {
+                // A blank line inside the text would end the cue early.
+                let mut text = utterance
+                    .text
+                    .lines()
+                    .filter(|l| !l.trim().is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if let Some(voice) = &utterance.voice {
+                    text.insert_str(0, voice);
+                }
+                if utterance.closed {
+                    text.push_str("</v>");
+                }
+                if !text.is_empty() {
+                    out.push_str(&text);
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+
+    /// `note` becomes a `NOTE` block before the first cue.
+    fn mark_machine_translated(&mut self, note: &str) {
+        let lines: Vec<&str> = note.lines().filter(|l| !l.trim().is_empty()).collect();
+        if !lines.is_empty() {
+            let note = format!("NOTE {}", lines.join("\n"));
+            self.blocks.insert(0, Block::Other(note));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vtt_translates_utterances_only() {
+        let input = "WEBVTT - meeting\r\n\r\nNOTE recorded live\r\n\r\nintro\r\n00:01.000 --> 00:04.500 align:start\r\n<v.lead Anna Smith>Good morning.\r\nLet's begin.</v>\r\n<v Ben>Morning!\r\n\r\n01:00:00.000 --> 01:00:02.000\r\nThanks, all.\r\n";
+        let mut vtt = VttFile::parse(input).unwrap();
+        assert_eq!(
+            vtt.segments(),
+            ["Good morning.\nLet's begin.", "Morning!", "Thanks, all."]
+        );
+        let speakers: Vec<_> = vtt
+            .cues()
+            .flat_map(|c| c.utterances.iter().map(Utterance::speaker))
+            .collect();
+        assert_eq!(speakers, [Some("Anna Smith"), Some("Ben"), None]);
+        assert!(vtt.hours);
+        vtt.replace_segments(&[
+            "Guten Morgen.\nFangen wir an.".to_string(),
+            "Morgen!".to_string(),
+            "Danke euch.".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(
+            vtt.render(),
+            "WEBVTT - meeting\n\nNOTE recorded live\n\nintro\n00:00:01.000 --> 00:00:04.500 align:start\n<v.lead Anna Smith>Guten Morgen.\nFangen wir an.</v>\n<v Ben>Morgen!\n\n01:00:00.000 --> 01:00:02.000\nDanke euch.\n"
+        );
+    }
+
+    #[test]
+    fn test_vtt_rejects_bad_input() {
+        assert_eq!(
+            VttFile::parse("1\n00:01.000 --> 00:02.000\n")
+                .unwrap_err()
+                .line,
+            1
+        );
+        assert_eq!(
+            VttFile::parse("WEBVTT\n\nid\nnot a timing\n")
+                .unwrap_err()
+                .line,
+            4
+        );
+        assert!(VttFile::parse("WEBVTT\n\n00:61.000 --> 00:62.000\nhi\n").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//! Whisper JSON transcripts, as written by `whisper --output_format json`
+//! and WhisperX.
+//!
+//! Each `segments[].text` is a segment. Timings, speaker labels and any
+//! other fields are kept, except `words` and `tokens`: they describe the
+//! source text and no longer line up with a translation, so they are
+//! dropped from translated segments. A top-level `text` is rebuilt from the
+//! translated segments.
+
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Serializer, Value};
+
+use super::{check_count, json_i18n::detect_indent, Format, FormatError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WhisperJson {
+    pub root: Value,
+    /// `None` for single-line output, as Whisper itself writes.
+    indent: Option<String>,
+    trailing_newline: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Utterance<'a> {
+    /// Seconds from the start of the recording.
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    /// The diarization label, such as WhisperX's `SPEAKER_00`.
+    pub speaker: Option<&'a str>,
+    pub text: &'a str,
+}
+
+impl WhisperJson {
+    fn items(&self) -> impl Iterator<Item = &serde_json::Map<String, Value>> {
+        self.root["segments"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_object)
+    }
+
+    pub fn utterances(&self) -> Vec<Utterance<'_>> {
+        self.items()
+            .map(|item| Utterance {
+                start: item.get("start").and_then(Value::as_f64),
+                end: item.get("end").and_then(Value::as_f64),
+                speaker: item.get("speaker").and_then(Value::as_str),
+                text: item
+                    .get("text")
+                    .and_then(Value::as_str)
+                    .unwrap_or("")
+                    .trim(),
+            })
+            .collect()
+    }
+}
+
+impl Format for WhisperJson {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+        let root: Value =
+            serde_json::from_str(input).map_err(|e| FormatError::new(e.line(), e.to_string()))?;
+        let segments = root
+            .get("segments")
+            .and_then(Value::as_array)
+            .ok_or_else(|| FormatError::new(0, "expected a `segments` array"))?;
+        for (i, segment) in segments.iter().enumerate() {
+            if !segment.get("text").is_some_and(Value::is_string) {
+                return Err(FormatError::new(
+                    0,
+                    format!("segment {} has no `text` string", i),
+                ));
+            }
+        }
+        Ok(Self {
+            indent: input.trim().contains('\n').then(|| detect_indent(input)),
+            trailing_newline: input.ends_with('\n'),
+            root,
+        })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.utterances().into_iter().map(|u| u.text).collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.items().count(), translated.len())?;
+        let Some(segments) = self.root["segments"].as_array_mut() else {
+            return Ok(());
+        };
+        let mut full = String::new();
+        for (segment, text) in segments.iter_mut().zip(translated) {
+            let Some(item) = segment.as_object_mut() else {
+                continue;
+            };
+            // Whisper starts each segment with the space that separated it
+            // from the previous one.
+            let original = item.get("text").and_then(Value::as_str).unwrap_or("");
+            let text = format!(
+                "{}{}",
+                &original[..original.len() - original.trim_start().len()],
+                text
+            );
+            full.push_str(&text);
+            item.insert("text".to_string(), Value::String(text));
+            item.remove("words");
+            item.remove("tokens");
+        }
+        if let Some(text) = self.root.get_mut("text") {
+            *text = Value::String(full);
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = match &self.indent {
+            Some(indent) => {
+                let mut buf = Vec::new();
+                let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+                let mut serializer = Serializer::with_formatter(&mut buf, formatter);
+                // Serializing a `Value` into memory cannot fail.
+                let _ = self.root.serialize(&mut serializer);
+                String::from_utf8(buf).unwrap_or_default()
+            }
+            None => self.root.to_string(),
+        };
+        if self.trailing_newline {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whisper_keeps_timing_and_speakers() {
+        let input = r#"{"text": " Hello there. How are you?", "segments": [{"id": 0, "start": 0.0, "end": 1.5, "text": " Hello there.", "tokens": [1, 2], "speaker": "SPEAKER_00", "words": [{"word": "Hello", "start": 0.0, "end": 0.4}]}, {"id": 1, "start": 1.5, "end": 3.0, "text": " How are you?", "speaker": "SPEAKER_01"}], "language": "en"}"#;
+        let mut transcript = WhisperJson::parse(input).unwrap();
+        assert_eq!(transcript.segments(), ["Hello there.", "How are you?"]);
+        assert_eq!(transcript.utterances()[1].speaker, Some("SPEAKER_01"));
+        assert_eq!(transcript.utterances()[1].start, Some(1.5));
+        transcript
+            .replace_segments(&["Hallo.".to_string(), "Wie geht's?".to_string()])
+            .unwrap();
+        assert_eq!(
+            transcript.render(),
+            r#"{"text":" Hallo. Wie geht's?","segments":[{"id":0,"start":0.0,"end":1.5,"text":" Hallo.","speaker":"SPEAKER_00"},{"id":1,"start":1.5,"end":3.0,"text":" Wie geht's?","speaker":"SPEAKER_01"}],"language":"en"}"#
+        );
+    }
+
+    #[test]
+    fn test_whisper_rejects_non_transcripts() {
+        assert!(WhisperJson::parse("{\"messages\": {}}").is_err());
+        assert!(WhisperJson::parse("{\"segments\": [{\"start\": 0}]}").is_err());
+        assert_eq!(
+            WhisperJson::parse("{\n\"segments\": [\n").unwrap_err().line,
+            3
+        );
+    }
+}
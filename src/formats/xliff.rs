@@ -0,0 +1,225 @@
+//! XLIFF 1.2 and 2.x bilingual files.
+//!
+//! Only `<source>`/`<target>` pairs are touched; the rest of the document
+//! is written back byte for byte. Segments are the raw inner markup of
+//! `<source>`, so inline tags (`<g>`, `<x/>`, `<ph>`) and entities pass
+//! through unchanged and translations are inserted as markup too.
+//!
+//! Machine translations are marked with `state="needs-review-translation"
+//! state-qualifier="mt-suggestion"` on the 1.2 `<target>`, or with
+//! `state="translated" subState="deeplx:mt"` on the 2.x `<segment>`.
+
+use std::ops::Range;
+
+use super::{
+    check_count,
+    xml::{attr, find_tag, line_of, set_attr},
+    Format, FormatError,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Placement {
+    /// Inner range of an existing `<target>`.
+    Replace(Range<usize>),
+    /// Offset just after `</source>`, with the indentation of `<source>`.
+    Insert(usize, String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unit {
+    /// `id` of the enclosing `<trans-unit>` or `<unit>`.
+    pub id: Option<String>,
+    pub source: String,
+    pub target: Option<String>,
+    placement: Placement,
+    /// Start tag of the existing `<target>`.
+    target_tag: Option<Range<usize>>,
+    /// Start tag of the enclosing XLIFF 2 `<segment>`.
+    segment_tag: Option<Range<usize>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XliffFile {
+    input: String,
+    pub units: Vec<Unit>,
+    machine_translated: bool,
+}
+
+fn enclosing_id(input: &str, before: usize) -> Option<String> {
+    let head = &input[..before];
+    let start = [head.rfind("<trans-unit"), head.rfind("<unit")]
+        .into_iter()
+        .flatten()
+        .max()?;
+    let end = start + head[start..].find('>')?;
+    attr(&head[start..end], "id")
+}
+
+fn indent_of(input: &str, offset: usize) -> String {
+    let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+    input[line_start..offset]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect()
+}
+
+impl Format for XliffFile {
+    fn parse(input: &str) -> Result<Self, FormatError> {
+        let mut units = Vec::new();
+        let mut pos = 0;
+        while let Some((start, content, self_closing)) = find_tag(input, pos, "source") {
+            let segment_tag = find_tag(&input[..start], pos, "segment")
+                .filter(|(_, _, self_closing)| !self_closing)
+                .map(|(s_start, s_end, _)| s_start..s_end);
+            pos = content;
+            if self_closing {
+                continue;
+            }
+            let close = input[content..]
+                .find("</source>")
+                .ok_or_else(|| FormatError::new(line_of(input, start), "unclosed <source>"))?;
+            let source = input[content..content + close].to_string();
+            let after = content + close + "</source>".len();
+            pos = after;
+
+            let gap = input[after..].len() - input[after..].trim_start().len();
+            let mut target_tag = None;
+            let placement = match find_tag(input, after + gap, "target") {
+                Some((t_start, t_content, false)) if t_start == after + gap => {
+                    let t_close = input[t_content..].find("</target>").ok_or_else(|| {
+                        FormatError::new(line_of(input, t_start), "unclosed <target>")
+                    })?;
+                    pos = t_content + t_close + "</target>".len();
+                    target_tag = Some(t_start..t_content);
+                    Placement::Replace(t_content..t_content + t_close)
+                }
+                _ => Placement::Insert(after, indent_of(input, start)),
+            };
+            let target = match &placement {
+                Placement::Replace(range) => Some(input[range.clone()].to_string()),
+                Placement::Insert(..) => None,
+            };
+            units.push(Unit {
+                id: enclosing_id(input, start),
+                source,
+                target,
+                placement,
+                target_tag,
+                segment_tag,
+            });
+        }
+        Ok(Self {
+            input: input.to_string(),
+            units,
+            machine_translated: false,
+        })
+    }
+
+    fn segments(&self) -> Vec<&str> {
+        self.units.iter().map(|u| u.source.as_str()).collect()
+    }
+
+    fn replace_segments(&mut self, translated: &[String]) -> Result<(), FormatError> {
+        check_count(self.units.len(), translated.len())?;
+        for (unit, text) in self.units.iter_mut().zip(translated) {
+            unit.target = Some(text.clone());
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::with_capacity(self.input.len());
+        let mut last = 0;
+        for unit in &self.units {
+            let Some(target) = &unit.target else {
+                continue;
+            };
+            let mut target_tag = "<target>".to_string();
+            if self.machine_translated {
+                match (&unit.segment_tag, &unit.target_tag) {
+                    (Some(tag), _) => {
+                        let tag_text = set_attr(&self.input[tag.clone()], "state", "translated");
+                        out.push_str(&self.input[last..tag.start]);
+                        out.push_str(&set_attr(&tag_text, "subState", "deeplx:mt"));
+                        last = tag.end;
+                    }
+                    (None, tag) => {
+                        let tag = tag.as_ref().map_or("<target>", |t| &self.input[t.clone()]);
+                        let tag = set_attr(tag, "state", "needs-review-translation");
+                        target_tag = set_attr(&tag, "state-qualifier", "mt-suggestion");
+                        if let Some(tag) = &unit.target_tag {
+                            out.push_str(&self.input[last..tag.start]);
+                            out.push_str(&target_tag);
+                            last = tag.end;
+                        }
+                    }
+                }
+            }
+            match &unit.placement {
+                Placement::Replace(range) => {
+                    out.push_str(&self.input[last..range.start]);
+                    out.push_str(target);
+                    last = range.end;
+                }
+                Placement::Insert(at, indent) => {
+                    out.push_str(&self.input[last..*at]);
+                    out.push('\n');
+                    out.push_str(indent);
+                    out.push_str(&target_tag);
+                    out.push_str(target);
+                    out.push_str("</target>");
+                    last = *at;
+                }
+            }
+        }
+        out.push_str(&self.input[last..]);
+        out
+    }
+
+    fn mark_machine_translated(&mut self, _note: &str) {
+        self.machine_translated = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = r#"<xliff version="1.2"><file source-language="en" target-language="de">
+<body>
+  <trans-unit id="greeting">
+    <source>Hello <g id="1">world</g></source>
+  </trans-unit>
+  <trans-unit id="bye">
+    <source>Bye</source>
+    <target state="new">Tschau</target>
+  </trans-unit>
+</body></file></xliff>"#;
+
+    #[test]
+    fn test_xliff_inserts_and_replaces_targets() {
+        let mut doc = XliffFile::parse(DOC).unwrap();
+        assert_eq!(doc.segments(), ["Hello <g id=\"1\">world</g>", "Bye"]);
+        assert_eq!(doc.units[0].id.as_deref(), Some("greeting"));
+        assert_eq!(doc.units[1].target.as_deref(), Some("Tschau"));
+        doc.replace_segments(&[
+            "Hallo <g id=\"1\">Welt</g>".to_string(),
+            "Tschüss".to_string(),
+        ])
+        .unwrap();
+        let rendered = doc.render();
+        assert!(rendered.contains(
+            "<source>Hello <g id=\"1\">world</g></source>\n    <target>Hallo <g id=\"1\">Welt</g></target>\n"
+        ));
+        assert!(rendered.contains("<target state=\"new\">Tschüss</target>"));
+        assert_eq!(XliffFile::parse(&rendered).unwrap().units.len(), 2);
+    }
+
+    #[test]
+    fn test_xliff_rejects_unclosed_source() {
+        assert_eq!(
+            XliffFile::parse("<xliff>\n<source>oops").unwrap_err().line,
+            2
+        );
+    }
+}
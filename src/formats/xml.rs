@@ -0,0 +1,67 @@
+//! Just enough XML scanning for formats that must be written back
+//! byte for byte around the parts that change.
+
+pub(super) fn line_of(input: &str, offset: usize) -> usize {
+    input[..offset].matches('\n').count() + 1
+}
+
+/// Finds the next `<name>` or `<name attr…>` start tag at or after `from`.
+/// Returns the offset of `<` and the offset just past `>`, and whether the
+/// tag is self-closing.
+pub(super) fn find_tag(input: &str, from: usize, name: &str) -> Option<(usize, usize, bool)> {
+    let open = format!("<{}", name);
+    let mut pos = from;
+    while let Some(found) = input[pos..].find(&open) {
+        let start = pos + found;
+        pos = start + open.len();
+        if !is_tag(&input[start..], name) {
+            continue;
+        }
+        let end = pos + input[pos..].find('>')? + 1;
+        return Some((start, end, input[..end].ends_with("/>")));
+    }
+    None
+}
+
+/// Whether `s` starts with a `<name` start tag, not just a longer name
+/// sharing the prefix.
+pub(super) fn is_tag(s: &str, name: &str) -> bool {
+    s.strip_prefix('<')
+        .and_then(|s| s.strip_prefix(name))
+        .and_then(|s| s.chars().next())
+        .is_some_and(|c| c == '>' || c == '/' || c.is_whitespace())
+}
+
+/// `tag` with attribute `name` set to `value`, replacing any earlier value.
+pub(super) fn set_attr(tag: &str, name: &str, value: &str) -> String {
+    let needle = format!("{}=\"", name);
+    let mut pos = 0;
+    while let Some(found) = tag[pos..].find(&needle) {
+        let start = pos + found;
+        pos = start + needle.len();
+        if !tag[..start].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let Some(len) = tag[pos..].find('"') else {
+            break;
+        };
+        return format!("{}{}{}", &tag[..pos], value, &tag[pos + len..]);
+    }
+    let end = tag.len() - usize::from(tag.ends_with("/>")) - 1;
+    format!("{} {}=\"{}\"{}", &tag[..end], name, value, &tag[end..])
+}
+
+pub(super) fn attr(tag: &str, name: &str) -> Option<String> {
+    let needle = format!("{}=\"", name);
+    let mut pos = 0;
+    while let Some(found) = tag[pos..].find(&needle) {
+        let start = pos + found;
+        pos = start + needle.len();
+        if !tag[..start].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let len = tag[pos..].find('"')?;
+        return Some(tag[pos..pos + len].to_string());
+    }
+    None
+}
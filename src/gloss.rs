@@ -0,0 +1,172 @@
+//! Word-by-word glosses for language-learning and dictionary-style reader
+//! apps: the sentence translation plus a translation of every word in it.
+//!
+//! Words are runs of letters and digits, so scripts written without spaces
+//! come back as whole runs rather than single words. Repeated words are
+//! only translated once. Word alternatives are filled in when the provider
+//! returns any.
+
+use std::{fmt, ops::Range, sync::Arc};
+
+use crate::{
+    dedup::translate_deduplicated,
+    error::DeepLError,
+    translator::{Translation, Translator},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlossEntry {
+    pub word: String,
+    /// Where `word` sits in the source text, in bytes.
+    pub range: Range<usize>,
+    pub translation: String,
+    pub alternatives: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gloss {
+    pub translation: Translation,
+    pub words: Vec<GlossEntry>,
+}
+
+/// Displays as the sentence translation followed by a two-column table of
+/// words and their glosses.
+impl fmt::Display for Gloss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.translation.text)?;
+        let width = self
+            .words
+            .iter()
+            .map(|w| w.word.chars().count())
+            .max()
+            .unwrap_or(0);
+        for entry in &self.words {
+            let pad = width - entry.word.chars().count();
+            write!(
+                f,
+                "  {}{}  {}",
+                entry.word,
+                " ".repeat(pad),
+                entry.translation
+            )?;
+            if !entry.alternatives.is_empty() {
+                write!(f, " ({})", entry.alternatives.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Byte ranges of the words in `text`.
+pub fn words(text: &str) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        let joiner = matches!(c, '\'' | '’' | '-') && start.is_some();
+        match (c.is_alphanumeric() || joiner, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                out.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        out.push(s..text.len());
+    }
+    for range in &mut out {
+        let word = &text[range.clone()];
+        range.end = range.start + word.trim_end_matches(['\'', '’', '-']).len();
+    }
+    out.retain(|r| text[r.clone()].chars().any(char::is_alphabetic));
+    out
+}
+
+pub async fn gloss(
+    provider: &dyn Translator,
+    text: &str,
+    src_lang: &str,
+    target_lang: &str,
+) -> Result<Gloss, Arc<DeepLError>> {
+    let translation = provider
+        .translate(text, src_lang, target_lang)
+        .await
+        .map_err(Arc::new)?;
+    // Single words are hard to detect; reuse the sentence's language.
+    let src = translation.detected_source.as_deref().unwrap_or(src_lang);
+    let ranges = words(text);
+    let segments: Vec<&str> = ranges.iter().map(|r| &text[r.clone()]).collect();
+    let outcome = translate_deduplicated(provider, &segments, src, target_lang).await;
+    let mut entries = Vec::with_capacity(ranges.len());
+    for (range, result) in ranges.into_iter().zip(outcome.results) {
+        let word = result?;
+        entries.push(GlossEntry {
+            word: text[range.clone()].to_string(),
+            range,
+            translation: word.text,
+            alternatives: word.alternatives,
+        });
+    }
+    Ok(Gloss {
+        translation,
+        words: entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::BoxFuture;
+
+    struct Dictionary;
+
+    impl Translator for Dictionary {
+        fn name(&self) -> &str {
+            "dictionary"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            let (text, alternatives) = match text {
+                "the" => ("der", vec!["die".to_string(), "das".to_string()]),
+                "cat's" => ("der Katze", vec![]),
+                "toy" => ("Spielzeug", vec![]),
+                _ => ("Das Spielzeug der Katze, das Spielzeug", vec![]),
+            };
+            Box::pin(async move {
+                Ok(Translation {
+                    text: text.to_string(),
+                    alternatives,
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_gloss_table() {
+        let gloss = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(gloss(&Dictionary, "the cat's toy, the toy 42", "EN", "DE"))
+            .unwrap();
+        assert_eq!(gloss.words.len(), 5);
+        assert_eq!(gloss.words[1].range, 4..9);
+        assert_eq!(
+            gloss.to_string(),
+            "Das Spielzeug der Katze, das Spielzeug\n\
+             \x20 the    der (die, das)\n\
+             \x20 cat's  der Katze\n\
+             \x20 toy    Spielzeug\n\
+             \x20 the    der (die, das)\n\
+             \x20 toy    Spielzeug\n"
+        );
+    }
+}
@@ -0,0 +1,531 @@
+//! Glossaries: fixed translations for a project's terminology.
+//!
+//! A glossary is kept as tab-separated `source<TAB>target` lines, the
+//! format DeepL itself accepts for glossaries. [`extract`] prepares one
+//! for a new document by listing the terms worth pinning down, with the
+//! targets left empty for a translator to fill in.
+//!
+//! The official API applies glossaries stored with
+//! [`DeepLClient::create_glossary`](crate::DeepLClient::create_glossary).
+//! Other backends get the same guarantee from [`Enforced`], which masks
+//! every term before translating and puts its fixed translation back
+//! afterwards.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::Range,
+    sync::Arc,
+};
+
+use crate::{
+    capabilities::Capabilities,
+    error::DeepLError,
+    options::TranslateOptions,
+    protect::{mask_with, unmask},
+    translator::{BoxFuture, Translation, Translator},
+    validate::Issue,
+};
+
+/// Words that never start or end a multi-word term.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "has", "have", "if", "in",
+    "into", "is", "it", "its", "not", "of", "on", "or", "our", "that", "the", "their", "this",
+    "to", "was", "we", "will", "with", "you", "your",
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Glossary {
+    /// Source term and its fixed translation, which is empty while
+    /// undecided.
+    pub entries: Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlossaryError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for GlossaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for GlossaryError {}
+
+impl Glossary {
+    pub fn parse_tsv(input: &str) -> Result<Self, GlossaryError> {
+        let mut entries = Vec::new();
+        for (i, line) in input.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (source, target) = line.split_once('\t').ok_or_else(|| GlossaryError {
+                line: i + 1,
+                message: "expected `source<TAB>target`".to_string(),
+            })?;
+            if source.trim().is_empty() {
+                return Err(GlossaryError {
+                    line: i + 1,
+                    message: "empty source term".to_string(),
+                });
+            }
+            entries.push((source.trim().to_string(), target.trim().to_string()));
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn to_tsv(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(source, target)| format!("{}\t{}\n", source, target))
+            .collect()
+    }
+
+    /// Entries that have a translation.
+    pub fn decided(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .filter(|(_, target)| !target.is_empty())
+            .map(|(s, t)| (s.as_str(), t.as_str()))
+    }
+
+    /// The fixed translation of `term`, ignoring ASCII case.
+    pub fn target_of(&self, term: &str) -> Option<&str> {
+        self.decided()
+            .find(|(source, _)| source.eq_ignore_ascii_case(term))
+            .map(|(_, target)| target)
+    }
+
+    /// Where decided source terms occur in `text` as whole words, ignoring
+    /// ASCII case.
+    pub fn spans(&self, text: &str) -> Vec<Range<usize>> {
+        let haystack = text.to_ascii_lowercase();
+        let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+        let mut spans = Vec::new();
+        for (source, _) in self.decided() {
+            let needle = source.to_ascii_lowercase();
+            for (start, _) in haystack.match_indices(&needle) {
+                let end = start + needle.len();
+                if !is_word(text[..start].chars().next_back())
+                    && !is_word(text[end..].chars().next())
+                {
+                    spans.push(start..end);
+                }
+            }
+        }
+        spans
+    }
+}
+
+/// Enforces a [`Glossary`] on a backend without glossary support: every
+/// source term is masked before `inner` translates, and replaced by its
+/// fixed translation afterwards. Fails with
+/// [`DeepLError::ValidationFailed`] when the translation lost a term.
+/// The glossary should be the one for the language pair in use.
+pub struct Enforced {
+    inner: Arc<dyn Translator>,
+    glossary: Arc<Glossary>,
+}
+
+impl Enforced {
+    pub fn new(inner: Arc<dyn Translator>, glossary: Arc<Glossary>) -> Self {
+        Self { inner, glossary }
+    }
+
+    async fn enforce(
+        &self,
+        text: &str,
+        src_lang: &str,
+        target_lang: &str,
+        options: Option<&TranslateOptions>,
+    ) -> Result<Translation, DeepLError> {
+        let masked = mask_with(text, self.glossary.spans(text));
+        // Placeholders and tags masked alongside come back as they were.
+        let tokens: Vec<String> = masked
+            .tokens
+            .iter()
+            .map(|t| self.glossary.target_of(t).unwrap_or(t).to_string())
+            .collect();
+        let mut translation = match options {
+            Some(options) => {
+                self.inner
+                    .translate_with_options(&masked.text, src_lang, target_lang, options)
+                    .await?
+            }
+            None => {
+                self.inner
+                    .translate(&masked.text, src_lang, target_lang)
+                    .await?
+            }
+        };
+        translation.text =
+            unmask(&translation.text, &tokens).map_err(|e| DeepLError::ValidationFailed {
+                segment: 0,
+                issues: vec![Issue::BrokenSentinel(e)],
+            })?;
+        translation.alternatives = translation
+            .alternatives
+            .iter()
+            .filter_map(|a| unmask(a, &tokens).ok())
+            .collect();
+        Ok(translation)
+    }
+}
+
+impl Translator for Enforced {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(self.enforce(text, src_lang, target_lang, None))
+    }
+
+    /// Passes `options` on to the inner translator.
+    fn translate_with_options<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+        options: &'a TranslateOptions,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(self.enforce(text, src_lang, target_lang, Some(options)))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TermKind {
+    /// A run of capitalised words such as "Acme Cloud Console".
+    Name,
+    /// ALL-CAPS, mixed case or digits: "API", "iPhone", "S3".
+    Identifier,
+    /// A recurring lower-case phrase such as "translation memory".
+    Phrase,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub term: String,
+    pub count: usize,
+    pub kind: TermKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Extract {
+    /// Terms seen fewer times are dropped.
+    pub min_count: usize,
+    pub max_words: usize,
+    pub max_terms: usize,
+}
+
+impl Default for Extract {
+    fn default() -> Self {
+        Self {
+            min_count: 2,
+            max_words: 4,
+            max_terms: 200,
+        }
+    }
+}
+
+struct Token<'a> {
+    word: &'a str,
+    /// The previous token ended a sentence or clause.
+    after_break: bool,
+    sentence_start: bool,
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let (mut after_break, mut sentence_start) = (true, true);
+    let mut start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        let inner = matches!(c, '-' | '_' | '\'' | '’') && start.is_some();
+        if c.is_alphanumeric() || inner {
+            start.get_or_insert(i);
+            continue;
+        }
+        if let Some(s) = start.take() {
+            let word = text[s..i].trim_end_matches(['-', '_', '\'', '’']);
+            if !word.is_empty() {
+                tokens.push(Token {
+                    word,
+                    after_break,
+                    sentence_start,
+                });
+            }
+            after_break = false;
+            sentence_start = false;
+        }
+        if matches!(c, '.' | '!' | '?' | '\n') {
+            after_break = true;
+            sentence_start = true;
+        } else if matches!(c, ',' | ';' | ':' | '(' | ')' | '"' | '“' | '”') {
+            after_break = true;
+        }
+    }
+    tokens
+}
+
+fn is_stopword(word: &str) -> bool {
+    STOPWORDS.contains(&word.to_lowercase().as_str())
+}
+
+fn is_capitalized(word: &str) -> bool {
+    word.chars().next().is_some_and(char::is_uppercase)
+}
+
+fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    let first = chars.next();
+    let letters = word.chars().filter(|c| c.is_alphabetic()).count();
+    let upper = word.chars().filter(|c| c.is_uppercase()).count();
+    (letters >= 2 && upper == letters)
+        || (word.chars().any(|c| c.is_ascii_digit()) && letters > 0)
+        || (first.is_some_and(char::is_lowercase) && chars.any(char::is_uppercase))
+}
+
+#[derive(Default)]
+struct Tally {
+    count: usize,
+    /// How often each spelling was seen, to report the usual one.
+    forms: HashMap<String, usize>,
+    kind: Option<TermKind>,
+}
+
+/// Lists the terms of `text` that a glossary should fix, most frequent
+/// first.
+pub fn extract(text: &str, options: Extract) -> Vec<Candidate> {
+    let tokens = tokenize(text);
+    let mut tallies: HashMap<String, Tally> = HashMap::new();
+    let mut add = |words: &[&str], kind: TermKind| {
+        let term = words.join(" ");
+        let tally = tallies.entry(term.to_lowercase()).or_default();
+        tally.count += 1;
+        *tally.forms.entry(term).or_default() += 1;
+        tally.kind = tally.kind.or(Some(kind));
+    };
+
+    // Words also written in lower case are vocabulary, not names, even
+    // when a sentence starts with them.
+    let vocabulary: HashSet<&str> = tokens
+        .iter()
+        .map(|t| t.word)
+        .filter(|w| w.chars().all(|c| !c.is_uppercase()))
+        .collect();
+    let titled = |w: &str| is_capitalized(w) && !is_identifier(w);
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let mut run = vec![tokens[i].word];
+        let mut j = i + 1;
+        if titled(tokens[i].word) {
+            while j < tokens.len() && !tokens[j].after_break && titled(tokens[j].word) {
+                run.push(tokens[j].word);
+                j += 1;
+            }
+        }
+        if run.len() > 1 {
+            let mut words = &run[..];
+            if tokens[i].sentence_start && vocabulary.contains(words[0].to_lowercase().as_str()) {
+                words = &words[1..];
+            }
+            while words.first().is_some_and(|w| is_stopword(w)) {
+                words = &words[1..];
+            }
+            if !words.is_empty() && words.len() <= options.max_words {
+                add(words, TermKind::Name);
+            }
+        } else {
+            let word = tokens[i].word;
+            if is_identifier(word) {
+                add(&[word], TermKind::Identifier);
+            } else if is_capitalized(word) && !tokens[i].sentence_start && !is_stopword(word) {
+                add(&[word], TermKind::Name);
+            }
+        }
+        i = j;
+    }
+
+    let lower = |w: &str| w.chars().all(|c| !c.is_uppercase());
+    for pair in tokens.windows(2) {
+        let (a, b) = (pair[0].word, pair[1].word);
+        // A sentence may capitalise the first word of a phrase.
+        let a = match pair[0].sentence_start {
+            true if lower(&a[a.chars().next().map_or(0, char::len_utf8)..]) => a.to_lowercase(),
+            _ => a.to_string(),
+        };
+        if !pair[1].after_break
+            && !is_stopword(&a)
+            && !is_stopword(b)
+            && lower(&a)
+            && lower(b)
+            && a.chars().count() > 2
+            && b.chars().count() > 2
+        {
+            add(&[&a, b], TermKind::Phrase);
+        }
+    }
+
+    let mut candidates: Vec<Candidate> = tallies
+        .into_values()
+        .filter(|t| t.count >= options.min_count.max(1))
+        .filter_map(|t| {
+            let term = t
+                .forms
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))?
+                .0;
+            Some(Candidate {
+                term,
+                count: t.count,
+                kind: t.kind?,
+            })
+        })
+        .collect();
+    let longer: Vec<(String, usize)> = candidates
+        .iter()
+        .filter(|c| c.term.contains(' '))
+        .map(|c| (format!(" {} ", c.term.to_lowercase()), c.count))
+        .collect();
+    candidates.retain(|c| {
+        let needle = format!(" {} ", c.term.to_lowercase());
+        !longer.iter().any(|(phrase, count)| {
+            *phrase != needle && phrase.contains(&needle) && *count >= c.count
+        })
+    });
+    candidates.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+    candidates.truncate(options.max_terms);
+    candidates
+}
+
+/// A glossary of the [`extract`]ed terms with empty targets.
+pub fn skeleton(text: &str, options: Extract) -> Glossary {
+    Glossary {
+        entries: extract(text, options)
+            .into_iter()
+            .map(|c| (c.term, String::new()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_candidates() {
+        let text = "Open the Acme Cloud Console. The Acme Cloud Console shows your API keys.\n\
+                    Translation memory is shared. Reuse the translation memory and the API.\n\
+                    Then sync your iPhone with Acme Cloud Console.";
+        let terms: Vec<_> = extract(text, Extract::default())
+            .into_iter()
+            .map(|c| (c.term, c.count, c.kind))
+            .collect();
+        assert_eq!(
+            terms,
+            [
+                ("Acme Cloud Console".to_string(), 3, TermKind::Name),
+                ("API".to_string(), 2, TermKind::Identifier),
+                ("translation memory".to_string(), 2, TermKind::Phrase),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skeleton_round_trips_as_tsv() {
+        let glossary = skeleton("Use S3 and S3 buckets.", Extract::default());
+        assert_eq!(glossary.to_tsv(), "S3\t\n");
+        let filled = Glossary::parse_tsv("S3\tS3\nbucket\tBucket\n\n").unwrap();
+        assert_eq!(filled.decided().count(), 2);
+        assert_eq!(Glossary::parse_tsv("only source").unwrap_err().line, 1);
+    }
+
+    struct Echo;
+
+    impl Translator for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                Ok(Translation {
+                    text: text.replace("Open", "Öffne"),
+                    ..Default::default()
+                })
+            })
+        }
+
+        /// Addresses the reader formally when asked to.
+        fn translate_with_options<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+            options: &'a TranslateOptions,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            let open = match options.formality {
+                Some(crate::options::Formality::Formal) => "Öffnen Sie",
+                _ => "Öffne",
+            };
+            Box::pin(async move {
+                Ok(Translation {
+                    text: text.replace("Open", open),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_enforced_terms() {
+        let glossary = Glossary::parse_tsv("bucket\tS3-Bucket\nregion\tRegion\ncloud\t\n").unwrap();
+        let text = "Open the Bucket in {region}, not the buckets of a Region.";
+        // `{region}` is a placeholder and stays one.
+        assert_eq!(glossary.spans(text), [9..15, 20..26, 50..56]);
+
+        let enforced = Enforced::new(Arc::new(Echo), Arc::new(glossary));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let translation = runtime
+            .block_on(enforced.translate(text, "EN", "DE"))
+            .unwrap();
+        assert_eq!(
+            translation.text,
+            "Öffne the S3-Bucket in {region}, not the buckets of a Region."
+        );
+        let formal = TranslateOptions {
+            formality: Some(crate::options::Formality::Formal),
+            ..Default::default()
+        };
+        let translation = runtime
+            .block_on(enforced.translate_with_options(text, "EN", "DE", &formal))
+            .unwrap();
+        assert_eq!(
+            translation.text,
+            "Öffnen Sie the S3-Bucket in {region}, not the buckets of a Region."
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Results remembered by a key the client picks, so that a retried
+//! submission gets the original result back instead of being carried out,
+//! and paid for upstream, a second time.
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use crate::clock::Instant;
+
+/// The request header clients send their key in.
+pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+
+/// Longer keys are refused, so clients cannot make the server hold
+/// arbitrary amounts of key data.
+pub const MAX_KEY_LEN: usize = 255;
+
+struct Entry<R, T> {
+    request: R,
+    at: Instant,
+    /// `None` while the first submission is still being carried out.
+    result: Option<T>,
+}
+
+/// Results of requests of type `R`, kept for `window` after they were
+/// produced.
+pub struct IdempotencyCache<R, T> {
+    window: Duration,
+    entries: Mutex<HashMap<String, Entry<R, T>>>,
+}
+
+pub enum Claim<'a, R, T> {
+    /// First use of the key: carry the request out and hand the result to
+    /// [`Pending::complete`].
+    New(Pending<'a, R, T>),
+    /// The result of an earlier submission with this key.
+    Replay(T),
+    /// An earlier submission with this key has not finished yet.
+    InProgress,
+    /// The key was used for a different request.
+    Mismatch,
+}
+
+impl<R: PartialEq, T: Clone> IdempotencyCache<R, T> {
+    pub fn new(window: Duration) -> Self {
+        IdempotencyCache {
+            window,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn claim(&self, key: &str, request: R) -> Claim<'_, R, T> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, e| e.result.is_none() || e.at.elapsed() < self.window);
+        match entries.get(key) {
+            Some(entry) if entry.request != request => Claim::Mismatch,
+            Some(Entry {
+                result: Some(result),
+                ..
+            }) => Claim::Replay(result.clone()),
+            Some(_) => Claim::InProgress,
+            None => {
+                entries.insert(
+                    key.to_string(),
+                    Entry {
+                        request,
+                        at: Instant::now(),
+                        result: None,
+                    },
+                );
+                Claim::New(Pending {
+                    cache: self,
+                    key: key.to_string(),
+                    done: false,
+                })
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A claimed key. Dropping it without completing, because the request
+/// failed or was abandoned, frees the key for another attempt.
+pub struct Pending<'a, R, T> {
+    cache: &'a IdempotencyCache<R, T>,
+    key: String,
+    done: bool,
+}
+
+impl<R, T> Pending<'_, R, T> {
+    pub fn complete(mut self, result: T) {
+        let mut entries = self.cache.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = entries.get_mut(&self.key) {
+            entry.at = Instant::now();
+            entry.result = Some(result);
+        }
+        self.done = true;
+    }
+}
+
+impl<R, T> Drop for Pending<'_, R, T> {
+    fn drop(&mut self) {
+        if !self.done {
+            let mut entries = self.cache.entries.lock().unwrap_or_else(|e| e.into_inner());
+            entries.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claims_replay_until_the_window_ends() {
+        let cache = IdempotencyCache::new(Duration::from_millis(50));
+        let Claim::New(pending) = cache.claim("k", "hello") else {
+            panic!("first claim should be new");
+        };
+        assert!(matches!(cache.claim("k", "hello"), Claim::InProgress));
+        pending.complete("HELLO");
+        assert!(matches!(cache.claim("k", "hello"), Claim::Replay("HELLO")));
+        assert!(matches!(cache.claim("k", "bye"), Claim::Mismatch));
+
+        // A failed attempt frees the key.
+        drop(cache.claim("other", "x"));
+        assert!(matches!(cache.claim("other", "x"), Claim::New(_)));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(matches!(cache.claim("k", "bye"), Claim::New(_)));
+        assert_eq!(cache.len(), 0);
+    }
+}
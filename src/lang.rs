@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+pub const SOURCE_LANGS: &[&str] = &[
+    "AR", "BG", "CS", "DA", "DE", "EL", "EN", "ES", "ET", "FI", "FR", "HU", "ID", "IT", "JA", "KO",
+    "LT", "LV", "NB", "NL", "PL", "PT", "RO", "RU", "SK", "SL", "SV", "TR", "UK", "ZH",
+];
+
+pub const TARGET_LANGS: &[&str] = &[
+    "AR", "BG", "CS", "DA", "DE", "EL", "EN", "EN-GB", "EN-US", "ES", "ET", "FI", "FR", "HU", "ID",
+    "IT", "JA", "KO", "LT", "LV", "NB", "NL", "PL", "PT", "PT-BR", "PT-PT", "RO", "RU", "SK", "SL",
+    "SV", "TR", "UK", "ZH", "ZH-HANS", "ZH-HANT",
+];
+
+/// What language upstream takes a text to be in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detection {
+    pub lang: String,
+    /// Whether upstream is sure of `lang`.
+    pub confident: bool,
+    /// Scores from 0.0 to 1.0 for the candidate languages.
+    pub scores: HashMap<String, f64>,
+}
+
+impl Detection {
+    pub fn confidence(&self) -> Option<f64> {
+        self.scores.get(&self.lang).copied()
+    }
+
+    /// Candidates from most to least likely.
+    pub fn ranked(&self) -> Vec<(&str, f64)> {
+        let mut ranked: Vec<(&str, f64)> =
+            self.scores.iter().map(|(l, s)| (l.as_str(), *s)).collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        ranked
+    }
+}
+
+pub fn is_auto(lang: &str) -> bool {
+    lang.is_empty() || lang.eq_ignore_ascii_case("auto")
+}
+
+/// Whether `lang` falls under `pattern`: the same code, a regional variant
+/// of it (`PT` covers `PT-BR`), or anything for `*`.
+pub fn matches(pattern: &str, lang: &str) -> bool {
+    if pattern == "*" || pattern.eq_ignore_ascii_case(lang) {
+        return true;
+    }
+    lang.len() > pattern.len()
+        && lang.as_bytes()[pattern.len()] == b'-'
+        && lang[..pattern.len()].eq_ignore_ascii_case(pattern)
+}
@@ -0,0 +1,163 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use crate::clock::{self, Instant};
+
+/// Counts itself in a gauge for as long as it lives, so a future dropped
+/// while it waits still takes itself off.
+pub(crate) struct Tally<'a>(&'a AtomicUsize);
+
+impl<'a> Tally<'a> {
+    pub(crate) fn new(gauge: &'a AtomicUsize) -> Self {
+        gauge.fetch_add(1, Ordering::SeqCst);
+        Self(gauge)
+    }
+}
+
+impl Drop for Tally<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// A token bucket holding up to `burst` permits, refilled continuously at
+/// `rate_per_sec`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+    rate_per_sec: f64,
+    burst: f64,
+    waiting: AtomicUsize,
+}
+
+impl RateLimiter {
+    pub fn new(rate_per_sec: f64, burst: u32) -> Self {
+        let burst = burst.max(1) as f64;
+        Self {
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                last: Instant::now(),
+            }),
+            rate_per_sec,
+            burst,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn per_minute(requests: f64, burst: u32) -> Self {
+        Self::new(requests / 60.0, burst)
+    }
+
+    pub fn rate_per_sec(&self) -> f64 {
+        self.rate_per_sec
+    }
+
+    /// Takes a permit if one is available, otherwise returns how long until
+    /// the next one is.
+    fn take(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last).as_secs_f64() * self.rate_per_sec;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst);
+        bucket.last = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        // A zero, negative or NaN rate never refills; poll once a second
+        // rather than computing a nonsensical wait.
+        Err(
+            Duration::try_from_secs_f64((1.0 - bucket.tokens) / self.rate_per_sec)
+                .unwrap_or(Duration::from_secs(1)),
+        )
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.take().is_ok()
+    }
+
+    pub async fn acquire(&self) {
+        let _waiting = Tally::new(&self.waiting);
+        while let Err(wait) = self.take() {
+            clock::sleep(wait).await;
+        }
+    }
+
+    /// Whole permits that could be taken right now.
+    pub fn available(&self) -> u32 {
+        let bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let refill = bucket.last.elapsed().as_secs_f64() * self.rate_per_sec;
+        (bucket.tokens + refill).min(self.burst) as u32
+    }
+
+    /// Tasks currently blocked in [`acquire`](Self::acquire).
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+}
+
+/// Requests per second in a rate such as `5`, `5/s`, `90/min` or
+/// `1000/h`.
+pub fn parse_rate(spec: &str) -> Option<f64> {
+    let (count, unit) = spec.trim().split_once('/').unwrap_or((spec.trim(), "s"));
+    let count: f64 = count.trim().parse().ok().filter(|n: &f64| *n > 0.0)?;
+    let secs = match unit.trim() {
+        "s" | "sec" | "second" => 1.0,
+        "m" | "min" | "minute" => 60.0,
+        "h" | "hour" => 3600.0,
+        _ => return None,
+    };
+    Some(count / secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_then_refill() {
+        let limiter = RateLimiter::new(1000.0, 2);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(limiter.available() >= 1);
+        assert!(limiter.try_acquire());
+    }
+
+    #[test]
+    fn test_abandoned_waits_are_not_counted() {
+        let limiter = RateLimiter::new(0.001, 1);
+        assert!(limiter.try_acquire());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let wait = tokio::time::timeout(Duration::from_millis(10), limiter.acquire());
+                assert!(wait.await.is_err());
+            });
+        assert_eq!(limiter.waiting(), 0);
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("5"), Some(5.0));
+        assert_eq!(parse_rate("90/min"), Some(1.5));
+        assert_eq!(parse_rate(" 7200 / h "), Some(2.0));
+        assert_eq!(parse_rate("0/s"), None);
+        assert_eq!(parse_rate("5/day"), None);
+        assert_eq!(RateLimiter::per_minute(30.0, 1).rate_per_sec(), 0.5);
+    }
+}
@@ -0,0 +1,78 @@
+//! A switch that stops a gateway's upstream traffic without a restart,
+//! for maintenance or while a ban is suspected.
+//!
+//! Clients given the [`Switch`] with
+//! [`DeepLClient::with_switch`](crate::DeepLClient::with_switch) keep
+//! answering from their cache in either mode and fail everything else with
+//! [`DeepLError::Paused`](crate::error::DeepLError::Paused), which the
+//! server answers with 503. In maintenance the cache also serves entries
+//! past their TTL, marked `stale`. A server given the same switch with
+//! [`Server::with_admin`](crate::server::Server::with_admin) flips it on
+//! `PUT /admin/mode`.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Normal,
+    /// Cached and stale results only.
+    Maintenance,
+    /// Cached results only.
+    Paused,
+}
+
+impl Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Maintenance => "maintenance",
+            Mode::Paused => "paused",
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A [`Mode`] shared by clones, so that a server and the clients behind it
+/// see every change at once.
+#[derive(Clone, Debug, Default)]
+pub struct Switch(Arc<AtomicU8>);
+
+impl Switch {
+    pub fn new(mode: Mode) -> Self {
+        let switch = Switch::default();
+        switch.set(mode);
+        switch
+    }
+
+    pub fn mode(&self) -> Mode {
+        match self.0.load(Ordering::SeqCst) {
+            1 => Mode::Maintenance,
+            2 => Mode::Paused,
+            _ => Mode::Normal,
+        }
+    }
+
+    pub fn set(&self, mode: Mode) {
+        let value = match mode {
+            Mode::Normal => 0,
+            Mode::Maintenance => 1,
+            Mode::Paused => 2,
+        };
+        self.0.store(value, Ordering::SeqCst);
+    }
+}
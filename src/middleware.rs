@@ -0,0 +1,116 @@
+//! Hooks around every translation request a [`DeepLClient`] sends, for
+//! logging, rewriting headers, adding credentials or collecting metrics
+//! without forking the crate.
+//!
+//! [`DeepLClient::with_middleware`] registers a [`Middleware`]. Each sees
+//! the request once the client has built it, in the order they were
+//! registered, and the response before the client reads it, in the
+//! reverse order, so that the first registered is the outermost. Requests
+//! that fail before a response arrives end without `on_response`.
+//!
+//! [`MethodSpacing`] is the spacing of the `"method"` key that
+//! [`RequestStrategy`] selects, as a middleware.
+//!
+//! [`DeepLClient`]: crate::DeepLClient
+//! [`DeepLClient::with_middleware`]: crate::DeepLClient::with_middleware
+
+use std::fmt;
+
+/// The header types of [`Request`] and [`Response`].
+pub use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::{error::DeepLError, translator::BoxFuture, RequestStrategy};
+
+/// A request as it is about to go out.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+/// A response as it came back, before the client checks its status.
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+pub trait Middleware: fmt::Debug + Send + Sync {
+    /// Called before `request` is sent; an error stops it and is returned
+    /// in place of the response.
+    fn on_request<'a>(&'a self, request: &'a mut Request) -> BoxFuture<'a, Result<(), DeepLError>> {
+        let _ = request;
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Called with the `response` to `request` as sent; an error is
+    /// returned in its place.
+    fn on_response<'a>(
+        &'a self,
+        request: &'a Request,
+        response: &'a mut Response,
+    ) -> BoxFuture<'a, Result<(), DeepLError>> {
+        let _ = (request, response);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Respaces the `"method"` key of web requests as the strategy says,
+/// whatever spacing the body came with. The client spaces its requests
+/// already, as [`with_strategy`](crate::DeepLClient::with_strategy) says;
+/// register this after middleware that reserializes the body, which
+/// loses the spacing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MethodSpacing(pub RequestStrategy);
+
+impl Middleware for MethodSpacing {
+    fn on_request<'a>(&'a self, request: &'a mut Request) -> BoxFuture<'a, Result<(), DeepLError>> {
+        Box::pin(async move {
+            let id = serde_json::from_str::<serde_json::Value>(&request.body)
+                .ok()
+                .and_then(|body| body.get("id")?.as_i64());
+            // Bodies for the official API have no id and no spacing.
+            if let Some(id) = id {
+                request.body = self.0.respace(&request.body, id);
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_method_spacing_respaces_whatever_came_before() {
+        let body = crate::build_batch_post_data_with(
+            RequestStrategy::WideSpaced,
+            crate::JobOptions::default(),
+            &["hi"],
+            "EN",
+            "DE",
+        );
+        let mut request = Request {
+            url: crate::DEEPL_API.to_string(),
+            headers: HeaderMap::new(),
+            body,
+        };
+        let spacing = MethodSpacing(RequestStrategy::Spaced);
+        futures_util::FutureExt::now_or_never(spacing.on_request(&mut request))
+            .unwrap()
+            .unwrap();
+        assert!(request.body.contains("\"method\": \"LMT_handle_texts\""));
+        let mut official = Request {
+            body: "{\"text\":[\"hi\"],\"target_lang\":\"DE\"}".to_string(),
+            ..request
+        };
+        let before = official.body.clone();
+        futures_util::FutureExt::now_or_never(spacing.on_request(&mut official))
+            .unwrap()
+            .unwrap();
+        assert_eq!(official.body, before);
+    }
+}
@@ -0,0 +1,205 @@
+//! Feeding screenshots and scans straight into translation.
+//!
+//! An [`ImageTextSource`] finds the text regions of an image;
+//! [`translate_image`] translates each of them and keeps its position, so
+//! the result can be drawn back over the original. With the `ocr` feature
+//! [`Tesseract`] provides a source backed by the `tesseract` command.
+
+use std::{fmt, sync::Arc};
+
+use crate::{
+    error::DeepLError,
+    translator::{BoxFuture, Translation, Translator},
+};
+
+#[cfg(feature = "ocr")]
+mod tesseract;
+
+#[cfg(feature = "ocr")]
+pub use tesseract::Tesseract;
+
+/// Pixel bounds of a region, from the image's top-left corner.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// The smallest rectangle containing both.
+    pub fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x.saturating_add(self.width)).max(other.x.saturating_add(other.width));
+        let bottom = (self.y.saturating_add(self.height)).max(other.y.saturating_add(other.height));
+        Rect {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub text: String,
+    pub bounds: Rect,
+    /// Recognition confidence from 0.0 to 1.0, when the engine reports it.
+    pub confidence: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum OcrError {
+    Io(std::io::Error),
+    /// The engine ran but reported failure.
+    Engine(String),
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OcrError::Io(e) => write!(f, "ocr: {}", e),
+            OcrError::Engine(message) => write!(f, "ocr failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for OcrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OcrError::Io(e) => Some(e),
+            OcrError::Engine(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for OcrError {
+    fn from(e: std::io::Error) -> Self {
+        OcrError::Io(e)
+    }
+}
+
+pub trait ImageTextSource: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Text regions of an encoded image (PNG, JPEG, ...), in reading
+    /// order. `lang` is the expected source language, or `auto`.
+    fn regions<'a>(
+        &'a self,
+        image: &'a [u8],
+        lang: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Region>, OcrError>>;
+}
+
+#[derive(Debug)]
+pub struct TranslatedRegion {
+    pub region: Region,
+    pub translation: Result<Translation, Arc<DeepLError>>,
+}
+
+/// Recognises the regions of `image` and translates each one on its own.
+/// A region that fails to translate does not fail the others.
+pub async fn translate_image(
+    source: &dyn ImageTextSource,
+    provider: &dyn Translator,
+    image: &[u8],
+    src_lang: &str,
+    target_lang: &str,
+) -> Result<Vec<TranslatedRegion>, OcrError> {
+    let regions = source.regions(image, src_lang).await?;
+    let mut out = Vec::with_capacity(regions.len());
+    for region in regions {
+        let translation = provider
+            .translate(&region.text, src_lang, target_lang)
+            .await
+            .map_err(Arc::new);
+        out.push(TranslatedRegion {
+            region,
+            translation,
+        });
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed;
+
+    impl ImageTextSource for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn regions<'a>(
+            &'a self,
+            _image: &'a [u8],
+            _lang: &'a str,
+        ) -> BoxFuture<'a, Result<Vec<Region>, OcrError>> {
+            Box::pin(async {
+                Ok(vec![Region {
+                    text: "EXIT".to_string(),
+                    bounds: Rect {
+                        x: 4,
+                        y: 8,
+                        width: 40,
+                        height: 12,
+                    },
+                    confidence: Some(0.9),
+                }])
+            })
+        }
+    }
+
+    struct Echo;
+
+    impl Translator for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                Ok(Translation {
+                    text: format!("<{}>", text),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_regions_keep_their_bounds() {
+        let regions = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(translate_image(&Fixed, &Echo, b"", "EN", "DE"))
+            .unwrap();
+        assert_eq!(regions[0].region.bounds.width, 40);
+        assert_eq!(regions[0].translation.as_ref().unwrap().text, "<EXIT>");
+        assert_eq!(
+            Rect::default().union(Rect {
+                x: 2,
+                y: 3,
+                width: 1,
+                height: 1
+            }),
+            Rect {
+                x: 0,
+                y: 0,
+                width: 3,
+                height: 4
+            }
+        );
+    }
+}
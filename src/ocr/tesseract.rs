@@ -0,0 +1,228 @@
+use std::process::Stdio;
+
+use futures_util::future;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use super::{ImageTextSource, OcrError, Rect, Region};
+use crate::{lang, translator::BoxFuture};
+
+/// Tesseract's names for the DeepL source languages it has models for.
+const LANGS: &[(&str, &str)] = &[
+    ("AR", "ara"),
+    ("BG", "bul"),
+    ("CS", "ces"),
+    ("DA", "dan"),
+    ("DE", "deu"),
+    ("EL", "ell"),
+    ("EN", "eng"),
+    ("ES", "spa"),
+    ("ET", "est"),
+    ("FI", "fin"),
+    ("FR", "fra"),
+    ("HU", "hun"),
+    ("ID", "ind"),
+    ("IT", "ita"),
+    ("JA", "jpn"),
+    ("KO", "kor"),
+    ("LT", "lit"),
+    ("LV", "lav"),
+    ("NB", "nor"),
+    ("NL", "nld"),
+    ("PL", "pol"),
+    ("PT", "por"),
+    ("RO", "ron"),
+    ("RU", "rus"),
+    ("SK", "slk"),
+    ("SL", "slv"),
+    ("SV", "swe"),
+    ("TR", "tur"),
+    ("UK", "ukr"),
+    ("ZH", "chi_sim"),
+];
+
+/// Runs the `tesseract` command on each image and groups the recognised
+/// words into one region per paragraph.
+#[derive(Clone, Debug)]
+pub struct Tesseract {
+    program: String,
+    /// Used when the source language is `auto` or has no model.
+    languages: String,
+    min_confidence: f64,
+}
+
+impl Default for Tesseract {
+    fn default() -> Self {
+        Self {
+            program: "tesseract".to_string(),
+            languages: "eng".to_string(),
+            min_confidence: 0.0,
+        }
+    }
+}
+
+impl Tesseract {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_program(mut self, program: impl Into<String>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Tesseract language models to fall back on, such as `eng+jpn`.
+    pub fn with_languages(mut self, languages: impl Into<String>) -> Self {
+        self.languages = languages.into();
+        self
+    }
+
+    /// Words recognised with less confidence, from 0.0 to 1.0, are dropped.
+    pub fn with_min_confidence(mut self, min_confidence: f64) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    fn languages_for(&self, lang: &str) -> &str {
+        if lang::is_auto(lang) {
+            return &self.languages;
+        }
+        LANGS
+            .iter()
+            .find(|(code, _)| lang::matches(code, lang))
+            .map_or(self.languages.as_str(), |(_, model)| model)
+    }
+
+    async fn run(&self, image: &[u8], lang: &str) -> Result<Vec<Region>, OcrError> {
+        let mut child = Command::new(&self.program)
+            .args(["stdin", "stdout", "-l", self.languages_for(lang), "tsv"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| OcrError::Engine("stdin unavailable".to_string()))?;
+        let write = async move {
+            let result = stdin.write_all(image).await;
+            drop(stdin);
+            result
+        };
+        let (written, output) = future::join(write, child.wait_with_output()).await;
+        let output = output?;
+        if !output.status.success() {
+            return Err(OcrError::Engine(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        written?;
+        Ok(parse_tsv(
+            &String::from_utf8_lossy(&output.stdout),
+            self.min_confidence,
+        ))
+    }
+}
+
+impl ImageTextSource for Tesseract {
+    fn name(&self) -> &str {
+        "tesseract"
+    }
+
+    fn regions<'a>(
+        &'a self,
+        image: &'a [u8],
+        lang: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Region>, OcrError>> {
+        Box::pin(self.run(image, lang))
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30ff}' | '\u{3400}'..='\u{9fff}' | '\u{ff00}'..='\u{ffef}')
+}
+
+/// Groups the word rows of Tesseract's TSV output into paragraphs.
+fn parse_tsv(tsv: &str, min_confidence: f64) -> Vec<Region> {
+    let mut regions: Vec<(String, Region, f64, usize)> = Vec::new();
+    for line in tsv.lines().skip(1) {
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() < 12 || cols[0] != "5" {
+            continue;
+        }
+        let (word, conf) = (cols[11].trim(), cols[10].parse::<f64>().unwrap_or(-1.0));
+        if word.is_empty() || conf < 0.0 || conf / 100.0 < min_confidence {
+            continue;
+        }
+        let num = |i: usize| cols[i].parse::<u32>().unwrap_or(0);
+        let key = format!("{}:{}:{}", cols[1], cols[2], cols[3]);
+        let bounds = Rect {
+            x: num(6),
+            y: num(7),
+            width: num(8),
+            height: num(9),
+        };
+        match regions.last_mut() {
+            Some((k, region, total, words)) if *k == key => {
+                let glued = region.text.chars().last().is_some_and(is_cjk)
+                    && word.chars().next().is_some_and(is_cjk);
+                if !glued {
+                    region.text.push(' ');
+                }
+                region.text.push_str(word);
+                region.bounds = region.bounds.union(bounds);
+                *total += conf;
+                *words += 1;
+            }
+            _ => regions.push((
+                key,
+                Region {
+                    text: word.to_string(),
+                    bounds,
+                    confidence: None,
+                },
+                conf,
+                1,
+            )),
+        }
+    }
+    regions
+        .into_iter()
+        .map(|(_, mut region, total, words)| {
+            region.confidence = Some(total / words as f64 / 100.0);
+            region
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tsv_groups_paragraphs() {
+        let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext\n\
+                   1\t1\t0\t0\t0\t0\t0\t0\t640\t480\t-1\t\n\
+                   5\t1\t1\t1\t1\t1\t10\t20\t50\t12\t96.5\tPress\n\
+                   5\t1\t1\t1\t2\t1\t10\t36\t40\t12\t91.5\tStart\n\
+                   5\t1\t2\t1\t1\t1\t300\t400\t30\t14\t88\t出口\n\
+                   5\t1\t2\t1\t1\t2\t330\t400\t30\t14\t90\tです\n\
+                   5\t1\t3\t1\t1\t1\t0\t0\t5\t5\t12\t~\n";
+        let regions = parse_tsv(tsv, 0.5);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].text, "Press Start");
+        assert_eq!(
+            regions[0].bounds,
+            Rect {
+                x: 10,
+                y: 20,
+                width: 50,
+                height: 28
+            }
+        );
+        assert_eq!(regions[0].confidence, Some(0.94));
+        assert_eq!(regions[1].text, "出口です");
+        assert_eq!(Tesseract::new().languages_for("ZH"), "chi_sim");
+        assert_eq!(Tesseract::new().languages_for("auto"), "eng");
+    }
+}
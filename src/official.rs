@@ -0,0 +1,260 @@
+//! The bodies of the official DeepL API (v2), for users with a Free or
+//! Pro `auth_key`. Responses are turned into a [`DeepLResponse`] so that
+//! everything built on the JSON-RPC client works unchanged.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    glossary::Glossary,
+    lang,
+    options::{Formality, Splitting, TagHandling},
+    DeepLResponse, DeeplResult, TranslatedText,
+};
+
+pub const DEEPL_API_FREE: &str = "https://api-free.deepl.com/v2/translate";
+pub const DEEPL_API_PRO: &str = "https://api.deepl.com/v2/translate";
+
+/// The endpoint serving `auth_key`: Free plan keys end in `:fx`.
+pub fn endpoint_for(auth_key: &str) -> &'static str {
+    if auth_key.trim().ends_with(":fx") {
+        DEEPL_API_FREE
+    } else {
+        DEEPL_API_PRO
+    }
+}
+
+/// The glossaries endpoint next to the `translate` one.
+pub fn glossaries_endpoint(translate_endpoint: &str) -> String {
+    let base = translate_endpoint.trim_end_matches('/');
+    let base = base.strip_suffix("/translate").unwrap_or(base);
+    format!("{}/glossaries", base)
+}
+
+/// The document translation endpoint next to the `translate` one.
+pub fn documents_endpoint(translate_endpoint: &str) -> String {
+    glossaries_endpoint(translate_endpoint).replace("/glossaries", "/document")
+}
+
+/// An API key, kept out of `Debug` output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AuthKey(pub String);
+
+impl fmt::Debug for AuthKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AuthKey(..)")
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct V2Request<'a> {
+    pub text: &'a [&'a str],
+    /// Left out to have DeepL detect the language.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_lang: Option<String>,
+    pub target_lang: String,
+    /// `prefer_more` or `prefer_less`, which unlike `more` and `less` fall
+    /// back to the default for target languages without formality.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formality: Option<&'static str>,
+    /// Only applied when `source_lang` is given and matches the
+    /// glossary's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glossary_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag_handling: Option<&'static str>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub ignore_tags: &'a [String],
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub non_splitting_tags: &'a [String],
+    /// `0` for no splitting or `nonewlines` for sentence ends only; left
+    /// out for the default, sentence ends and line breaks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_sentences: Option<&'static str>,
+}
+
+impl<'a> V2Request<'a> {
+    pub fn new(texts: &'a [&'a str], src_lang: &str, target_lang: &str) -> Self {
+        V2Request {
+            text: texts,
+            source_lang: (!lang::is_auto(src_lang)).then(|| src_lang.to_uppercase()),
+            target_lang: target_lang.to_uppercase(),
+            formality: None,
+            glossary_id: None,
+            tag_handling: None,
+            ignore_tags: &[],
+            non_splitting_tags: &[],
+            split_sentences: None,
+        }
+    }
+
+    pub fn with_formality(mut self, formality: Option<Formality>) -> Self {
+        self.formality = formality.map(|f| match f {
+            Formality::Formal => "prefer_more",
+            Formality::Informal => "prefer_less",
+        });
+        self
+    }
+
+    pub fn with_glossary_id(mut self, glossary_id: Option<&'a str>) -> Self {
+        self.glossary_id = glossary_id;
+        self
+    }
+
+    pub fn with_tag_handling(mut self, tag_handling: Option<&'a TagHandling>) -> Self {
+        if let Some(handling) = tag_handling {
+            self.tag_handling = Some(handling.mode.as_str());
+            self.ignore_tags = &handling.ignore_tags;
+            self.non_splitting_tags = &handling.non_splitting_tags;
+        }
+        self
+    }
+
+    pub fn with_splitting(mut self, splitting: Option<Splitting>) -> Self {
+        self.split_sentences = match splitting {
+            Some(Splitting::Sentences) => Some("nonewlines"),
+            Some(Splitting::Off) => Some("0"),
+            Some(Splitting::Newlines | Splitting::Paragraphs) | None => None,
+        };
+        self
+    }
+}
+
+/// The body creating a glossary from the entries of a [`Glossary`] that
+/// have a translation.
+#[derive(Serialize, Debug)]
+pub struct CreateGlossary<'a> {
+    pub name: &'a str,
+    pub source_lang: String,
+    pub target_lang: String,
+    pub entries: String,
+    pub entries_format: &'static str,
+}
+
+impl<'a> CreateGlossary<'a> {
+    pub fn new(name: &'a str, src_lang: &str, target_lang: &str, glossary: &Glossary) -> Self {
+        CreateGlossary {
+            name,
+            source_lang: src_lang.to_uppercase(),
+            target_lang: target_lang.to_uppercase(),
+            entries: glossary
+                .decided()
+                .map(|(source, target)| format!("{}\t{}\n", source, target))
+                .collect(),
+            entries_format: "tsv",
+        }
+    }
+}
+
+/// A glossary stored with DeepL.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlossaryInfo {
+    pub glossary_id: String,
+    pub name: String,
+    pub source_lang: String,
+    pub target_lang: String,
+    #[serde(default)]
+    pub entry_count: u64,
+    /// Whether DeepL finished processing the entries; translations using
+    /// the glossary fail until it has.
+    #[serde(default)]
+    pub ready: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GlossaryList {
+    pub glossaries: Vec<GlossaryInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct V2Response {
+    pub translations: Vec<V2Translation>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct V2Translation {
+    #[serde(default)]
+    pub detected_source_language: String,
+    pub text: String,
+}
+
+impl From<V2Response> for DeepLResponse {
+    fn from(resp: V2Response) -> Self {
+        let lang = resp
+            .translations
+            .first()
+            .map(|t| t.detected_source_language.clone())
+            .unwrap_or_default();
+        DeepLResponse {
+            jsonrpc: "2.0".to_string(),
+            id: 0,
+            result: DeeplResult {
+                texts: resp
+                    .translations
+                    .into_iter()
+                    .map(|t| TranslatedText {
+                        alternatives: Vec::new(),
+                        text: t.text,
+                    })
+                    .collect(),
+                lang,
+                // The v2 API reports neither confidence nor scores.
+                lang_is_confident: false,
+                detected_languages: Default::default(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::TagMode;
+
+    #[test]
+    fn test_v2_bodies() {
+        assert_eq!(endpoint_for("0a1b-2c3d:fx"), DEEPL_API_FREE);
+        assert_eq!(endpoint_for("0a1b-2c3d"), DEEPL_API_PRO);
+        assert_eq!(format!("{:?}", AuthKey("secret".into())), "AuthKey(..)");
+
+        let body = serde_json::to_value(V2Request::new(&["hi", "bye"], "auto", "de")).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"text": ["hi", "bye"], "target_lang": "DE"})
+        );
+        let body = V2Request::new(&["hi"], "EN", "DE").with_formality(Some(Formality::Formal));
+        assert_eq!(
+            serde_json::to_value(body).unwrap()["formality"],
+            "prefer_more"
+        );
+        let handling = TagHandling::new(TagMode::Xml).with_ignore_tags(["code"]);
+        let body = V2Request::new(&["<p>hi</p>"], "EN", "DE").with_tag_handling(Some(&handling));
+        let body = serde_json::to_value(body).unwrap();
+        assert_eq!(
+            (&body["tag_handling"], &body["ignore_tags"]),
+            (&serde_json::json!("xml"), &serde_json::json!(["code"]))
+        );
+        assert!(body.get("non_splitting_tags").is_none());
+        let body = V2Request::new(&["hi"], "EN", "DE").with_splitting(Some(Splitting::Off));
+        assert_eq!(serde_json::to_value(body).unwrap()["split_sentences"], "0");
+        assert_eq!(
+            glossaries_endpoint(DEEPL_API_FREE),
+            "https://api-free.deepl.com/v2/glossaries"
+        );
+        let glossary = Glossary::parse_tsv("bucket\tBucket\nregion\t\n").unwrap();
+        let body = CreateGlossary::new("docs", "en", "de", &glossary);
+        assert_eq!(
+            (body.entries.as_str(), body.source_lang.as_str()),
+            ("bucket\tBucket\n", "EN")
+        );
+
+        let resp: V2Response = serde_json::from_str(
+            r#"{"translations":[{"detected_source_language":"EN","text":"Hallo"},{"detected_source_language":"EN","text":"Tschüss"}]}"#,
+        )
+        .unwrap();
+        let resp = DeepLResponse::from(resp);
+        assert_eq!(resp.result.lang, "EN");
+        assert_eq!(resp.result.texts[1].text, "Tschüss");
+    }
+}
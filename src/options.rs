@@ -0,0 +1,439 @@
+//! Translation options that differ by language pair.
+//!
+//! A table like
+//!
+//! ```json
+//! {
+//!   "defaults": {"provider": "deepl"},
+//!   "pairs": [
+//!     {"source": "EN", "target": "JA", "formality": "formal", "provider": "deepl-pro"},
+//!     {"source": "EN", "target": "DE", "model": "next-gen"}
+//!   ]
+//! }
+//! ```
+//!
+//! is resolved for every request: the defaults first, then each matching
+//! pair in file order, then whatever the caller set for the request itself.
+//! `source` and `target` default to `*`; `PT` also covers `PT-BR`.
+//!
+//! Options reach providers through [`Translator::translate_with_options`].
+//! Wrappers such as caches only forward plain `translate` calls, so put
+//! [`Routed`] directly in front of the backends.
+
+use std::{fmt, path::Path, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capabilities::Capabilities,
+    error::DeepLError,
+    lang,
+    translator::{BoxFuture, Translation, Translator},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Formality {
+    #[serde(alias = "more")]
+    Formal,
+    #[serde(alias = "less")]
+    Informal,
+}
+
+impl Formality {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Formality::Formal => "formal",
+            Formality::Informal => "informal",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagMode {
+    Html,
+    Xml,
+}
+
+impl TagMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TagMode::Html => "html",
+            TagMode::Xml => "xml",
+        }
+    }
+}
+
+/// How to case a translation after it comes back; see
+/// [`recase`](crate::casing::recase).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Casing {
+    /// All capitals, lower case, title case or a capital first letter, as
+    /// the source has it.
+    PreserveSource,
+    Sentence,
+    /// Title case as the target language writes it.
+    Title,
+}
+
+impl Casing {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Casing::PreserveSource => "preserve_source",
+            Casing::Sentence => "sentence",
+            Casing::Title => "title",
+        }
+    }
+}
+
+/// Where DeepL splits a text into the segments it translates one by one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Splitting {
+    /// At line breaks and at the end of sentences, which suits
+    /// line-oriented data such as lists and subtitles.
+    #[default]
+    Newlines,
+    /// At the end of sentences only, joining hard-wrapped lines of prose.
+    Sentences,
+    /// At blank lines only, so each paragraph is translated in one piece.
+    Paragraphs,
+    /// Not at all: the text is one segment.
+    Off,
+}
+
+impl Splitting {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Splitting::Newlines => "newlines",
+            Splitting::Sentences => "sentences",
+            Splitting::Paragraphs => "paragraphs",
+            Splitting::Off => "off",
+        }
+    }
+}
+
+/// Markup in the text, which comes back valid: tags stay around what
+/// they enclosed and ignored elements are not translated. The official
+/// API handles it itself; the web endpoint cannot, so tags and ignored
+/// elements are masked before sending and every tag is non-splitting
+/// there.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagHandling {
+    pub mode: TagMode,
+    /// Elements whose content is left as it is, such as `code`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore_tags: Vec<String>,
+    /// Elements that never break a sentence, such as `b` in XML.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub non_splitting_tags: Vec<String>,
+}
+
+impl TagHandling {
+    pub fn new(mode: TagMode) -> Self {
+        TagHandling {
+            mode,
+            ignore_tags: Vec::new(),
+            non_splitting_tags: Vec::new(),
+        }
+    }
+
+    pub fn with_ignore_tags(mut self, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.ignore_tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_non_splitting_tags(
+        mut self,
+        tags: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.non_splitting_tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+/// Settings for one request. `None` leaves the choice to the next layer
+/// down, and finally to the provider.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TranslateOptions {
+    /// [`Translator::name`] of the provider to use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formality: Option<Formality>,
+    /// A provider-specific model, such as DeepL's `next-gen`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag_handling: Option<TagHandling>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub casing: Option<Casing>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub splitting: Option<Splitting>,
+}
+
+impl TranslateOptions {
+    /// Overrides every setting that `other` sets.
+    pub fn merge(&mut self, other: &TranslateOptions) {
+        if other.provider.is_some() {
+            self.provider = other.provider.clone();
+        }
+        if other.formality.is_some() {
+            self.formality = other.formality;
+        }
+        if other.model.is_some() {
+            self.model = other.model.clone();
+        }
+        if other.tag_handling.is_some() {
+            self.tag_handling = other.tag_handling.clone();
+        }
+        if other.casing.is_some() {
+            self.casing = other.casing;
+        }
+        if other.splitting.is_some() {
+            self.splitting = other.splitting;
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct PairOverride {
+    #[serde(default = "any")]
+    pub source: String,
+    #[serde(default = "any")]
+    pub target: String,
+    #[serde(flatten)]
+    pub options: TranslateOptions,
+}
+
+fn any() -> String {
+    "*".to_string()
+}
+
+#[derive(Debug)]
+pub enum OptionsError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionsError::Io(e) => write!(f, "cannot read options: {}", e),
+            OptionsError::Parse(e) => write!(f, "invalid options file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OptionsError {}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PairOptions {
+    pub defaults: TranslateOptions,
+    pub pairs: Vec<PairOverride>,
+}
+
+impl PairOptions {
+    pub fn from_json(json: &str) -> Result<Self, OptionsError> {
+        serde_json::from_str(json).map_err(OptionsError::Parse)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, OptionsError> {
+        Self::from_json(&std::fs::read_to_string(path).map_err(OptionsError::Io)?)
+    }
+
+    /// The defaults with every matching pair merged over them.
+    pub fn resolve(&self, src_lang: &str, target_lang: &str) -> TranslateOptions {
+        let mut options = self.defaults.clone();
+        for pair in &self.pairs {
+            if lang::matches(&pair.source, src_lang) && lang::matches(&pair.target, target_lang) {
+                options.merge(&pair.options);
+            }
+        }
+        options
+    }
+}
+
+/// Sends each request to the provider its resolved options name, with
+/// those options. Requests that name no provider go to the first one.
+pub struct Routed {
+    providers: Vec<Arc<dyn Translator>>,
+    options: Arc<PairOptions>,
+}
+
+impl Routed {
+    pub fn new(options: Arc<PairOptions>) -> Self {
+        Self {
+            providers: Vec::new(),
+            options,
+        }
+    }
+
+    pub fn with_provider(mut self, provider: Arc<dyn Translator>) -> Self {
+        self.providers.push(provider);
+        self
+    }
+
+    fn provider(&self, options: &TranslateOptions) -> Result<&dyn Translator, DeepLError> {
+        let found = match &options.provider {
+            Some(name) => self.providers.iter().find(|p| p.name() == name),
+            None => self.providers.first(),
+        };
+        found.map(|p| p.as_ref()).ok_or(DeepLError::NoProvider)
+    }
+}
+
+impl Translator for Routed {
+    fn name(&self) -> &str {
+        "routed"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.providers
+            .first()
+            .map(|p| p.capabilities())
+            .unwrap_or_default()
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move {
+            let options = self.options.resolve(src_lang, target_lang);
+            self.provider(&options)?
+                .translate_with_options(text, src_lang, target_lang, &options)
+                .await
+        })
+    }
+
+    fn translate_with_options<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+        request: &'a TranslateOptions,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move {
+            let mut options = self.options.resolve(src_lang, target_lang);
+            options.merge(request);
+            self.provider(&options)?
+                .translate_with_options(text, src_lang, target_lang, &options)
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = r#"{
+        "defaults": {"provider": "deepl", "formality": "informal"},
+        "pairs": [
+            {"source": "EN", "target": "JA", "formality": "formal", "provider": "deepl-pro"},
+            {"target": "DE", "model": "next-gen"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_pairs_merge_over_defaults() {
+        let table = PairOptions::from_json(TABLE).unwrap();
+        let ja = table.resolve("en", "JA");
+        assert_eq!(ja.provider.as_deref(), Some("deepl-pro"));
+        assert_eq!(ja.formality, Some(Formality::Formal));
+        assert_eq!(ja.model, None);
+        let de = table.resolve("FR", "DE");
+        assert_eq!(
+            (de.provider.as_deref(), de.formality, de.model.as_deref()),
+            (Some("deepl"), Some(Formality::Informal), Some("next-gen"))
+        );
+        assert_eq!(table.resolve("FR", "JA"), table.defaults);
+    }
+
+    /// Answers with its name and the formality it was asked for.
+    struct Echo(&'static str);
+
+    impl Translator for Echo {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            src_lang: &'a str,
+            target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            static NONE: TranslateOptions = TranslateOptions {
+                provider: None,
+                formality: None,
+                model: None,
+                tag_handling: None,
+                casing: None,
+                splitting: None,
+            };
+            self.translate_with_options(text, src_lang, target_lang, &NONE)
+        }
+
+        fn translate_with_options<'a>(
+            &'a self,
+            _text: &'a str,
+            _src_lang: &'a str,
+            _target_lang: &'a str,
+            options: &'a TranslateOptions,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                Ok(Translation {
+                    text: format!("{} {:?}", self.0, options.formality),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_routed_picks_provider_per_pair() {
+        let routed = Routed::new(Arc::new(PairOptions::from_json(TABLE).unwrap()))
+            .with_provider(Arc::new(Echo("deepl")))
+            .with_provider(Arc::new(Echo("deepl-pro")));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let text = |src, target, request: TranslateOptions| {
+            runtime
+                .block_on(routed.translate_with_options("hi", src, target, &request))
+                .map(|t| t.text)
+        };
+        assert_eq!(
+            text("EN", "JA", TranslateOptions::default()).unwrap(),
+            "deepl-pro Some(Formal)"
+        );
+        let informal = TranslateOptions {
+            formality: Some(Formality::Informal),
+            ..Default::default()
+        };
+        assert_eq!(
+            text("EN", "JA", informal).unwrap(),
+            "deepl-pro Some(Informal)"
+        );
+        assert_eq!(
+            text("EN", "DE", TranslateOptions::default()).unwrap(),
+            "deepl Some(Informal)"
+        );
+        let missing = TranslateOptions {
+            provider: Some("google".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            text("EN", "DE", missing),
+            Err(DeepLError::NoProvider)
+        ));
+    }
+}
@@ -0,0 +1,379 @@
+//! The JSON-RPC payloads exchanged with DeepL and the logic that builds
+//! them. This module depends on serde alone, so requests can be produced
+//! here and sent through any HTTP stack.
+
+use std::{collections::HashMap, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock,
+    options::{Formality, Splitting},
+};
+
+pub const DEEPL_API: &str = "https://www2.deepl.com/jsonrpc";
+
+/// Where requests carrying a Pro web account's `dl_session` cookie go.
+pub const DEEPL_PRO_API: &str = "https://api.deepl.com/jsonrpc";
+
+/// Headers the DeepL iOS app sends with every request.
+pub const HEADERS: &[(&str, &str)] = &[
+    ("Content-Type", "application/json"),
+    ("Accept", "*/*"),
+    ("x-app-os-name", "iOS"),
+    ("x-app-os-version", "16.3.0"),
+    ("Accept-Language", "en-US,en;q=0.9"),
+    ("Accept-Encoding", "gzip, deflate, br"),
+    ("x-app-device", "iPhone13,2"),
+    ("User-Agent", "DeepL-iOS/2.9.1 iOS 16.3.0 (iPhone13,2)"),
+    ("x-app-build", "510265"),
+    ("x-app-version", "2.9.1"),
+    ("Connection", "keep-alive"),
+];
+
+#[derive(Serialize, Debug)]
+pub struct Lang<'a> {
+    pub source_lang_user_selected: &'a str,
+    pub target_lang: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CommonJobParams<'a> {
+    pub was_spoken: bool,
+    pub transcribe_as: &'a str,
+    /// `formal` or `informal`; left out for the default tone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formality: Option<&'a str>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Params<'a> {
+    pub texts: Vec<Text<'a>>,
+    pub splitting: &'a str,
+    pub lang: Lang<'a>,
+    pub timestamp: u128,
+    #[serde(rename = "commonJobParams")]
+    pub common_job_params: CommonJobParams<'a>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Text<'a> {
+    pub text: &'a str,
+    pub request_alternatives: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PostData<'a> {
+    pub jsonrpc: &'a str,
+    pub method: &'a str,
+    pub id: i64,
+    pub params: Params<'a>,
+}
+
+impl Default for PostData<'_> {
+    fn default() -> Self {
+        Self {
+            jsonrpc: "2.0",
+            method: "LMT_handle_texts",
+            id: 0,
+            params: Params {
+                texts: vec![Text {
+                    text: "",
+                    request_alternatives: 0,
+                }],
+                splitting: "newlines",
+                lang: Lang {
+                    source_lang_user_selected: "auto",
+                    target_lang: "ZH",
+                },
+                timestamp: 0,
+                common_job_params: CommonJobParams {
+                    was_spoken: false,
+                    transcribe_as: "",
+                    formality: None,
+                },
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeepLResponse {
+    pub jsonrpc: String,
+    pub id: i64,
+    pub result: DeeplResult,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeeplResult {
+    pub texts: Vec<TranslatedText>,
+    pub lang: String,
+    pub lang_is_confident: bool,
+    #[serde(rename = "detectedLanguages")]
+    pub detected_languages: HashMap<String, f64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TranslatedText {
+    pub alternatives: Vec<Alternative>,
+    pub text: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Alternative {
+    pub text: String,
+}
+
+/// SplitMix64, enough to spread ids without pulling in a RNG crate.
+pub(crate) fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn random_number_id() -> i64 {
+    id_at(clock::now())
+}
+
+fn id_at(now: SystemTime) -> i64 {
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let num = 8300000 + (splitmix64(timestamp) % (8399998 - 8300000)) as i64;
+
+    num * 1000
+}
+
+pub fn timestamp_for_i_count(i_count: u128) -> u128 {
+    timestamp_at(i_count, clock::now())
+}
+
+fn timestamp_at(mut i_count: u128, now: SystemTime) -> u128 {
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    if i_count != 0 {
+        i_count += 1;
+        timestamp - timestamp % i_count + i_count
+    } else {
+        timestamp
+    }
+}
+
+pub fn dump_post_data(post_data: PostData) -> String {
+    serde_json::to_string(&post_data).unwrap_or_default()
+}
+
+/// How the serialized request is respaced before it is sent. Upstream has
+/// rejected some spellings of the `"method"` key at times, and which ones
+/// changes; `experiment` compares them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RequestStrategy {
+    /// `"method" : "` or `"method": "` depending on the request id.
+    #[default]
+    Alternating,
+    /// Always `"method": "`.
+    Spaced,
+    /// Always `"method" : "`.
+    WideSpaced,
+    /// serde_json's output as is.
+    Compact,
+}
+
+impl RequestStrategy {
+    pub const ALL: [RequestStrategy; 4] = [
+        RequestStrategy::Alternating,
+        RequestStrategy::Spaced,
+        RequestStrategy::WideSpaced,
+        RequestStrategy::Compact,
+    ];
+
+    /// The fixed spacing request `id` gets: `self`, unless it is
+    /// [`Alternating`](Self::Alternating), which picks one by the id.
+    pub fn resolve(self, id: i64) -> RequestStrategy {
+        match self {
+            RequestStrategy::Alternating if (id + 5) % 29 == 0 || (id + 3) % 13 == 0 => {
+                RequestStrategy::WideSpaced
+            }
+            RequestStrategy::Alternating => RequestStrategy::Spaced,
+            other => other,
+        }
+    }
+
+    /// `body`, the request with id `id`, with its `"method"` key spaced as
+    /// this strategy says, however it was spaced before.
+    pub fn respace(self, body: &str, id: i64) -> String {
+        let compact = body
+            .replacen("\"method\" : \"", "\"method\":\"", 1)
+            .replacen("\"method\": \"", "\"method\":\"", 1);
+        match self.resolve(id) {
+            RequestStrategy::WideSpaced => compact.replace("\"method\":\"", "\"method\" : \""),
+            RequestStrategy::Spaced => compact.replace("\"method\":\"", "\"method\": \""),
+            RequestStrategy::Alternating | RequestStrategy::Compact => compact,
+        }
+    }
+}
+
+impl std::fmt::Display for RequestStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RequestStrategy::Alternating => "alternating",
+            RequestStrategy::Spaced => "spaced",
+            RequestStrategy::WideSpaced => "wide-spaced",
+            RequestStrategy::Compact => "compact",
+        })
+    }
+}
+
+/// What a request asks of DeepL besides the texts and the languages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JobOptions {
+    /// Alternative translations wanted for each text.
+    pub alternatives: u32,
+    pub formality: Option<Formality>,
+    pub splitting: Splitting,
+}
+
+pub fn build_post_data(text: &str, src_lang: &str, target_lang: &str) -> String {
+    build_batch_post_data(&[text], src_lang, target_lang)
+}
+
+/// A request translating every text in `texts`; the response lists them
+/// in the same order.
+pub fn build_batch_post_data(texts: &[&str], src_lang: &str, target_lang: &str) -> String {
+    build_batch_post_data_with(
+        RequestStrategy::default(),
+        JobOptions::default(),
+        texts,
+        src_lang,
+        target_lang,
+    )
+}
+
+/// [`build_batch_post_data`], spaced as `strategy` says and asking for
+/// what `options` asks for.
+pub fn build_batch_post_data_with(
+    strategy: RequestStrategy,
+    options: JobOptions,
+    texts: &[&str],
+    src_lang: &str,
+    target_lang: &str,
+) -> String {
+    build_batch_post_data_frozen(
+        Frozen::now(),
+        strategy,
+        options,
+        texts,
+        src_lang,
+        target_lang,
+    )
+}
+
+/// What a request takes from the clock and the random number generator,
+/// fixed so that the same request can be built again byte for byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frozen {
+    pub id: i64,
+    pub now: SystemTime,
+}
+
+impl Frozen {
+    /// The id and time a request built now gets.
+    pub fn now() -> Self {
+        Frozen::at(clock::now())
+    }
+
+    /// The id and time a request built at `now` gets.
+    pub fn at(now: SystemTime) -> Self {
+        Frozen {
+            id: id_at(now),
+            now,
+        }
+    }
+}
+
+/// [`build_batch_post_data_with`] with the id and the time in `frozen`.
+pub fn build_batch_post_data_frozen(
+    frozen: Frozen,
+    strategy: RequestStrategy,
+    options: JobOptions,
+    texts: &[&str],
+    src_lang: &str,
+    target_lang: &str,
+) -> String {
+    let count =
+        texts
+            .iter()
+            .flat_map(|t| t.as_bytes())
+            .fold(timestamp_at(0, frozen.now), |i_count, e| {
+                if *e == 10 {
+                    i_count + 1
+                } else {
+                    i_count
+                }
+            });
+    let mut post_data = PostData::default();
+    let id = frozen.id;
+    post_data.id = id;
+    post_data.params.timestamp = timestamp_at(count, frozen.now);
+    post_data.params.texts = texts
+        .iter()
+        .map(|text| Text {
+            text,
+            request_alternatives: options.alternatives as i32,
+        })
+        .collect();
+    post_data.params.lang.source_lang_user_selected = src_lang;
+    post_data.params.lang.target_lang = target_lang;
+    post_data.params.splitting = options.splitting.as_str();
+    post_data.params.common_job_params.formality = options.formality.map(Formality::as_str);
+
+    strategy.respace(&dump_post_data(post_data), id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_post_data() {
+        let body = build_post_data("hello\nworld", "EN", "ZH");
+        assert!(body.contains("\"method\": \"") || body.contains("\"method\" : \""));
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["params"]["texts"][0]["text"], "hello\nworld");
+        assert_eq!(value["params"]["lang"]["target_lang"], "ZH");
+        let id = value["id"].as_i64().unwrap();
+        assert!((8_300_000_000..8_399_998_000).contains(&id));
+
+        let body = build_batch_post_data(&["one", "two\nlines"], "EN", "ZH");
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let texts = value["params"]["texts"].as_array().unwrap();
+        assert_eq!((texts.len(), &texts[1]["text"]), (2, &"two\nlines".into()));
+
+        let body = build_batch_post_data_with(
+            RequestStrategy::WideSpaced,
+            JobOptions::default(),
+            &["hi"],
+            "EN",
+            "ZH",
+        );
+        assert!(body.contains("\"method\" : \""));
+        let options = JobOptions {
+            alternatives: 3,
+            formality: Some(Formality::Informal),
+            splitting: Splitting::Paragraphs,
+        };
+        let body =
+            build_batch_post_data_with(RequestStrategy::Compact, options, &["hi"], "EN", "DE");
+        assert!(body.contains("\"method\":\""));
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["params"]["texts"][0]["request_alternatives"], 3);
+        assert_eq!(value["params"]["commonJobParams"]["formality"], "informal");
+        assert_eq!(value["params"]["splitting"], "paragraphs");
+        assert!(!build_post_data("hi", "EN", "DE").contains("formality"));
+    }
+}
@@ -0,0 +1,284 @@
+//! Post-edit rules applied to every translation, so recurring MT quirks
+//! are fixed in one place instead of in every consumer.
+//!
+//! Rules are loaded from a JSON file holding a list like
+//!
+//! ```json
+//! [
+//!   {"target": "DE", "replace": {"find": "\\bEmail\\b", "with": "E-Mail"}},
+//!   {"source": "EN", "target": "FR", "punctuation": {"ellipsis": true}},
+//!   {"target": "*", "case": "upper_first"}
+//! ]
+//! ```
+//!
+//! and run in file order. `source` and `target` default to `*`; `PT`
+//! also covers `PT-BR`.
+
+use std::{borrow::Cow, fmt, path::Path, sync::Arc};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    capabilities::Capabilities,
+    error::DeepLError,
+    lang,
+    translator::{BoxFuture, Translation, Translator},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseFix {
+    UpperFirst,
+    LowerFirst,
+    Upper,
+    Lower,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Punctuation {
+    /// `...` becomes `…`.
+    pub ellipsis: bool,
+    /// Runs of spaces become one.
+    pub collapse_spaces: bool,
+    /// Removes whitespace in front of these characters.
+    pub no_space_before: String,
+}
+
+#[derive(Debug)]
+pub enum Action {
+    Replace { find: Regex, with: String },
+    Case(CaseFix),
+    Punctuation(Punctuation),
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    pub source: String,
+    pub target: String,
+    pub action: Action,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RawAction {
+    Replace { find: String, with: String },
+    Case(CaseFix),
+    Punctuation(Punctuation),
+}
+
+#[derive(Deserialize)]
+struct RawRule {
+    #[serde(default = "any")]
+    source: String,
+    #[serde(default = "any")]
+    target: String,
+    #[serde(flatten)]
+    action: RawAction,
+}
+
+fn any() -> String {
+    "*".to_string()
+}
+
+#[derive(Debug)]
+pub enum RuleError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Regex { rule: usize, error: regex::Error },
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Io(e) => write!(f, "cannot read rules: {}", e),
+            RuleError::Parse(e) => write!(f, "invalid rules file: {}", e),
+            RuleError::Regex { rule, error } => write!(f, "rule {}: {}", rule, error),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+fn fix_case(text: &str, fix: CaseFix) -> String {
+    let mut chars = text.chars();
+    match fix {
+        CaseFix::Upper => text.to_uppercase(),
+        CaseFix::Lower => text.to_lowercase(),
+        CaseFix::UpperFirst => chars
+            .next()
+            .map_or_else(String::new, |c| c.to_uppercase().chain(chars).collect()),
+        CaseFix::LowerFirst => chars
+            .next()
+            .map_or_else(String::new, |c| c.to_lowercase().chain(chars).collect()),
+    }
+}
+
+fn fix_punctuation(text: &str, rules: &Punctuation) -> String {
+    let mut text = if rules.ellipsis {
+        text.replace("...", "…")
+    } else {
+        text.to_string()
+    };
+    if rules.collapse_spaces {
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            if !(c == ' ' && out.ends_with(' ')) {
+                out.push(c);
+            }
+        }
+        text = out;
+    }
+    if !rules.no_space_before.is_empty() {
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            if rules.no_space_before.contains(c) {
+                out.truncate(out.trim_end_matches([' ', '\u{a0}', '\u{202f}']).len());
+            }
+            out.push(c);
+        }
+        text = out;
+    }
+    text
+}
+
+#[derive(Debug, Default)]
+pub struct PostEdit {
+    rules: Vec<Rule>,
+}
+
+impl PostEdit {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, RuleError> {
+        let raw: Vec<RawRule> = serde_json::from_str(json).map_err(RuleError::Parse)?;
+        let mut rules = Vec::with_capacity(raw.len());
+        for (i, rule) in raw.into_iter().enumerate() {
+            let action = match rule.action {
+                RawAction::Replace { find, with } => Action::Replace {
+                    find: Regex::new(&find).map_err(|error| RuleError::Regex { rule: i, error })?,
+                    with,
+                },
+                RawAction::Case(fix) => Action::Case(fix),
+                RawAction::Punctuation(p) => Action::Punctuation(p),
+            };
+            rules.push(Rule {
+                source: rule.source,
+                target: rule.target,
+                action,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RuleError> {
+        Self::from_json(&std::fs::read_to_string(path).map_err(RuleError::Io)?)
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Runs every rule for this language pair over `text`.
+    pub fn apply<'t>(&self, text: &'t str, src_lang: &str, target_lang: &str) -> Cow<'t, str> {
+        let mut text = Cow::Borrowed(text);
+        for rule in &self.rules {
+            if !lang::matches(&rule.source, src_lang) || !lang::matches(&rule.target, target_lang) {
+                continue;
+            }
+            let edited = match &rule.action {
+                Action::Replace { find, with } => match find.replace_all(&text, with.as_str()) {
+                    Cow::Borrowed(_) => continue,
+                    Cow::Owned(s) => s,
+                },
+                Action::Case(fix) => fix_case(&text, *fix),
+                Action::Punctuation(p) => fix_punctuation(&text, p),
+            };
+            if edited != *text {
+                text = Cow::Owned(edited);
+            }
+        }
+        text
+    }
+}
+
+/// Runs the text and alternatives of every translation from `inner`
+/// through a [`PostEdit`] rule set.
+pub struct PostEdited {
+    inner: Arc<dyn Translator>,
+    rules: Arc<PostEdit>,
+}
+
+impl PostEdited {
+    pub fn new(inner: Arc<dyn Translator>, rules: Arc<PostEdit>) -> Self {
+        Self { inner, rules }
+    }
+}
+
+impl Translator for PostEdited {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move {
+            let mut translation = self.inner.translate(text, src_lang, target_lang).await?;
+            let src = match &translation.detected_source {
+                Some(detected) if lang::is_auto(src_lang) => detected.clone(),
+                _ => src_lang.to_string(),
+            };
+            let edit = |s: &str| self.rules.apply(s, &src, target_lang).into_owned();
+            translation.text = edit(&translation.text);
+            translation.alternatives = translation.alternatives.iter().map(|a| edit(a)).collect();
+            Ok(translation)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_apply_per_language_pair() {
+        let rules = PostEdit::from_json(
+            r#"[
+                {"target": "DE", "replace": {"find": "\\bEmail\\b", "with": "E-Mail"}},
+                {"source": "EN", "target": "FR", "punctuation": {"ellipsis": true, "no_space_before": ",."}},
+                {"target": "PT", "case": "upper_first"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules.apply("Ihre Email, Emails", "EN", "DE"),
+            "Ihre E-Mail, Emails"
+        );
+        assert_eq!(rules.apply("Ihre Email", "EN", "FR"), "Ihre Email");
+        assert_eq!(
+            rules.apply("Attendez ... , bien", "EN", "FR"),
+            "Attendez …, bien"
+        );
+        assert_eq!(rules.apply("olá", "EN", "PT-BR"), "Olá");
+        assert!(matches!(rules.apply("ok", "EN", "DE"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_invalid_regex_names_the_rule() {
+        let err =
+            PostEdit::from_json(r#"[{"case": "lower"}, {"replace": {"find": "(", "with": ""}}]"#)
+                .unwrap_err();
+        assert!(matches!(err, RuleError::Regex { rule: 1, .. }));
+    }
+}
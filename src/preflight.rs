@@ -0,0 +1,225 @@
+//! Checks a translation request before it is sent: language codes, length
+//! and placeholder balance, so that callers can fail fast with a message
+//! an end user understands instead of a failed upstream request.
+
+use std::fmt;
+
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+
+use crate::{
+    capabilities::Capabilities,
+    chunk::split_text,
+    lang,
+    options::{Casing, Formality, Splitting, TagHandling},
+    validate,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct TranslateRequest {
+    pub text: String,
+    #[serde(default = "auto")]
+    pub source_lang: String,
+    pub target_lang: String,
+    /// Accepted as `formal`/`more` or `informal`/`less`.
+    #[serde(default)]
+    pub formality: Option<Formality>,
+    /// `{"mode": "html"}` or `"xml"`, optionally with `ignore_tags` and
+    /// `non_splitting_tags`.
+    #[serde(default)]
+    pub tag_handling: Option<TagHandling>,
+    /// `preserve_source`, `sentence` or `title`.
+    #[serde(default)]
+    pub casing: Option<Casing>,
+    /// `newlines`, `sentences`, `paragraphs` or `off`.
+    #[serde(default)]
+    pub splitting: Option<Splitting>,
+}
+
+fn auto() -> String {
+    "auto".to_string()
+}
+
+/// Elements that never have a closing tag.
+const VOID_TAGS: &[&str] = &[
+    "area", "br", "col", "embed", "hr", "img", "input", "meta", "wbr",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    EmptyText,
+    UnsupportedSource(String),
+    UnsupportedTarget(String),
+    UnbalancedBrackets,
+    /// A tag opened and never closed, or closed without being opened.
+    UnbalancedTag(String),
+}
+
+impl Problem {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Problem::EmptyText => "empty_text",
+            Problem::UnsupportedSource(_) => "unsupported_source",
+            Problem::UnsupportedTarget(_) => "unsupported_target",
+            Problem::UnbalancedBrackets => "unbalanced_brackets",
+            Problem::UnbalancedTag(_) => "unbalanced_tag",
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::EmptyText => write!(f, "no text to translate"),
+            Problem::UnsupportedSource(l) => write!(f, "unsupported source language: {}", l),
+            Problem::UnsupportedTarget(l) => write!(f, "unsupported target language: {}", l),
+            Problem::UnbalancedBrackets => write!(f, "brackets are not balanced"),
+            Problem::UnbalancedTag(t) => write!(f, "unbalanced tag `{}`", t),
+        }
+    }
+}
+
+impl Serialize for Problem {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Problem", 2)?;
+        s.serialize_field("kind", self.kind())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
+}
+
+/// What [`check`] found.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Preflight {
+    pub valid: bool,
+    pub chars: usize,
+    /// Requests the text would be split into at the backend's length
+    /// limit. More than one needs [`Chunked`](crate::chunk::Chunked).
+    pub chunks: usize,
+    pub placeholders: Vec<String>,
+    pub problems: Vec<Problem>,
+}
+
+fn supported(langs: &[String], lang: &str) -> bool {
+    langs.is_empty() || langs.iter().any(|l| lang::matches(l, lang))
+}
+
+/// Unmatched tags in `text`, in the order they turn up.
+fn unbalanced_tags(text: &str) -> Vec<String> {
+    let mut open: Vec<String> = Vec::new();
+    let mut out = Vec::new();
+    for tag in validate::tags(text) {
+        if let Some(name) = tag.strip_prefix("</") {
+            let name = name.trim_end_matches('>');
+            match open.iter().rposition(|o| o == name) {
+                Some(i) => out.extend(open.drain(i..).skip(1).map(|o| format!("<{}>", o))),
+                None => out.push(tag),
+            }
+        } else if !tag.ends_with("/>") {
+            let name = tag.trim_start_matches('<').trim_end_matches('>');
+            if !VOID_TAGS.contains(&name) {
+                open.push(name.to_string());
+            }
+        }
+    }
+    out.extend(open.into_iter().map(|o| format!("<{}>", o)));
+    out
+}
+
+/// Checks `request` against what a backend with `capabilities` accepts,
+/// without translating anything.
+pub fn check(request: &TranslateRequest, capabilities: &Capabilities) -> Preflight {
+    let mut problems = Vec::new();
+    if request.text.trim().is_empty() {
+        problems.push(Problem::EmptyText);
+    }
+    if !lang::is_auto(&request.source_lang)
+        && !supported(&capabilities.source_langs, &request.source_lang)
+    {
+        problems.push(Problem::UnsupportedSource(request.source_lang.clone()));
+    }
+    if lang::is_auto(&request.target_lang)
+        || !supported(&capabilities.target_langs, &request.target_lang)
+    {
+        problems.push(Problem::UnsupportedTarget(request.target_lang.clone()));
+    }
+    if !validate::brackets_balanced(&request.text) {
+        problems.push(Problem::UnbalancedBrackets);
+    }
+    problems.extend(
+        unbalanced_tags(&request.text)
+            .into_iter()
+            .map(Problem::UnbalancedTag),
+    );
+    let chars = request.text.chars().count();
+    let chunks = match capabilities.max_chars {
+        Some(max) if chars > max => split_text(&request.text, max).len(),
+        _ => usize::from(chars > 0),
+    };
+    Preflight {
+        valid: problems.is_empty(),
+        chars,
+        chunks,
+        placeholders: validate::placeholders(&request.text)
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        problems,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(text: &str, source_lang: &str, target_lang: &str) -> TranslateRequest {
+        TranslateRequest {
+            text: text.to_string(),
+            source_lang: source_lang.to_string(),
+            target_lang: target_lang.to_string(),
+            formality: None,
+            tag_handling: None,
+            casing: None,
+            splitting: None,
+        }
+    }
+
+    #[test]
+    fn test_check_reports_every_problem() {
+        let caps = Capabilities {
+            source_langs: vec!["EN".to_string()],
+            target_langs: vec!["DE".to_string(), "PT".to_string()],
+            max_chars: Some(20),
+            ..Default::default()
+        };
+        let ok = check(
+            &request("Hello {name}. <b>Bye</b><br>", "en", "PT-BR"),
+            &caps,
+        );
+        assert!(ok.valid, "{:?}", ok.problems);
+        assert_eq!((ok.chars, ok.chunks), (28, 2));
+        assert_eq!(ok.placeholders, ["{name}"]);
+
+        let bad = check(&request("Hello (<b>there", "FR", "auto"), &caps);
+        assert!(!bad.valid);
+        assert_eq!(
+            bad.problems,
+            [
+                Problem::UnsupportedSource("FR".to_string()),
+                Problem::UnsupportedTarget("auto".to_string()),
+                Problem::UnbalancedBrackets,
+                Problem::UnbalancedTag("<b>".to_string()),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&bad.problems[1]).unwrap(),
+            serde_json::json!({
+                "kind": "unsupported_target",
+                "message": "unsupported target language: auto"
+            })
+        );
+        assert_eq!(
+            check(&request(" ", "auto", "DE"), &caps).problems,
+            [Problem::EmptyText]
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Restoring the surface form of short UI strings.
+//!
+//! Translators tend to normalise "SAVE", "Open File" or "Loading…" into
+//! sentence case and their own idea of punctuation. For strings short
+//! enough to be labels, [`Preserve`] copies the source's leading and
+//! trailing punctuation and its ALL-CAPS or Title Case pattern back onto
+//! the translation.
+
+use std::sync::Arc;
+
+use crate::{
+    capabilities::Capabilities,
+    error::DeepLError,
+    translator::{BoxFuture, Translation, Translator},
+};
+
+fn is_trailing(c: char) -> bool {
+    matches!(
+        c,
+        '.' | '!' | '?' | ':' | ';' | '…' | '。' | '！' | '？' | '：' | '；' | '؟'
+    )
+}
+
+fn is_leading(c: char) -> bool {
+    matches!(c, '•' | '-' | '*' | '·' | '–' | '—' | '>') || c == ' '
+}
+
+/// Folds full-width and script-specific marks so that "。" counts as the
+/// same punctuation as ".".
+fn fold(c: char) -> char {
+    match c {
+        '。' => '.',
+        '！' => '!',
+        '？' | '؟' => '?',
+        '：' => ':',
+        '；' => ';',
+        c => c,
+    }
+}
+
+fn trailing(s: &str) -> &str {
+    &s[s.trim_end_matches(is_trailing).len()..]
+}
+
+fn leading(s: &str) -> &str {
+    &s[..s.len() - s.trim_start_matches(is_leading).len()]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Casing {
+    Upper,
+    Title,
+    UpperFirst,
+    Other,
+}
+
+fn casing(s: &str) -> Casing {
+    let letters = || s.chars().filter(|c| c.is_uppercase() || c.is_lowercase());
+    if letters().count() >= 2 && letters().all(char::is_uppercase) {
+        return Casing::Upper;
+    }
+    let initials: Vec<_> = s
+        .split_whitespace()
+        .filter_map(|w| w.chars().find(|c| c.is_alphabetic()))
+        .collect();
+    if initials.len() >= 2 && initials.iter().all(|c| c.is_uppercase()) {
+        Casing::Title
+    } else if initials.first().is_some_and(|c| c.is_uppercase()) {
+        Casing::UpperFirst
+    } else {
+        Casing::Other
+    }
+}
+
+fn upper_initial(word: &str) -> String {
+    match word.char_indices().find(|(_, c)| c.is_alphabetic()) {
+        Some((i, c)) => {
+            let rest = &word[i + c.len_utf8()..];
+            format!("{}{}{}", &word[..i], c.to_uppercase(), rest)
+        }
+        None => word.to_string(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Preserve {
+    pub case: bool,
+    pub punctuation: bool,
+    /// Longer sources are left alone; their casing and punctuation are
+    /// prose rather than a UI convention.
+    pub max_words: usize,
+}
+
+impl Default for Preserve {
+    fn default() -> Self {
+        Self {
+            case: true,
+            punctuation: true,
+            max_words: 6,
+        }
+    }
+}
+
+impl Preserve {
+    pub fn apply(&self, source: &str, translated: &str) -> String {
+        if source.split_whitespace().count() > self.max_words || translated.trim().is_empty() {
+            return translated.to_string();
+        }
+        let mut out = translated.to_string();
+        if self.case && out.chars().any(|c| c.is_uppercase() || c.is_lowercase()) {
+            out = match casing(source) {
+                Casing::Upper => out.to_uppercase(),
+                Casing::Title => out
+                    .split(' ')
+                    .map(upper_initial)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                Casing::UpperFirst => upper_initial(&out),
+                Casing::Other => out,
+            };
+        }
+        if self.punctuation {
+            let (want, have) = (trailing(source), trailing(&out));
+            if !want.chars().map(fold).eq(have.chars().map(fold)) {
+                out.truncate(out.len() - have.len());
+                out.push_str(want);
+            }
+            let prefix = leading(source);
+            if !prefix.is_empty() && !out.starts_with(prefix) {
+                out = format!("{}{}", prefix, out.trim_start_matches(is_leading));
+            }
+        }
+        out
+    }
+}
+
+/// Runs every translation from `inner` through [`Preserve::apply`].
+pub struct Preserving {
+    inner: Arc<dyn Translator>,
+    preserve: Preserve,
+}
+
+impl Preserving {
+    pub fn new(inner: Arc<dyn Translator>, preserve: Preserve) -> Self {
+        Self { inner, preserve }
+    }
+}
+
+impl Translator for Preserving {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move {
+            let mut translation = self.inner.translate(text, src_lang, target_lang).await?;
+            translation.text = self.preserve.apply(text, &translation.text);
+            for alternative in &mut translation.alternatives {
+                *alternative = self.preserve.apply(text, alternative);
+            }
+            Ok(translation)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restores_ui_conventions() {
+        let p = Preserve::default();
+        assert_eq!(p.apply("SAVE", "Speichern"), "SPEICHERN");
+        assert_eq!(
+            p.apply("Open File", "ouvrir le fichier"),
+            "Ouvrir Le Fichier"
+        );
+        assert_eq!(p.apply("Save…", "Speichern."), "Speichern…");
+        assert_eq!(p.apply("Loading...", "Wird geladen"), "Wird geladen...");
+        assert_eq!(p.apply("OK", "Einverstanden."), "EINVERSTANDEN");
+        assert_eq!(p.apply("• item", "Element"), "• Element");
+        assert_eq!(p.apply("Done!", "完成！"), "完成！");
+        assert_eq!(p.apply("file name", "Dateiname"), "Dateiname");
+    }
+
+    #[test]
+    fn test_leaves_prose_alone() {
+        let p = Preserve::default();
+        let source = "This is a long sentence that reads as prose, not a label";
+        assert_eq!(
+            p.apply(source, "Das ist ein langer Satz."),
+            "Das ist ein langer Satz."
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Hiding placeholders and markup from the translator.
+//!
+//! [`mask`] swaps every placeholder and tag for a numbered sentinel such as
+//! `⟦0⟧`, which translators leave alone far more reliably than `{name}` or
+//! `<b class="x">`. [`unmask`] puts the originals back and refuses
+//! translations that lost, duplicated or invented a sentinel.
+
+use std::{fmt, ops::Range};
+
+use crate::validate::{placeholder_spans, tag_spans};
+
+const OPEN: char = '⟦';
+const CLOSE: char = '⟧';
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Masked {
+    pub text: String,
+    /// The original text of sentinel `n` at index `n`.
+    pub tokens: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MaskError {
+    Missing(usize),
+    Duplicated(usize),
+    Unknown(usize),
+}
+
+impl fmt::Display for MaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaskError::Missing(n) => write!(f, "sentinel {}{}{} is missing", OPEN, n, CLOSE),
+            MaskError::Duplicated(n) => {
+                write!(f, "sentinel {}{}{} appears more than once", OPEN, n, CLOSE)
+            }
+            MaskError::Unknown(n) => write!(f, "unknown sentinel {}{}{}", OPEN, n, CLOSE),
+        }
+    }
+}
+
+impl std::error::Error for MaskError {}
+
+/// A sentinel at the start of `s`: its length in bytes and its number.
+fn sentinel(s: &str) -> Option<(usize, usize)> {
+    let rest = s.strip_prefix(OPEN)?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+    if digits == 0 || !rest[digits..].starts_with(CLOSE) {
+        return None;
+    }
+    let n = rest[..digits].parse().ok()?;
+    Some((OPEN.len_utf8() + digits + CLOSE.len_utf8(), n))
+}
+
+fn sentinel_spans(text: &str) -> Vec<Range<usize>> {
+    text.match_indices(OPEN)
+        .filter_map(|(i, _)| sentinel(&text[i..]).map(|(len, _)| i..i + len))
+        .collect()
+}
+
+/// Replaces placeholders, tags and anything that already looks like a
+/// sentinel with fresh sentinels. Where spans overlap, as with a tag whose
+/// attribute holds a placeholder, the one starting first wins.
+pub fn mask(text: &str) -> Masked {
+    mask_with(text, [])
+}
+
+/// Like [`mask`], but also hides the byte ranges in `extra`, such as the
+/// [`Entities`](crate::entities::Entities) found in `text`. Ranges that
+/// are out of bounds or split a character are ignored.
+pub fn mask_with(text: &str, extra: impl IntoIterator<Item = Range<usize>>) -> Masked {
+    let mut spans = placeholder_spans(text);
+    spans.extend(tag_spans(text).into_iter().map(|(span, _)| span));
+    spans.extend(sentinel_spans(text));
+    spans.extend(
+        extra
+            .into_iter()
+            .filter(|span| !span.is_empty() && text.get(span.clone()).is_some()),
+    );
+    spans.sort_by_key(|s| (s.start, std::cmp::Reverse(s.end)));
+
+    let mut masked = Masked::default();
+    let mut last = 0;
+    for span in spans {
+        if span.start < last {
+            continue;
+        }
+        masked.text.push_str(&text[last..span.start]);
+        masked.text.push(OPEN);
+        masked.text.push_str(&masked.tokens.len().to_string());
+        masked.text.push(CLOSE);
+        masked.tokens.push(text[span.clone()].to_string());
+        last = span.end;
+    }
+    masked.text.push_str(&text[last..]);
+    masked
+}
+
+/// [`mask`] for HTML or XML: character references such as `&amp;` and
+/// whole elements named in `ignore_tags`, with their content, are hidden
+/// too. Names match ignoring case; an element left open is not hidden.
+pub fn mask_markup(text: &str, ignore_tags: &[String]) -> Masked {
+    let mut extra = element_spans(text, ignore_tags);
+    extra.extend(entity_spans(text));
+    mask_with(text, extra)
+}
+
+fn element_spans(text: &str, names: &[String]) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    // The element being hidden: its name, where it starts and how deep
+    // its own name nests inside it.
+    let mut open: Option<(String, usize, usize)> = None;
+    for (span, tag) in tag_spans(text) {
+        let name = &tag[1..tag.len() - 1];
+        match &mut open {
+            None => {
+                if !name.starts_with('/')
+                    && !name.ends_with('/')
+                    && names.iter().any(|n| n.eq_ignore_ascii_case(name))
+                {
+                    open = Some((name.to_string(), span.start, 1));
+                }
+            }
+            Some((open_name, start, depth)) => {
+                if name == open_name {
+                    *depth += 1;
+                } else if name.strip_prefix('/') == Some(open_name) {
+                    *depth -= 1;
+                    if *depth == 0 {
+                        out.push(*start..span.end);
+                        open = None;
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+fn entity_spans(text: &str) -> Vec<Range<usize>> {
+    text.match_indices('&')
+        .filter_map(|(i, _)| {
+            let rest = &text[i + 1..];
+            let name = &rest[..rest.find(';')?];
+            let valid = match name.strip_prefix('#') {
+                Some(n) => match n.strip_prefix(['x', 'X']) {
+                    Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+                    None => !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()),
+                },
+                None => {
+                    (1..=32).contains(&name.len())
+                        && name.chars().all(|c| c.is_ascii_alphanumeric())
+                }
+            };
+            valid.then(|| i..i + name.len() + 2)
+        })
+        .collect()
+}
+
+/// Restores the tokens of a [`mask`]ed text in `translated`. Every sentinel
+/// must appear exactly once.
+pub fn unmask(translated: &str, tokens: &[String]) -> Result<String, MaskError> {
+    let mut seen = vec![false; tokens.len()];
+    let mut out = String::with_capacity(translated.len());
+    let mut rest = translated;
+    while let Some(i) = rest.find(OPEN) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        match sentinel(rest) {
+            Some((len, n)) => {
+                let token = tokens.get(n).ok_or(MaskError::Unknown(n))?;
+                if std::mem::replace(&mut seen[n], true) {
+                    return Err(MaskError::Duplicated(n));
+                }
+                out.push_str(token);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push(OPEN);
+                rest = &rest[OPEN.len_utf8()..];
+            }
+        }
+    }
+    out.push_str(rest);
+    match seen.iter().position(|s| !s) {
+        Some(n) => Err(MaskError::Missing(n)),
+        None => Ok(out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_round_trip() {
+        let text = "Hi {name}, <a href=\"{url}\">open</a> %1$d ⟦0⟧ 👍🏽";
+        let masked = mask(text);
+        assert_eq!(masked.text, "Hi ⟦0⟧, ⟦1⟧open⟦2⟧ ⟦3⟧ ⟦4⟧ 👍🏽");
+        assert_eq!(masked.tokens[1], "<a href=\"{url}\">");
+        assert_eq!(masked.tokens[4], "⟦0⟧");
+        assert_eq!(unmask(&masked.text, &masked.tokens).unwrap(), text);
+    }
+
+    #[test]
+    fn test_mask_markup_hides_ignored_elements() {
+        let text = "<p>Run <code>a &amp;&amp; <code>b</code></code> &#x27;now&#39; & <b>go</b></p>";
+        let masked = mask_markup(text, &["CODE".to_string()]);
+        assert_eq!(masked.text, "⟦0⟧Run ⟦1⟧ ⟦2⟧now⟦3⟧ & ⟦4⟧go⟦5⟧⟦6⟧");
+        assert_eq!(masked.tokens[1], "<code>a &amp;&amp; <code>b</code></code>");
+        assert_eq!(unmask(&masked.text, &masked.tokens).unwrap(), text);
+    }
+
+    #[test]
+    fn test_unmask_checks_sentinels() {
+        let tokens = vec!["{a}".to_string(), "{b}".to_string()];
+        assert_eq!(unmask("⟦1⟧ ⟦0⟧ ⟦", &tokens).unwrap(), "{b} {a} ⟦");
+        assert_eq!(unmask("⟦0⟧", &tokens), Err(MaskError::Missing(1)));
+        assert_eq!(unmask("⟦0⟧⟦0⟧⟦1⟧", &tokens), Err(MaskError::Duplicated(0)));
+        assert_eq!(unmask("⟦7⟧", &tokens), Err(MaskError::Unknown(7)));
+    }
+}
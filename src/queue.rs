@@ -0,0 +1,67 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeferredJob<J> {
+    pub identity: String,
+    pub chars: u64,
+    pub ready_at: SystemTime,
+    pub job: J,
+}
+
+/// Jobs waiting for their upstream identity to become available again.
+/// Serializable, to be kept across restarts with
+/// [`StateDir`](crate::state::StateDir).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobQueue<J> {
+    jobs: Vec<DeferredJob<J>>,
+}
+
+impl<J> Default for JobQueue<J> {
+    fn default() -> Self {
+        Self { jobs: Vec::new() }
+    }
+}
+
+impl<J> JobQueue<J> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, job: DeferredJob<J>) {
+        let pos = self
+            .jobs
+            .iter()
+            .position(|e| e.ready_at > job.ready_at)
+            .unwrap_or(self.jobs.len());
+        self.jobs.insert(pos, job);
+    }
+
+    /// Removes and returns every job whose `ready_at` is not after `now`,
+    /// oldest first.
+    pub fn take_ready(&mut self, now: SystemTime) -> Vec<DeferredJob<J>> {
+        let split = self
+            .jobs
+            .iter()
+            .position(|e| e.ready_at > now)
+            .unwrap_or(self.jobs.len());
+        self.jobs.drain(..split).collect()
+    }
+
+    pub fn next_ready_at(&self) -> Option<SystemTime> {
+        self.jobs.first().map(|e| e.ready_at)
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn pending_for(&self, identity: &str) -> usize {
+        self.jobs.iter().filter(|e| e.identity == identity).count()
+    }
+}
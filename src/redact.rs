@@ -0,0 +1,100 @@
+//! Keeping user text out of diagnostics.
+//!
+//! Anything the crate logs about a request goes through a [`Redaction`]
+//! policy first, so enabling debug logging does not copy source texts and
+//! translations, which may contain personal data, into log storage.
+
+use std::{fmt, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Redaction {
+    /// Log only the length.
+    #[default]
+    Full,
+    /// Log a stable hash, so repeated texts can be correlated.
+    Hashed,
+    /// Log the first `n` characters.
+    Truncated(usize),
+    /// Log the text as it is.
+    Off,
+}
+
+impl Redaction {
+    pub fn apply(self, text: &str) -> Redacted<'_> {
+        Redacted { text, policy: self }
+    }
+}
+
+/// Parses `full`, `hashed`, `off` or `truncate:<n>`.
+impl FromStr for Redaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "full" => Ok(Redaction::Full),
+            "hashed" | "hash" => Ok(Redaction::Hashed),
+            "off" | "none" => Ok(Redaction::Off),
+            other => other
+                .strip_prefix("truncate:")
+                .and_then(|n| n.parse().ok())
+                .map(Redaction::Truncated)
+                .ok_or_else(|| format!("unknown redaction policy `{}`", s)),
+        }
+    }
+}
+
+/// 64-bit FNV-1a; stable across builds and platforms, unlike
+/// `DefaultHasher`.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A text formatted according to a [`Redaction`] policy.
+#[derive(Clone, Copy, Debug)]
+pub struct Redacted<'a> {
+    text: &'a str,
+    policy: Redaction,
+}
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chars = self.text.chars().count();
+        match self.policy {
+            Redaction::Full => write!(f, "<{} chars>", chars),
+            Redaction::Hashed => write!(f, "<{} chars, fnv1a {:016x}>", chars, fnv1a(self.text)),
+            Redaction::Truncated(n) if chars > n => {
+                let end = self.text.char_indices().nth(n).map_or(0, |(i, _)| i);
+                write!(f, "{:?}… (+{} chars)", &self.text[..end], chars - n)
+            }
+            Redaction::Truncated(_) | Redaction::Off => write!(f, "{:?}", self.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction_policies() {
+        let text = "Meet Ada at 5";
+        assert_eq!(Redaction::Full.apply(text).to_string(), "<13 chars>");
+        assert_eq!(
+            Redaction::Hashed.apply(text).to_string(),
+            Redaction::Hashed.apply("Meet Ada at 5").to_string()
+        );
+        assert_ne!(
+            Redaction::Hashed.apply(text).to_string(),
+            Redaction::Hashed.apply("Meet Bob at 5").to_string()
+        );
+        assert_eq!(
+            Redaction::Truncated(4).apply(text).to_string(),
+            "\"Meet\"… (+9 chars)"
+        );
+        assert_eq!(Redaction::Off.apply(text).to_string(), "\"Meet Ada at 5\"");
+        assert_eq!("truncate:8".parse(), Ok(Redaction::Truncated(8)));
+        assert!("loud".parse::<Redaction>().is_err());
+    }
+}
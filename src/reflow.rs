@@ -0,0 +1,274 @@
+//! Undoing the line wrapping of text extracted from PDFs, so that DeepL
+//! sees whole sentences instead of fragments cut at the end of each line.
+//!
+//! [`reflow`] removes soft hyphens, joins words hyphenated across a line
+//! break and joins the hard-wrapped lines of each paragraph. A paragraph
+//! ends at a blank line or a page break, before a list item or an
+//! indented line that follows a full stop, and after a line much shorter
+//! than the widest, which is how headings and the last lines of
+//! paragraphs look once the layout is gone.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reflow {
+    /// Join words hyphenated across a line break, `trans-` `lation`; the
+    /// hyphen is kept before a capital or a digit, as in `Jean-` `Paul`.
+    pub dehyphenate: bool,
+    /// Join the lines of a paragraph with a space, or with nothing between
+    /// CJK characters.
+    pub join_lines: bool,
+    /// A line shorter than this fraction of the widest line ends its
+    /// paragraph.
+    pub short_line: f64,
+}
+
+impl Default for Reflow {
+    fn default() -> Self {
+        Self {
+            dehyphenate: true,
+            join_lines: true,
+            short_line: 0.6,
+        }
+    }
+}
+
+const SOFT_HYPHEN: char = '\u{ad}';
+
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30ff}' | '\u{3400}'..='\u{9fff}' | '\u{ff00}'..='\u{ffef}')
+}
+
+/// The width of `text` in columns, two to a CJK character.
+pub(crate) fn width(text: &str) -> usize {
+    text.chars().map(|c| if is_cjk(c) { 2 } else { 1 }).sum()
+}
+
+/// The length of the list marker `line` starts with, spaces after it
+/// included: a bullet, or a number or letter followed by `.` or `)`.
+pub(crate) fn list_marker(line: &str) -> Option<usize> {
+    let marker = line.split(char::is_whitespace).next().unwrap_or("");
+    let bullet = marker.chars().count() == 1 && marker.starts_with(['•', '◦', '▪', '–', '-', '*']);
+    let label = marker
+        .strip_suffix('.')
+        .or_else(|| marker.strip_suffix(')'))
+        .unwrap_or("");
+    let numbered =
+        !label.is_empty() && label.len() <= 3 && label.chars().all(|c| c.is_ascii_digit());
+    let lettered = label.len() == 1 && label.chars().all(|c| c.is_ascii_lowercase());
+    let rest = line[marker.len()..].trim_start();
+    ((bullet || numbered || lettered) && !rest.is_empty()).then(|| line.len() - rest.len())
+}
+
+fn is_list_item(line: &str) -> bool {
+    list_marker(line.trim_start()).is_some()
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn ends_sentence(line: &str) -> bool {
+    line.trim_end_matches(['"', '\'', '”', '’', ')'])
+        .ends_with(['.', '!', '?', ':', '。', '！', '？'])
+}
+
+/// Appends `next` to the paragraph `text` ends with.
+pub(crate) fn join(text: &mut String, next: &str, options: &Reflow) {
+    if text.ends_with(SOFT_HYPHEN) {
+        text.pop();
+        text.push_str(next);
+        return;
+    }
+    let mut chars = text.chars().rev();
+    let hyphenated = chars.next() == Some('-') && chars.next().is_some_and(char::is_alphabetic);
+    if options.dehyphenate && hyphenated {
+        if next.starts_with(char::is_lowercase) {
+            text.pop();
+        }
+        text.push_str(next);
+        return;
+    }
+    let glued = text.chars().last().is_some_and(is_cjk) && next.starts_with(is_cjk);
+    if !glued {
+        text.push(' ');
+    }
+    text.push_str(next);
+}
+
+/// The paragraphs of `text`, each on one line.
+pub fn paragraphs(text: &str, options: &Reflow) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let widest = lines.iter().map(|l| width(l.trim())).max().unwrap_or(0);
+    let short = (widest as f64 * options.short_line) as usize;
+
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    let mut previous: Option<&str> = None;
+    for &line in &lines {
+        if line.trim().is_empty() || line.contains('\u{c}') {
+            // A page break may share its line with the next page's text.
+            if !current.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+            previous = None;
+            let rest = line.rsplit('\u{c}').next().unwrap_or("");
+            if rest.trim().is_empty() {
+                continue;
+            }
+        }
+        let line = line.rsplit('\u{c}').next().unwrap_or(line);
+        let breaks = previous.is_some_and(|prev| {
+            let hyphenated = prev.ends_with('-') || prev.ends_with(SOFT_HYPHEN);
+            !options.join_lines
+                || is_list_item(line)
+                || (!hyphenated && width(prev.trim()) < short)
+                || (ends_sentence(prev) && indent(line) > indent(prev))
+        });
+        if breaks {
+            paragraphs.push(std::mem::take(&mut current));
+        }
+        if current.is_empty() {
+            current.push_str(line.trim());
+        } else {
+            join(&mut current, line.trim(), options);
+        }
+        previous = Some(line);
+    }
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+    for paragraph in &mut paragraphs {
+        paragraph.retain(|c| c != SOFT_HYPHEN);
+    }
+    paragraphs
+}
+
+/// `text` with its paragraphs reflowed, separated by blank lines.
+pub fn reflow(text: &str, options: &Reflow) -> String {
+    paragraphs(text, options).join("\n\n")
+}
+
+/// `word` split into the pieces a line may break between: each CJK
+/// character, and the runs of other characters.
+fn pieces(word: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    for (i, c) in word.char_indices() {
+        if is_cjk(c) {
+            if start < i {
+                out.push(&word[start..i]);
+            }
+            start = i + c.len_utf8();
+            out.push(&word[i..start]);
+        }
+    }
+    if start < word.len() {
+        out.push(&word[start..]);
+    }
+    out
+}
+
+/// `text` wrapped into lines of at most `columns` columns, filling each
+/// as far as it goes, with CJK characters two columns wide. Words longer
+/// than a line get one of their own; CJK text breaks between any two
+/// characters, but not before closing punctuation.
+pub fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut used = 0;
+    for word in text.split_whitespace() {
+        let mut spaced = true;
+        for piece in pieces(word) {
+            let piece_width = width(piece);
+            let space = usize::from(spaced && !line.is_empty());
+            let closing = piece.starts_with(['。', '，', '、', '！', '？', '」', '）', '：', '；']);
+            if !line.is_empty() && used + space + piece_width > columns && !closing {
+                lines.push(std::mem::take(&mut line));
+                used = 0;
+            } else if space == 1 {
+                line.push(' ');
+                used += 1;
+            }
+            line.push_str(piece);
+            used += piece_width;
+            spaced = false;
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapped_lines_join_into_paragraphs() {
+        let text = "1 Introduction\n\
+                    Machine translation of scanned docu-\n\
+                    ments suffers when every line is sent as\n\
+                    a sentence of its own. Jean-\n\
+                    Paul noticed it in 2019, with long\u{ad}\n\
+                    standing tools.\n\
+                    \x20 A new paragraph starts indented and\n\
+                    runs on.\n\
+                    • first point on its own line\n\
+                    • second point\n\
+                    \u{c}机器翻译的质量取决于输入文本\n\
+                    的完整性。\n";
+        assert_eq!(
+            paragraphs(text, &Reflow::default()),
+            [
+                "1 Introduction",
+                "Machine translation of scanned documents suffers when every line is sent as \
+                 a sentence of its own. Jean-Paul noticed it in 2019, with longstanding tools.",
+                "A new paragraph starts indented and runs on.",
+                "• first point on its own line",
+                "• second point",
+                "机器翻译的质量取决于输入文本的完整性。",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_options_turn_off_joining() {
+        let text = "a well-\nknown line that is as long as the\nnext one.";
+        let kept = Reflow {
+            dehyphenate: false,
+            ..Reflow::default()
+        };
+        assert_eq!(
+            reflow(text, &kept),
+            "a well- known line that is as long as the next one."
+        );
+        let lines = Reflow {
+            join_lines: false,
+            ..Reflow::default()
+        };
+        assert_eq!(
+            reflow(text, &lines)
+                .lines()
+                .filter(|l| !l.is_empty())
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_wrap_fills_lines_up_to_the_width() {
+        assert_eq!(
+            wrap("the quick brown fox jumps over the lazy dog", 15),
+            ["the quick brown", "fox jumps over", "the lazy dog"]
+        );
+        assert_eq!(
+            wrap("a supercalifragilistic word", 10),
+            ["a", "supercalifragilistic", "word"]
+        );
+        assert_eq!(
+            wrap("机器翻译的质量很好", 8),
+            ["机器翻译", "的质量很", "好"]
+        );
+        assert_eq!(wrap("机器翻译。", 8), ["机器翻译。"]);
+    }
+}
@@ -0,0 +1,185 @@
+//! Reports of file translation jobs for CI pipelines to archive: JSON
+//! through serde, or a standalone HTML page from [`JobReport::to_html`].
+
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::formats::FileStats;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Translated,
+    /// Written with some segments left untranslated.
+    Partial,
+    /// Already up to date.
+    Unchanged,
+    /// Would be translated; reported by dry runs.
+    Pending,
+    /// Left alone because the target was edited by hand since it was
+    /// last translated.
+    Conflict,
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileReport {
+    pub source: String,
+    /// `None` when the source could not be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub status: FileStatus,
+    #[serde(flatten)]
+    pub stats: FileStats,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobReport {
+    pub translated: usize,
+    #[serde(default)]
+    pub partial: usize,
+    pub unchanged: usize,
+    pub pending: usize,
+    #[serde(default)]
+    pub conflicts: usize,
+    pub failed: usize,
+    /// Sums over every file.
+    pub totals: FileStats,
+    pub duration_ms: u64,
+    pub files: Vec<FileReport>,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl JobReport {
+    pub fn push(&mut self, file: FileReport) {
+        match file.status {
+            FileStatus::Translated => self.translated += 1,
+            FileStatus::Partial => self.partial += 1,
+            FileStatus::Unchanged => self.unchanged += 1,
+            FileStatus::Pending => self.pending += 1,
+            FileStatus::Conflict => self.conflicts += 1,
+            FileStatus::Failed => self.failed += 1,
+        }
+        self.totals.segments += file.stats.segments;
+        self.totals.chars += file.stats.chars;
+        self.totals.duplicates += file.stats.duplicates;
+        self.totals.cached += file.stats.cached;
+        self.totals.failed += file.stats.failed;
+        self.files.push(file);
+    }
+
+    /// A self-contained HTML summary, files needing attention first.
+    pub fn to_html(&self) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Translation report</title>\n<style>\nbody { font-family: sans-serif; }\ntable { border-collapse: collapse; }\nth, td { border: 1px solid #ccc; padding: 2px 8px; text-align: left; }\n.failed { background: #fdd; }\n.partial, .conflict { background: #ffd; }\n</style>\n</head>\n<body>\n",
+        );
+        let _ = writeln!(
+            out,
+            "<h1>Translation report</h1>\n<p>{} translated, {} partial, {} unchanged, {} pending, {} conflicts, {} failed; {} segments, {} characters, {} duplicates, {} cached, {} untranslated, {:.1}s.</p>",
+            self.translated,
+            self.partial,
+            self.unchanged,
+            self.pending,
+            self.conflicts,
+            self.failed,
+            self.totals.segments,
+            self.totals.chars,
+            self.totals.duplicates,
+            self.totals.cached,
+            self.totals.failed,
+            self.duration_ms as f64 / 1000.0
+        );
+        out.push_str("<table>\n<tr><th>Source</th><th>Language</th><th>Target</th><th>Status</th><th>Segments</th><th>Characters</th><th>Cached</th><th>Untranslated</th><th>Time (ms)</th><th>Error</th></tr>\n");
+        let mut files: Vec<&FileReport> = self.files.iter().collect();
+        files.sort_by_key(|f| match f.status {
+            FileStatus::Failed => 0,
+            FileStatus::Conflict | FileStatus::Partial => 1,
+            _ => 2,
+        });
+        for file in files {
+            let status = match file.status {
+                FileStatus::Translated => "translated",
+                FileStatus::Partial => "partial",
+                FileStatus::Unchanged => "unchanged",
+                FileStatus::Pending => "pending",
+                FileStatus::Conflict => "conflict",
+                FileStatus::Failed => "failed",
+            };
+            let _ = writeln!(
+                out,
+                "<tr class=\"{status}\"><td>{}</td><td>{}</td><td>{}</td><td>{status}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&file.source),
+                escape(file.lang.as_deref().unwrap_or("")),
+                escape(file.target.as_deref().unwrap_or("")),
+                file.stats.segments,
+                file.stats.chars,
+                file.stats.cached,
+                file.stats.failed,
+                file.duration_ms,
+                escape(file.error.as_deref().unwrap_or("")),
+            );
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_report() {
+        let mut report = JobReport::default();
+        report.push(FileReport {
+            source: "docs/a.md".to_string(),
+            lang: Some("ja".to_string()),
+            target: Some("i18n/ja/docs/a.md".to_string()),
+            status: FileStatus::Translated,
+            stats: FileStats {
+                segments: 3,
+                chars: 40,
+                duplicates: 1,
+                cached: 1,
+                failed: 0,
+            },
+            error: None,
+            duration_ms: 120,
+        });
+        report.push(FileReport {
+            source: "docs/<b>.md".to_string(),
+            lang: None,
+            target: None,
+            status: FileStatus::Failed,
+            stats: FileStats::default(),
+            error: Some("permission denied".to_string()),
+            duration_ms: 0,
+        });
+        assert_eq!((report.translated, report.failed), (1, 1));
+        assert_eq!(report.totals.segments, 3);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["files"][0]["cached"], 1);
+        assert_eq!(json["files"][1]["status"], "failed");
+        assert!(json["files"][1].get("lang").is_none());
+        assert_eq!(serde_json::from_value::<JobReport>(json).unwrap(), report);
+
+        let html = report.to_html();
+        assert!(
+            html.contains("1 translated, 0 partial, 0 unchanged, 0 pending, 0 conflicts, 1 failed")
+        );
+        assert!(html.find("docs/&lt;b&gt;.md").unwrap() < html.find("docs/a.md").unwrap());
+    }
+}
@@ -0,0 +1,138 @@
+//! When and how long to wait before trying a failed request again.
+
+use std::time::{Duration, SystemTime};
+
+use crate::{clock, error::DeepLError, payload::splitmix64};
+
+/// Exponential backoff: attempt `n` waits `base_delay * 2^(n-1)`, give or
+/// take `jitter`, up to `max_delay`. A `Retry-After` from upstream wins
+/// over the computed delay.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first one included.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    /// Longer waits, including a `Retry-After`, give up instead.
+    pub max_delay: Duration,
+    /// Spread of each delay as a fraction of it, from 0.0 to 1.0, so
+    /// clients that failed together do not retry together.
+    pub jitter: f64,
+    /// Upstream statuses worth retrying. 429 covers every rate limit.
+    pub statuses: Vec<u16>,
+    /// Whether to retry when upstream cannot be reached at all.
+    pub network: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+            statuses: vec![429, 500, 502, 503, 504],
+            network: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn retries(&self, error: &DeepLError) -> bool {
+        match error {
+            #[cfg(feature = "client")]
+            DeepLError::Network(_) => self.network,
+            DeepLError::RateLimited { .. } => self.statuses.contains(&429),
+            DeepLError::Blocked { .. } => self.statuses.contains(&403),
+            DeepLError::Status { status, .. } => self.statuses.contains(status),
+            _ => false,
+        }
+    }
+
+    /// How long to wait before attempt `attempt + 1` after `error`, or
+    /// `None` to give up.
+    pub fn delay(&self, attempt: u32, error: &DeepLError) -> Option<Duration> {
+        let seed = clock::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let random = (splitmix64(seed ^ u64::from(attempt)) >> 11) as f64 / (1u64 << 53) as f64;
+        self.delay_with(attempt, error, random)
+    }
+
+    /// [`delay`](Self::delay) with `random` in `0.0..1.0` picking the
+    /// jitter.
+    fn delay_with(&self, attempt: u32, error: &DeepLError, random: f64) -> Option<Duration> {
+        if attempt >= self.max_attempts || !self.retries(error) {
+            return None;
+        }
+        let delay = match error {
+            DeepLError::RateLimited {
+                retry_after: Some(retry_after),
+            } => *retry_after,
+            _ => {
+                let backoff = self.base_delay.as_secs_f64() * 2f64.powi(attempt as i32 - 1);
+                let spread = self.jitter.clamp(0.0, 1.0) * (2.0 * random - 1.0);
+                Duration::from_secs_f64(
+                    (backoff * (1.0 + spread)).min(self.max_delay.as_secs_f64()),
+                )
+            }
+        };
+        (delay <= self.max_delay).then_some(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_stops() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter: 0.5,
+            ..RetryPolicy::default()
+        };
+        let unavailable = DeepLError::Status {
+            status: 503,
+            body: String::new(),
+        };
+        let delays: Vec<_> = (1..=4)
+            .map(|attempt| policy.delay_with(attempt, &unavailable, 0.5))
+            .collect();
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                None
+            ]
+        );
+        assert_eq!(
+            policy.delay_with(1, &unavailable, 0.0),
+            Some(Duration::from_millis(50))
+        );
+
+        let limited = |secs| DeepLError::RateLimited {
+            retry_after: Some(Duration::from_secs(secs)),
+        };
+        assert_eq!(
+            policy.delay_with(1, &limited(1), 0.5),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(policy.delay_with(1, &limited(60), 0.5), None);
+        // Without a Retry-After, rate limits back off like the rest.
+        let unsaid = DeepLError::RateLimited { retry_after: None };
+        assert_eq!(
+            policy.delay_with(2, &unsaid, 0.0),
+            Some(Duration::from_millis(100))
+        );
+        let bad_request = DeepLError::Status {
+            status: 400,
+            body: String::new(),
+        };
+        assert_eq!(policy.delay_with(1, &bad_request, 0.5), None);
+    }
+}
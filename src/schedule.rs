@@ -0,0 +1,462 @@
+//! Per-upstream-identity quiet hours and character budgets.
+//!
+//! A [`Calendar`] holds each identity's (a host, proxy or session)
+//! [`UpstreamPolicy`] and what it has used. Clients given one
+//! [`with_calendar`](crate::DeepLClient::with_calendar) refuse requests it
+//! does not admit; a [`Scheduler`] sharing it parks such work in a
+//! [`JobQueue`] instead, until the quiet window ends or the budget
+//! refreshes.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    budget::{Period, Usage},
+    clock,
+    error::DeepLError,
+    queue::{DeferredJob, JobQueue},
+    telemetry::{Telemetry, TelemetrySink},
+};
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// A daily window, in seconds since local midnight, during which no
+/// requests are sent. `start > end` wraps around midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl QuietHours {
+    pub fn new(start_hour: u32, start_minute: u32, end_hour: u32, end_minute: u32) -> Self {
+        Self {
+            start: (start_hour % 24) * 3600 + (start_minute % 60) * 60,
+            end: (end_hour % 24) * 3600 + (end_minute % 60) * 60,
+        }
+    }
+
+    fn contains(&self, secs_of_day: u32) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&secs_of_day)
+        } else {
+            secs_of_day >= self.start || secs_of_day < self.end
+        }
+    }
+
+    fn secs_until_end(&self, secs_of_day: u32) -> u32 {
+        if secs_of_day < self.end {
+            self.end - secs_of_day
+        } else {
+            SECS_PER_DAY as u32 - secs_of_day + self.end
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UpstreamPolicy {
+    pub quiet_hours: Vec<QuietHours>,
+    /// Characters the identity may send per `period`.
+    pub char_budget: Option<u64>,
+    pub period: Period,
+    /// Offset of the identity's local day from UTC, in seconds.
+    pub utc_offset: i32,
+}
+
+impl UpstreamPolicy {
+    fn local_secs(&self, at: SystemTime) -> i64 {
+        unix_secs(at) + self.utc_offset as i64
+    }
+
+    /// The budget window `at` falls in and when the next one starts.
+    fn window(&self, at: SystemTime) -> (i64, SystemTime) {
+        let (window, next) = self
+            .period
+            .window(unix_secs(at).max(0) as u64, self.utc_offset);
+        (window, SystemTime::UNIX_EPOCH + Duration::from_secs(next))
+    }
+
+    fn quiet_until(&self, at: SystemTime) -> Option<SystemTime> {
+        let secs_of_day = self.local_secs(at).rem_euclid(SECS_PER_DAY) as u32;
+        self.quiet_hours
+            .iter()
+            .filter(|q| q.contains(secs_of_day))
+            .map(|q| at + Duration::from_secs(q.secs_until_end(secs_of_day) as u64))
+            .max()
+    }
+}
+
+#[derive(Debug)]
+pub enum Admission<J> {
+    Run(J),
+    Deferred { until: SystemTime },
+}
+
+/// Why an identity cannot take a request yet.
+enum Wait {
+    Quiet {
+        until: SystemTime,
+    },
+    Budget {
+        used: u64,
+        limit: u64,
+        until: SystemTime,
+    },
+}
+
+#[derive(Debug, Default)]
+struct Book {
+    policies: HashMap<String, UpstreamPolicy>,
+    usage: HashMap<String, Usage>,
+}
+
+impl Book {
+    fn used(&self, identity: &str, at: SystemTime) -> u64 {
+        match (self.policies.get(identity), self.usage.get(identity)) {
+            (Some(policy), Some(usage)) => usage.in_window(policy.window(at).0),
+            _ => 0,
+        }
+    }
+
+    /// Whether `chars` characters may go through `identity` at `at`.
+    fn check(&self, identity: &str, chars: u64, at: SystemTime) -> Result<(), Wait> {
+        let Some(policy) = self.policies.get(identity) else {
+            return Ok(());
+        };
+        if let Some(until) = policy.quiet_until(at) {
+            return Err(Wait::Quiet { until });
+        }
+        if let Some(limit) = policy.char_budget {
+            let used = self.used(identity, at);
+            // A job larger than the whole budget still runs, alone, in a
+            // fresh period rather than waiting forever.
+            if used > 0 && used.saturating_add(chars) > limit {
+                let until = policy.window(at).1;
+                return Err(Wait::Budget { used, limit, until });
+            }
+        }
+        Ok(())
+    }
+
+    fn charge(&mut self, identity: &str, chars: u64, at: SystemTime) {
+        let Some(policy) = self.policies.get(identity) else {
+            return;
+        };
+        let window = policy.window(at).0;
+        self.usage
+            .entry(identity.to_string())
+            .or_default()
+            .add(window, chars);
+    }
+
+    fn release(&mut self, identity: &str, chars: u64, at: SystemTime) {
+        let (Some(policy), Some(usage)) =
+            (self.policies.get(identity), self.usage.get_mut(identity))
+        else {
+            return;
+        };
+        usage.remove(policy.window(at).0, chars);
+    }
+}
+
+/// The policies of upstream identities and what each has used, shared by
+/// clients and schedulers.
+#[derive(Debug, Default)]
+pub struct Calendar {
+    book: Mutex<Book>,
+}
+
+impl Calendar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn book(&self) -> std::sync::MutexGuard<'_, Book> {
+        self.book.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_policy(&self, identity: &str, policy: UpstreamPolicy) {
+        self.book().policies.insert(identity.to_string(), policy);
+    }
+
+    /// Characters already charged to `identity` in the budget period
+    /// containing `now`.
+    pub fn used(&self, identity: &str, now: SystemTime) -> u64 {
+        self.book().used(identity, now)
+    }
+
+    /// The earliest instant at or after `now` at which `chars` characters
+    /// may be sent through `identity`.
+    pub fn next_slot(&self, identity: &str, chars: u64, now: SystemTime) -> SystemTime {
+        let book = self.book();
+        let mut at = now;
+        // Quiet windows and period boundaries can only push `at` forward,
+        // so a handful of rounds is enough to settle on a free slot.
+        for _ in 0..8 {
+            match book.check(identity, chars, at) {
+                Ok(()) => break,
+                Err(Wait::Quiet { until } | Wait::Budget { until, .. }) => at = until,
+            }
+        }
+        at
+    }
+
+    /// Books `chars` characters for `identity` now if its policy allows
+    /// it. In quiet hours this fails with [`DeepLError::RateLimited`] until
+    /// they end, and past the budget with [`DeepLError::BudgetExceeded`].
+    pub fn admit(&self, identity: &str, chars: u64) -> Result<(), DeepLError> {
+        let now = clock::now();
+        let mut book = self.book();
+        let until_then = |until: SystemTime| until.duration_since(now).unwrap_or_default();
+        match book.check(identity, chars, now) {
+            Ok(()) => {
+                book.charge(identity, chars, now);
+                Ok(())
+            }
+            Err(Wait::Quiet { until }) => Err(DeepLError::RateLimited {
+                retry_after: Some(until_then(until)),
+            }),
+            Err(Wait::Budget { used, limit, until }) => Err(DeepLError::BudgetExceeded {
+                used,
+                limit,
+                resets_in: until_then(until),
+            }),
+        }
+    }
+
+    /// Gives back `chars` that [`admit`](Self::admit) booked for a
+    /// request that was not sent after all.
+    pub fn release(&self, identity: &str, chars: u64) {
+        self.book().release(identity, chars, clock::now());
+    }
+
+    /// Books `chars` if `identity` can take them at `now`, or says when it
+    /// can.
+    fn try_charge(&self, identity: &str, chars: u64, now: SystemTime) -> Result<(), SystemTime> {
+        let mut book = self.book();
+        if book.check(identity, chars, now).is_ok() {
+            book.charge(identity, chars, now);
+            return Ok(());
+        }
+        drop(book);
+        Err(self.next_slot(identity, chars, now))
+    }
+}
+
+#[derive(Debug)]
+pub struct Scheduler<J> {
+    calendar: Arc<Calendar>,
+    queue: JobQueue<J>,
+    telemetry: Telemetry,
+}
+
+impl<J> Default for Scheduler<J> {
+    fn default() -> Self {
+        Self {
+            calendar: Arc::new(Calendar::new()),
+            queue: JobQueue::new(),
+            telemetry: Telemetry::default(),
+        }
+    }
+}
+
+impl<J> Scheduler<J> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedules against `calendar`, so that jobs and the requests of
+    /// clients given the same calendar share each identity's budget.
+    pub fn with_calendar(mut self, calendar: Arc<Calendar>) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    /// Counts submitted jobs in `deeplx_jobs_total`, by `admission` `run`
+    /// or `deferred`, and reports the queue length as the
+    /// `deeplx_jobs_pending` gauge.
+    pub fn with_telemetry(mut self, sink: Arc<dyn TelemetrySink>) -> Self {
+        self.telemetry = Telemetry::new(sink);
+        self
+    }
+
+    pub fn calendar(&self) -> &Arc<Calendar> {
+        &self.calendar
+    }
+
+    fn report_pending(&self) {
+        self.telemetry
+            .gauge("deeplx_jobs_pending", self.queue.len() as f64, &[]);
+    }
+
+    pub fn set_policy(&mut self, identity: &str, policy: UpstreamPolicy) {
+        self.calendar.set_policy(identity, policy);
+    }
+
+    /// Characters already charged to `identity` in the budget period
+    /// containing `now`.
+    pub fn used(&self, identity: &str, now: SystemTime) -> u64 {
+        self.calendar.used(identity, now)
+    }
+
+    /// Runs `job` now if the identity's policy allows it, otherwise queues
+    /// it for the next free slot.
+    pub fn submit(&mut self, identity: &str, chars: u64, job: J, now: SystemTime) -> Admission<J> {
+        let ready_at = match self.calendar.try_charge(identity, chars, now) {
+            Ok(()) => {
+                self.telemetry
+                    .counter("deeplx_jobs_total", 1, &[("admission", "run")]);
+                return Admission::Run(job);
+            }
+            Err(ready_at) => ready_at,
+        };
+        self.queue.push(DeferredJob {
+            identity: identity.to_string(),
+            chars,
+            ready_at,
+            job,
+        });
+        self.telemetry
+            .counter("deeplx_jobs_total", 1, &[("admission", "deferred")]);
+        self.report_pending();
+        Admission::Deferred { until: ready_at }
+    }
+
+    /// Releases queued jobs whose slot has arrived, charging their budget.
+    /// Jobs that no longer fit (because earlier releases or clients used
+    /// the budget) are put back with a new slot.
+    pub fn poll(&mut self, now: SystemTime) -> Vec<DeferredJob<J>> {
+        let mut ready = Vec::new();
+        for deferred in self.queue.take_ready(now) {
+            match self
+                .calendar
+                .try_charge(&deferred.identity, deferred.chars, now)
+            {
+                Ok(()) => ready.push(deferred),
+                Err(slot) => self.queue.push(DeferredJob {
+                    ready_at: slot,
+                    ..deferred
+                }),
+            }
+        }
+        self.report_pending();
+        ready
+    }
+
+    pub fn queue(&self) -> &JobQueue<J> {
+        &self.queue
+    }
+}
+
+fn unix_secs(at: SystemTime) -> i64 {
+    at.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u64, hour: u64, minute: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(day * 86_400 + hour * 3600 + minute * 60)
+    }
+
+    #[test]
+    fn test_quiet_hours_defer_until_window_end() {
+        let mut scheduler = Scheduler::new();
+        scheduler.set_policy(
+            "proxy-a",
+            UpstreamPolicy {
+                quiet_hours: vec![QuietHours::new(22, 0, 6, 0)],
+                ..Default::default()
+            },
+        );
+        match scheduler.submit("proxy-a", 10, "job", at(3, 23, 30)) {
+            Admission::Deferred { until } => assert_eq!(until, at(4, 6, 0)),
+            Admission::Run(_) => panic!("job ran during quiet hours"),
+        }
+        assert!(scheduler.poll(at(4, 5, 59)).is_empty());
+        let released = scheduler.poll(at(4, 6, 0));
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].job, "job");
+    }
+
+    #[test]
+    fn test_daily_budget_refreshes_next_day() {
+        let mut scheduler = Scheduler::new();
+        scheduler.set_policy(
+            "session-1",
+            UpstreamPolicy {
+                char_budget: Some(100),
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            scheduler.submit("session-1", 80, 1, at(0, 12, 0)),
+            Admission::Run(1)
+        ));
+        match scheduler.submit("session-1", 30, 2, at(0, 13, 0)) {
+            Admission::Deferred { until } => assert_eq!(until, at(1, 0, 0)),
+            Admission::Run(_) => panic!("budget was not enforced"),
+        }
+        assert_eq!(scheduler.poll(at(1, 0, 0)).len(), 1);
+        assert_eq!(scheduler.used("session-1", at(1, 0, 1)), 30);
+    }
+
+    #[test]
+    fn test_unconfigured_identity_runs_immediately() {
+        let mut scheduler = Scheduler::new();
+        assert!(matches!(
+            scheduler.submit("unknown", u64::MAX, (), at(0, 3, 0)),
+            Admission::Run(())
+        ));
+    }
+
+    #[test]
+    fn test_clients_and_jobs_share_the_calendar() {
+        let calendar = Arc::new(Calendar::new());
+        let mut scheduler = Scheduler::new().with_calendar(calendar.clone());
+        scheduler.set_policy(
+            "session-1",
+            UpstreamPolicy {
+                char_budget: Some(100),
+                period: Period::Month,
+                ..Default::default()
+            },
+        );
+        let now = clock::now();
+        assert!(matches!(
+            scheduler.submit("session-1", 80, (), now),
+            Admission::Run(())
+        ));
+        assert!(matches!(
+            calendar.admit("session-1", 30),
+            Err(DeepLError::BudgetExceeded {
+                used: 80,
+                limit: 100,
+                ..
+            })
+        ));
+        calendar.admit("session-1", 20).unwrap();
+        assert_eq!(calendar.used("session-1", now), 100);
+
+        // Two windows covering the whole day.
+        calendar.set_policy(
+            "proxy-a",
+            UpstreamPolicy {
+                quiet_hours: vec![QuietHours::new(0, 0, 12, 0), QuietHours::new(12, 0, 0, 0)],
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            calendar.admit("proxy-a", 1),
+            Err(DeepLError::RateLimited {
+                retry_after: Some(_)
+            })
+        ));
+    }
+}
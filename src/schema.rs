@@ -0,0 +1,186 @@
+//! Noticing when DeepL changes the shape of its responses.
+//!
+//! Every response body is compared with the fields [`DeepLResponse`]
+//! expects. Unknown fields are harmless to decoding but are usually the
+//! first visible sign of an API change; missing ones break it. Each
+//! distinct difference is reported once, while a counter keeps track of
+//! how many responses drifted in total.
+//!
+//! [`DeepLResponse`]: crate::DeepLResponse
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use serde_json::Value;
+
+enum Shape {
+    Leaf,
+    /// An object with arbitrary keys, such as `detectedLanguages`.
+    Map,
+    /// Known fields and whether each one is required.
+    Object(&'static [(&'static str, bool, Shape)]),
+    Array(&'static Shape),
+}
+
+const ALTERNATIVE: Shape = Shape::Object(&[("text", true, Shape::Leaf)]);
+
+const TEXT: Shape = Shape::Object(&[
+    ("alternatives", true, Shape::Array(&ALTERNATIVE)),
+    ("text", true, Shape::Leaf),
+]);
+
+const RESPONSE: Shape = Shape::Object(&[
+    ("jsonrpc", true, Shape::Leaf),
+    ("id", true, Shape::Leaf),
+    (
+        "result",
+        true,
+        Shape::Object(&[
+            ("texts", true, Shape::Array(&TEXT)),
+            ("lang", true, Shape::Leaf),
+            ("lang_is_confident", true, Shape::Leaf),
+            ("detectedLanguages", true, Shape::Map),
+        ]),
+    ),
+]);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Drift {
+    /// Paths like `result.texts[].score` that the response has but we
+    /// don't know about.
+    pub unknown: Vec<String>,
+    /// Paths we require but the response lacks.
+    pub missing: Vec<String>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.unknown.is_empty() && self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown fields [{}], missing fields [{}]",
+            self.unknown.join(", "),
+            self.missing.join(", ")
+        )
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn walk(
+    value: &Value,
+    shape: &Shape,
+    path: &str,
+    unknown: &mut BTreeSet<String>,
+    missing: &mut BTreeSet<String>,
+) {
+    match (shape, value) {
+        (Shape::Object(fields), Value::Object(map)) => {
+            for (key, required, child) in fields.iter() {
+                match map.get(*key) {
+                    Some(v) => walk(v, child, &join(path, key), unknown, missing),
+                    None if *required => {
+                        missing.insert(join(path, key));
+                    }
+                    None => {}
+                }
+            }
+            for key in map.keys() {
+                if !fields.iter().any(|(k, _, _)| k == key) {
+                    unknown.insert(join(path, key));
+                }
+            }
+        }
+        (Shape::Array(item), Value::Array(items)) => {
+            let path = format!("{}[]", path);
+            for v in items {
+                walk(v, item, &path, unknown, missing);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Compares a response body with the expected schema.
+pub fn response_drift(value: &Value) -> Drift {
+    let (mut unknown, mut missing) = (BTreeSet::new(), BTreeSet::new());
+    walk(value, &RESPONSE, "", &mut unknown, &mut missing);
+    Drift {
+        unknown: unknown.into_iter().collect(),
+        missing: missing.into_iter().collect(),
+    }
+}
+
+/// Remembers which drifts were already reported.
+#[derive(Debug, Default)]
+pub struct SchemaWatch {
+    seen: Mutex<HashSet<Drift>>,
+    drifted: AtomicU64,
+}
+
+impl SchemaWatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the drift of `value` the first time that exact drift is
+    /// seen, and `None` for conforming or already reported responses.
+    pub fn observe(&self, value: &Value) -> Option<Drift> {
+        let drift = response_drift(value);
+        if drift.is_empty() {
+            return None;
+        }
+        self.drifted.fetch_add(1, Ordering::Relaxed);
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        seen.insert(drift.clone()).then_some(drift)
+    }
+
+    /// Responses that did not match the schema so far.
+    pub fn drifted(&self) -> u64 {
+        self.drifted.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_drift_is_reported_once() {
+        let watch = SchemaWatch::new();
+        let ok = json!({"jsonrpc": "2.0", "id": 1, "result": {
+            "texts": [{"text": "Hallo", "alternatives": [{"text": "Hi"}]}],
+            "lang": "EN", "lang_is_confident": true, "detectedLanguages": {"EN": 0.9, "DE": 0.1}
+        }});
+        assert_eq!(watch.observe(&ok), None);
+
+        let drifted = json!({"jsonrpc": "2.0", "id": 1, "result": {
+            "texts": [{"text": "Hallo", "score": 0.7}],
+            "lang": "EN", "lang_is_confident": true, "detectedLanguages": {}
+        }});
+        let drift = watch.observe(&drifted).unwrap();
+        assert_eq!(drift.unknown, ["result.texts[].score"]);
+        assert_eq!(drift.missing, ["result.texts[].alternatives"]);
+        assert_eq!(watch.observe(&drifted), None);
+        assert_eq!(watch.drifted(), 2);
+    }
+}
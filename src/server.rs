@@ -0,0 +1,1014 @@
+//! A DeepLX-compatible HTTP service.
+//!
+//! `POST /translate` takes `{"text", "source_lang", "target_lang"}`, and
+//! optionally `"formality": "formal"` or `"informal"`, a `"tag_handling"`
+//! [`TagHandling`](crate::options::TagHandling), a `"casing"`
+//! [`Casing`](crate::options::Casing) and a `"splitting"`
+//! [`Splitting`](crate::options::Splitting), and answers in the shape other DeepLX implementations use, so clients such
+//! as Bob or Immersive Translate can point at it unchanged. With a token
+//! set, requests must carry it as `Authorization: Bearer <token>` or
+//! `?token=<token>`. Translations go through the server's
+//! [`Filters`] before they are returned, and with a [`Signer`] every
+//! response carries its signature in [`SIGNATURE_HEADER`].
+//!
+//! Given a window, repeated `POST /translate` requests carrying the same
+//! `Idempotency-Key` are answered with the first result instead of being
+//! translated again.
+//!
+//! Given a client for it, `POST /v1/translate` is DeepLX's route for Pro
+//! accounts: it takes the same body and sends each request with the
+//! `dl_session` cookie the request carries, or else the next token of the
+//! client's [`SessionPool`](crate::session::SessionPool).
+//!
+//! `POST /validate` takes the same body and answers with the
+//! [`Preflight`](preflight::Preflight) report instead of a translation.
+//!
+//! With an admin token, `GET /admin/mode` reports the gateway's
+//! [`Mode`] and `PUT /admin/mode` with `{"mode": "maintenance"}` changes
+//! it; see [`maintenance`](crate::maintenance).
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use hyper::{
+    body::HttpBody,
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE, COOKIE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    client::DeepLClient,
+    diag,
+    error::DeepLError,
+    filter::Filters,
+    idempotency::{Claim, IdempotencyCache, IDEMPOTENCY_HEADER, MAX_KEY_LEN},
+    maintenance::{Mode, Switch},
+    options::TranslateOptions,
+    preflight,
+    session::SessionPool,
+    signing::{Signer, SIGNATURE_HEADER},
+    telemetry::{PrometheusSink, Telemetry, TelemetrySink},
+    translator::Translator,
+};
+
+pub use crate::preflight::TranslateRequest;
+
+/// Marks a response replayed for a repeated idempotency key.
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Larger request bodies are refused with 413.
+const MAX_BODY: u64 = 1 << 20;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TranslateResponse {
+    pub code: u16,
+    pub id: i64,
+    pub data: String,
+    pub alternatives: Vec<String>,
+    pub source_lang: String,
+    pub target_lang: String,
+    pub method: String,
+    /// Why the server's filters distrust this translation.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quality_flags: Vec<String>,
+}
+
+pub struct Server {
+    translator: Arc<dyn Translator>,
+    token: Option<String>,
+    filters: Filters,
+    compat: Compat,
+    signer: Option<Signer>,
+    priority: Option<(String, Arc<dyn Translator>)>,
+    idempotency: Option<IdempotencyCache<TranslateRequest, Value>>,
+    telemetry: Telemetry,
+    metrics: Option<Arc<PrometheusSink>>,
+    admin: Option<(String, Switch)>,
+    pro: Option<DeepLClient>,
+}
+
+/// How closely responses follow the Go DeepLX server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compat {
+    /// DeepLX's fields plus `quality_flags`, and error messages saying
+    /// what went wrong.
+    #[default]
+    Extended,
+    /// Byte for byte what the Go server writes: keys in alphabetical
+    /// order, `null` for no alternatives, HTML characters escaped, and its
+    /// fixed error codes and messages.
+    Strict,
+}
+
+impl Server {
+    pub fn new(translator: Arc<dyn Translator>) -> Self {
+        Server {
+            translator,
+            token: None,
+            filters: Filters::new(),
+            compat: Compat::default(),
+            signer: None,
+            priority: None,
+            idempotency: None,
+            telemetry: Telemetry::default(),
+            metrics: None,
+            admin: None,
+            pro: None,
+        }
+    }
+
+    /// Reports every request to `sink`, in `deeplx_http_requests_total`
+    /// by `path` and `status` and in `deeplx_http_request_seconds` by
+    /// `path`.
+    pub fn with_telemetry(mut self, sink: Arc<dyn TelemetrySink>) -> Self {
+        self.telemetry = Telemetry::new(sink);
+        self
+    }
+
+    /// Reports to `sink` like [`with_telemetry`](Self::with_telemetry)
+    /// and serves what it collected, from this server and anything else
+    /// sharing it, on `GET /metrics` without asking for the token.
+    pub fn with_metrics(mut self, sink: Arc<PrometheusSink>) -> Self {
+        self.telemetry = Telemetry::new(sink.clone());
+        self.metrics = Some(sink);
+        self
+    }
+
+    /// Answers `POST /translate` requests repeating the
+    /// [`IDEMPOTENCY_HEADER`] of a successful one within `window` with
+    /// its result, marked by `idempotent-replayed: true`, without
+    /// translating again. Reusing a key for another request is refused
+    /// with 422, and while the first is still running with 409.
+    pub fn with_idempotency(mut self, window: Duration) -> Self {
+        self.idempotency = Some(IdempotencyCache::new(window));
+        self
+    }
+
+    /// Signs every response body with `signer`.
+    pub fn with_signer(mut self, signer: Signer) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    pub fn with_compat(mut self, compat: Compat) -> Self {
+        self.compat = compat;
+        self
+    }
+
+    /// Checks every translation with `filters` before returning it.
+    pub fn with_filters(mut self, filters: Filters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Only serves requests carrying `token`.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into()).filter(|t| !t.is_empty());
+        self
+    }
+
+    /// Serves requests carrying `token` with `translator` instead, such as
+    /// a client [`with_priority`](crate::DeepLClient::with_priority) that
+    /// keeps working past the character budget.
+    pub fn with_priority(
+        mut self,
+        token: impl Into<String>,
+        translator: Arc<dyn Translator>,
+    ) -> Self {
+        self.priority = Some((token.into(), translator)).filter(|(t, _)| !t.is_empty());
+        self
+    }
+
+    /// Serves `GET` and `PUT /admin/mode` to requests carrying `token`
+    /// as `Authorization: Bearer <token>`, reporting and flipping
+    /// `switch`. Clients given the same switch follow at once.
+    pub fn with_admin(mut self, token: impl Into<String>, switch: Switch) -> Self {
+        self.admin = Some((token.into(), switch)).filter(|(t, _)| !t.is_empty());
+        self
+    }
+
+    /// Serves `POST /v1/translate` with `client`. A request carrying a
+    /// `dl_session` cookie is sent with that token alone, through a pool
+    /// of its own; the others go through the client's session pool, and
+    /// are refused with 401 if it has none.
+    pub fn with_pro(mut self, client: DeepLClient) -> Self {
+        self.pro = Some(client);
+        self
+    }
+
+    /// The client for a `POST /v1/translate` request, or why there is
+    /// none.
+    fn pro_for(&self, req: &Request<Body>) -> Result<Arc<dyn Translator>, Failure> {
+        let Some(client) = &self.pro else {
+            return Err(Failure::NotFound);
+        };
+        if self.translator_for(req).is_none() {
+            return Err(Failure::Unauthorized);
+        }
+        let cookie = req
+            .headers()
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(';'))
+            .find_map(|pair| pair.trim().strip_prefix("dl_session="))
+            .filter(|token| !token.is_empty());
+        match cookie {
+            Some(token) => {
+                Ok(Arc::new(client.clone().with_session_pool(Arc::new(
+                    SessionPool::new([token.to_string()]),
+                ))))
+            }
+            None if client.session_pool().is_some() => Ok(Arc::new(client.clone())),
+            None => Err(Failure::NoSession),
+        }
+    }
+
+    /// The translator for `req`, or `None` if it lacks a valid token.
+    fn translator_for(&self, req: &Request<Body>) -> Option<Arc<dyn Translator>> {
+        let bearer = bearer(req);
+        let query = req
+            .uri()
+            .query()
+            .and_then(|q| q.split('&').find_map(|pair| pair.strip_prefix("token=")));
+        let presented = bearer.or(query);
+        match (&self.priority, &self.token) {
+            (Some((token, translator)), _) if presented == Some(token.as_str()) => {
+                Some(translator.clone())
+            }
+            (_, Some(token)) if presented != Some(token.as_str()) => None,
+            _ => Some(self.translator.clone()),
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        let path = match req.uri().path() {
+            "/" => "/",
+            "/translate" => "/translate",
+            "/v1/translate" => "/v1/translate",
+            "/validate" => "/validate",
+            "/metrics" => "/metrics",
+            "/admin/mode" => "/admin/mode",
+            _ => "other",
+        };
+        let started = Instant::now();
+        let response = self.route(req).await;
+        self.telemetry.counter(
+            "deeplx_http_requests_total",
+            1,
+            &[("path", path), ("status", response.status().as_str())],
+        );
+        self.telemetry.histogram(
+            "deeplx_http_request_seconds",
+            started.elapsed().as_secs_f64(),
+            &[("path", path)],
+        );
+        response
+    }
+
+    async fn route(&self, req: Request<Body>) -> Response<Body> {
+        match (req.method(), req.uri().path()) {
+            (&Method::GET, "/") => {
+                let message = match self.compat {
+                    Compat::Extended => "deeplx-rs",
+                    Compat::Strict => GO_BANNER,
+                };
+                self.reply(StatusCode::OK, json!({"code": 200, "message": message}))
+            }
+            (&Method::POST, "/translate") => {
+                let key = match req.headers().get(IDEMPOTENCY_HEADER).map(|v| v.to_str()) {
+                    Some(Ok(key)) if !key.is_empty() && key.len() <= MAX_KEY_LEN => {
+                        Some(key.to_string())
+                    }
+                    Some(_) => {
+                        return self.fail(Failure::BadRequest(format!(
+                            "invalid {} header: at most {} visible ASCII characters",
+                            IDEMPOTENCY_HEADER, MAX_KEY_LEN
+                        )))
+                    }
+                    None => None,
+                };
+                match self.read_request(req).await {
+                    Ok((translator, request)) if !request.text.is_empty() => {
+                        self.translate_once(key, &*translator, request).await
+                    }
+                    Ok(_) => self.fail(Failure::EmptyText),
+                    Err(failure) => self.fail(failure),
+                }
+            }
+            (&Method::POST, "/v1/translate") => {
+                let translator = match self.pro_for(&req) {
+                    Ok(translator) => translator,
+                    Err(failure) => return self.fail(failure),
+                };
+                match read_json::<TranslateRequest>(req).await {
+                    Ok(request) if !request.text.is_empty() => {
+                        match self.translate(&*translator, request).await {
+                            Ok(mut body) => {
+                                body["method"] = json!("Pro");
+                                self.reply(StatusCode::OK, body)
+                            }
+                            Err(failure) => self.fail(failure),
+                        }
+                    }
+                    Ok(_) => self.fail(Failure::EmptyText),
+                    Err(failure) => self.fail(failure),
+                }
+            }
+            (&Method::POST, "/validate") => match self.read_request(req).await {
+                Ok((translator, request)) => {
+                    let preflight = preflight::check(&request, &translator.capabilities());
+                    let mut body = json!(preflight);
+                    body["code"] = json!(200);
+                    self.reply(StatusCode::OK, body)
+                }
+                Err(failure) => self.fail(failure),
+            },
+            (&Method::GET, "/metrics") if self.metrics.is_some() => {
+                let text = self
+                    .metrics
+                    .as_ref()
+                    .map(|m| m.render())
+                    .unwrap_or_default();
+                let mut response = Response::new(Body::from(text));
+                response.headers_mut().insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; version=0.0.4"),
+                );
+                response
+            }
+            (&Method::GET | &Method::PUT, "/admin/mode") if self.admin.is_some() => {
+                self.admin(req).await
+            }
+            (_, "/" | "/translate" | "/validate") => self.fail(Failure::MethodNotAllowed),
+            (_, "/admin/mode") if self.admin.is_some() => self.fail(Failure::MethodNotAllowed),
+            (_, "/v1/translate") if self.pro.is_some() => self.fail(Failure::MethodNotAllowed),
+            _ => self.fail(Failure::NotFound),
+        }
+    }
+
+    async fn read_request(
+        &self,
+        req: Request<Body>,
+    ) -> Result<(Arc<dyn Translator>, TranslateRequest), Failure> {
+        let Some(translator) = self.translator_for(&req) else {
+            return Err(Failure::Unauthorized);
+        };
+        Ok((translator, read_json(req).await?))
+    }
+
+    async fn admin(&self, req: Request<Body>) -> Response<Body> {
+        #[derive(Deserialize)]
+        struct SetMode {
+            mode: Mode,
+        }
+
+        let Some((token, switch)) = &self.admin else {
+            return self.fail(Failure::NotFound);
+        };
+        if bearer(&req) != Some(token.as_str()) {
+            return self.fail(Failure::Unauthorized);
+        }
+        if req.method() == Method::PUT {
+            match read_json::<SetMode>(req).await {
+                Ok(SetMode { mode }) => {
+                    switch.set(mode);
+                    diag::log_warn!("gateway switched to {} mode", mode);
+                }
+                Err(failure) => return self.fail(failure),
+            }
+        }
+        self.reply(StatusCode::OK, json!({"code": 200, "mode": switch.mode()}))
+    }
+
+    async fn translate(
+        &self,
+        translator: &dyn Translator,
+        request: TranslateRequest,
+    ) -> Result<Value, Failure> {
+        let options = TranslateOptions {
+            formality: request.formality,
+            tag_handling: request.tag_handling.clone(),
+            casing: request.casing,
+            splitting: request.splitting,
+            ..Default::default()
+        };
+        let result = translator
+            .translate_with_options(
+                &request.text,
+                &request.source_lang,
+                &request.target_lang,
+                &options,
+            )
+            .await;
+        let translation = result.and_then(|mut translation| {
+            self.filters.apply(&request.text, &mut translation)?;
+            Ok(translation)
+        });
+        let translation = match translation {
+            Ok(translation) => translation,
+            Err(e) => return Err(Failure::Translate(e)),
+        };
+        let quality_flags = translation
+            .extension("quality_flags")
+            .and_then(Value::as_array)
+            .map(|flags| {
+                flags
+                    .iter()
+                    .filter_map(|f| f.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let id = translation
+            .extension("id")
+            .and_then(Value::as_i64)
+            .unwrap_or_default();
+        let body = TranslateResponse {
+            code: 200,
+            id,
+            source_lang: translation
+                .detected_source
+                .unwrap_or(request.source_lang)
+                .to_uppercase(),
+            target_lang: request.target_lang.to_uppercase(),
+            data: translation.text,
+            alternatives: translation.alternatives,
+            method: "Free".to_string(),
+            quality_flags,
+        };
+        let body = match self.compat {
+            Compat::Extended => json!(body),
+            // Go writes map keys sorted and a nil slice as null.
+            Compat::Strict => json!({
+                "alternatives": Some(body.alternatives).filter(|a| !a.is_empty()),
+                "code": body.code,
+                "data": body.data,
+                "id": body.id,
+                "method": body.method,
+                "source_lang": body.source_lang,
+                "target_lang": body.target_lang,
+            }),
+        };
+        Ok(body)
+    }
+
+    /// [`translate`](Self::translate) once per idempotency key: repeated
+    /// submissions within the window get the first result back.
+    async fn translate_once(
+        &self,
+        key: Option<String>,
+        translator: &dyn Translator,
+        request: TranslateRequest,
+    ) -> Response<Body> {
+        let (Some(cache), Some(key)) = (&self.idempotency, key) else {
+            return match self.translate(translator, request).await {
+                Ok(body) => self.reply(StatusCode::OK, body),
+                Err(failure) => self.fail(failure),
+            };
+        };
+        match cache.claim(&key, request.clone()) {
+            Claim::New(pending) => match self.translate(translator, request).await {
+                Ok(body) => {
+                    pending.complete(body.clone());
+                    self.reply(StatusCode::OK, body)
+                }
+                Err(failure) => self.fail(failure),
+            },
+            Claim::Replay(body) => {
+                let mut response = self.reply(StatusCode::OK, body);
+                response
+                    .headers_mut()
+                    .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+                response
+            }
+            Claim::InProgress => self.fail(Failure::InProgress),
+            Claim::Mismatch => self.fail(Failure::KeyReused),
+        }
+    }
+
+    /// Serves requests on `addr` until the future is dropped.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), hyper::Error> {
+        self.serve_with_shutdown(addr, std::future::pending()).await
+    }
+
+    /// Serves requests on `addr` until `signal` completes, then stops
+    /// accepting connections and returns once in-flight requests finish.
+    pub async fn serve_with_shutdown(
+        self,
+        addr: SocketAddr,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), hyper::Error> {
+        let server = Arc::new(self);
+        let make = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.handle(req).await) }
+                }))
+            }
+        });
+        hyper::Server::try_bind(&addr)?
+            .serve(make)
+            .with_graceful_shutdown(signal)
+            .await
+    }
+
+    fn fail(&self, failure: Failure) -> Response<Body> {
+        let (status, message) = match self.compat {
+            Compat::Extended => failure.extended(),
+            Compat::Strict => failure.strict(),
+        };
+        self.reply(status, json!({"code": status.as_u16(), "message": message}))
+    }
+
+    fn reply(&self, status: StatusCode, body: Value) -> Response<Body> {
+        let signature = self.signer.as_ref().map(|s| s.sign(&body));
+        let (body, content_type) = match self.compat {
+            Compat::Extended => (body.to_string(), "application/json"),
+            Compat::Strict => (
+                go_escape(&body.to_string()),
+                "application/json; charset=utf-8",
+            ),
+        };
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = status;
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        if let Some(signature) = signature.and_then(|s| HeaderValue::from_str(&s).ok()) {
+            response.headers_mut().insert(SIGNATURE_HEADER, signature);
+        }
+        response
+    }
+}
+
+fn bearer(req: &Request<Body>) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// The JSON body of `req`, up to [`MAX_BODY`].
+async fn read_json<T: DeserializeOwned>(req: Request<Body>) -> Result<T, Failure> {
+    if req.body().size_hint().lower() > MAX_BODY {
+        return Err(Failure::TooLarge);
+    }
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) if body.len() as u64 <= MAX_BODY => body,
+        Ok(_) => return Err(Failure::TooLarge),
+        Err(e) => return Err(Failure::BadRequest(e.to_string())),
+    };
+    serde_json::from_slice(&body)
+        .map_err(|e| Failure::BadRequest(format!("invalid request: {}", e)))
+}
+
+/// What the Go server answers on `GET /`.
+const GO_BANNER: &str = "DeepL Free API, Developed by sjlleo and missuo. Go to /translate with POST. http://github.com/OwO-Network/DeepLX";
+
+enum Failure {
+    Unauthorized,
+    NoSession,
+    NotFound,
+    MethodNotAllowed,
+    EmptyText,
+    TooLarge,
+    BadRequest(String),
+    InProgress,
+    KeyReused,
+    Translate(DeepLError),
+}
+
+impl Failure {
+    fn extended(self) -> (StatusCode, String) {
+        let (status, message) = match self {
+            Failure::Unauthorized => (StatusCode::UNAUTHORIZED, "invalid access token"),
+            Failure::NoSession => (StatusCode::UNAUTHORIZED, "no dl_session found"),
+            Failure::NotFound => (StatusCode::NOT_FOUND, "not found"),
+            Failure::MethodNotAllowed => (StatusCode::METHOD_NOT_ALLOWED, "method not allowed"),
+            Failure::EmptyText => (StatusCode::BAD_REQUEST, "text is empty"),
+            Failure::TooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "request body too large"),
+            Failure::BadRequest(message) => return (StatusCode::BAD_REQUEST, message),
+            Failure::InProgress => (
+                StatusCode::CONFLICT,
+                "a request with this idempotency key is in progress",
+            ),
+            Failure::KeyReused => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "idempotency key was used for a different request",
+            ),
+            Failure::Translate(e) => {
+                let status = match &e {
+                    DeepLError::InvalidLanguage { .. } => StatusCode::BAD_REQUEST,
+                    DeepLError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+                    DeepLError::Rejected { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+                    DeepLError::BudgetExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+                    e if e.is_unavailable() => StatusCode::SERVICE_UNAVAILABLE,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                return (status, e.to_string());
+            }
+        };
+        (status, message.to_string())
+    }
+
+    /// The codes and messages of the Go implementation, which answers
+    /// unknown methods like unknown paths and every failed translation
+    /// other than a rate limit with 500.
+    fn strict(self) -> (StatusCode, String) {
+        let (status, message) = match self {
+            // Go has no idempotency keys; say what it would say if it had.
+            failure @ (Failure::InProgress | Failure::KeyReused) => return failure.extended(),
+            Failure::Unauthorized => (StatusCode::UNAUTHORIZED, "Invalid access token"),
+            Failure::NoSession => (StatusCode::UNAUTHORIZED, "No dl_session Found"),
+            Failure::NotFound | Failure::MethodNotAllowed => {
+                (StatusCode::NOT_FOUND, "Path not found")
+            }
+            Failure::EmptyText => (StatusCode::NOT_FOUND, "No text to translate"),
+            Failure::TooLarge | Failure::BadRequest(_) => {
+                (StatusCode::BAD_REQUEST, "Invalid request payload")
+            }
+            Failure::Translate(DeepLError::RateLimited { .. }) => {
+                (StatusCode::TOO_MANY_REQUESTS, "Too Many Requests")
+            }
+            Failure::Translate(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
+        };
+        (status, message.to_string())
+    }
+}
+
+/// Escapes JSON the way Go's `encoding/json` does: `<`, `>` and `&` and
+/// the JavaScript line separators as `\u` sequences. These only occur
+/// inside strings, so the whole document can be rewritten.
+fn go_escape(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    for c in json.chars() {
+        match c {
+            '<' => out.push_str("\\u003c"),
+            '>' => out.push_str("\\u003e"),
+            '&' => out.push_str("\\u0026"),
+            '\u{2028}' => out.push_str("\\u2028"),
+            '\u{2029}' => out.push_str("\\u2029"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        filter::{Action, Placeholders},
+        translator::{BoxFuture, Translation},
+    };
+
+    struct Upper;
+
+    impl Translator for Upper {
+        fn name(&self) -> &str {
+            "upper"
+        }
+
+        fn translate<'a>(
+            &'a self,
+            text: &'a str,
+            _src_lang: &'a str,
+            target_lang: &'a str,
+        ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+            Box::pin(async move {
+                if target_lang == "XX" {
+                    return Err(DeepLError::InvalidLanguage {
+                        lang: target_lang.to_string(),
+                    });
+                }
+                Ok(Translation {
+                    text: text.to_uppercase(),
+                    detected_source: Some("EN".to_string()),
+                    alternatives: Vec::new(),
+                    meta: Default::default(),
+                })
+            })
+        }
+    }
+
+    fn call(server: &Server, req: Request<Body>) -> (u16, Value) {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let response = server.handle(req).await;
+                let status = response.status().as_u16();
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                (status, serde_json::from_slice(&body).unwrap())
+            })
+    }
+
+    fn post(uri: &str, body: &str) -> Request<Body> {
+        Request::post(uri)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_translate_answers_like_deeplx() {
+        let server = Server::new(Arc::new(Upper)).with_token("secret");
+        let body = r#"{"text": "hello", "target_lang": "zh"}"#;
+        let (status, json) = call(&server, post("/translate?token=secret", body));
+        assert_eq!(status, 200);
+        assert_eq!(
+            (
+                &json["code"],
+                &json["data"],
+                &json["source_lang"],
+                &json["target_lang"]
+            ),
+            (&200.into(), &"HELLO".into(), &"EN".into(), &"ZH".into())
+        );
+
+        let formal = r#"{"text": "hello", "target_lang": "DE", "formality": "less"}"#;
+        assert_eq!(
+            call(&server, post("/translate?token=secret", formal)).0,
+            200
+        );
+
+        let mut req = post("/translate", body);
+        req.headers_mut()
+            .insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert_eq!(call(&server, req).0, 200);
+        let (status, json) = call(&server, post("/translate?token=wrong", body));
+        assert_eq!((status, &json["code"]), (401, &401.into()));
+    }
+
+    #[test]
+    fn test_validate_checks_without_translating() {
+        let server = Server::new(Arc::new(Upper));
+        let body = r#"{"text": "(hello {n}", "target_lang": "auto"}"#;
+        let (status, json) = call(&server, post("/validate", body));
+        assert_eq!((status, &json["valid"]), (200, &false.into()));
+        assert_eq!(json["placeholders"], serde_json::json!(["{n}"]));
+        let kinds: Vec<_> = json["problems"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, ["unsupported_target", "unbalanced_brackets"]);
+
+        let body = r#"{"text": "hello", "target_lang": "DE"}"#;
+        let (_, json) = call(&server, post("/validate", body));
+        assert_eq!((&json["valid"], &json["chunks"]), (&true.into(), &1.into()));
+        assert!(json.get("data").is_none());
+    }
+
+    #[test]
+    fn test_metrics_count_requests() {
+        let metrics = Arc::new(PrometheusSink::new());
+        let server = Server::new(Arc::new(Upper)).with_metrics(metrics.clone());
+        let body = r#"{"text": "hello", "target_lang": "DE"}"#;
+        call(&server, post("/translate", body));
+        call(&server, post("/nowhere", body));
+        let text = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let response = server
+                    .handle(Request::get("/metrics").body(Body::empty()).unwrap())
+                    .await;
+                hyper::body::to_bytes(response.into_body()).await.unwrap()
+            });
+        let text = String::from_utf8(text.to_vec()).unwrap();
+        assert!(text.contains("deeplx_http_requests_total{path=\"/translate\",status=\"200\"} 1\n"));
+        assert!(text.contains("deeplx_http_requests_total{path=\"other\",status=\"404\"} 1\n"));
+        assert!(text.contains("deeplx_http_request_seconds_count{path=\"/translate\"} 1\n"));
+    }
+
+    #[test]
+    fn test_idempotency_keys_replay_results() {
+        struct Counting(AtomicUsize);
+
+        impl Translator for Counting {
+            fn name(&self) -> &str {
+                "counting"
+            }
+
+            fn translate<'a>(
+                &'a self,
+                text: &'a str,
+                src_lang: &'a str,
+                target_lang: &'a str,
+            ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Upper.translate(text, src_lang, target_lang)
+            }
+        }
+
+        let counting = Arc::new(Counting(AtomicUsize::new(0)));
+        let server = Server::new(counting.clone()).with_idempotency(Duration::from_secs(60));
+        let keyed = |body: &str| {
+            let mut req = post("/translate", body);
+            req.headers_mut()
+                .insert(IDEMPOTENCY_HEADER, "job-1".parse().unwrap());
+            req
+        };
+        let body = r#"{"text": "hello", "target_lang": "DE"}"#;
+        let (status, first) = call(&server, keyed(body));
+        assert_eq!((status, &first["data"]), (200, &"HELLO".into()));
+        let replayed = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(server.handle(keyed(body)));
+        assert_eq!(replayed.headers()[REPLAYED_HEADER], "true");
+        assert_eq!(counting.0.load(Ordering::SeqCst), 1);
+
+        let (status, _) = call(&server, keyed(r#"{"text": "bye", "target_lang": "DE"}"#));
+        assert_eq!(status, 422);
+        call(&server, post("/translate", body));
+        assert_eq!(counting.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_priority_token_bypasses_spent_budget() {
+        struct Spent;
+
+        impl Translator for Spent {
+            fn name(&self) -> &str {
+                "spent"
+            }
+
+            fn translate<'a>(
+                &'a self,
+                _text: &'a str,
+                _src_lang: &'a str,
+                _target_lang: &'a str,
+            ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+                Box::pin(async {
+                    Err(DeepLError::BudgetExceeded {
+                        used: 100,
+                        limit: 100,
+                        resets_in: Duration::from_secs(60),
+                    })
+                })
+            }
+        }
+
+        let server = Server::new(Arc::new(Spent))
+            .with_token("secret")
+            .with_priority("vip", Arc::new(Upper));
+        let body = r#"{"text": "hello", "target_lang": "DE"}"#;
+        assert_eq!(call(&server, post("/translate?token=secret", body)).0, 429);
+        let (status, json) = call(&server, post("/translate?token=vip", body));
+        assert_eq!((status, &json["data"]), (200, &"HELLO".into()));
+        assert_eq!(call(&server, post("/translate?token=other", body)).0, 401);
+    }
+
+    #[test]
+    fn test_responses_are_signed() {
+        let signer = Signer::new("key");
+        let server = Server::new(Arc::new(Upper)).with_signer(signer.clone());
+        let req = post("/translate", r#"{"text": "<hi>", "target_lang": "DE"}"#);
+        let (header, body) = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let response = server.handle(req).await;
+                let header = response.headers()[SIGNATURE_HEADER]
+                    .to_str()
+                    .unwrap()
+                    .to_string();
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                (header, serde_json::from_slice::<Value>(&body).unwrap())
+            });
+        assert_eq!(signer.verify(&header, &body, None), Ok(()));
+    }
+
+    #[test]
+    fn test_bad_requests_get_json_errors() {
+        let server = Server::new(Arc::new(Upper))
+            .with_filters(Filters::new().with(Placeholders, Action::Reject));
+        let cases = [
+            (post("/translate", "{"), 400),
+            (
+                post("/translate", r#"{"text": "", "target_lang": "ZH"}"#),
+                400,
+            ),
+            (
+                post("/translate", r#"{"text": "hi", "target_lang": "XX"}"#),
+                400,
+            ),
+            (
+                post(
+                    "/translate",
+                    r#"{"text": "{n} files", "target_lang": "DE"}"#,
+                ),
+                422,
+            ),
+            (Request::get("/translate").body(Body::empty()).unwrap(), 405),
+            (post("/v2/translate", "{}"), 404),
+            (post("/v1/translate", "{}"), 404),
+        ];
+        for (req, expected) in cases {
+            let (status, json) = call(&server, req);
+            assert_eq!((status, &json["code"]), (expected, &expected.into()));
+            assert!(json["message"].is_string());
+        }
+    }
+
+    #[test]
+    fn test_pro_route_sends_the_request_session() {
+        use std::io::{BufRead, BufReader, Write};
+
+        let upstream = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/jsonrpc", upstream.local_addr().unwrap());
+        let cookie = std::thread::spawn(move || {
+            let (stream, _) = upstream.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut cookie = String::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                if let Some(value) = line.strip_prefix("cookie: ") {
+                    cookie = value.trim().to_string();
+                }
+                line.clear();
+            }
+            let body = r#"{"jsonrpc":"2.0","id":1,"result":{"texts":[{"text":"Hallo","alternatives":[]}],"lang":"EN","lang_is_confident":true,"detectedLanguages":{}}}"#;
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            (&stream).write_all(reply.as_bytes()).unwrap();
+            cookie
+        });
+
+        let server = Server::new(Arc::new(Upper)).with_pro(DeepLClient::with_endpoint(&endpoint));
+        let body = r#"{"text": "hello", "target_lang": "DE"}"#;
+        let (status, json) = call(&server, post("/v1/translate", body));
+        assert_eq!(
+            (status, &json["message"]),
+            (401, &"no dl_session found".into())
+        );
+        assert_eq!(
+            call(
+                &server,
+                Request::get("/v1/translate").body(Body::empty()).unwrap()
+            )
+            .0,
+            405
+        );
+
+        let req = Request::post("/v1/translate")
+            .header(COOKIE, "theme=dark; dl_session=abc123")
+            .body(Body::from(body))
+            .unwrap();
+        let (status, json) = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let response = server.handle(req).await;
+                let status = response.status().as_u16();
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                (status, serde_json::from_slice::<Value>(&body).unwrap())
+            });
+        assert_eq!(
+            (status, &json["data"], &json["method"]),
+            (200, &"Hallo".into(), &"Pro".into())
+        );
+        assert!(cookie.join().unwrap().contains("dl_session=abc123"));
+    }
+
+    #[test]
+    fn test_admin_switches_mode() {
+        let switch = Switch::default();
+        let server = Server::new(Arc::new(Upper)).with_admin("root", switch.clone());
+        let admin = |method: &str, body: &str| {
+            Request::builder()
+                .method(method)
+                .uri("/admin/mode")
+                .header(AUTHORIZATION, "Bearer root")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let (status, json) = call(&server, admin("PUT", r#"{"mode": "paused"}"#));
+        assert_eq!((status, &json["mode"]), (200, &"paused".into()));
+        assert_eq!(switch.mode(), Mode::Paused);
+        assert_eq!(call(&server, admin("PUT", r#"{"mode": "off"}"#)).0, 400);
+        assert_eq!(call(&server, admin("POST", "")).0, 405);
+        let anonymous = Request::get("/admin/mode").body(Body::empty()).unwrap();
+        assert_eq!(call(&server, anonymous).0, 401);
+        let (_, json) = call(&server, admin("GET", ""));
+        assert_eq!(json["mode"], "paused");
+    }
+}
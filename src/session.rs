@@ -0,0 +1,262 @@
+//! A pool of DeepL Pro `dl_session` tokens.
+//!
+//! Requests use one token for `rotate_every` before moving on to the next,
+//! skipping tokens that upstream rejected. A rejected token is tried again
+//! after `retry_invalid_after`, in case it was renewed meanwhile.
+
+use std::{fmt, sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{clock::Instant, signing::sha256, state::Stateful};
+
+#[derive(Default)]
+struct Session {
+    token: String,
+    requests: u64,
+    chars: u64,
+    failures: u64,
+    invalid_since: Option<Instant>,
+}
+
+/// What one token has been used for, with the token itself shortened to
+/// its last four characters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionUsage {
+    pub hint: String,
+    pub requests: u64,
+    pub chars: u64,
+    pub failures: u64,
+    pub healthy: bool,
+}
+
+pub struct SessionPool {
+    sessions: Mutex<Vec<Session>>,
+    started: Instant,
+    rotate_every: Duration,
+    retry_invalid_after: Duration,
+}
+
+// Tokens are credentials and must not end up in logs.
+impl fmt::Debug for SessionPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionPool")
+            .field("sessions", &self.len())
+            .field("rotate_every", &self.rotate_every)
+            .field("retry_invalid_after", &self.retry_invalid_after)
+            .finish()
+    }
+}
+
+impl SessionPool {
+    /// Pools the non-empty `tokens`, rotating every minute and retrying
+    /// rejected tokens after an hour.
+    pub fn new(tokens: impl IntoIterator<Item = String>) -> Self {
+        let sessions = tokens
+            .into_iter()
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .map(|token| Session {
+                token,
+                ..Session::default()
+            })
+            .collect();
+        Self {
+            sessions: Mutex::new(sessions),
+            started: Instant::now(),
+            rotate_every: Duration::from_secs(60),
+            retry_invalid_after: Duration::from_secs(60 * 60),
+        }
+    }
+
+    /// How long each token is used before the next one takes over; zero
+    /// moves on with every request.
+    pub fn rotate_every(mut self, every: Duration) -> Self {
+        self.rotate_every = every;
+        self
+    }
+
+    pub fn retry_invalid_after(mut self, after: Duration) -> Self {
+        self.retry_invalid_after = after;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Session>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn healthy(&self, session: &Session) -> bool {
+        session
+            .invalid_since
+            .map_or(true, |at| at.elapsed() >= self.retry_invalid_after)
+    }
+
+    /// The current token and its index, or `None` when every token has
+    /// been rejected recently.
+    pub fn pick(&self) -> Option<(usize, String)> {
+        let sessions = self.lock();
+        if sessions.is_empty() {
+            return None;
+        }
+        let slot = match self.rotate_every.as_nanos() {
+            0 => sessions
+                .iter()
+                .map(|s| s.requests + s.failures)
+                .sum::<u64>() as u128,
+            every => self.started.elapsed().as_nanos() / every,
+        };
+        let first = (slot % sessions.len() as u128) as usize;
+        (0..sessions.len())
+            .map(|i| (first + i) % sessions.len())
+            .find(|&i| self.healthy(&sessions[i]))
+            .map(|i| (i, sessions[i].token.clone()))
+    }
+
+    pub fn record_use(&self, index: usize, chars: usize) {
+        if let Some(session) = self.lock().get_mut(index) {
+            session.requests += 1;
+            session.chars += chars as u64;
+            session.invalid_since = None;
+        }
+    }
+
+    pub fn record_failure(&self, index: usize) {
+        if let Some(session) = self.lock().get_mut(index) {
+            session.failures += 1;
+        }
+    }
+
+    /// Takes a token out of rotation after upstream rejected it.
+    pub fn invalidate(&self, index: usize) {
+        if let Some(session) = self.lock().get_mut(index) {
+            session.failures += 1;
+            session.invalid_since = Some(Instant::now());
+        }
+    }
+
+    /// Every token, usable or not, in rotation order.
+    #[cfg(feature = "client")]
+    pub(crate) fn tokens(&self) -> Vec<String> {
+        self.lock().iter().map(|s| s.token.clone()).collect()
+    }
+
+    pub fn usage(&self) -> Vec<SessionUsage> {
+        self.lock()
+            .iter()
+            .map(|s| {
+                let skip = s.token.chars().count().saturating_sub(4);
+                SessionUsage {
+                    hint: format!("…{}", s.token.chars().skip(skip).collect::<String>()),
+                    requests: s.requests,
+                    chars: s.chars,
+                    failures: s.failures,
+                    healthy: self.healthy(s),
+                }
+            })
+            .collect()
+    }
+}
+
+/// What [`SessionPool`] saves of a token, keyed by a hash of it so the
+/// state file holds no credentials.
+#[derive(Serialize, Deserialize)]
+struct SavedSession {
+    key: String,
+    requests: u64,
+    chars: u64,
+    failures: u64,
+    /// How long ago upstream rejected the token.
+    invalid_for_ms: Option<u64>,
+}
+
+fn token_key(token: &str) -> String {
+    sha256(token.as_bytes())[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl Stateful for SessionPool {
+    fn state_name(&self) -> &str {
+        "sessions"
+    }
+
+    fn export_state(&self) -> Value {
+        let saved: Vec<SavedSession> = self
+            .lock()
+            .iter()
+            .map(|s| SavedSession {
+                key: token_key(&s.token),
+                requests: s.requests,
+                chars: s.chars,
+                failures: s.failures,
+                invalid_for_ms: s.invalid_since.map(|at| at.elapsed().as_millis() as u64),
+            })
+            .collect();
+        serde_json::json!(saved)
+    }
+
+    /// Restores the counters of tokens still in the pool; tokens added
+    /// since start fresh.
+    fn import_state(&self, state: Value) -> Result<(), serde_json::Error> {
+        let saved: Vec<SavedSession> = serde_json::from_value(state)?;
+        for session in self.lock().iter_mut() {
+            let key = token_key(&session.token);
+            if let Some(s) = saved.iter().find(|s| s.key == key) {
+                session.requests = s.requests;
+                session.chars = s.chars;
+                session.failures = s.failures;
+                session.invalid_since = s
+                    .invalid_for_ms
+                    .and_then(|ms| Instant::now().checked_sub(Duration::from_millis(ms)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_rotates_past_invalid_tokens() {
+        let pool = SessionPool::new([
+            "token-a".to_string(),
+            " ".to_string(),
+            "token-b".to_string(),
+        ])
+        .rotate_every(Duration::ZERO);
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.pick(), Some((0, "token-a".to_string())));
+        pool.record_use(0, 5);
+        assert_eq!(pool.pick().unwrap().0, 1);
+
+        pool.invalidate(1);
+        assert_eq!(pool.pick().unwrap().0, 0);
+        pool.invalidate(0);
+        assert_eq!(pool.pick(), None);
+
+        let usage = pool.usage();
+        assert_eq!(usage[0].hint, "…en-a");
+        assert_eq!(
+            (usage[0].requests, usage[0].chars, usage[0].failures),
+            (1, 5, 1)
+        );
+        assert!(!usage[0].healthy);
+        assert!(!format!("{:?}", pool).contains("token"));
+
+        let revived = SessionPool::new(["token-a".to_string()]).retry_invalid_after(Duration::ZERO);
+        revived.invalidate(0);
+        assert_eq!(revived.pick().map(|(i, _)| i), Some(0));
+    }
+}
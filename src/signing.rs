@@ -0,0 +1,321 @@
+//! HMAC-SHA256 signatures over translation results, so services behind a
+//! gateway can check that a result came from it unchanged.
+//!
+//! The signature covers the unix time and the result in canonical JSON
+//! (object keys sorted, no whitespace), and travels as
+//! `t=<unix seconds>,v1=<hex>`, with `kid=<key id>` first when the key has
+//! an id. SHA-256 is implemented here to avoid pulling in a crypto crate
+//! for one hash.
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use serde_json::Value;
+
+use crate::clock;
+
+/// The header signed server responses carry the signature in.
+pub const SIGNATURE_HEADER: &str = "x-deeplx-signature";
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut out = [0u8; 32];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(message);
+    let mut outer: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&sha256(&inner));
+    sha256(&outer)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `value` with object keys sorted and no whitespace, so that
+/// reformatting or reordering a document does not change its signature.
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    Malformed,
+    /// Signed with another key, or the result was changed.
+    Mismatch,
+    /// Signed longer ago than the verifier accepts.
+    Expired,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Malformed => write!(f, "malformed signature"),
+            SignatureError::Mismatch => write!(f, "signature does not match"),
+            SignatureError::Expired => write!(f, "signature expired"),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+#[derive(Clone)]
+pub struct Signer {
+    key: Vec<u8>,
+    key_id: Option<String>,
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signer")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Signer {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Signer {
+            key: key.into(),
+            key_id: None,
+        }
+    }
+
+    /// Names the key in signatures, so verifiers can rotate keys.
+    pub fn with_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.key_id = Some(key_id.into());
+        self
+    }
+
+    fn mac(&self, timestamp: u64, value: &Value) -> [u8; 32] {
+        let message = format!("{}.{}", timestamp, canonical_json(value));
+        hmac_sha256(&self.key, message.as_bytes())
+    }
+
+    /// The signature header value for `value`, signed now.
+    pub fn sign(&self, value: &Value) -> String {
+        let now = clock::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.sign_at(now, value)
+    }
+
+    pub fn sign_at(&self, timestamp: u64, value: &Value) -> String {
+        let sig = hex(&self.mac(timestamp, value));
+        match &self.key_id {
+            Some(kid) => format!("kid={},t={},v1={}", kid, timestamp, sig),
+            None => format!("t={},v1={}", timestamp, sig),
+        }
+    }
+
+    /// Checks `header` against `value`. Signatures older than `max_age`
+    /// are refused when it is given.
+    pub fn verify(
+        &self,
+        header: &str,
+        value: &Value,
+        max_age: Option<Duration>,
+    ) -> Result<(), SignatureError> {
+        let mut timestamp = None;
+        let mut sig = None;
+        for part in header.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", t)) => timestamp = t.parse::<u64>().ok(),
+                Some(("v1", v)) => sig = Some(v),
+                Some(("kid", _)) => {}
+                _ => return Err(SignatureError::Malformed),
+            }
+        }
+        let (Some(timestamp), Some(sig)) = (timestamp, sig) else {
+            return Err(SignatureError::Malformed);
+        };
+        let expected = hex(&self.mac(timestamp, value));
+        // Compare every byte, so timing does not reveal how much matched.
+        let differ = expected.len() != sig.len()
+            || expected
+                .bytes()
+                .zip(sig.bytes())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                != 0;
+        if differ {
+            return Err(SignatureError::Mismatch);
+        }
+        if let Some(max_age) = max_age {
+            let signed = SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp);
+            let age = clock::now().duration_since(signed).unwrap_or_default();
+            if age > max_age {
+                return Err(SignatureError::Expired);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_sha256_and_hmac_vectors() {
+        assert_eq!(
+            hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&sha256(&[b'a'; 1000])),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+        // RFC 4231, test cases 2 and 6.
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_signatures_verify_canonically() {
+        let signer = Signer::new("secret").with_key_id("2026-10");
+        let result = json!({"data": "Hallo", "code": 200, "alternatives": ["Hi"]});
+        let header = signer.sign(&result);
+        assert!(header.starts_with("kid=2026-10,t="));
+
+        let reordered: Value =
+            serde_json::from_str(r#"{ "alternatives": ["Hi"], "code": 200, "data": "Hallo" }"#)
+                .unwrap();
+        assert_eq!(canonical_json(&reordered), canonical_json(&result));
+        assert_eq!(signer.verify(&header, &reordered, None), Ok(()));
+
+        let tampered = json!({"data": "Tschüss", "code": 200, "alternatives": ["Hi"]});
+        assert_eq!(
+            signer.verify(&header, &tampered, None),
+            Err(SignatureError::Mismatch)
+        );
+        assert_eq!(
+            Signer::new("other").verify(&header, &result, None),
+            Err(SignatureError::Mismatch)
+        );
+        let old = signer.sign_at(1_000, &result);
+        assert_eq!(
+            signer.verify(&old, &result, Some(Duration::from_secs(60))),
+            Err(SignatureError::Expired)
+        );
+        assert_eq!(
+            signer.verify("v1=00", &result, None),
+            Err(SignatureError::Malformed)
+        );
+    }
+}
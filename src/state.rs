@@ -0,0 +1,207 @@
+//! Operational state saved to a directory on shutdown and loaded again on
+//! startup, so that restarts for upgrades keep session health, usage
+//! counters, cached results and pending jobs.
+//!
+//! Each part implementing [`Stateful`] lives in `<dir>/<name>.json`.
+//! [`StateHooks`] restores every registered part at startup and saves them
+//! all at shutdown. Parts that are not shared, such as a
+//! [`JobQueue`](crate::queue::JobQueue), go through [`StateDir`] directly.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::sync::write_atomic;
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(io::Error),
+    /// A state file that does not hold what its part expects.
+    Parse {
+        name: String,
+        error: serde_json::Error,
+    },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Io(e) => write!(f, "cannot access state: {}", e),
+            StateError::Parse { name, error } => write!(f, "invalid state {}: {}", name, error),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<io::Error> for StateError {
+    fn from(e: io::Error) -> Self {
+        StateError::Io(e)
+    }
+}
+
+/// Something whose state survives restarts.
+pub trait Stateful: Send + Sync {
+    /// The file name, without `.json`.
+    fn state_name(&self) -> &str;
+
+    fn export_state(&self) -> Value;
+
+    fn import_state(&self, state: Value) -> Result<(), serde_json::Error>;
+}
+
+#[derive(Clone, Debug)]
+pub struct StateDir {
+    path: PathBuf,
+}
+
+impl StateDir {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        StateDir { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn file(&self, name: &str) -> PathBuf {
+        self.path.join(format!("{}.json", name))
+    }
+
+    /// Writes `value` as `<name>.json`, creating the directory if needed.
+    pub fn save<T: Serialize + ?Sized>(&self, name: &str, value: &T) -> Result<(), StateError> {
+        fs::create_dir_all(&self.path)?;
+        let json = serde_json::to_vec_pretty(value).map_err(|error| StateError::Parse {
+            name: name.to_string(),
+            error,
+        })?;
+        Ok(write_atomic(&self.file(name), &json, false)?)
+    }
+
+    /// Reads `<name>.json`; `None` if it was never saved.
+    pub fn load<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>, StateError> {
+        let bytes = match fs::read(self.file(name)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|error| StateError::Parse {
+                name: name.to_string(),
+                error,
+            })
+    }
+}
+
+/// The parts of a deployment whose state is restored at startup and saved
+/// at shutdown.
+pub struct StateHooks {
+    dir: StateDir,
+    parts: Vec<Arc<dyn Stateful>>,
+}
+
+impl StateHooks {
+    pub fn new(dir: StateDir) -> Self {
+        StateHooks {
+            dir,
+            parts: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, part: Arc<dyn Stateful>) -> Self {
+        self.parts.push(part);
+        self
+    }
+
+    pub fn dir(&self) -> &StateDir {
+        &self.dir
+    }
+
+    /// Loads every part that has saved state, returning how many did.
+    pub fn restore(&self) -> Result<usize, StateError> {
+        let mut restored = 0;
+        for part in &self.parts {
+            let name = part.state_name();
+            if let Some(state) = self.dir.load::<Value>(name)? {
+                part.import_state(state)
+                    .map_err(|error| StateError::Parse {
+                        name: name.to_string(),
+                        error,
+                    })?;
+                restored += 1;
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Saves every part, carrying on past failures and returning the
+    /// first.
+    pub fn save(&self) -> Result<(), StateError> {
+        let mut first = None;
+        for part in &self.parts {
+            if let Err(e) = self.dir.save(part.state_name(), &part.export_state()) {
+                first.get_or_insert(e);
+            }
+        }
+        first.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::{
+        budget::{Budget, Period},
+        queue::{DeferredJob, JobQueue},
+        session::SessionPool,
+    };
+
+    #[test]
+    fn test_state_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("deeplx-state-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let sessions = Arc::new(SessionPool::new(["a".to_string(), "b".to_string()]));
+        sessions.record_use(0, 120);
+        sessions.invalidate(1);
+        let budget = Arc::new(Budget::new(Period::Month));
+        budget.charge(500, false).unwrap();
+        let hooks = StateHooks::new(StateDir::new(&dir))
+            .with(sessions.clone())
+            .with(budget.clone());
+        hooks.save().unwrap();
+        let mut queue = JobQueue::new();
+        queue.push(DeferredJob {
+            identity: "deepl".to_string(),
+            chars: 5,
+            ready_at: SystemTime::UNIX_EPOCH + Duration::from_secs(60),
+            job: "hello".to_string(),
+        });
+        hooks.dir().save("jobs", &queue).unwrap();
+
+        // The tokens changed order across the restart; state follows them.
+        let sessions = Arc::new(SessionPool::new(["b".to_string(), "a".to_string()]));
+        let budget = Arc::new(Budget::new(Period::Month));
+        let hooks = StateHooks::new(StateDir::new(&dir))
+            .with(sessions.clone())
+            .with(budget.clone());
+        assert_eq!(hooks.restore().unwrap(), 2);
+        let usage = sessions.usage();
+        assert_eq!((usage[1].requests, usage[1].chars), (1, 120));
+        assert!(!usage[0].healthy);
+        assert_eq!(budget.usage().used, 500);
+        let queue: JobQueue<String> = hooks.dir().load("jobs").unwrap().unwrap();
+        assert_eq!(queue.pending_for("deepl"), 1);
+        assert!(hooks.dir().load::<Value>("missing").unwrap().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
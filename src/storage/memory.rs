@@ -0,0 +1,250 @@
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{expiry, parse_counter, unix_millis, Entries, Storage, StorageError, StorageResult};
+use crate::{clock, state::Stateful, translator::BoxFuture};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<u64>,
+}
+
+impl Entry {
+    fn live(&self, now: u64) -> bool {
+        self.expires_at.map_or(true, |at| at > now)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: Mutex<BTreeMap<(String, String), Entry>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with<T>(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<(String, String), Entry>) -> T,
+    ) -> StorageResult<T> {
+        let mut entries = self.entries.lock().map_err(|_| StorageError::Poisoned)?;
+        Ok(f(&mut entries))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEntry {
+    ns: String,
+    key: String,
+    #[serde(flatten)]
+    entry: Entry,
+}
+
+/// Saves live entries, cached translations and counters alike.
+impl Stateful for MemoryStorage {
+    fn state_name(&self) -> &str {
+        "storage"
+    }
+
+    fn export_state(&self) -> Value {
+        let now = unix_millis(clock::now());
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let saved: Vec<SavedEntry> = entries
+            .iter()
+            .filter(|(_, e)| e.live(now))
+            .map(|((ns, key), e)| SavedEntry {
+                ns: ns.clone(),
+                key: key.clone(),
+                entry: Entry {
+                    value: e.value.clone(),
+                    expires_at: e.expires_at,
+                },
+            })
+            .collect();
+        serde_json::json!(saved)
+    }
+
+    fn import_state(&self, state: Value) -> Result<(), serde_json::Error> {
+        let saved: Vec<SavedEntry> = serde_json::from_value(state)?;
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        for s in saved {
+            entries.insert((s.ns, s.key), s.entry);
+        }
+        Ok(())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+    ) -> BoxFuture<'a, StorageResult<Option<Vec<u8>>>> {
+        let now = unix_millis(clock::now());
+        Box::pin(async move {
+            self.with(|entries| {
+                let k = (ns.to_string(), key.to_string());
+                match entries.get(&k) {
+                    Some(e) if e.live(now) => Some(e.value.clone()),
+                    Some(_) => {
+                        entries.remove(&k);
+                        None
+                    }
+                    None => None,
+                }
+            })
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<()>> {
+        Box::pin(async move {
+            self.with(|entries| {
+                entries.insert(
+                    (ns.to_string(), key.to_string()),
+                    Entry {
+                        value,
+                        expires_at: expiry(ttl),
+                    },
+                );
+            })
+        })
+    }
+
+    fn delete<'a>(&'a self, ns: &'a str, key: &'a str) -> BoxFuture<'a, StorageResult<()>> {
+        Box::pin(async move {
+            self.with(|entries| {
+                entries.remove(&(ns.to_string(), key.to_string()));
+            })
+        })
+    }
+
+    fn scan<'a>(&'a self, ns: &'a str, prefix: &'a str) -> BoxFuture<'a, StorageResult<Entries>> {
+        let now = unix_millis(clock::now());
+        Box::pin(async move {
+            self.with(|entries| {
+                entries
+                    .range((ns.to_string(), prefix.to_string())..)
+                    .take_while(|((n, k), _)| n == ns && k.starts_with(prefix))
+                    .filter(|(_, e)| e.live(now))
+                    .map(|((_, k), e)| (k.clone(), e.value.clone()))
+                    .collect()
+            })
+        })
+    }
+
+    fn incr<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        by: i64,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<i64>> {
+        let now = unix_millis(clock::now());
+        Box::pin(async move {
+            self.with(|entries| {
+                let entry = entries
+                    .entry((ns.to_string(), key.to_string()))
+                    .or_insert_with(|| Entry {
+                        value: b"0".to_vec(),
+                        expires_at: expiry(ttl),
+                    });
+                if !entry.live(now) {
+                    *entry = Entry {
+                        value: b"0".to_vec(),
+                        expires_at: expiry(ttl),
+                    };
+                }
+                let value = parse_counter(&entry.value) + by;
+                entry.value = value.to_string().into_bytes();
+                value
+            })
+        })
+    }
+
+    fn put_if_absent<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<bool>> {
+        let now = unix_millis(clock::now());
+        Box::pin(async move {
+            self.with(|entries| {
+                let k = (ns.to_string(), key.to_string());
+                if entries.get(&k).is_some_and(|e| e.live(now)) {
+                    return false;
+                }
+                entries.insert(
+                    k,
+                    Entry {
+                        value,
+                        expires_at: expiry(ttl),
+                    },
+                );
+                true
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage() {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let storage = MemoryStorage::new();
+            storage.put("a", "k1", b"1".to_vec(), None).await.unwrap();
+            storage.put("a", "k2", b"2".to_vec(), None).await.unwrap();
+            storage.put("a", "x", b"3".to_vec(), None).await.unwrap();
+            storage.put("b", "k3", b"4".to_vec(), None).await.unwrap();
+            storage
+                .put("a", "k0", b"gone".to_vec(), Some(Duration::ZERO))
+                .await
+                .unwrap();
+
+            assert_eq!(storage.get("a", "k1").await.unwrap(), Some(b"1".to_vec()));
+            assert_eq!(storage.get("b", "k1").await.unwrap(), None);
+            assert_eq!(storage.get("a", "k0").await.unwrap(), None);
+            let keys: Vec<_> = storage
+                .scan("a", "k")
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect();
+            assert_eq!(keys, ["k1", "k2"]);
+
+            storage.delete("a", "k1").await.unwrap();
+            assert_eq!(storage.get("a", "k1").await.unwrap(), None);
+
+            assert_eq!(storage.incr("c", "n", 2, None).await.unwrap(), 2);
+            assert_eq!(storage.incr("c", "n", 3, None).await.unwrap(), 5);
+            assert!(storage
+                .put_if_absent("c", "lock", b"a".to_vec(), None)
+                .await
+                .unwrap());
+            assert!(!storage
+                .put_if_absent("c", "lock", b"b".to_vec(), None)
+                .await
+                .unwrap());
+        });
+    }
+}
@@ -0,0 +1,124 @@
+//! Namespaced key-value persistence shared by the cache, job queue, usage
+//! accounting and rate-limit state.
+//!
+//! [`MemoryStorage`] is always available; SQLite and Redis backends are
+//! enabled with the `storage-sqlite` and `storage-redis` features.
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use crate::{clock, translator::BoxFuture};
+
+mod memory;
+#[cfg(feature = "storage-redis")]
+mod redis;
+#[cfg(feature = "storage-sqlite")]
+mod sqlite;
+
+#[cfg(feature = "storage-redis")]
+pub use self::redis::RedisStorage;
+pub use memory::MemoryStorage;
+#[cfg(feature = "storage-sqlite")]
+pub use sqlite::SqliteStorage;
+
+pub const NS_CACHE: &str = "cache";
+pub const NS_JOBS: &str = "jobs";
+pub const NS_USAGE: &str = "usage";
+pub const NS_RATE_LIMIT: &str = "rate_limit";
+pub const NS_COOLDOWN: &str = "cooldown";
+pub const NS_LEADER: &str = "leader";
+
+#[derive(Debug)]
+pub enum StorageError {
+    #[cfg(feature = "storage-sqlite")]
+    Sqlite(rusqlite::Error),
+    #[cfg(feature = "storage-redis")]
+    Redis(::redis::RedisError),
+    Poisoned,
+    /// A store reached over a socket, such as the local coordinator,
+    /// could not be.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "storage-sqlite")]
+            StorageError::Sqlite(e) => write!(f, "sqlite: {}", e),
+            #[cfg(feature = "storage-redis")]
+            StorageError::Redis(e) => write!(f, "redis: {}", e),
+            StorageError::Poisoned => write!(f, "storage lock poisoned"),
+            StorageError::Io(e) => write!(f, "storage unreachable: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+pub type Entries = Vec<(String, Vec<u8>)>;
+
+pub trait Storage: Send + Sync {
+    fn get<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+    ) -> BoxFuture<'a, StorageResult<Option<Vec<u8>>>>;
+
+    /// Stores `value`, replacing any previous one. With a `ttl` the entry
+    /// disappears once it has elapsed.
+    fn put<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<()>>;
+
+    fn delete<'a>(&'a self, ns: &'a str, key: &'a str) -> BoxFuture<'a, StorageResult<()>>;
+
+    /// All live entries in `ns` whose key starts with `prefix`, sorted by
+    /// key.
+    fn scan<'a>(&'a self, ns: &'a str, prefix: &'a str) -> BoxFuture<'a, StorageResult<Entries>>;
+
+    /// Atomically adds `by` to the decimal counter at `key`, treating a
+    /// missing entry as 0, and returns the new value. `ttl` only applies
+    /// when the entry is created.
+    fn incr<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        by: i64,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<i64>>;
+
+    /// Stores `value` only if there is no live entry at `key`, returning
+    /// whether it did.
+    fn put_if_absent<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<bool>>;
+}
+
+fn parse_counter(value: &[u8]) -> i64 {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0)
+}
+
+pub(crate) fn unix_millis(at: SystemTime) -> u64 {
+    at.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn expiry(ttl: Option<Duration>) -> Option<u64> {
+    ttl.map(|ttl| clock::now().checked_add(ttl).map_or(u64::MAX, unix_millis))
+}
@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use super::{Entries, Storage, StorageError, StorageResult};
+use crate::translator::BoxFuture;
+
+impl From<redis::RedisError> for StorageError {
+    fn from(e: redis::RedisError) -> Self {
+        StorageError::Redis(e)
+    }
+}
+
+/// Entries live under `{prefix}{ns}:{key}`; expiry is left to Redis.
+#[derive(Clone)]
+pub struct RedisStorage {
+    conn: ConnectionManager,
+    prefix: String,
+}
+
+impl RedisStorage {
+    pub async fn connect(url: &str, prefix: impl Into<String>) -> StorageResult<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(Self {
+            conn: ConnectionManager::new(client).await?,
+            prefix: prefix.into(),
+        })
+    }
+
+    pub fn connection(&self) -> ConnectionManager {
+        self.conn.clone()
+    }
+
+    pub fn key(&self, ns: &str, key: &str) -> String {
+        format!("{}{}:{}", self.prefix, ns, key)
+    }
+}
+
+fn escape_glob(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+impl Storage for RedisStorage {
+    fn get<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+    ) -> BoxFuture<'a, StorageResult<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            Ok(conn.get(self.key(ns, key)).await?)
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<()>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let mut cmd = redis::cmd("SET");
+            cmd.arg(self.key(ns, key)).arg(value);
+            if let Some(ttl) = ttl {
+                cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+            }
+            Ok(cmd.query_async(&mut conn).await?)
+        })
+    }
+
+    fn delete<'a>(&'a self, ns: &'a str, key: &'a str) -> BoxFuture<'a, StorageResult<()>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            Ok(conn.del(self.key(ns, key)).await?)
+        })
+    }
+
+    fn scan<'a>(&'a self, ns: &'a str, prefix: &'a str) -> BoxFuture<'a, StorageResult<Entries>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let base = self.key(ns, "");
+            let pattern = format!("{}*", escape_glob(&self.key(ns, prefix)));
+            let mut keys: Vec<String> = Vec::new();
+            let mut cursor: u64 = 0;
+            loop {
+                let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(100)
+                    .query_async(&mut conn)
+                    .await?;
+                keys.extend(batch);
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+            keys.sort();
+            keys.dedup();
+            if keys.is_empty() {
+                return Ok(Vec::new());
+            }
+            let values: Vec<Option<Vec<u8>>> =
+                redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+            Ok(keys
+                .into_iter()
+                .zip(values)
+                .filter_map(|(k, v)| Some((k[base.len()..].to_string(), v?)))
+                .collect())
+        })
+    }
+
+    fn incr<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        by: i64,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<i64>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let key = self.key(ns, key);
+            let value: i64 = conn.incr(&key, by).await?;
+            if let Some(ttl) = ttl {
+                // NX keeps the expiry set by whoever created the counter.
+                redis::cmd("PEXPIRE")
+                    .arg(&key)
+                    .arg(ttl.as_millis().max(1) as u64)
+                    .arg("NX")
+                    .query_async::<_, ()>(&mut conn)
+                    .await?;
+            }
+            Ok(value)
+        })
+    }
+
+    fn put_if_absent<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<bool>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let mut cmd = redis::cmd("SET");
+            cmd.arg(self.key(ns, key)).arg(value).arg("NX");
+            if let Some(ttl) = ttl {
+                cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+            }
+            let reply: Option<String> = cmd.query_async(&mut conn).await?;
+            Ok(reply.is_some())
+        })
+    }
+}
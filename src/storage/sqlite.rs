@@ -0,0 +1,241 @@
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{expiry, parse_counter, unix_millis, Entries, Storage, StorageError, StorageResult};
+use crate::translator::BoxFuture;
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Sqlite(e)
+    }
+}
+
+/// A single-file store. Queries run on the calling task; they are short
+/// and local, which keeps this simpler than shipping them to a thread pool.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: impl AsRef<Path>) -> StorageResult<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> StorageResult<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> StorageResult<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS kv (
+                ns TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value BLOB NOT NULL,
+                expires_at INTEGER,
+                PRIMARY KEY (ns, key)
+            )",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> StorageResult<T> {
+        let conn = self.conn.lock().map_err(|_| StorageError::Poisoned)?;
+        Ok(f(&conn)?)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+    ) -> BoxFuture<'a, StorageResult<Option<Vec<u8>>>> {
+        let now = unix_millis(SystemTime::now()) as i64;
+        Box::pin(async move {
+            self.with(|conn| {
+                conn.query_row(
+                    "SELECT value FROM kv
+                     WHERE ns = ?1 AND key = ?2 AND (expires_at IS NULL OR expires_at > ?3)",
+                    params![ns, key, now],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<()>> {
+        let expires_at = expiry(ttl).map(|at| at as i64);
+        Box::pin(async move {
+            self.with(|conn| {
+                conn.execute(
+                    "INSERT OR REPLACE INTO kv (ns, key, value, expires_at) VALUES (?1, ?2, ?3, ?4)",
+                    params![ns, key, value, expires_at],
+                )
+                .map(|_| ())
+            })
+        })
+    }
+
+    fn delete<'a>(&'a self, ns: &'a str, key: &'a str) -> BoxFuture<'a, StorageResult<()>> {
+        Box::pin(async move {
+            self.with(|conn| {
+                conn.execute(
+                    "DELETE FROM kv WHERE ns = ?1 AND key = ?2",
+                    params![ns, key],
+                )
+                .map(|_| ())
+            })
+        })
+    }
+
+    fn scan<'a>(&'a self, ns: &'a str, prefix: &'a str) -> BoxFuture<'a, StorageResult<Entries>> {
+        let now = unix_millis(SystemTime::now()) as i64;
+        Box::pin(async move {
+            self.with(|conn| {
+                // substr() rather than LIKE so `%` and `_` in prefixes are
+                // matched literally.
+                let mut stmt = conn.prepare(
+                    "SELECT key, value FROM kv
+                     WHERE ns = ?1 AND substr(key, 1, length(?2)) = ?2
+                       AND (expires_at IS NULL OR expires_at > ?3)
+                     ORDER BY key",
+                )?;
+                let rows = stmt.query_map(params![ns, prefix, now], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?;
+                rows.collect()
+            })
+        })
+    }
+
+    fn incr<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        by: i64,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<i64>> {
+        let now = unix_millis(SystemTime::now()) as i64;
+        let expires_at = expiry(ttl).map(|at| at as i64);
+        Box::pin(async move {
+            self.with(|conn| {
+                let tx = conn.unchecked_transaction()?;
+                let current: Option<Vec<u8>> = tx
+                    .query_row(
+                        "SELECT value FROM kv
+                         WHERE ns = ?1 AND key = ?2 AND (expires_at IS NULL OR expires_at > ?3)",
+                        params![ns, key, now],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                let value = current.as_deref().map_or(0, parse_counter) + by;
+                if current.is_some() {
+                    tx.execute(
+                        "UPDATE kv SET value = ?3 WHERE ns = ?1 AND key = ?2",
+                        params![ns, key, value.to_string().into_bytes()],
+                    )?;
+                } else {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO kv (ns, key, value, expires_at) VALUES (?1, ?2, ?3, ?4)",
+                        params![ns, key, value.to_string().into_bytes(), expires_at],
+                    )?;
+                }
+                tx.commit()?;
+                Ok(value)
+            })
+        })
+    }
+
+    fn put_if_absent<'a>(
+        &'a self,
+        ns: &'a str,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, StorageResult<bool>> {
+        let now = unix_millis(SystemTime::now()) as i64;
+        let expires_at = expiry(ttl).map(|at| at as i64);
+        Box::pin(async move {
+            self.with(|conn| {
+                let tx = conn.unchecked_transaction()?;
+                tx.execute(
+                    "DELETE FROM kv WHERE ns = ?1 AND key = ?2 AND expires_at <= ?3",
+                    params![ns, key, now],
+                )?;
+                let inserted = tx.execute(
+                    "INSERT OR IGNORE INTO kv (ns, key, value, expires_at) VALUES (?1, ?2, ?3, ?4)",
+                    params![ns, key, value, expires_at],
+                )?;
+                tx.commit()?;
+                Ok(inserted == 1)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_storage() {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let storage = SqliteStorage::open_in_memory().unwrap();
+            storage
+                .put("usage", "s1:day", b"10".to_vec(), None)
+                .await
+                .unwrap();
+            storage
+                .put("usage", "s1:day", b"12".to_vec(), None)
+                .await
+                .unwrap();
+            storage
+                .put("usage", "s2%day", b"3".to_vec(), None)
+                .await
+                .unwrap();
+            storage
+                .put("usage", "s1:old", b"x".to_vec(), Some(Duration::ZERO))
+                .await
+                .unwrap();
+            assert_eq!(
+                storage.get("usage", "s1:day").await.unwrap(),
+                Some(b"12".to_vec())
+            );
+            assert_eq!(storage.get("usage", "s1:old").await.unwrap(), None);
+            assert_eq!(storage.scan("usage", "s1").await.unwrap().len(), 1);
+            assert_eq!(storage.scan("usage", "s2%").await.unwrap().len(), 1);
+            storage.delete("usage", "s1:day").await.unwrap();
+            assert!(storage.scan("usage", "s1").await.unwrap().is_empty());
+
+            assert_eq!(storage.incr("rl", "n", 1, None).await.unwrap(), 1);
+            assert_eq!(storage.incr("rl", "n", 1, None).await.unwrap(), 2);
+            assert!(storage
+                .put_if_absent("rl", "lock", b"a".to_vec(), None)
+                .await
+                .unwrap());
+            assert!(!storage
+                .put_if_absent("rl", "lock", b"b".to_vec(), None)
+                .await
+                .unwrap());
+        });
+    }
+}
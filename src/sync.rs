@@ -0,0 +1,539 @@
+//! Keeping a translated copy of a source tree up to date.
+//!
+//! A [`Layout`] decides where the translation of each source file goes,
+//! and the [`Manifest`] remembers the hash of every source file when it was
+//! last translated, so later runs only retranslate what changed. For locale
+//! files with keys it also remembers each key's source hash, which is how
+//! [`gaps`] tells an outdated translation from a current one.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::formats::KeyedEntry;
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A stable 64-bit FNV-1a hash, as 16 hex digits. Good enough to notice
+/// changes; not meant to resist tampering.
+pub fn content_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// `<root>/<lang>/<source path>`.
+    Tree { root: PathBuf },
+    /// `<dir>/<stem>.<lang>.<ext>` next to the source.
+    Sibling,
+    /// A path template such as `{dir}/{stem}_{lang}.{ext}` or
+    /// `locales/{lang}/{stem}.{ext}`; see [`Layout::template`].
+    Template(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TemplateError {
+    pub placeholder: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown placeholder `{{{}}}` in output template; expected dir, stem, ext or lang",
+            self.placeholder
+        )
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    /// `{dir}/`, which disappears for files at the top of the tree.
+    DirSlash,
+    Dir,
+    Stem,
+    Ext,
+    Lang,
+}
+
+fn parts(template: &str) -> Result<Vec<Part>, TemplateError> {
+    let mut out = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|c| open + c) else {
+            break;
+        };
+        if open > 0 {
+            out.push(Part::Literal(rest[..open].to_string()));
+        }
+        let mut after = &rest[close + 1..];
+        out.push(match &rest[open + 1..close] {
+            "dir" if after.starts_with('/') => {
+                after = &after[1..];
+                Part::DirSlash
+            }
+            "dir" => Part::Dir,
+            "stem" => Part::Stem,
+            "ext" => Part::Ext,
+            "lang" => Part::Lang,
+            other => {
+                return Err(TemplateError {
+                    placeholder: other.to_string(),
+                })
+            }
+        });
+        rest = after;
+    }
+    if !rest.is_empty() {
+        out.push(Part::Literal(rest.to_string()));
+    }
+    Ok(out)
+}
+
+/// Whether `path` can be produced by `parts` for one of `langs`.
+fn matches(parts: &[Part], path: &str, langs: &[String]) -> bool {
+    let Some((part, rest)) = parts.split_first() else {
+        return path.is_empty();
+    };
+    let any = |ok: &dyn Fn(&str) -> bool| {
+        (0..=path.len())
+            .filter(|&n| path.is_char_boundary(n))
+            .any(|n| ok(&path[..n]) && matches(rest, &path[n..], langs))
+    };
+    match part {
+        Part::Literal(literal) => path
+            .strip_prefix(literal.as_str())
+            .is_some_and(|path| matches(rest, path, langs)),
+        Part::DirSlash => any(&|dir| dir.is_empty() || dir.ends_with('/')),
+        Part::Dir => any(&|_| true),
+        Part::Stem => any(&|stem| !stem.is_empty() && !stem.contains('/')),
+        Part::Ext => any(&|ext| !ext.contains(['/', '.'])),
+        Part::Lang => langs.iter().any(|l| {
+            path.strip_prefix(l.as_str())
+                .is_some_and(|path| matches(rest, path, langs))
+        }),
+    }
+}
+
+impl Layout {
+    /// A [`Layout::Template`], checking its placeholders: `{dir}` (the
+    /// source's directory), `{stem}` (its file name without the
+    /// extension), `{ext}` and `{lang}`. A template without `{lang}`
+    /// translates in place.
+    pub fn template(template: &str) -> Result<Self, TemplateError> {
+        parts(template)?;
+        Ok(Layout::Template(template.to_string()))
+    }
+
+    /// Whether every language gets its own target, rather than all of them
+    /// overwriting one file.
+    pub fn per_language(&self) -> bool {
+        match self {
+            Layout::Tree { .. } | Layout::Sibling => true,
+            Layout::Template(template) => parts(template).unwrap_or_default().contains(&Part::Lang),
+        }
+    }
+
+    pub fn target(&self, source: &Path, lang: &str) -> PathBuf {
+        match self {
+            Layout::Tree { root } => root.join(lang).join(source),
+            Layout::Template(template) => {
+                let dir = source.parent().map(manifest_key).unwrap_or_default();
+                let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+                let ext = source.extension().unwrap_or_default().to_string_lossy();
+                let rendered: String = parts(template)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|part| match part {
+                        Part::Literal(literal) => literal.clone(),
+                        Part::DirSlash if dir.is_empty() => String::new(),
+                        Part::DirSlash => format!("{}/", dir),
+                        Part::Dir => dir.clone(),
+                        Part::Stem => stem.to_string(),
+                        Part::Ext => ext.to_string(),
+                        Part::Lang => lang.to_string(),
+                    })
+                    .collect();
+                // Without an extension `{stem}.{ext}` would end in a dot.
+                rendered
+                    .split('/')
+                    .filter(|c| !c.is_empty())
+                    .map(|c| c.trim_end_matches('.'))
+                    .collect()
+            }
+            Layout::Sibling => {
+                let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+                let name = match source.extension() {
+                    Some(ext) => format!("{}.{}.{}", stem, lang, ext.to_string_lossy()),
+                    None => format!("{}.{}", stem, lang),
+                };
+                source.with_file_name(name)
+            }
+        }
+    }
+
+    /// Whether `path` is itself a translation produced by this layout, so
+    /// it is not picked up as a source.
+    pub fn is_target(&self, path: &Path, langs: &[String]) -> bool {
+        match self {
+            Layout::Tree { root } => path.starts_with(root),
+            Layout::Sibling => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                langs.iter().any(|l| stem.ends_with(&format!(".{}", l)))
+            }
+            // An in-place template has no targets apart from its sources.
+            Layout::Template(_) if !self.per_language() => false,
+            Layout::Template(template) => matches(
+                &parts(template).unwrap_or_default(),
+                &manifest_key(path),
+                langs,
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub source_hash: String,
+    /// Where the translation was written, relative to the tree root.
+    pub target: String,
+    pub target_hash: String,
+    /// Source hash of every key, for locale files.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Language, then source path relative to the tree root with `/`
+    /// separators.
+    pub languages: BTreeMap<String, BTreeMap<String, FileRecord>>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            languages: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SyncError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    Version(u32),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::Io(e) => write!(f, "cannot access manifest: {}", e),
+            SyncError::Parse(e) => write!(f, "invalid manifest: {}", e),
+            SyncError::Version(v) => write!(f, "unsupported manifest version {}", v),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SyncError::Io(e) => Some(e),
+            SyncError::Parse(e) => Some(e),
+            SyncError::Version(_) => None,
+        }
+    }
+}
+
+/// Replaces `path` with `contents` through a temporary file in the same
+/// directory and a rename, so a crash leaves either the old file or the new
+/// one, never a truncated mix. With `backup` the old file is first copied
+/// to `<path>.bak`.
+pub fn write_atomic(path: &Path, contents: &[u8], backup: bool) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        if backup && path.exists() {
+            fs::copy(path, path.with_file_name(format!("{}.bak", name)))?;
+        }
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// `path` with `/` separators, for manifest keys.
+pub fn manifest_key(path: &Path) -> String {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`; a missing file is an empty manifest.
+    pub fn load(path: &Path) -> Result<Self, SyncError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(SyncError::Io(e)),
+        };
+        let manifest: Self = serde_json::from_str(&text).map_err(SyncError::Parse)?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(SyncError::Version(manifest.version));
+        }
+        Ok(manifest)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SyncError> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(SyncError::Io)?;
+        }
+        let mut text = serde_json::to_string_pretty(self).map_err(SyncError::Parse)?;
+        text.push('\n');
+        write_atomic(path, text.as_bytes(), false).map_err(SyncError::Io)
+    }
+
+    pub fn record(&self, lang: &str, source: &str) -> Option<&FileRecord> {
+        self.languages.get(lang)?.get(source)
+    }
+
+    /// Whether `source` was translated into `lang` from exactly this
+    /// content.
+    pub fn is_current(&self, lang: &str, source: &str, source_hash: &str) -> bool {
+        self.record(lang, source)
+            .is_some_and(|r| r.source_hash == source_hash)
+    }
+
+    /// Whether the translation of `source` into `lang`, now reading
+    /// `target`, was changed by hand since it was written. Retranslating it
+    /// would throw that work away.
+    pub fn is_edited(&self, lang: &str, source: &str, target: &str) -> bool {
+        self.record(lang, source)
+            .is_some_and(|r| r.target_hash != content_hash(target))
+    }
+
+    pub fn insert(&mut self, lang: &str, source: &str, record: FileRecord) {
+        self.languages
+            .entry(lang.to_string())
+            .or_default()
+            .insert(source.to_string(), record);
+    }
+}
+
+fn source_text(entry: &KeyedEntry) -> &str {
+    entry.source.as_deref().unwrap_or(&entry.text)
+}
+
+/// The source hash of every key, for [`FileRecord::keys`].
+pub fn key_hashes(entries: &[KeyedEntry]) -> BTreeMap<String, String> {
+    entries
+        .iter()
+        .map(|e| (e.key.clone(), content_hash(source_text(e))))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gap {
+    Missing,
+    /// Translated from a source text that has since changed, or marked as
+    /// needing review.
+    Outdated,
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Gap::Missing => "missing",
+            Gap::Outdated => "outdated",
+        })
+    }
+}
+
+/// Keys of `source` that `target` lacks or has translated from an older
+/// source. `record` is the manifest entry from when `target` was last
+/// translated; without one only missing keys can be found.
+pub fn gaps(
+    source: &[KeyedEntry],
+    target: &[KeyedEntry],
+    record: Option<&FileRecord>,
+) -> Vec<(String, Gap)> {
+    let target: BTreeMap<&str, &KeyedEntry> = target.iter().map(|e| (e.key.as_str(), e)).collect();
+    source
+        .iter()
+        .filter_map(|entry| {
+            let gap = match target.get(entry.key.as_str()) {
+                None => Gap::Missing,
+                Some(t) if t.text.is_empty() => Gap::Missing,
+                Some(t) if t.stale => Gap::Outdated,
+                Some(t) if t.source.is_some() && t.source != entry.source => Gap::Outdated,
+                Some(_) => {
+                    let recorded = record.and_then(|r| r.keys.get(&entry.key));
+                    if recorded.map_or(true, |h| *h == content_hash(source_text(entry))) {
+                        return None;
+                    }
+                    Gap::Outdated
+                }
+            };
+            Some((entry.key.clone(), gap))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_targets() {
+        let source = Path::new("docs/guide/intro.md");
+        let tree = Layout::Tree {
+            root: PathBuf::from("i18n"),
+        };
+        assert_eq!(
+            tree.target(source, "ja"),
+            Path::new("i18n/ja/docs/guide/intro.md")
+        );
+        assert!(tree.is_target(Path::new("i18n/ja/docs/a.md"), &[]));
+        assert_eq!(
+            Layout::Sibling.target(source, "ja"),
+            Path::new("docs/guide/intro.ja.md")
+        );
+        assert!(Layout::Sibling.is_target(Path::new("docs/intro.ja.md"), &["ja".to_string()]));
+        assert!(!Layout::Sibling.is_target(source, &["ja".to_string()]));
+    }
+
+    #[test]
+    fn test_template_layouts() {
+        let langs = ["ja".to_string(), "pt-BR".to_string()];
+        let suffix = Layout::template("{dir}/{stem}_{lang}.{ext}").unwrap();
+        assert_eq!(
+            suffix.target(Path::new("res/strings.json"), "ja"),
+            Path::new("res/strings_ja.json")
+        );
+        assert_eq!(
+            suffix.target(Path::new("strings.json"), "pt-BR"),
+            Path::new("strings_pt-BR.json")
+        );
+        assert!(suffix.is_target(Path::new("strings_pt-BR.json"), &langs));
+        assert!(suffix.is_target(Path::new("a/b/strings_ja.json"), &langs));
+        assert!(!suffix.is_target(Path::new("a/strings.json"), &langs));
+
+        let locales = Layout::template("locales/{lang}/{stem}.{ext}").unwrap();
+        assert_eq!(
+            locales.target(Path::new("src/en/messages.po"), "ja"),
+            Path::new("locales/ja/messages.po")
+        );
+        assert!(locales.per_language());
+
+        let in_place = Layout::template("{dir}/{stem}.{ext}").unwrap();
+        assert!(!in_place.per_language());
+        assert_eq!(
+            in_place.target(Path::new("README"), "ja"),
+            Path::new("README")
+        );
+        assert!(!in_place.is_target(Path::new("README"), &langs));
+        assert_eq!(
+            Layout::template("{name}.{lang}").unwrap_err().placeholder,
+            "name"
+        );
+    }
+
+    #[test]
+    fn test_manifest_tracks_source_hashes() {
+        assert_eq!(content_hash(""), "cbf29ce484222325");
+        let mut manifest = Manifest::default();
+        let hash = content_hash("# Intro\n");
+        manifest.insert(
+            "ja",
+            "docs/intro.md",
+            FileRecord {
+                source_hash: hash.clone(),
+                target: "i18n/ja/docs/intro.md".to_string(),
+                target_hash: content_hash("# はじめに\n"),
+                keys: BTreeMap::new(),
+            },
+        );
+        assert!(manifest.is_current("ja", "docs/intro.md", &hash));
+        assert!(!manifest.is_current("ja", "docs/intro.md", &content_hash("# Intro 2\n")));
+        assert!(!manifest.is_current("de", "docs/intro.md", &hash));
+        assert!(!manifest.is_edited("ja", "docs/intro.md", "# はじめに\n"));
+        assert!(manifest.is_edited("ja", "docs/intro.md", "# はじめに！\n"));
+        assert!(!manifest.is_edited("de", "docs/intro.md", ""));
+
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert_eq!(serde_json::from_str::<Manifest>(&json).unwrap(), manifest);
+        assert!(Manifest::load(Path::new("/nonexistent/manifest.json"))
+            .unwrap()
+            .languages
+            .is_empty());
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_backup() {
+        let dir = std::env::temp_dir().join(format!("deeplx-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ja.json");
+        write_atomic(&path, b"{}\n", true).unwrap();
+        assert!(!dir.join("ja.json.bak").exists());
+        write_atomic(&path, b"{\"a\": 1}\n", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"a\": 1}\n");
+        assert_eq!(fs::read_to_string(dir.join("ja.json.bak")).unwrap(), "{}\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_gaps() {
+        let entry = |key: &str, text: &str| KeyedEntry {
+            key: key.to_string(),
+            text: text.to_string(),
+            source: None,
+            stale: false,
+        };
+        let old = [entry("open", "Open"), entry("save", "Save")];
+        let record = FileRecord {
+            keys: key_hashes(&old),
+            ..FileRecord::default()
+        };
+        let source = [
+            entry("open", "Open"),
+            entry("save", "Save all"),
+            entry("quit", "Quit"),
+            entry("help", "Help"),
+        ];
+        let target = [
+            entry("open", "Öffnen"),
+            entry("save", "Speichern"),
+            entry("help", ""),
+        ];
+        assert_eq!(
+            gaps(&source, &target, Some(&record)),
+            [
+                ("save".to_string(), Gap::Outdated),
+                ("quit".to_string(), Gap::Missing),
+                ("help".to_string(), Gap::Missing),
+            ]
+        );
+        assert_eq!(gaps(&source, &target, None).len(), 2);
+    }
+}
@@ -0,0 +1,377 @@
+//! Metrics and events, sent wherever a deployment collects them.
+//!
+//! The client, [`Server`](crate::server::Server), the shared cache of
+//! [`Coordinated`](crate::cluster::Coordinated) and the
+//! [`Scheduler`](crate::schedule::Scheduler) report through a
+//! [`TelemetrySink`]: [`NoopSink`] by default, [`LogSink`] for the debug
+//! log, [`PrometheusSink`] for a scrape endpoint or [`StatsdSink`] for a
+//! StatsD agent. Metric names follow Prometheus conventions, such as
+//! `deeplx_upstream_requests_total`.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+    io,
+    net::{ToSocketAddrs, UdpSocket},
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use crate::diag;
+
+/// Label names and values; keep the values few, such as an outcome or a
+/// route, since every combination is a separate series.
+pub type Labels<'a> = &'a [(&'a str, &'a str)];
+
+pub trait TelemetrySink: Send + Sync {
+    /// Adds `value` to a running total.
+    fn counter(&self, name: &str, value: u64, labels: Labels<'_>);
+
+    /// Sets a level that goes up and down, such as queue length.
+    fn gauge(&self, name: &str, value: f64, labels: Labels<'_>);
+
+    /// Records one observation, such as a latency in seconds.
+    fn histogram(&self, name: &str, value: f64, labels: Labels<'_>);
+
+    /// Reports something that happened, such as a token being taken out
+    /// of rotation.
+    fn event(&self, name: &str, fields: Labels<'_>);
+}
+
+/// The sink a component reports to, [`NoopSink`] unless set.
+#[derive(Clone)]
+pub struct Telemetry(Arc<dyn TelemetrySink>);
+
+impl Telemetry {
+    pub fn new(sink: Arc<dyn TelemetrySink>) -> Self {
+        Telemetry(sink)
+    }
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Telemetry(Arc::new(NoopSink))
+    }
+}
+
+impl fmt::Debug for Telemetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Telemetry(..)")
+    }
+}
+
+impl Deref for Telemetry {
+    type Target = dyn TelemetrySink;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopSink;
+
+impl TelemetrySink for NoopSink {
+    fn counter(&self, _name: &str, _value: u64, _labels: Labels<'_>) {}
+
+    fn gauge(&self, _name: &str, _value: f64, _labels: Labels<'_>) {}
+
+    fn histogram(&self, _name: &str, _value: f64, _labels: Labels<'_>) {}
+
+    fn event(&self, _name: &str, _fields: Labels<'_>) {}
+}
+
+/// Writes every metric and event to the debug log, which needs the
+/// `tracing` or `log` feature.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogSink;
+
+fn join_labels(labels: Labels<'_>) -> String {
+    labels
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl TelemetrySink for LogSink {
+    fn counter(&self, name: &str, value: u64, labels: Labels<'_>) {
+        diag::log_debug!("counter {}{{{}}} +{}", name, join_labels(labels), value);
+    }
+
+    fn gauge(&self, name: &str, value: f64, labels: Labels<'_>) {
+        diag::log_debug!("gauge {}{{{}}} = {}", name, join_labels(labels), value);
+    }
+
+    fn histogram(&self, name: &str, value: f64, labels: Labels<'_>) {
+        diag::log_debug!("histogram {}{{{}}} {}", name, join_labels(labels), value);
+    }
+
+    fn event(&self, name: &str, fields: Labels<'_>) {
+        diag::log_debug!("event {} {}", name, join_labels(fields));
+    }
+}
+
+/// Histogram bucket bounds, suited to request latencies in seconds.
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The counter events are tallied in.
+const EVENTS: &str = "deeplx_events_total";
+
+#[derive(Debug)]
+enum Series {
+    Counter(u64),
+    Gauge(f64),
+    Histogram {
+        /// Observations at or below each of [`BUCKETS`].
+        buckets: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+impl Series {
+    fn kind(&self) -> &'static str {
+        match self {
+            Series::Counter(_) => "counter",
+            Series::Gauge(_) => "gauge",
+            Series::Histogram { .. } => "histogram",
+        }
+    }
+}
+
+/// Keeps metrics in memory for [`render`](Self::render) to serve in the
+/// Prometheus text format, as `deeplx serve` does on `GET /metrics`.
+/// Events are counted in `deeplx_events_total` by name.
+#[derive(Debug, Default)]
+pub struct PrometheusSink {
+    /// Series by metric name, then by rendered labels.
+    metrics: Mutex<BTreeMap<String, BTreeMap<String, Series>>>,
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render_labels(labels: Labels<'_>) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// `labels` (rendered, possibly empty) with one more label added.
+fn with_label(labels: &str, name: &str, value: &str) -> String {
+    let pair = format!("{}=\"{}\"", name, value);
+    match labels.strip_suffix('}') {
+        Some(rest) => format!("{},{}}}", rest, pair),
+        None => format!("{{{}}}", pair),
+    }
+}
+
+impl PrometheusSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(&self, name: &str, labels: Labels<'_>, new: Series, f: impl FnOnce(&mut Series)) {
+        let mut metrics = self.metrics.lock().unwrap_or_else(|e| e.into_inner());
+        let family = metrics.entry(name.to_string()).or_default();
+        let series = family.entry(render_labels(labels)).or_insert(new);
+        f(series);
+    }
+
+    /// Every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let metrics = self.metrics.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        for (name, family) in metrics.iter() {
+            let Some(first) = family.values().next() else {
+                continue;
+            };
+            let _ = writeln!(out, "# TYPE {} {}", name, first.kind());
+            for (labels, series) in family {
+                match series {
+                    Series::Counter(value) => {
+                        let _ = writeln!(out, "{}{} {}", name, labels, value);
+                    }
+                    Series::Gauge(value) => {
+                        let _ = writeln!(out, "{}{} {}", name, labels, value);
+                    }
+                    Series::Histogram {
+                        buckets,
+                        sum,
+                        count,
+                    } => {
+                        for (bound, n) in BUCKETS.iter().zip(buckets) {
+                            let le = with_label(labels, "le", &bound.to_string());
+                            let _ = writeln!(out, "{}_bucket{} {}", name, le, n);
+                        }
+                        let le = with_label(labels, "le", "+Inf");
+                        let _ = writeln!(out, "{}_bucket{} {}", name, le, count);
+                        let _ = writeln!(out, "{}_sum{} {}", name, labels, sum);
+                        let _ = writeln!(out, "{}_count{} {}", name, labels, count);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl TelemetrySink for PrometheusSink {
+    fn counter(&self, name: &str, value: u64, labels: Labels<'_>) {
+        self.update(name, labels, Series::Counter(0), |s| {
+            if let Series::Counter(total) = s {
+                *total += value;
+            }
+        });
+    }
+
+    fn gauge(&self, name: &str, value: f64, labels: Labels<'_>) {
+        self.update(name, labels, Series::Gauge(0.0), |s| {
+            if let Series::Gauge(level) = s {
+                *level = value;
+            }
+        });
+    }
+
+    fn histogram(&self, name: &str, value: f64, labels: Labels<'_>) {
+        let new = Series::Histogram {
+            buckets: vec![0; BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        };
+        self.update(name, labels, new, |s| {
+            if let Series::Histogram {
+                buckets,
+                sum,
+                count,
+            } = s
+            {
+                for (bound, n) in BUCKETS.iter().zip(buckets.iter_mut()) {
+                    if value <= *bound {
+                        *n += 1;
+                    }
+                }
+                *sum += value;
+                *count += 1;
+            }
+        });
+    }
+
+    fn event(&self, name: &str, _fields: Labels<'_>) {
+        self.counter(EVENTS, 1, &[("event", name)]);
+    }
+}
+
+/// Sends every metric over UDP in the StatsD line format, with labels as
+/// DogStatsD tags and events as counters. Lost packets go unnoticed, as
+/// is usual for StatsD.
+#[derive(Debug)]
+pub struct StatsdSink {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+impl StatsdSink {
+    /// Sends to the agent at `addr`, such as `127.0.0.1:8125`.
+    pub fn new(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(StatsdSink {
+            socket,
+            prefix: String::new(),
+        })
+    }
+
+    /// Prepended to every metric name, followed by a dot.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn line(&self, name: &str, value: &str, kind: &str, labels: Labels<'_>) -> String {
+        let mut line = String::new();
+        if !self.prefix.is_empty() {
+            line.push_str(&self.prefix);
+            line.push('.');
+        }
+        let _ = write!(line, "{}:{}|{}", name, value, kind);
+        if !labels.is_empty() {
+            let tags: Vec<String> = labels.iter().map(|(k, v)| format!("{}:{}", k, v)).collect();
+            let _ = write!(line, "|#{}", tags.join(","));
+        }
+        line
+    }
+
+    fn send(&self, line: String) {
+        let _ = self.socket.send(line.as_bytes());
+    }
+}
+
+impl TelemetrySink for StatsdSink {
+    fn counter(&self, name: &str, value: u64, labels: Labels<'_>) {
+        self.send(self.line(name, &value.to_string(), "c", labels));
+    }
+
+    fn gauge(&self, name: &str, value: f64, labels: Labels<'_>) {
+        self.send(self.line(name, &value.to_string(), "g", labels));
+    }
+
+    fn histogram(&self, name: &str, value: f64, labels: Labels<'_>) {
+        self.send(self.line(name, &value.to_string(), "h", labels));
+    }
+
+    fn event(&self, name: &str, fields: Labels<'_>) {
+        self.send(self.line(name, "1", "c", fields));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_rendering() {
+        let sink = PrometheusSink::new();
+        sink.counter("requests_total", 2, &[("outcome", "ok")]);
+        sink.counter("requests_total", 1, &[("outcome", "ok")]);
+        sink.gauge("queue", 4.0, &[]);
+        sink.histogram("seconds", 0.3, &[("path", "/translate")]);
+        sink.event("token_invalidated", &[("token", "3")]);
+        let text = sink.render();
+        assert!(text.contains("# TYPE requests_total counter\nrequests_total{outcome=\"ok\"} 3\n"));
+        assert!(text.contains("queue 4\n"));
+        assert!(text.contains("seconds_bucket{path=\"/translate\",le=\"0.25\"} 0\n"));
+        assert!(text.contains("seconds_bucket{path=\"/translate\",le=\"0.5\"} 1\n"));
+        assert!(text.contains("seconds_bucket{path=\"/translate\",le=\"+Inf\"} 1\n"));
+        assert!(text.contains("seconds_count{path=\"/translate\"} 1\n"));
+        assert!(text.contains("deeplx_events_total{event=\"token_invalidated\"} 1\n"));
+    }
+
+    #[test]
+    fn test_statsd_lines() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sink = StatsdSink::new(agent.local_addr().unwrap())
+            .unwrap()
+            .with_prefix("deeplx");
+        sink.counter("requests_total", 1, &[("outcome", "ok")]);
+        sink.histogram("seconds", 0.5, &[]);
+        let mut buf = [0; 128];
+        let n = agent.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"deeplx.requests_total:1|c|#outcome:ok");
+        let n = agent.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"deeplx.seconds:0.5|h");
+    }
+}
@@ -0,0 +1,174 @@
+use std::{collections::HashMap, future::Future, pin::Pin};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    capabilities::Capabilities, error::DeepLError, options::TranslateOptions, DeepLResponse,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+/// Not `Send` on wasm32, where the browser's `fetch` futures are not.
+#[cfg(target_arch = "wasm32")]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// A translation result independent of the backend that produced it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Translation {
+    pub text: String,
+    pub detected_source: Option<String>,
+    pub alternatives: Vec<String>,
+    pub meta: TranslationMeta,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TranslationMeta {
+    pub provider: String,
+    /// How sure the backend is about this result, from 0.0 to 1.0, when
+    /// it reports anything of the kind.
+    pub confidence: Option<f64>,
+    /// Backend-specific fields that have no place in [`Translation`],
+    /// keyed by the backend's own field names.
+    pub extensions: HashMap<String, Value>,
+}
+
+impl Translation {
+    pub fn extension(&self, key: &str) -> Option<&Value> {
+        self.meta.extensions.get(key)
+    }
+
+    /// The main translation followed by the alternatives, in the order the
+    /// backend ranked them.
+    pub fn candidates(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.text.as_str()).chain(self.alternatives.iter().map(String::as_str))
+    }
+
+    /// Whether this is an expired cache entry served because the upstream
+    /// was unavailable.
+    pub fn is_stale(&self) -> bool {
+        self.extension("stale") == Some(&Value::Bool(true))
+    }
+
+    /// Whether this was answered from a cache instead of the backend.
+    pub fn is_cached(&self) -> bool {
+        self.extension("cached") == Some(&Value::Bool(true))
+    }
+}
+
+pub trait Translator: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>>;
+
+    /// [`translate`](Translator::translate) with options resolved for this
+    /// request. Backends without such settings ignore them.
+    fn translate_with_options<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+        _options: &'a TranslateOptions,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        self.translate(text, src_lang, target_lang)
+    }
+
+    /// Translates every text in `texts`, returning the results in input
+    /// order or the first failure. Backends that can send several texts
+    /// in one request do; by default they are translated one at a time.
+    fn translate_batch<'a>(
+        &'a self,
+        texts: &'a [&'a str],
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Translation>, DeepLError>> {
+        Box::pin(async move {
+            let mut out = Vec::with_capacity(texts.len());
+            for text in texts {
+                out.push(self.translate(text, src_lang, target_lang).await?);
+            }
+            Ok(out)
+        })
+    }
+}
+
+impl From<DeepLResponse> for Translation {
+    fn from(resp: DeepLResponse) -> Self {
+        let result = resp.result;
+        let text = result
+            .texts
+            .iter()
+            .map(|t| t.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let alternatives = result
+            .texts
+            .first()
+            .map(|t| t.alternatives.iter().map(|a| a.text.clone()).collect())
+            .unwrap_or_default();
+
+        let confidence = result.detected_languages.get(&result.lang).copied();
+
+        let mut extensions = HashMap::new();
+        extensions.insert("id".to_string(), Value::from(resp.id));
+        extensions.insert(
+            "lang_is_confident".to_string(),
+            Value::from(result.lang_is_confident),
+        );
+        extensions.insert(
+            "detectedLanguages".to_string(),
+            Value::from_iter(
+                result
+                    .detected_languages
+                    .into_iter()
+                    .map(|(lang, score)| (lang, Value::from(score))),
+            ),
+        );
+
+        Translation {
+            text,
+            detected_source: Some(result.lang).filter(|l| !l.is_empty()),
+            alternatives,
+            meta: TranslationMeta {
+                provider: "deepl".to_string(),
+                confidence,
+                extensions,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translation_from_deepl_response() {
+        let resp: DeepLResponse = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","id":1000,"result":{"texts":[{"alternatives":[{"text":"你好，世界"}],"text":"你好世界"}],"lang":"EN","lang_is_confident":true,"detectedLanguages":{"EN":0.9}}}"#,
+        )
+        .unwrap();
+        let translation = Translation::from(resp);
+        assert_eq!(translation.text, "你好世界");
+        assert_eq!(translation.detected_source.as_deref(), Some("EN"));
+        assert_eq!(translation.alternatives, vec!["你好，世界"]);
+        assert_eq!(
+            translation.extension("lang_is_confident"),
+            Some(&Value::Bool(true))
+        );
+        assert_eq!(translation.meta.confidence, Some(0.9));
+        assert_eq!(
+            translation.extension("detectedLanguages").unwrap()["EN"],
+            0.9
+        );
+    }
+}
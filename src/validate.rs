@@ -0,0 +1,333 @@
+//! Checks that a translation kept the parts of the source that must not
+//! change: placeholders, markup tags and bracket structure.
+
+use std::{collections::HashMap, fmt, ops::Range, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    capabilities::Capabilities,
+    error::DeepLError,
+    protect::MaskError,
+    translator::{BoxFuture, Translation, Translator},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    MissingToken(String),
+    ExtraToken(String),
+    MissingTag(String),
+    ExtraTag(String),
+    UnbalancedBrackets,
+    MissingEntity(String),
+    /// The translator lost, duplicated or invented a masking sentinel.
+    BrokenSentinel(MaskError),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingToken(t) => write!(f, "missing placeholder `{}`", t),
+            Issue::ExtraToken(t) => write!(f, "unexpected placeholder `{}`", t),
+            Issue::MissingTag(t) => write!(f, "missing tag `{}`", t),
+            Issue::ExtraTag(t) => write!(f, "unexpected tag `{}`", t),
+            Issue::UnbalancedBrackets => write!(f, "brackets are no longer balanced"),
+            Issue::MissingEntity(e) => write!(f, "missing protected entity `{}`", e),
+            Issue::BrokenSentinel(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Record issues in the `validation_issues` extension and carry on.
+    #[default]
+    Lenient,
+    /// Turn any issue into [`DeepLError::ValidationFailed`].
+    Strict,
+}
+
+fn scan_brace(s: &str) -> Option<usize> {
+    if let Some(rest) = s.strip_prefix("{{") {
+        return rest.find("}}").map(|end| end + 4);
+    }
+    let rest = s.strip_prefix('{')?;
+    let end = rest.find('}')?;
+    let name = &rest[..end];
+    let ok = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | ':' | '-'));
+    ok.then_some(end + 2)
+}
+
+fn scan_printf(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = 1;
+    if bytes.get(i) == Some(&b'(') {
+        i += s[i..].find(')')? + 1;
+    }
+    while i < bytes.len() && (bytes[i].is_ascii_digit() || b"$-+#.".contains(&bytes[i])) {
+        i += 1;
+    }
+    match bytes.get(i) {
+        Some(b'l') if bytes.get(i + 1).is_some_and(|b| b"dui".contains(b)) => Some(i + 2),
+        Some(c) if b"sdifuxXoeEgGcp@".contains(c) => Some(i + 1),
+        _ => None,
+    }
+}
+
+/// Byte ranges of placeholders such as `{name}`, `{{var}}`, `${x}`, `%s`,
+/// `%1$d` and `%(count)d`, in order of appearance.
+pub fn placeholder_spans(text: &str) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let len = match rest.as_bytes()[0] {
+            b'{' => scan_brace(rest),
+            b'$' if rest.starts_with("${") => scan_brace(&rest[1..]).map(|l| l + 1),
+            b'%' if rest.starts_with("%%") => {
+                i += 2;
+                continue;
+            }
+            b'%' => scan_printf(rest),
+            _ => None,
+        };
+        match len {
+            Some(len) => {
+                out.push(i..i + len);
+                i += len;
+            }
+            None => i += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    out
+}
+
+/// The placeholders found by [`placeholder_spans`].
+pub fn placeholders(text: &str) -> Vec<&str> {
+    placeholder_spans(text)
+        .into_iter()
+        .map(|span| &text[span])
+        .collect()
+}
+
+/// Byte ranges of markup tags, attributes included, each with the tag
+/// normalised to `<name>`, `</name>` or `<name/>`.
+pub fn tag_spans(text: &str) -> Vec<(Range<usize>, String)> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while let Some(found) = text[pos..].find('<') {
+        let start = pos + found;
+        pos = start + 1;
+        let rest = &text[pos..];
+        let closing = rest.starts_with('/');
+        let body = rest.trim_start_matches('/');
+        let name_len = body
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | ':' | '_')))
+            .unwrap_or(body.len());
+        if name_len == 0 || !body.as_bytes()[0].is_ascii_alphabetic() {
+            continue;
+        }
+        let Some(end) = body.find('>') else { break };
+        let name = body[..name_len].to_ascii_lowercase();
+        let self_closing = body[..end].ends_with('/');
+        let body_start = text.len() - body.len();
+        pos = body_start + end + 1;
+        out.push((
+            start..pos,
+            match (closing, self_closing) {
+                (true, _) => format!("</{}>", name),
+                (false, true) => format!("<{}/>", name),
+                (false, false) => format!("<{}>", name),
+            },
+        ));
+    }
+    out
+}
+
+/// Markup tags normalised to `<name>`, `</name>` or `<name/>`, ignoring
+/// attributes, whose values may legitimately be translated.
+pub fn tags(text: &str) -> Vec<String> {
+    tag_spans(text).into_iter().map(|(_, tag)| tag).collect()
+}
+
+/// Whether (), [] and {} nest properly. Full-width forms count as their
+/// ASCII equivalents since translations into CJK often switch to them.
+pub fn brackets_balanced(text: &str) -> bool {
+    let mut stack = Vec::new();
+    for c in text.chars() {
+        match c {
+            '(' | '（' => stack.push(')'),
+            '[' | '［' => stack.push(']'),
+            '{' | '｛' => stack.push('}'),
+            ')' | '）' | ']' | '］' | '}' | '｝' => {
+                let want = match c {
+                    '）' => ')',
+                    '］' => ']',
+                    '｝' => '}',
+                    c => c,
+                };
+                if stack.pop() != Some(want) {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    stack.is_empty()
+}
+
+fn diff<T: AsRef<str>>(
+    source: &[T],
+    translation: &[T],
+    missing: fn(String) -> Issue,
+    extra: fn(String) -> Issue,
+    issues: &mut Vec<Issue>,
+) {
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    for t in source {
+        *counts.entry(t.as_ref()).or_default() += 1;
+    }
+    for t in translation {
+        *counts.entry(t.as_ref()).or_default() -= 1;
+    }
+    let mut keys: Vec<_> = counts.into_iter().filter(|(_, n)| *n != 0).collect();
+    keys.sort();
+    for (token, n) in keys {
+        let issue = if n > 0 { missing } else { extra };
+        issues.extend((0..n.unsigned_abs()).map(|_| issue(token.to_string())));
+    }
+}
+
+pub fn validate(source: &str, translation: &str) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    diff(
+        &placeholders(source),
+        &placeholders(translation),
+        Issue::MissingToken,
+        Issue::ExtraToken,
+        &mut issues,
+    );
+    diff(
+        &tags(source),
+        &tags(translation),
+        Issue::MissingTag,
+        Issue::ExtraTag,
+        &mut issues,
+    );
+    if brackets_balanced(source) && !brackets_balanced(translation) {
+        issues.push(Issue::UnbalancedBrackets);
+    }
+    issues
+}
+
+/// Validates every `(source, translation)` pair. In strict mode the first
+/// failing segment is returned as an error; otherwise the issues of every
+/// segment are returned, empty where the segment is fine.
+pub fn validate_segments(
+    pairs: &[(&str, &str)],
+    mode: Mode,
+) -> Result<Vec<Vec<Issue>>, DeepLError> {
+    let mut all = Vec::with_capacity(pairs.len());
+    for (segment, (source, translation)) in pairs.iter().enumerate() {
+        let issues = validate(source, translation);
+        if mode == Mode::Strict && !issues.is_empty() {
+            return Err(DeepLError::ValidationFailed { segment, issues });
+        }
+        all.push(issues);
+    }
+    Ok(all)
+}
+
+/// Runs every translation from `inner` through [`validate`].
+pub struct Validated {
+    inner: Arc<dyn Translator>,
+    mode: Mode,
+}
+
+impl Validated {
+    pub fn new(inner: Arc<dyn Translator>, mode: Mode) -> Self {
+        Self { inner, mode }
+    }
+}
+
+impl Translator for Validated {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        src_lang: &'a str,
+        target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move {
+            let mut translation = self.inner.translate(text, src_lang, target_lang).await?;
+            let mut issues = validate_segments(&[(text, &translation.text)], self.mode)?;
+            let issues = issues.pop().unwrap_or_default();
+            if !issues.is_empty() {
+                translation.meta.extensions.insert(
+                    "validation_issues".to_string(),
+                    issues.iter().map(|i| Value::from(i.to_string())).collect(),
+                );
+            }
+            Ok(translation)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholders_and_tags() {
+        assert_eq!(
+            placeholders("Hi {name}, you have %1$d new %(kind)s in ${box}, 100%% {{sure}} { not }"),
+            ["{name}", "%1$d", "%(kind)s", "${box}", "{{sure}}"]
+        );
+        assert_eq!(
+            tags("<b class=\"x\">bold</b> <br/> a < b"),
+            ["<b>", "</b>", "<br/>"]
+        );
+        assert!(brackets_balanced("f(a[1]) （注）"));
+        assert!(!brackets_balanced("f(a]"));
+    }
+
+    #[test]
+    fn test_validate_reports_diffs() {
+        let issues = validate("Hello <b>{name}</b> (friend)", "你好 <b>{nom}</b> (朋友");
+        assert_eq!(
+            issues,
+            [
+                Issue::MissingToken("{name}".to_string()),
+                Issue::ExtraToken("{nom}".to_string()),
+                Issue::UnbalancedBrackets,
+            ]
+        );
+        assert!(validate("<i>%s</i>", "<i>%s</i>").is_empty());
+    }
+
+    #[test]
+    fn test_strict_mode_fails_per_segment() {
+        let pairs = [("ok", "好"), ("{n} files", "文件")];
+        match validate_segments(&pairs, Mode::Strict) {
+            Err(DeepLError::ValidationFailed { segment, issues }) => {
+                assert_eq!(segment, 1);
+                assert_eq!(issues, [Issue::MissingToken("{n}".to_string())]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            validate_segments(&pairs, Mode::Lenient).unwrap()[1].len(),
+            1
+        );
+    }
+}
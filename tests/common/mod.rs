@@ -0,0 +1,88 @@
+//! A throwaway HTTP server that answers every request with one canned
+//! response, for driving the client against misbehaving upstreams.
+
+#![allow(dead_code)]
+
+use std::future::Future;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+pub fn block_on<F: Future>(f: F) -> F::Output {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(f)
+}
+
+/// Builds a raw HTTP/1.1 response with the given status line, extra
+/// header lines and body.
+pub fn response(status: &str, headers: &[&str], body: &str) -> String {
+    let mut out = format!("HTTP/1.1 {}\r\n", status);
+    for header in headers {
+        out.push_str(header);
+        out.push_str("\r\n");
+    }
+    out.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    out
+}
+
+/// Starts a server on a random local port that replies with `raw` to
+/// every request. Returns the endpoint URL. Must be called inside a
+/// runtime.
+pub async fn serve(raw: String) -> String {
+    serve_sequence(vec![raw]).await
+}
+
+/// Like [`serve`], replying with each of `raws` in turn and then with the
+/// last one.
+pub async fn serve_sequence(raws: Vec<String>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut raws = raws.into_iter();
+        let mut last = String::new();
+        while let Ok((mut socket, _)) = listener.accept().await {
+            if let Some(next) = raws.next() {
+                last = next;
+            }
+            let raw = last.clone();
+            tokio::spawn(async move {
+                read_request(&mut socket).await;
+                let _ = socket.write_all(raw.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            });
+        }
+    });
+    format!("http://{}/jsonrpc", addr)
+}
+
+async fn read_request(socket: &mut tokio::net::TcpStream) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        match socket.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+        let text = String::from_utf8_lossy(&buf);
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length = text[..end]
+                .lines()
+                .filter_map(|l| l.split_once(':'))
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                .and_then(|(_, v)| v.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if buf.len() >= end + 4 + length {
+                return;
+            }
+        }
+    }
+}
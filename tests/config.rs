@@ -0,0 +1,46 @@
+//! A client built from a config file uses its endpoint, proxy and
+//! timeouts.
+
+#![cfg(all(feature = "client", feature = "config"))]
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{block_on, response, serve};
+use deeplx_rs::{config::Config, error::DeepLError, DeepLClient, Translator};
+use tokio::net::TcpListener;
+
+#[test]
+fn test_client_follows_the_config() {
+    block_on(async {
+        let proxy = serve(response("403 Forbidden", &[], "via proxy")).await;
+        let config = Config::parse(&format!(
+            "endpoint = \"http://deepl.invalid/jsonrpc\"\nproxy = \"{}\"\nretries = 0\n",
+            proxy.trim_end_matches("/jsonrpc")
+        ))
+        .unwrap();
+        let client = DeepLClient::from_config(&config).unwrap();
+        match client.translate("hello", "EN", "DE").await {
+            Err(DeepLError::Blocked { body }) => assert_eq!(body, "via proxy"),
+            other => panic!("proxy gave {:?}", other),
+        }
+
+        // Accepts connections and never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent = format!("http://{}/jsonrpc", listener.local_addr().unwrap());
+        let config = Config::parse(&format!(
+            "endpoint = \"{}\"\nproxy = \"direct\"\n\n[timeouts]\nrequest = 1\n",
+            silent
+        ))
+        .unwrap();
+        let client = DeepLClient::from_config(&config).unwrap();
+        let started = Instant::now();
+        assert!(matches!(
+            client.translate("hello", "EN", "DE").await,
+            Err(DeepLError::Network(_))
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(listener);
+    });
+}
@@ -0,0 +1,146 @@
+//! Serializer snapshot tests for strict compatibility mode, which is to
+//! answer byte for byte like the Go DeepLX server.
+//!
+//! `tests/snapshots/deeplx/*.json` hold the bodies expected for each
+//! request. They were written by hand from the Go server's handlers, its
+//! `gin.H` maps as `encoding/json` renders them, not taken from a running
+//! Go server, so they pin this crate's serializer to that reading of the
+//! Go source rather than prove it against the real thing.
+
+#![cfg(feature = "server")]
+
+mod common;
+
+use std::{collections::HashMap, fs, sync::Arc};
+
+use common::block_on;
+use deeplx_rs::{
+    error::DeepLError,
+    server::{Compat, Server},
+    translator::{BoxFuture, Translation, TranslationMeta, Translator},
+};
+use hyper::{Body, Request};
+use serde_json::Value;
+
+/// Uppercases, with alternatives for "Good day", and fails as told by the
+/// text.
+struct Fixed;
+
+impl Translator for Fixed {
+    fn name(&self) -> &str {
+        "fixed"
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        _src_lang: &'a str,
+        _target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move {
+            match text {
+                "busy" => return Err(DeepLError::RateLimited { retry_after: None }),
+                "broken" => return Err(DeepLError::NoProvider),
+                _ => {}
+            }
+            let (text, alternatives) = match text {
+                "Good day" => (
+                    "Guten Tag".to_string(),
+                    vec!["Hallo".to_string(), "Servus".to_string()],
+                ),
+                text => (text.to_uppercase(), Vec::new()),
+            };
+            Ok(Translation {
+                text,
+                detected_source: Some("EN".to_string()),
+                alternatives,
+                meta: TranslationMeta {
+                    extensions: HashMap::from([("id".to_string(), Value::from(8_300_000_000i64))]),
+                    ..Default::default()
+                },
+            })
+        })
+    }
+}
+
+fn post(uri: &str, body: &str) -> Request<Body> {
+    Request::post(uri)
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[test]
+fn test_strict_responses_match_their_snapshots() {
+    let server = Server::new(Arc::new(Fixed))
+        .with_token("secret")
+        .with_compat(Compat::Strict);
+    let cases = [
+        (
+            "translate",
+            200,
+            post(
+                "/translate?token=secret",
+                r#"{"text": "<b>Tom & Jerry</b>", "source_lang": "EN", "target_lang": "ZH"}"#,
+            ),
+        ),
+        (
+            "alternatives",
+            200,
+            post(
+                "/translate?token=secret",
+                r#"{"text": "Good day", "target_lang": "DE"}"#,
+            ),
+        ),
+        (
+            "too_many_requests",
+            429,
+            post(
+                "/translate?token=secret",
+                r#"{"text": "busy", "target_lang": "DE"}"#,
+            ),
+        ),
+        (
+            "internal_error",
+            500,
+            post(
+                "/translate?token=secret",
+                r#"{"text": "broken", "target_lang": "DE"}"#,
+            ),
+        ),
+        (
+            "no_text",
+            404,
+            post(
+                "/translate?token=secret",
+                r#"{"text": "", "target_lang": "DE"}"#,
+            ),
+        ),
+        ("path_not_found", 404, post("/v2/translate", "{}")),
+        (
+            "invalid_token",
+            401,
+            post(
+                "/translate?token=wrong",
+                r#"{"text": "hi", "target_lang": "DE"}"#,
+            ),
+        ),
+    ];
+    for (name, status, req) in cases {
+        let (got_status, content_type, body) = block_on(async {
+            let response = server.handle(req).await;
+            let content_type = response.headers()["content-type"].clone();
+            let status = response.status().as_u16();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            (status, content_type, body)
+        });
+        let expected = fs::read(format!("tests/snapshots/deeplx/{}.json", name)).unwrap();
+        assert_eq!(got_status, status, "{}", name);
+        assert_eq!(content_type, "application/json; charset=utf-8");
+        assert_eq!(
+            String::from_utf8_lossy(&body),
+            String::from_utf8_lossy(&expected),
+            "{}",
+            name
+        );
+    }
+}
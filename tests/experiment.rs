@@ -0,0 +1,37 @@
+//! Strategies are compared on the same workload.
+
+#![cfg(feature = "client")]
+
+mod common;
+
+use common::{block_on, response, serve_sequence};
+use deeplx_rs::{experiment::Experiment, DeepLClient, RequestStrategy};
+
+const OK: &str = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[{\"text\":\"Hallo\",\"alternatives\":[]}],\"lang\":\"EN\",\"lang_is_confident\":true,\"detectedLanguages\":{}}}";
+
+#[test]
+fn test_strategies_are_interleaved_and_counted() {
+    let report = block_on(async {
+        // Requests alternate spaced, compact, spaced, compact.
+        let endpoint = serve_sequence(vec![
+            response("200 OK", &[], OK),
+            response("403 Forbidden", &[], ""),
+            response("200 OK", &[], OK),
+            response("429 Too Many Requests", &["Retry-After: 0"], ""),
+        ])
+        .await;
+        Experiment::new(["Hello".to_string()])
+            .strategies([RequestStrategy::Spaced, RequestStrategy::Compact])
+            .rounds(2)
+            .run(&DeepLClient::with_endpoint(endpoint))
+            .await
+    });
+    let [spaced, compact] = &report.strategies[..] else {
+        panic!("{:?}", report);
+    };
+    assert_eq!((spaced.requests, spaced.succeeded), (2, 2));
+    assert_eq!((compact.banned, compact.rate_limited), (1, 1));
+    assert_eq!(compact.ban_rate(), 0.5);
+    assert_eq!(report.best(), Some(RequestStrategy::Spaced));
+    assert!(report.to_string().contains("best: spaced"));
+}
@@ -0,0 +1,149 @@
+//! Golden-file tests for the format modules.
+//!
+//! Each `tests/golden/<name>` is translated with a deterministic
+//! pseudo-translator and compared with `tests/golden/<stem>.expected.<ext>`.
+//! Run with `GOLDEN_UPDATE=1` to rewrite the expected files after an
+//! intentional change, then review the diff.
+
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use common::block_on;
+use deeplx_rs::{
+    error::DeepLError,
+    formats::{
+        translate_file, AndroidStrings, AssFile, Format, JsonFile, MarkdownFile, PoFile, SrtFile,
+        VttFile, WhisperJson, XliffFile,
+    },
+    protect::{mask, unmask},
+    translator::{BoxFuture, Translation, Translator},
+};
+
+/// Accents vowels and brackets the segment, leaving placeholders, tags,
+/// entities and backslash escapes alone, so any text the format modules
+/// failed to hand over (or handed over twice) stands out in the output.
+struct Pseudo;
+
+fn accent(c: char) -> char {
+    match c {
+        'a' => 'á',
+        'e' => 'é',
+        'i' => 'í',
+        'o' => 'ó',
+        'u' => 'ú',
+        'A' => 'Á',
+        'E' => 'É',
+        'I' => 'Í',
+        'O' => 'Ó',
+        'U' => 'Ú',
+        c => c,
+    }
+}
+
+fn pseudo(text: &str) -> String {
+    let masked = mask(text);
+    let mut out = String::from("[");
+    let (mut in_entity, mut escaped) = (false, false);
+    for c in masked.text.chars() {
+        if escaped {
+            escaped = false;
+            out.push(c);
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '&' => in_entity = true,
+            ';' | ' ' => in_entity = false,
+            _ => {}
+        }
+        out.push(if in_entity { c } else { accent(c) });
+    }
+    out.push(']');
+    unmask(&out, &masked.tokens).unwrap()
+}
+
+impl Translator for Pseudo {
+    fn name(&self) -> &str {
+        "pseudo"
+    }
+
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        _src_lang: &'a str,
+        _target_lang: &'a str,
+    ) -> BoxFuture<'a, Result<Translation, DeepLError>> {
+        Box::pin(async move {
+            Ok(Translation {
+                text: pseudo(text),
+                ..Default::default()
+            })
+        })
+    }
+}
+
+fn check<F: Format>(name: &str) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let input = fs::read_to_string(dir.join(name)).unwrap();
+    let output = block_on(translate_file::<F>(&Pseudo, &input, "EN", "DE"))
+        .unwrap_or_else(|e| panic!("{}: {}", name, e));
+
+    let (stem, ext) = name.rsplit_once('.').unwrap();
+    let expected_path = dir.join(format!("{}.expected.{}", stem, ext));
+    if std::env::var_os("GOLDEN_UPDATE").is_some() {
+        fs::write(&expected_path, &output).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&expected_path).unwrap();
+    assert_eq!(
+        output, expected,
+        "{} no longer matches its golden file",
+        name
+    );
+}
+
+#[test]
+fn test_golden_srt() {
+    check::<SrtFile>("subtitles_bom.srt");
+}
+
+#[test]
+fn test_golden_po() {
+    check::<PoFile>("messages_plural.po");
+}
+
+#[test]
+fn test_golden_android() {
+    check::<AndroidStrings>("strings.xml");
+}
+
+#[test]
+fn test_golden_json() {
+    check::<JsonFile>("nested.json");
+}
+
+#[test]
+fn test_golden_xliff() {
+    check::<XliffFile>("sample.xlf");
+}
+
+#[test]
+fn test_golden_ass() {
+    check::<AssFile>("sample.ass");
+}
+
+#[test]
+fn test_golden_vtt() {
+    check::<VttFile>("meeting.vtt");
+}
+
+#[test]
+fn test_golden_whisper() {
+    check::<WhisperJson>("podcast.json");
+}
+
+#[test]
+fn test_golden_markdown() {
+    check::<MarkdownFile>("guide.md");
+}
@@ -0,0 +1,682 @@
+//! Malformed upstream responses must surface as errors, never panics.
+
+#![cfg(feature = "client")]
+
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{block_on, response, serve, serve_sequence};
+use deeplx_rs::{
+    batch::BatchOptions,
+    budget::{Budget, Period},
+    cache::TranslationCache,
+    cookies::CookieJar,
+    document::{DocumentOptions, DocumentOutput, DocumentState},
+    error::DeepLError,
+    glossary::Glossary,
+    maintenance::{Mode, Switch},
+    middleware::{self, Middleware},
+    options::{Casing, Formality, TagHandling, TagMode},
+    retry::RetryPolicy,
+    session::SessionPool,
+    telemetry::PrometheusSink,
+    translator::BoxFuture,
+    DeepLClient, Translation, Translator,
+};
+
+const OK: &str = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[{\"text\":\"你好\",\"alternatives\":[]}],\"lang\":\"EN\",\"lang_is_confident\":true,\"detectedLanguages\":{}}}";
+const CHALLENGE: &str = "<!DOCTYPE html><html><head><title>Just a moment...</title></head><body><script src=\"/cdn-cgi/challenge-platform/h/b/orchestrate/chl_page/v1\"></script></body></html>";
+
+fn translate(raw: String) -> Result<Translation, DeepLError> {
+    block_on(async move {
+        let client = DeepLClient::with_endpoint(serve(raw).await);
+        client.translate("hello", "EN", "ZH").await
+    })
+}
+
+#[test]
+fn test_malformed_bodies_are_errors() {
+    let bodies = [
+        "",
+        "not json",
+        "<html><body>502 Bad Gateway</body></html>",
+        "{\"jsonrpc\":\"2.0\"}",
+        "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[{}]}}",
+        "{\"result\":null}",
+        "[]",
+        "\u{feff}{",
+    ];
+    for body in bodies {
+        let raw = response("200 OK", &["Content-Type: application/json"], body);
+        match translate(raw) {
+            Err(DeepLError::Deserialize(_)) => {}
+            other => panic!("{:?} gave {:?}", body, other),
+        }
+    }
+}
+
+#[test]
+fn test_error_statuses_are_errors() {
+    let raw = response("429 Too Many Requests", &["Retry-After: soon"], "");
+    match translate(raw) {
+        Err(DeepLError::RateLimited { retry_after }) => {
+            assert_eq!(retry_after, None)
+        }
+        other => panic!("429 gave {:?}", other),
+    }
+
+    let raw = response("403 Forbidden", &[], "blocked");
+    assert!(matches!(translate(raw), Err(DeepLError::Blocked { body }) if body == "blocked"));
+
+    let raw = response("403 Forbidden", &["Content-Type: text/html"], CHALLENGE);
+    assert!(matches!(
+        translate(raw),
+        Err(DeepLError::ChallengeRequired { status: 403, marker, .. }) if marker == "cloudflare"
+    ));
+
+    let raw = response("500 Internal Server Error", &[], "\u{0}\u{1}");
+    assert!(matches!(
+        translate(raw),
+        Err(DeepLError::Status { status: 500, .. })
+    ));
+}
+
+#[test]
+fn test_batch_results_keep_input_order() {
+    let two = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[{\"text\":\"一\",\"alternatives\":[]},{\"text\":\"二\",\"alternatives\":[]}],\"lang\":\"EN\",\"lang_is_confident\":true,\"detectedLanguages\":{}}}";
+    let result = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve(response("200 OK", &[], two)).await);
+        client.translate_batch(&["one", "two"], "EN", "ZH").await
+    });
+    let texts: Vec<String> = result.unwrap().into_iter().map(|t| t.text).collect();
+    assert_eq!(texts, ["一", "二"]);
+
+    // One text back for two sent must not be paired up silently.
+    let result = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve(response("200 OK", &[], OK)).await);
+        client.translate_batch(&["one", "two"], "EN", "ZH").await
+    });
+    assert!(matches!(result, Err(DeepLError::Deserialize(_))));
+}
+
+#[test]
+fn test_batches_follow_the_casing_policy() {
+    let hallo = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[{\"text\":\"hallo welt\",\"alternatives\":[]}],\"lang\":\"EN\",\"lang_is_confident\":true,\"detectedLanguages\":{}}}";
+    let (single, batch) = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve(response("200 OK", &[], hallo)).await)
+            .with_casing(Some(Casing::PreserveSource));
+        let single = Translator::translate(&client, "HELLO WORLD", "EN", "DE").await;
+        let batch = Translator::translate_batch(&client, &["HELLO WORLD"], "EN", "DE").await;
+        (single.unwrap(), batch.unwrap())
+    });
+    assert_eq!(single.text, "HALLO WELT");
+    assert_eq!(batch[0].text, single.text);
+}
+
+#[test]
+fn test_translate_many_keeps_failed_items() {
+    let replies = vec![
+        response("200 OK", &[], OK),
+        response("500 Internal Server Error", &[], "down"),
+        response("200 OK", &[], OK),
+    ];
+    let batch = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve_sequence(replies).await);
+        let options = BatchOptions::new("EN", "ZH").with_concurrency(1);
+        client
+            .translate_many(["one", "two", "three"], &options)
+            .await
+    });
+    assert_eq!(batch.failed(), 1);
+    assert!(matches!(
+        batch.errors().next(),
+        Some((1, DeepLError::Status { status: 500, .. }))
+    ));
+    assert_eq!(batch.results[2].as_ref().unwrap().text, "你好");
+}
+
+#[test]
+fn test_detect_language() {
+    let body = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[{\"text\":\"Hello\",\"alternatives\":[]}],\"lang\":\"DE\",\"lang_is_confident\":false,\"detectedLanguages\":{\"DE\":0.6,\"NL\":0.3,\"EN\":0.1}}}";
+    let detection = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve(response("200 OK", &[], body)).await);
+        client.detect_language("Hallo").await
+    })
+    .unwrap();
+    assert_eq!(
+        (detection.lang.as_str(), detection.confident),
+        ("DE", false)
+    );
+    assert_eq!(detection.confidence(), Some(0.6));
+    assert_eq!(detection.ranked()[1], ("NL", 0.3));
+
+    // Nothing detected is an error, not an empty language.
+    let body = OK.replace("\"lang\":\"EN\"", "\"lang\":\"\"");
+    let result = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve(response("200 OK", &[], &body)).await);
+        client.detect_language("?").await
+    });
+    assert!(matches!(result, Err(DeepLError::Deserialize(_))));
+}
+
+#[test]
+fn test_translate_with_alternatives() {
+    let body = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[{\"text\":\"Bank\",\"alternatives\":[{\"text\":\"Ufer\"},{\"text\":\"Sitzbank\"}]}],\"lang\":\"EN\",\"lang_is_confident\":true,\"detectedLanguages\":{}}}";
+    let translation = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve(response("200 OK", &[], body)).await);
+        client
+            .translate_with_alternatives("bank", "EN", "DE", 2)
+            .await
+    })
+    .unwrap();
+    assert_eq!(
+        translation.candidates().collect::<Vec<_>>(),
+        ["Bank", "Ufer", "Sitzbank"]
+    );
+}
+
+#[test]
+fn test_web_tag_handling_keeps_markup() {
+    let body = |text: &str| {
+        format!("{{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{{\"texts\":[{{\"text\":\"{}\",\"alternatives\":[]}}],\"lang\":\"EN\",\"lang_is_confident\":true,\"detectedLanguages\":{{}}}}}}", text)
+    };
+    let replies = vec![
+        response("200 OK", &[], &body("⟦0⟧Hallo ⟦1⟧⟦2⟧")),
+        response("200 OK", &[], &body("⟦0⟧Hallo")),
+    ];
+    let (kept, broken) = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve_sequence(replies).await).with_tag_handling(
+            Some(TagHandling::new(TagMode::Html).with_ignore_tags(["code"])),
+        );
+        let text = "<b>Hello <code>x &lt; y</code></b>";
+        (
+            client.translate(text, "EN", "DE").await,
+            client.translate(text, "EN", "DE").await,
+        )
+    });
+    assert_eq!(kept.unwrap().text, "<b>Hallo <code>x &lt; y</code></b>");
+    assert!(matches!(
+        broken,
+        Err(DeepLError::ValidationFailed { segment: 0, .. })
+    ));
+}
+
+#[test]
+fn test_batches_go_through_the_cache() {
+    let two = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[{\"text\":\"一\",\"alternatives\":[]},{\"text\":\"二\",\"alternatives\":[]}],\"lang\":\"EN\",\"lang_is_confident\":true,\"detectedLanguages\":{}}}";
+    // The second batch must send only the text the first did not have:
+    // a two-text request would be answered with one text and fail.
+    let replies = vec![response("200 OK", &[], two), response("200 OK", &[], OK)];
+    let cache = Arc::new(TranslationCache::new(8));
+    let (first, second, single) = block_on({
+        let cache = cache.clone();
+        async move {
+            let client =
+                DeepLClient::with_endpoint(serve_sequence(replies).await).with_cache(cache);
+            (
+                client.translate_batch(&["one", "two"], "EN", "ZH").await,
+                Translator::translate_batch(&client, &["two", "three"], "EN", "ZH").await,
+                client.translate("one", "EN", "ZH").await,
+            )
+        }
+    });
+    let texts = |result: Result<Vec<Translation>, DeepLError>| -> Vec<String> {
+        result.unwrap().into_iter().map(|t| t.text).collect()
+    };
+    assert_eq!(texts(first), ["一", "二"]);
+    assert_eq!(texts(second), ["二", "你好"]);
+    assert_eq!(single.unwrap().text, "一");
+    assert_eq!(cache.stats().entries, 3);
+}
+
+#[test]
+fn test_cache_answers_repeated_requests() {
+    let replies = vec![
+        response("200 OK", &[], OK),
+        response("500 Internal Server Error", &[], "down"),
+    ];
+    let cache = Arc::new(TranslationCache::new(8));
+    let (first, again, formal) = block_on({
+        let cache = cache.clone();
+        async move {
+            let client =
+                DeepLClient::with_endpoint(serve_sequence(replies).await).with_cache(cache);
+            (
+                client.translate("hello", "EN", "ZH").await,
+                client.translate("hello\n", "EN", "ZH").await,
+                client
+                    .clone()
+                    .with_formality(Some(Formality::Formal))
+                    .translate("hello", "EN", "ZH")
+                    .await,
+            )
+        }
+    });
+    assert_eq!(first.unwrap().text, "你好");
+    assert_eq!(again.unwrap().text, "你好");
+    assert!(formal.is_err());
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 1));
+}
+
+#[test]
+fn test_switched_off_clients_answer_from_cache() {
+    let switch = Switch::default();
+    let cache = Arc::new(TranslationCache::new(8).with_ttl(Duration::ZERO));
+    let (fresh, stale, missed, paused) = block_on({
+        let switch = switch.clone();
+        async move {
+            let client = DeepLClient::with_endpoint(serve(response("200 OK", &[], OK)).await)
+                .with_cache(cache)
+                .with_switch(switch.clone());
+            let fresh = client.translate("hello", "EN", "ZH").await;
+            switch.set(Mode::Maintenance);
+            let stale = client.translate("hello", "EN", "ZH").await;
+            let missed = client.translate("bye", "EN", "ZH").await;
+            switch.set(Mode::Paused);
+            (
+                fresh,
+                stale,
+                missed,
+                client.translate("hello", "EN", "ZH").await,
+            )
+        }
+    });
+    assert!(!fresh.unwrap().is_stale());
+    assert!(stale.unwrap().is_stale());
+    assert!(matches!(
+        missed,
+        Err(DeepLError::Paused {
+            mode: Mode::Maintenance
+        })
+    ));
+    // Paused, expired entries are not served either.
+    assert!(matches!(paused, Err(e) if e.is_unavailable()));
+}
+
+#[test]
+fn test_stale_entries_stand_in_for_an_unavailable_upstream() {
+    let replies = vec![
+        response("200 OK", &[], OK),
+        response("503 Service Unavailable", &[], "down"),
+        response("400 Bad Request", &[], "bad"),
+        response("503 Service Unavailable", &[], "down"),
+    ];
+    let cache = Arc::new(TranslationCache::new(8).with_ttl(Duration::ZERO));
+    let (fresh, stale, rejected, too_old) = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve_sequence(replies).await)
+            .with_cache(cache)
+            .serve_stale(Duration::from_secs(60));
+        (
+            client.translate("hello", "EN", "ZH").await,
+            client.translate("hello", "EN", "ZH").await,
+            client.translate("hello", "EN", "ZH").await,
+            client
+                .clone()
+                .serve_stale(Duration::ZERO)
+                .translate("hello", "EN", "ZH")
+                .await,
+        )
+    });
+    assert!(!fresh.unwrap().is_stale());
+    let stale = stale.unwrap();
+    assert_eq!(stale.text, "你好");
+    assert!(stale.is_stale());
+    // Only an unavailable upstream falls back, and only within the limit.
+    assert!(matches!(
+        rejected,
+        Err(DeepLError::Status { status: 400, .. })
+    ));
+    assert!(matches!(
+        too_old,
+        Err(DeepLError::Status { status: 503, .. })
+    ));
+}
+
+#[test]
+fn test_official_api_backend() {
+    let body = r#"{"translations":[{"detected_source_language":"EN","text":"Hallo"},{"detected_source_language":"EN","text":"Welt"}]}"#;
+    let translations = block_on(async move {
+        let client = DeepLClient::with_endpoint(serve(response("200 OK", &[], body)).await)
+            .with_auth_key("key:fx");
+        client
+            .translate_batch(&["Hello", "world"], "auto", "DE")
+            .await
+    })
+    .unwrap();
+    let texts: Vec<_> = translations.iter().map(|t| t.text.as_str()).collect();
+    assert_eq!(texts, ["Hallo", "Welt"]);
+    assert_eq!(translations[0].detected_source.as_deref(), Some("EN"));
+
+    // A bad key is a plain status, not an IP ban.
+    let result = block_on(async move {
+        let forbidden = response("403 Forbidden", &[], r#"{"message":"Wrong key"}"#);
+        let client = DeepLClient::with_endpoint(serve(forbidden).await).with_auth_key("key");
+        client.translate_raw("Hello", "EN", "DE").await
+    });
+    assert!(matches!(
+        result,
+        Err(DeepLError::Status { status: 403, .. })
+    ));
+    assert_eq!(
+        DeepLClient::new().with_auth_key("key:fx").endpoint(),
+        deeplx_rs::official::DEEPL_API_FREE
+    );
+}
+
+#[test]
+fn test_official_glossaries() {
+    let created = r#"{"glossary_id":"g-1","name":"docs","source_lang":"en","target_lang":"de","entry_count":1,"ready":true}"#;
+    let listed = format!(r#"{{"glossaries":[{}]}}"#, created);
+    let result = block_on(async move {
+        let endpoint = serve_sequence(vec![
+            response("201 Created", &[], created),
+            response("200 OK", &[], &listed),
+        ])
+        .await;
+        let client = DeepLClient::with_endpoint(format!("{}/v2/translate", endpoint))
+            .with_auth_key("key:fx");
+        let glossary = Glossary::parse_tsv("bucket\tBucket\n").unwrap();
+        let info = client.create_glossary("docs", "EN", "DE", &glossary).await;
+        (info, client.list_glossaries().await)
+    });
+    let info = result.0.unwrap();
+    assert_eq!((info.glossary_id.as_str(), info.entry_count), ("g-1", 1));
+    assert_eq!(result.1.unwrap(), [info]);
+
+    // The web endpoint has no glossaries.
+    let result = block_on(DeepLClient::new().list_glossaries());
+    assert!(matches!(result, Err(DeepLError::NoProvider)));
+}
+
+#[test]
+fn test_document_translation() {
+    let path = std::env::temp_dir().join(format!("deeplx-doc-{}.docx", std::process::id()));
+    std::fs::write(&path, b"PK original").unwrap();
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let options = DocumentOptions::new("DE")
+        .with_poll_interval(Duration::from_millis(1))
+        .with_progress({
+            let seen = seen.clone();
+            move |status| seen.lock().unwrap().push(status.status)
+        });
+    let doc = path.clone();
+    let result = block_on(async move {
+        let endpoint = serve_sequence(vec![
+            response("200 OK", &[], r#"{"document_id":"D1","document_key":"K1"}"#),
+            response(
+                "200 OK",
+                &[],
+                r#"{"document_id":"D1","status":"translating","seconds_remaining":0}"#,
+            ),
+            response(
+                "200 OK",
+                &[],
+                r#"{"document_id":"D1","status":"done","billed_characters":8}"#,
+            ),
+            response("200 OK", &[], "PK translated"),
+        ])
+        .await;
+        let client = DeepLClient::with_endpoint(format!("{}/v2/translate", endpoint))
+            .with_auth_key("key:fx");
+        client.translate_document(&doc, &options).await
+    });
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        result.unwrap(),
+        DocumentOutput::Bytes(b"PK translated".to_vec())
+    );
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [DocumentState::Translating, DocumentState::Done]
+    );
+}
+
+#[test]
+fn test_budget_hard_limit_spares_priority_requests() {
+    let budget = Arc::new(Budget::new(Period::Day).with_hard_limit(8));
+    let result = block_on(async move {
+        let endpoint = serve(response("200 OK", &[], OK)).await;
+        let client = DeepLClient::with_endpoint(endpoint).with_budget(budget.clone());
+        let spent = client.translate_raw("Hello world", "EN", "DE").await;
+        let priority = client
+            .with_priority(true)
+            .translate_raw("Hello world", "EN", "DE")
+            .await;
+        (spent, priority.map(|_| ()), budget.usage())
+    });
+    assert!(matches!(
+        result.0,
+        Err(DeepLError::BudgetExceeded {
+            used: 0,
+            limit: 8,
+            ..
+        })
+    ));
+    assert!(result.1.is_ok());
+    assert_eq!((result.2.used, result.2.rejected), (11, 1));
+}
+
+#[test]
+fn test_requests_past_the_soft_limit_are_counted() {
+    let sink = Arc::new(PrometheusSink::new());
+    block_on(async {
+        let client = DeepLClient::with_endpoint(serve(response("200 OK", &[], OK)).await)
+            .with_telemetry(sink.clone())
+            .with_budget(Arc::new(Budget::new(Period::Day).with_soft_limit(6)));
+        for text in ["hello", "hello", "hello"] {
+            client.translate(text, "EN", "ZH").await.unwrap();
+        }
+    });
+    assert!(sink
+        .render()
+        .contains("deeplx_budget_over_soft_limit_total 2\n"));
+}
+
+#[test]
+fn test_transient_errors_are_retried() {
+    let policy = RetryPolicy {
+        base_delay: Duration::from_millis(1),
+        ..RetryPolicy::default()
+    };
+    let result = block_on(async move {
+        let endpoint = serve_sequence(vec![
+            response("503 Service Unavailable", &[], ""),
+            response("429 Too Many Requests", &["Retry-After: 0"], ""),
+            response("200 OK", &[], OK),
+        ])
+        .await;
+        let client = DeepLClient::with_endpoint(endpoint).with_retry(policy);
+        client.translate("hello", "EN", "ZH").await
+    });
+    assert_eq!(result.unwrap().text, "你好");
+
+    let raw = response("400 Bad Request", &[], "");
+    let result = block_on(async move {
+        let client =
+            DeepLClient::with_endpoint(serve(raw).await).with_retry(RetryPolicy::default());
+        client.translate("hello", "EN", "ZH").await
+    });
+    assert!(matches!(
+        result,
+        Err(DeepLError::Status { status: 400, .. })
+    ));
+}
+
+#[test]
+fn test_rejected_sessions_rotate() {
+    let pool = Arc::new(
+        SessionPool::new(["expired", "challenged", "fresh"].map(String::from))
+            .rotate_every(Duration::from_secs(3600)),
+    );
+    let sessions = pool.clone();
+    let result = block_on(async move {
+        let endpoint = serve_sequence(vec![
+            response("401 Unauthorized", &[], ""),
+            response("403 Forbidden", &["Content-Type: text/html"], CHALLENGE),
+            response("200 OK", &[], OK),
+        ])
+        .await;
+        let client = DeepLClient::with_endpoint(endpoint).with_session_pool(sessions);
+        client.translate("hello", "EN", "ZH").await
+    });
+    assert_eq!(result.unwrap().text, "你好");
+    let usage = pool.usage();
+    assert!(!usage[0].healthy && !usage[1].healthy);
+    assert_eq!((usage[2].requests, usage[2].chars), (1, 5));
+}
+
+#[test]
+fn test_unsupported_languages_are_refused_locally() {
+    let client = DeepLClient::with_endpoint("http://127.0.0.1:9");
+    let result = block_on(client.translate("hello", "EN", "XX"));
+    assert!(matches!(result, Err(DeepLError::InvalidLanguage { lang }) if lang == "XX"));
+    let result = block_on(client.translate("hello", "klingon", "DE"));
+    assert!(matches!(result, Err(DeepLError::InvalidLanguage { lang }) if lang == "klingon"));
+}
+
+#[test]
+fn test_empty_texts_translate_to_empty_string() {
+    let body = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[],\"lang\":\"EN\",\"lang_is_confident\":true,\"detectedLanguages\":{}}}";
+    let translation = translate(response("200 OK", &[], body)).unwrap();
+    assert_eq!(translation.text, "");
+    assert!(translation.alternatives.is_empty());
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn test_blocking_client_inside_and_outside_a_runtime() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let endpoint = runtime.block_on(serve(response("200 OK", &[], OK)));
+    let client = deeplx_rs::blocking::DeepLClient::with_endpoint(endpoint);
+    assert_eq!(client.translate("hello", "EN", "ZH").unwrap().text, "你好");
+    let inside = runtime.block_on(async { client.translate("hello", "EN", "ZH") });
+    assert_eq!(inside.unwrap().text, "你好");
+    let refused = client.translate("hello", "EN", "XX");
+    assert!(matches!(refused, Err(DeepLError::InvalidLanguage { .. })));
+}
+
+#[test]
+fn test_cookie_jar_keeps_cookies_from_responses() {
+    let jar = Arc::new(CookieJar::new());
+    let raw = response(
+        "200 OK",
+        &[
+            "Set-Cookie: LMTBID=v2|abc; Path=/; Max-Age=3600",
+            "Set-Cookie: old=1; Max-Age=0",
+        ],
+        OK,
+    );
+    let result = block_on(async {
+        let client = DeepLClient::with_endpoint(serve(raw).await).with_cookie_jar(jar.clone());
+        client.translate("hello", "EN", "ZH").await?;
+        client.translate("hello", "EN", "ZH").await
+    });
+    assert_eq!(result.unwrap().text, "你好");
+    let cookies = jar.cookies();
+    assert_eq!(cookies.len(), 1);
+    assert_eq!(
+        (cookies[0].name.as_str(), cookies[0].domain.as_str()),
+        ("LMTBID", "127.0.0.1")
+    );
+}
+
+#[derive(Debug, Default)]
+struct Recorder {
+    seen: Mutex<Vec<String>>,
+}
+
+impl Middleware for Recorder {
+    fn on_request<'a>(
+        &'a self,
+        request: &'a mut middleware::Request,
+    ) -> BoxFuture<'a, Result<(), DeepLError>> {
+        Box::pin(async move {
+            request
+                .headers
+                .insert("x-trace", middleware::HeaderValue::from_static("abc"));
+            self.seen.lock().unwrap().push(request.body.clone());
+            Ok(())
+        })
+    }
+
+    fn on_response<'a>(
+        &'a self,
+        _request: &'a middleware::Request,
+        response: &'a mut middleware::Response,
+    ) -> BoxFuture<'a, Result<(), DeepLError>> {
+        Box::pin(async move {
+            response.body = response.body.replace("你好", "hi");
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Deny;
+
+impl Middleware for Deny {
+    fn on_request<'a>(
+        &'a self,
+        _request: &'a mut middleware::Request,
+    ) -> BoxFuture<'a, Result<(), DeepLError>> {
+        Box::pin(async { Err(DeepLError::NoProvider) })
+    }
+}
+
+#[test]
+fn test_middleware_sees_requests_and_rewrites_responses() {
+    let recorder = Arc::new(Recorder::default());
+    let (translated, denied) = block_on(async {
+        let endpoint = serve(response("200 OK", &[], OK)).await;
+        let client = DeepLClient::with_endpoint(endpoint).with_middleware(recorder.clone());
+        let translated = client.translate("hello", "EN", "ZH").await;
+        let denied = client
+            .with_middleware(Arc::new(Deny))
+            .translate("hello", "EN", "ZH")
+            .await;
+        (translated, denied)
+    });
+    assert_eq!(translated.unwrap().text, "hi");
+    assert!(matches!(denied, Err(DeepLError::NoProvider)));
+    let seen = recorder.seen.lock().unwrap();
+    assert_eq!(seen.len(), 2);
+    assert!(seen[0].contains("\"text\":\"hello\""));
+}
+
+#[test]
+fn test_telemetry_counts_upstream_statuses_and_cache_hits() {
+    let sink = Arc::new(PrometheusSink::new());
+    let results = block_on(async {
+        let endpoint = serve_sequence(vec![
+            response("429 Too Many Requests", &["Retry-After: 1"], ""),
+            response("200 OK", &[], OK),
+        ])
+        .await;
+        let client = DeepLClient::with_endpoint(endpoint)
+            .with_telemetry(sink.clone())
+            .with_cache(Arc::new(TranslationCache::new(10)));
+        let mut results = Vec::new();
+        for _ in 0..3 {
+            results.push(client.translate("hello", "EN", "ZH").await);
+        }
+        results
+    });
+    assert!(matches!(results[0], Err(DeepLError::RateLimited { .. })));
+    assert!(results[1].is_ok() && results[2].is_ok());
+    let text = sink.render();
+    assert!(text.contains("deeplx_upstream_responses_total{status=\"429\"} 1\n"));
+    assert!(text.contains("deeplx_upstream_responses_total{status=\"200\"} 1\n"));
+    assert!(text.contains("deeplx_cache_lookups_total{result=\"hit\"} 1\n"));
+    assert!(text.contains("deeplx_cache_lookups_total{result=\"miss\"} 2\n"));
+    assert!(text.contains("deeplx_cache_hits_total 1\n"));
+    assert!(text.contains("deeplx_cache_misses_total 2\n"));
+    assert!(text.contains("deeplx_cache_hit_ratio 0.333"));
+}
@@ -0,0 +1,115 @@
+//! Serializer snapshot tests for the request bodies sent upstream.
+//!
+//! Each `tests/snapshots/payloads/<name>.json` holds a request's inputs,
+//! including the id and the time it was built at, and the `body` this
+//! crate's serializer built for them. Rebuilt with the same frozen id and
+//! clock, the body must match byte for byte: DeepL has blocked requests
+//! over changes as small as a space, a reordered field or another number
+//! format. The bodies come from the serializer itself, not from traffic
+//! to DeepL, so they catch accidental changes, not drift from what the
+//! apps send. Run with `GOLDEN_UPDATE=1` to rewrite the bodies after an
+//! intentional change, then review the diff.
+
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use deeplx_rs::{
+    build_batch_post_data_frozen,
+    options::{Formality, Splitting},
+    Frozen, JobOptions, RequestStrategy,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize)]
+struct Snapshot {
+    id: i64,
+    now_ms: u64,
+    strategy: RequestStrategy,
+    texts: Vec<String>,
+    source_lang: String,
+    target_lang: String,
+    alternatives: u32,
+    formality: Option<Formality>,
+    #[serde(default)]
+    splitting: Splitting,
+    body: String,
+}
+
+impl Snapshot {
+    fn build(&self) -> String {
+        let frozen = Frozen {
+            id: self.id,
+            now: SystemTime::UNIX_EPOCH + Duration::from_millis(self.now_ms),
+        };
+        let texts: Vec<&str> = self.texts.iter().map(String::as_str).collect();
+        build_batch_post_data_frozen(
+            frozen,
+            self.strategy,
+            JobOptions {
+                alternatives: self.alternatives,
+                formality: self.formality,
+                splitting: self.splitting,
+            },
+            &texts,
+            &self.source_lang,
+            &self.target_lang,
+        )
+    }
+}
+
+#[test]
+fn test_payloads_match_their_snapshots() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/payloads");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    for path in paths {
+        let text = fs::read_to_string(&path).unwrap();
+        let snapshot: Snapshot = serde_json::from_str(&text).unwrap();
+        let body = snapshot.build();
+        if std::env::var_os("GOLDEN_UPDATE").is_some() {
+            let mut file: Value = serde_json::from_str(&text).unwrap();
+            file["body"] = body.into();
+            let mut text = serde_json::to_string_pretty(&file).unwrap();
+            text.push('\n');
+            fs::write(&path, text).unwrap();
+            continue;
+        }
+        assert_eq!(
+            body,
+            snapshot.body,
+            "{} no longer matches its snapshot",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn test_frozen_clock_decides_id_and_timestamp() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+    let frozen = Frozen::at(now);
+    // Ids change once a second.
+    assert_eq!(frozen.id, Frozen::at(now + Duration::from_millis(500)).id);
+    let build = || {
+        build_batch_post_data_frozen(
+            frozen,
+            RequestStrategy::default(),
+            JobOptions::default(),
+            &["a\nb"],
+            "EN",
+            "DE",
+        )
+    };
+    assert_eq!(build(), build());
+    let body: Value = serde_json::from_str(&build()).unwrap();
+    assert_eq!(body["id"], frozen.id);
+    assert_eq!(body["params"]["timestamp"], 1_700_000_000_125u64);
+}
@@ -0,0 +1,77 @@
+//! Property tests for the placeholder protection round trip.
+
+use deeplx_rs::{
+    protect::{mask, unmask},
+    validate::{placeholders, tags},
+};
+use proptest::prelude::*;
+
+fn plain() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[a-zA-Z0-9 ,.!?']{1,12}",
+        "[а-яё ]{1,6}",
+        "[一-龥]{1,4}",
+        Just("😀".to_string()),
+        Just("👍🏽".to_string()),
+        Just("🇯🇵".to_string()),
+        Just("👨‍👩‍👧".to_string()),
+        Just("100%% ".to_string()),
+        Just(" { not } ".to_string()),
+        Just(" a < b ".to_string()),
+        Just("⟦".to_string()),
+    ]
+}
+
+fn placeholder() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[a-z_]{1,8}".prop_map(|n| format!("{{{}}}", n)),
+        "[a-z_]{1,8}".prop_map(|n| format!("{{{{{}}}}}", n)),
+        "[a-z]{1,8}".prop_map(|n| format!("${{{}}}", n)),
+        "[a-z]{1,8}".prop_map(|n| format!("%({})d", n)),
+        (1u8..10).prop_map(|n| format!("%{}$s", n)),
+        Just("%s".to_string()),
+        Just("%ld".to_string()),
+        (0u16..500).prop_map(|n| format!("⟦{}⟧", n)),
+    ]
+}
+
+/// Text mixing plain runs, placeholders and (possibly nested) tags whose
+/// attributes may themselves carry placeholders.
+fn text() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![plain(), placeholder()];
+    let tree = leaf.prop_recursive(4, 32, 4, |inner| {
+        (
+            prop_oneof![Just("b"), Just("i"), Just("a"), Just("x:span")],
+            prop::option::of(placeholder()),
+            prop::collection::vec(inner, 0..4),
+        )
+            .prop_map(|(tag, attr, children)| {
+                let attr = attr.map_or(String::new(), |a| format!(" title=\"{}\"", a));
+                format!("<{}{}>{}</{}>", tag, attr, children.concat(), tag)
+            })
+    });
+    prop::collection::vec(prop_oneof![tree, Just("<br/>".to_string())], 0..6)
+        .prop_map(|parts| parts.concat())
+}
+
+proptest! {
+    #[test]
+    fn prop_identity_translation_round_trips(text in text()) {
+        let masked = mask(&text);
+        prop_assert!(placeholders(&masked.text).is_empty());
+        prop_assert!(tags(&masked.text).is_empty());
+        prop_assert_eq!(unmask(&masked.text, &masked.tokens).unwrap(), text);
+    }
+
+    #[test]
+    fn prop_lost_or_duplicated_sentinels_are_rejected(text in text(), pick in any::<prop::sample::Index>()) {
+        let masked = mask(&text);
+        prop_assume!(!masked.tokens.is_empty());
+        let n = pick.index(masked.tokens.len());
+        let sentinel = format!("⟦{}⟧", n);
+        let dropped = masked.text.replacen(&sentinel, "", 1);
+        prop_assert!(unmask(&dropped, &masked.tokens).is_err());
+        let doubled = format!("{}{}", masked.text, sentinel);
+        prop_assert!(unmask(&doubled, &masked.tokens).is_err());
+    }
+}
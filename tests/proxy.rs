@@ -0,0 +1,35 @@
+//! Requests go through the configured proxy.
+
+#![cfg(feature = "client")]
+
+mod common;
+
+use common::{block_on, response, serve};
+use deeplx_rs::{error::DeepLError, DeepLClient, ProxyConfig, Translator};
+
+#[test]
+fn test_requests_go_through_the_proxy() {
+    block_on(async {
+        // The endpoint does not resolve, so only the proxy can answer.
+        let proxy = serve(response("403 Forbidden", &[], "via proxy")).await;
+        let proxy = proxy.trim_end_matches("/jsonrpc");
+        let client = DeepLClient::with_endpoint("http://deepl.invalid/jsonrpc")
+            .with_proxy(ProxyConfig::url(proxy).with_auth("user", "secret"))
+            .unwrap();
+        match client.translate("hello", "EN", "DE").await {
+            Err(DeepLError::Blocked { body }) => assert_eq!(body, "via proxy"),
+            other => panic!("proxy gave {:?}", other),
+        }
+
+        let direct = DeepLClient::with_endpoint("http://deepl.invalid/jsonrpc")
+            .with_proxy(ProxyConfig::Direct)
+            .unwrap();
+        assert!(matches!(
+            direct.translate("hello", "EN", "DE").await,
+            Err(DeepLError::Network(_))
+        ));
+    });
+    assert!(DeepLClient::new()
+        .with_proxy(ProxyConfig::url("gopher://proxy"))
+        .is_err());
+}
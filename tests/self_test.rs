@@ -0,0 +1,78 @@
+#![cfg(feature = "client")]
+
+mod common;
+
+use std::sync::Arc;
+
+use common::{block_on, response, serve, serve_sequence};
+use deeplx_rs::{doctor::CheckStatus, session::SessionPool, DeepLClient, ProxyConfig};
+
+const HALLO: &str = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"texts\":[{\"text\":\"Hallo, Welt!\",\"alternatives\":[]}],\"lang\":\"EN\",\"lang_is_confident\":true,\"detectedLanguages\":{\"EN\":0.9}}}";
+
+fn self_test(raw: String) -> deeplx_rs::doctor::SelfTest {
+    block_on(async move {
+        DeepLClient::with_endpoint(serve(raw).await)
+            .self_test()
+            .await
+    })
+}
+
+#[test]
+fn test_self_test_reports_success_and_latency() {
+    let report = self_test(response("200 OK", &[], HALLO));
+    assert!(report.passed(), "{}", report);
+    assert!(report.latency.is_some());
+    assert!(report.to_string().contains("Hallo, Welt!"));
+}
+
+#[test]
+fn test_self_test_explains_rate_limits() {
+    let report = self_test(response("429 Too Many Requests", &["Retry-After: 30"], ""));
+    assert!(!report.passed());
+    let check = report
+        .checks
+        .iter()
+        .find(|c| c.name == "connectivity")
+        .unwrap();
+    assert_eq!(check.status, CheckStatus::Fail);
+    assert!(check.detail.contains("wait 30s"), "{}", check.detail);
+}
+
+#[test]
+fn test_self_test_probes_each_session_and_the_proxy() {
+    let report = block_on(async {
+        let ok = response("200 OK", &[], HALLO);
+        // The proxy probe, DeepL rejecting the first token, then the
+        // second token and the translation through it.
+        let upstream =
+            serve_sequence(vec![ok.clone(), response("401 Unauthorized", &[], ""), ok]).await;
+        let proxy = upstream.trim_end_matches("/jsonrpc").to_string();
+        let pool = SessionPool::new(["first-aaaa".to_string(), "second-bbbb".to_string()]);
+        DeepLClient::with_endpoint("http://deepl.invalid/jsonrpc")
+            .with_proxy(ProxyConfig::url(proxy))
+            .unwrap()
+            .with_session_pool(Arc::new(pool))
+            .self_test()
+            .await
+    });
+    assert!(report.passed(), "{}", report);
+    let named = |name| {
+        report
+            .checks
+            .iter()
+            .filter(|c| c.name == name)
+            .map(|c| (c.status, c.detail.as_str()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(named("proxy").len(), 1);
+    assert_eq!(named("proxy")[0].0, CheckStatus::Pass);
+    let sessions = named("dl_session");
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0].0, CheckStatus::Warn);
+    assert!(
+        sessions[0].1.contains("…aaaa rejected"),
+        "{}",
+        sessions[0].1
+    );
+    assert_eq!(sessions[1], (CheckStatus::Pass, "…bbbb accepted"));
+}